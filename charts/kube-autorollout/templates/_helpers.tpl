@@ -60,3 +60,14 @@ Create the name of the service account to use
 {{- default "default" .Values.serviceAccount.name }}
 {{- end }}
 {{- end }}
+
+{{/*
+Probe targeting the health port while the health probes are served on a port of their own
+*/}}
+{{- define "kube-autorollout.healthProbe" -}}
+{{- $probe := deepCopy .probe }}
+{{- if and .Values.config.webserver.health $probe.httpGet }}
+{{- $_ := set $probe.httpGet "port" "health" }}
+{{- end }}
+{{- toYaml $probe }}
+{{- end }}
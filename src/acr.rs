@@ -0,0 +1,202 @@
+use crate::secret_string::SecretString;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use std::env;
+use tracing::{debug, info};
+
+/// Username ACR expects together with a refresh token obtained from the token exchange
+static ACR_REFRESH_TOKEN_USERNAME: &str = "00000000-0000-0000-0000-000000000000";
+static ACR_SCOPE: &str = "https://containerregistry.azure.net/.default";
+static ACR_RESOURCE: &str = "https://containerregistry.azure.net";
+static DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com/";
+static INSTANCE_METADATA_TOKEN_URL: &str =
+    "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01";
+/// Lifetime assumed for refresh tokens whose expiry can not be read, ACR issues them for three hours
+const DEFAULT_REFRESH_TOKEN_LIFETIME_HOURS: i64 = 3;
+
+/// ACR refresh token, already in the `base64(<username>:<refresh token>)` form expected for basic authentication
+pub struct AcrCredential {
+    pub authorization: SecretString,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct AadTokenResponse {
+    access_token: SecretString,
+}
+
+#[derive(Deserialize)]
+struct AcrExchangeResponse {
+    refresh_token: SecretString,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    exp: Option<i64>,
+}
+
+/// Exchanges a Microsoft Entra token of the controller's managed identity for an ACR refresh token of the registry.
+/// The refresh token is used as password of the registry's token authentication, just like `az acr login` does.
+pub async fn get_refresh_token(
+    client: &Client,
+    registry: &str,
+    client_id: Option<&str>,
+    tenant_id: Option<&str>,
+) -> Result<AcrCredential> {
+    let aad_token = get_aad_token(client, client_id, tenant_id)
+        .await
+        .context("Failed to get Microsoft Entra token of the managed identity")?;
+
+    let url = format!("https://{}/oauth2/exchange", registry);
+    let mut form = vec![
+        ("grant_type", "access_token"),
+        ("service", registry),
+        ("access_token", aad_token.expose_secret()),
+    ];
+    if let Some(tenant_id) = tenant_id {
+        form.push(("tenant", tenant_id));
+    }
+    let response = client
+        .post(&url)
+        .form(&form)
+        .send()
+        .await
+        .with_context(|| format!("Failed to exchange Microsoft Entra token at {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "ACR token exchange with {} failed with status {}: {}",
+            registry,
+            status,
+            body
+        );
+    }
+    let refresh_token = response
+        .json::<AcrExchangeResponse>()
+        .await
+        .context("Failed to parse ACR token exchange response")?
+        .refresh_token;
+
+    let expires_at = jwt_expiry(refresh_token.expose_secret())
+        .unwrap_or_else(|| Utc::now() + Duration::hours(DEFAULT_REFRESH_TOKEN_LIFETIME_HOURS));
+    info!(
+        registry = %registry,
+        expires_at = %expires_at,
+        "Obtained ACR refresh token"
+    );
+    let authorization = STANDARD.encode(format!(
+        "{}:{}",
+        ACR_REFRESH_TOKEN_USERNAME,
+        refresh_token.expose_secret()
+    ));
+    Ok(AcrCredential {
+        authorization: SecretString::new(authorization),
+        expires_at,
+    })
+}
+
+/// Uses AKS workload identity if its federated token is projected into the pod, the managed identity of the node
+/// through the instance metadata service otherwise
+async fn get_aad_token(
+    client: &Client,
+    client_id: Option<&str>,
+    tenant_id: Option<&str>,
+) -> Result<SecretString> {
+    if let Ok(token_file) = env::var("AZURE_FEDERATED_TOKEN_FILE") {
+        let client_id = client_id
+            .map(str::to_string)
+            .or_else(|| env::var("AZURE_CLIENT_ID").ok())
+            .context("Workload identity requires a client ID, set AZURE_CLIENT_ID or clientId")?;
+        let tenant_id = tenant_id
+            .map(str::to_string)
+            .or_else(|| env::var("AZURE_TENANT_ID").ok())
+            .context("Workload identity requires a tenant ID, set AZURE_TENANT_ID or tenantId")?;
+        let authority_host =
+            env::var("AZURE_AUTHORITY_HOST").unwrap_or_else(|_| DEFAULT_AUTHORITY_HOST.to_string());
+        let assertion = tokio::fs::read_to_string(&token_file)
+            .await
+            .with_context(|| format!("Failed to read federated token file {}", token_file))?;
+        debug!(client_id = %client_id, "Using AKS workload identity");
+
+        let url = format!(
+            "{}/{}/oauth2/v2.0/token",
+            authority_host.trim_end_matches('/'),
+            tenant_id
+        );
+        let response = client
+            .post(&url)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", client_id.as_str()),
+                ("scope", ACR_SCOPE),
+                (
+                    "client_assertion_type",
+                    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+                ),
+                ("client_assertion", assertion.trim()),
+            ])
+            .send()
+            .await
+            .with_context(|| format!("Failed to request Microsoft Entra token from {}", url))?;
+        return parse_aad_token_response(response).await;
+    }
+
+    debug!(client_id = ?client_id, "Using the managed identity of the node");
+    let mut url = format!("{}&resource={}", INSTANCE_METADATA_TOKEN_URL, ACR_RESOURCE);
+    if let Some(client_id) = client_id {
+        url.push_str(&format!("&client_id={}", client_id));
+    }
+    let response = client
+        .get(&url)
+        .header("Metadata", "true")
+        .send()
+        .await
+        .context("Failed to request managed identity token from the instance metadata service")?;
+    parse_aad_token_response(response).await
+}
+
+async fn parse_aad_token_response(response: reqwest::Response) -> Result<SecretString> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "Microsoft Entra token request failed with status {}: {}",
+            status,
+            body
+        );
+    }
+    Ok(response
+        .json::<AadTokenResponse>()
+        .await
+        .context("Failed to parse Microsoft Entra token response")?
+        .access_token)
+}
+
+/// Reads the expiry of a JWT without verifying it, the registry verifies the token
+fn jwt_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let payload = token.split('.').nth(1)?;
+    let claims: JwtClaims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    Utc.timestamp_opt(claims.exp?, 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_refresh_token_expiry() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"exp":1767225600,"grant_type":"refresh_token"}"#);
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.signature", payload);
+
+        assert_eq!(
+            jwt_expiry(&token),
+            Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).single()
+        );
+        assert_eq!(jwt_expiry("not-a-jwt"), None);
+    }
+}
@@ -0,0 +1,603 @@
+use crate::approval::{self, PendingApproval};
+use crate::build_info;
+use crate::config::{ControllerApi, SHARED_TOKEN_APPROVER};
+use crate::controller::{self, WorkloadSelector};
+use crate::history;
+use crate::rollout::Rollout;
+use crate::schedule::WorkloadSchedule;
+use crate::secret_string::SecretString;
+use crate::skip_reason::SkipReason;
+use crate::state::{ControllerContext, TrackedContainer, TrackedWorkload};
+use axum::extract::{Path, Query, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use kube::Api;
+use serde::Serialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkloadResponse {
+    kind: String,
+    namespace: String,
+    name: String,
+    containers: Vec<ContainerResponse>,
+    last_checked_at: Option<DateTime<Utc>>,
+    last_rollout_at: Option<DateTime<Utc>>,
+    deferred: Option<String>,
+    skip_reason: Option<SkipReason>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerResponse {
+    name: String,
+    image: String,
+    running_digest: String,
+    /// Latest digest fetched from the registry
+    registry_digest: Option<String>,
+    registry_host: String,
+    up_to_date: bool,
+}
+
+impl WorkloadResponse {
+    fn from_tracked_workload(workload: &TrackedWorkload) -> Self {
+        WorkloadResponse {
+            kind: workload.kind.clone(),
+            namespace: workload.namespace.clone(),
+            name: workload.name.clone(),
+            containers: workload
+                .containers
+                .iter()
+                .map(|(name, container)| ContainerResponse::from_tracked_container(name, container))
+                .collect(),
+            last_checked_at: workload.last_checked_at,
+            last_rollout_at: workload.last_rollout_at,
+            deferred: workload.skipped.as_ref().map(SkipReason::to_string),
+            skip_reason: workload.skipped.clone(),
+        }
+    }
+}
+
+impl ContainerResponse {
+    fn from_tracked_container(name: &str, container: &TrackedContainer) -> Self {
+        ContainerResponse {
+            name: name.to_string(),
+            image: container.image.clone(),
+            running_digest: container.running_digest.clone(),
+            registry_digest: container.registry_digests.last().cloned(),
+            registry_host: container.registry_host.clone(),
+            up_to_date: container
+                .registry_digests
+                .contains(&container.running_digest),
+        }
+    }
+}
+
+/// Lists the labeled workloads checked since the controller started, with the digests of their containers
+pub async fn list_workloads(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let state = ctx.state.read().unwrap();
+    let workloads: Vec<WorkloadResponse> = state
+        .workloads
+        .values()
+        .map(WorkloadResponse::from_tracked_workload)
+        .collect();
+    Json(workloads)
+}
+
+/// Lists the persisted rollout history of all workloads, available with `history` configured
+pub async fn list_history(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    let Some(history) = &ctx.config.history else {
+        return (StatusCode::NOT_FOUND, "The rollout history is not enabled").into_response();
+    };
+    match history::read(&ctx, history).await {
+        Ok(workloads) => Json(workloads).into_response(),
+        Err(err) => {
+            error!(error = ?err, "Failed to read rollout history");
+            (StatusCode::BAD_GATEWAY, format!("{:#}", err)).into_response()
+        }
+    }
+}
+
+/// Endpoints acting on workloads are only served with `api` configured
+fn is_authorized(ctx: &ControllerContext, headers: &HeaderMap) -> bool {
+    authorized_caller(ctx.config.api.as_ref(), headers).is_some()
+}
+
+/// Caller of the API identified by its bearer token
+#[derive(Debug, PartialEq)]
+enum Caller<'a> {
+    /// Holder of the shared `api.token`, e.g. a CI pipeline
+    SharedToken,
+    /// Approver of `api.approvers` by name
+    Approver(&'a str),
+}
+
+fn authorized_caller<'a>(
+    api: Option<&'a ControllerApi>,
+    headers: &HeaderMap,
+) -> Option<Caller<'a>> {
+    let api = api?;
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))?;
+    if api
+        .token
+        .as_ref()
+        .is_some_and(|shared| token_matches(shared, token))
+    {
+        return Some(Caller::SharedToken);
+    }
+    api.approvers
+        .iter()
+        .find(|approver| token_matches(&approver.token, token))
+        .map(|approver| Caller::Approver(&approver.name))
+}
+
+/// Compares in constant time, so that the response time does not tell how much of a guessed token is right
+fn token_matches(expected: &SecretString, token: &str) -> bool {
+    expected
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(token.as_bytes())
+        .into()
+}
+
+/// Name recorded for the caller approving a rollout. The shared token can not tell people apart, so it only approves
+/// rollouts that need a single approver.
+fn approver_name<'a>(caller: Caller<'a>, required_approvers: usize) -> Option<&'a str> {
+    match caller {
+        Caller::Approver(name) => Some(name),
+        Caller::SharedToken if required_approvers <= 1 => Some(SHARED_TOKEN_APPROVER),
+        Caller::SharedToken => None,
+    }
+}
+
+/// Starts a reconciliation of the selected workloads outside the schedule, e.g. right after a CI pipeline pushed
+/// an image
+pub async fn trigger_reconcile(
+    State(ctx): State<ControllerContext>,
+    Query(selector): Query<WorkloadSelector>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    if !is_authorized(&ctx, &headers) {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if !ctx.is_leader() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "This replica is not the leader",
+        )
+            .into_response();
+    }
+
+    info!(selector = ?selector, "Reconciling workloads on request");
+    let response = json!({ "selector": selector });
+    tokio::spawn(async move {
+        if let Err(err) = controller::run_for_selection(ctx, &selector).await {
+            error!(
+                error = ?err,
+                selector = ?selector,
+                "Error while reconciling workloads on request"
+            );
+        }
+    });
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+/// Lists the rollouts waiting for approval, by approval ID
+pub async fn list_approvals(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let state = ctx.state.read().unwrap();
+    Json(state.pending_approvals.clone())
+}
+
+/// Workloads that are no longer checked after failing repeatedly, with their last error
+pub async fn list_dead_letters(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let state = ctx.state.read().unwrap();
+    Json(state.work_queue.dead_letters())
+}
+
+/// Completion of the last run and the expected and actual start of the last scheduled runs, to spot scheduling gaps
+pub async fn last_run(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let state = ctx.state.read().unwrap();
+    Json(json!({
+        "completedCycles": state.completed_cycles,
+        "lastCompletedRunAt": state.last_completed_run_at,
+        "skippedRuns": state.skipped_runs,
+        "lastTick": state.schedule_ticks.last_tick,
+        "lastGap": state.schedule_ticks.last_gap,
+        "scheduleGaps": state.schedule_ticks.gaps,
+    }))
+}
+
+/// The configuration as last loaded, after the expansion of environment variables, the parsing of the image pull
+/// secrets and with defaults applied, secrets redacted
+pub async fn effective_config(
+    State(ctx): State<ControllerContext>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    if !is_authorized(&ctx, &headers) {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match ctx.config.to_redacted_json() {
+        Ok(config) => Json(config).into_response(),
+        Err(err) => {
+            error!(error = ?err, "Failed to render the configuration");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Build, features and the effective configuration of this instance with its secrets redacted, to audit which
+/// configuration an instance actually runs
+pub async fn info(State(ctx): State<ControllerContext>, headers: HeaderMap) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    if !is_authorized(&ctx, &headers) {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let config = match ctx.config.to_redacted_json() {
+        Ok(config) => config,
+        Err(err) => {
+            error!(error = ?err, "Failed to render the configuration");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let (started_at, config_error) = {
+        let state = ctx.state.read().unwrap();
+        (state.started_at, state.config_error.clone())
+    };
+    let next_run_at = WorkloadSchedule::of_controller(&ctx.config)
+        .and_then(|schedule| schedule.occurrence_after(Utc::now(), 1))
+        .ok();
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "gitSha": build_info::git_sha(),
+        "buildDate": build_info::build_date(),
+        "features": build_info::enabled_features(),
+        "startedAt": started_at,
+        "leader": ctx.is_leader(),
+        "scheduling": {
+            "cronSchedule": config["cronSchedule"],
+            "checkInterval": config["checkInterval"],
+            "watchResyncInterval": config["watch"]["resyncInterval"],
+            "nextRunAt": next_run_at,
+            "reconcileConcurrency": config["reconcileConcurrency"],
+            "reconcileTimeouts": config["reconcileTimeouts"],
+        },
+        "configError": config_error,
+        "config": config,
+    }))
+    .into_response()
+}
+
+/// Operations in flight and the load of the async runtime, to tell whether the Kubernetes API or a registry stalls a
+/// reconcile pass
+#[cfg(feature = "runtime-diagnostics")]
+pub async fn list_tasks(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    Json(json!({
+        "inFlight": ctx.in_flight.snapshot(),
+        "runtime": {
+            "workers": metrics.num_workers(),
+            "aliveTasks": metrics.num_alive_tasks(),
+            "globalQueueDepth": metrics.global_queue_depth(),
+        },
+    }))
+}
+
+/// The last registry requests with their status, latency and headers, credentials redacted
+pub async fn list_registry_requests(
+    State(ctx): State<ControllerContext>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    if !is_authorized(&ctx, &headers) {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(ctx.registry_requests.snapshot()).into_response()
+}
+
+/// Records the caller as approver of a pending rollout. Once `requiredApprovers` distinct approvers approved it, its
+/// workload is checked right away, so that it is rolled out without waiting for the next scheduled run.
+pub async fn approve_rollout(
+    State(ctx): State<ControllerContext>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    let Some(caller) = authorized_caller(ctx.config.api.as_ref(), &headers) else {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(approver) = approver_name(caller, ctx.config.required_approvers).map(str::to_string)
+    else {
+        warn!("Rejecting approval with the shared API token, as several approvers are required");
+        return (
+            StatusCode::FORBIDDEN,
+            "Rollouts require several approvers, approve with the token of an approver",
+        )
+            .into_response();
+    };
+    if !ctx.is_leader() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "This replica is not the leader",
+        )
+            .into_response();
+    }
+    let Some(pending) = ctx
+        .state
+        .read()
+        .unwrap()
+        .pending_approvals
+        .get(&id)
+        .cloned()
+    else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No rollout is waiting for approval {}", id),
+        )
+            .into_response();
+    };
+
+    let result = match pending.kind.as_str() {
+        "Deployment" => approve_pending::<Deployment>(&ctx, &id, &pending, &approver).await,
+        "StatefulSet" => approve_pending::<StatefulSet>(&ctx, &id, &pending, &approver).await,
+        "DaemonSet" => approve_pending::<DaemonSet>(&ctx, &id, &pending, &approver).await,
+        kind => Err(anyhow::anyhow!("Unsupported workload kind {}", kind)),
+    };
+    let approved_by = match result {
+        Ok(approved_by) => approved_by,
+        Err(err) => {
+            error!(error = ?err, approval = %id, "Failed to approve rollout");
+            return (StatusCode::BAD_GATEWAY, format!("{:#}", err)).into_response();
+        }
+    };
+    let required_approvers = ctx.config.required_approvers;
+    info!(
+        approval = %id,
+        approver = %approver,
+        approvals = %approved_by.len(),
+        required_approvers = %required_approvers,
+        kind = %pending.kind,
+        resource = %pending.name,
+        namespace = %pending.namespace,
+        "Approved rollout on request"
+    );
+    let response = json!({
+        "approvedBy": approved_by,
+        "requiredApprovers": required_approvers,
+    });
+    if approved_by.len() < required_approvers {
+        return (StatusCode::ACCEPTED, Json(response)).into_response();
+    }
+
+    let selector = WorkloadSelector {
+        kind: Some(pending.kind),
+        namespace: Some(pending.namespace),
+        name: Some(pending.name),
+    };
+    tokio::spawn(async move {
+        if let Err(err) = controller::run_for_selection(ctx, &selector).await {
+            error!(
+                error = ?err,
+                selector = ?selector,
+                "Error while rolling out approved workload"
+            );
+        }
+    });
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+async fn approve_pending<T: Rollout>(
+    ctx: &ControllerContext,
+    id: &str,
+    pending: &PendingApproval,
+    approver: &str,
+) -> anyhow::Result<Vec<String>> {
+    let api: Api<T> = Api::namespaced(ctx.kube_client.clone(), &pending.namespace);
+    approval::approve(&api, &pending.name, id, approver).await
+}
+
+pub async fn pause_workload(
+    State(ctx): State<ControllerContext>,
+    Path((namespace, kind, name)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    set_paused(ctx, headers, &namespace, &kind, &name, true).await
+}
+
+pub async fn resume_workload(
+    State(ctx): State<ControllerContext>,
+    Path((namespace, kind, name)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    set_paused(ctx, headers, &namespace, &kind, &name, false).await
+}
+
+/// Pauses or resumes automatic rollouts of a workload through the `kube-autorollout/paused` annotation. The kind is
+/// matched case-insensitively, in singular or plural.
+async fn set_paused(
+    ctx: ControllerContext,
+    headers: HeaderMap,
+    namespace: &str,
+    kind: &str,
+    name: &str,
+    paused: bool,
+) -> axum::response::Response {
+    let ctx = ctx.with_latest_config();
+    if !is_authorized(&ctx, &headers) {
+        warn!("Rejecting API request with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let result = match kind.to_lowercase().trim_end_matches('s') {
+        "deployment" => patch_paused::<Deployment>(&ctx, namespace, name, paused).await,
+        "statefulset" => patch_paused::<StatefulSet>(&ctx, namespace, name, paused).await,
+        "daemonset" => patch_paused::<DaemonSet>(&ctx, namespace, name, paused).await,
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Unsupported workload kind {}", kind),
+            )
+                .into_response();
+        }
+    };
+    match result {
+        Ok(()) => {
+            info!(
+                kind = %kind,
+                resource = %name,
+                namespace = %namespace,
+                paused = %paused,
+                "Changed pause state of workload on request"
+            );
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => {
+            let status = match err.downcast_ref::<kube::Error>() {
+                Some(kube::Error::Api(response)) if response.code == 404 => StatusCode::NOT_FOUND,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            error!(error = ?err, "Failed to change pause state of workload");
+            (status, format!("{:#}", err)).into_response()
+        }
+    }
+}
+
+async fn patch_paused<T: Rollout>(
+    ctx: &ControllerContext,
+    namespace: &str,
+    name: &str,
+    paused: bool,
+) -> anyhow::Result<()> {
+    let api: Api<T> = Api::namespaced(ctx.kube_client.clone(), namespace);
+    T::set_paused(&api, name, paused).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::PendingRollout;
+    use crate::image_reference::ImageReference;
+    use crate::rollout::ChangedContainer;
+    use crate::state::{ContainerImageReference, ControllerState};
+
+    #[test]
+    fn workloads_list_the_latest_registry_digest_of_their_containers() {
+        let mut state = ControllerState::default();
+        let reference = ContainerImageReference {
+            container_name: "app".to_string(),
+            image_reference: ImageReference::parse("ghcr.io/org/app:latest").unwrap(),
+            digest: "sha256:old".to_string(),
+            init_container: false,
+        };
+        state.record_container_check(
+            "Deployment",
+            "default",
+            "app",
+            &reference,
+            "ghcr.io",
+            &["sha256:old".to_string(), "sha256:new".to_string()],
+        );
+
+        let response =
+            WorkloadResponse::from_tracked_workload(&state.workloads["Deployment/default/app"]);
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["kind"], "Deployment");
+        assert_eq!(
+            response["containers"][0],
+            json!({
+                "name": "app",
+                "image": "ghcr.io/org/app:latest",
+                "runningDigest": "sha256:old",
+                "registryDigest": "sha256:new",
+                "registryHost": "ghcr.io",
+                "upToDate": true,
+            })
+        );
+        assert!(response["lastCheckedAt"].is_string());
+        assert!(response["lastRolloutAt"].is_null());
+    }
+
+    fn api() -> ControllerApi {
+        serde_yaml_ng::from_str(
+            r#"
+        token: shared-token
+        approvers:
+          - name: alice
+            token: alice-token
+          - name: bob
+            token: bob-token
+        "#,
+        )
+        .unwrap()
+    }
+
+    fn caller<'a>(api: &'a ControllerApi, token: &str) -> Option<Caller<'a>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        authorized_caller(Some(api), &headers)
+    }
+
+    #[test]
+    fn callers_are_identified_by_their_token() {
+        let api = api();
+        assert_eq!(caller(&api, "alice-token"), Some(Caller::Approver("alice")));
+        assert_eq!(caller(&api, "bob-token"), Some(Caller::Approver("bob")));
+        assert_eq!(caller(&api, "shared-token"), Some(Caller::SharedToken));
+        assert_eq!(caller(&api, "alice-token-suffix"), None);
+        assert_eq!(caller(&api, "unknown-token"), None);
+        assert_eq!(authorized_caller(None, &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn shared_token_does_not_count_towards_several_approvers() {
+        let api = api();
+        let changed = ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:old".to_string(),
+            new_digest: "sha256:new".to_string(),
+            init_container: false,
+            previous_image: None,
+        };
+        let pending = PendingRollout::new("Deployment", "default", "app", &[changed]);
+        let mut annotated = pending.clone();
+        for token in ["shared-token", "alice-token"] {
+            let caller = caller(&api, token).unwrap();
+            if let Some(approver) = approver_name(caller, 2) {
+                annotated.add_approver(approver);
+            }
+        }
+        assert_eq!(annotated.approved_by, vec!["alice"]);
+
+        let mut deployment = Deployment::default();
+        deployment.metadata.annotations = Some(
+            [(
+                "kube-autorollout/pending-rollout".to_string(),
+                serde_json::to_string(&annotated).unwrap(),
+            )]
+            .into(),
+        );
+        assert!(!approval::is_approved(&deployment, &pending, 2));
+        assert_eq!(
+            approver_name(Caller::SharedToken, 1),
+            Some(SHARED_TOKEN_APPROVER)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use crate::controller::describe_changed_container;
+use crate::events::{publish, workload_reference};
+use crate::notify::{self, Notification};
+use crate::rollout::{ChangedContainer, Rollout};
+use crate::skip_reason::SkipReason;
+use crate::state::ControllerContext;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use kube::api::{Patch, PatchParams};
+use kube::runtime::events::EventType;
+use kube::{Api, ResourceExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// Overrides `requireApproval` per workload with "true" or "false"
+static KUBE_AUTOROLLOUT_REQUIRE_APPROVAL_ANNOTATION: &str = "kube-autorollout/require-approval";
+/// Rollout waiting for approval, written by kube-autorollout
+static KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION: &str = "kube-autorollout/pending-rollout";
+/// Set to "true" to approve the pending rollout, unless more than one approver is required
+static KUBE_AUTOROLLOUT_APPROVE_ANNOTATION: &str = "kube-autorollout/approve";
+/// Set to "notify" to only report available updates of the workload, which are applied like approved rollouts
+static KUBE_AUTOROLLOUT_POLICY_ANNOTATION: &str = "kube-autorollout/policy";
+
+/// Rollout detected for a workload that requires approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingRollout {
+    /// Derived from the workload and the new digests or images, so a further change requires a new approval
+    pub(crate) id: String,
+    pub(crate) changed_containers: Vec<ChangedContainer>,
+    /// Distinct approvers of the rollout so far
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) approved_by: Vec<String>,
+}
+
+/// Pending rollout as tracked in the controller state and listed by the API
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingApproval {
+    pub(crate) kind: String,
+    pub(crate) namespace: String,
+    pub(crate) name: String,
+    pub(crate) changed_containers: Vec<ChangedContainer>,
+    pub(crate) requested_at: DateTime<Utc>,
+    /// Whether the workload only reports available updates through the `notify` policy
+    pub(crate) notify_only: bool,
+    pub(crate) approved_by: Vec<String>,
+}
+
+impl PendingRollout {
+    pub fn new(
+        kind: &str,
+        namespace: &str,
+        name: &str,
+        changed_containers: &[ChangedContainer],
+    ) -> Self {
+        let mut content = format!("{}/{}/{}", kind, namespace, name);
+        for changed in changed_containers {
+            content.push_str(&format!(";{}@{}", changed.container_name, changed.target()));
+        }
+        let digest = ring::digest::digest(&ring::digest::SHA256, content.as_bytes());
+        let id: String = digest.as_ref()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        PendingRollout {
+            id,
+            changed_containers: changed_containers.to_vec(),
+            approved_by: Vec::new(),
+        }
+    }
+
+    /// Records an approver, who counts once no matter how often they approve
+    pub fn add_approver(&mut self, approver: &str) -> bool {
+        if self
+            .approved_by
+            .iter()
+            .any(|approved_by| approved_by == approver)
+        {
+            return false;
+        }
+        self.approved_by.push(approver.to_string());
+        true
+    }
+}
+
+/// Whether the workload only reports available updates, set with `kube-autorollout/policy: notify`
+pub fn is_notify_only<T: Rollout>(resource: &T) -> bool {
+    resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_POLICY_ANNOTATION)
+        .is_some_and(|policy| policy == "notify")
+}
+
+/// Whether rollouts of the workload wait for approval, selected by its annotations or the configured default.
+/// Updates of workloads with the `notify` policy are always held back until they are approved.
+pub fn is_required<T: Rollout>(resource: &T, default: bool) -> bool {
+    if is_notify_only(resource) {
+        return true;
+    }
+    match resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_REQUIRE_APPROVAL_ANNOTATION)
+        .map(String::as_str)
+    {
+        Some("true") => true,
+        Some("false") => false,
+        _ => default,
+    }
+}
+
+fn pending_rollout<T: Rollout>(resource: &T) -> Option<PendingRollout> {
+    let value = resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION)?;
+    serde_json::from_str(value).ok()
+}
+
+/// A rollout is approved when its pending rollout is still the one detected now and enough distinct approvers
+/// approved it. The approval annotation carries no approver and is only accepted when a single approver suffices.
+pub fn is_approved<T: Rollout>(
+    resource: &T,
+    pending: &PendingRollout,
+    required_approvers: usize,
+) -> bool {
+    let Some(annotated) = pending_rollout(resource).filter(|annotated| annotated.id == pending.id)
+    else {
+        return false;
+    };
+    annotated.approved_by.len() >= required_approvers
+        || required_approvers <= 1
+            && resource
+                .annotations()
+                .get(KUBE_AUTOROLLOUT_APPROVE_ANNOTATION)
+                .is_some_and(|value| value == "true")
+}
+
+/// Records the rollout as pending on the workload, unless it is pending already. A previous approval is removed, as
+/// it approved other digests.
+pub async fn request<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+    pending: PendingRollout,
+) -> Result<()> {
+    let notify_only = is_notify_only(resource);
+    let annotated = pending_rollout(resource).filter(|annotated| annotated.id == pending.id);
+    let already_pending = annotated.is_some();
+    if !already_pending {
+        patch_annotations(
+            api,
+            name,
+            json!({
+                KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION: serde_json::to_string(&pending)?,
+                KUBE_AUTOROLLOUT_APPROVE_ANNOTATION: Value::Null,
+            }),
+        )
+        .await?;
+        info!(
+            kind = %kind,
+            resource = %name,
+            namespace = %namespace,
+            approval = %pending.id,
+            notify_only = %notify_only,
+            "Rollout is waiting for approval"
+        );
+        let (reason, message, title) = match notify_only {
+            true => (
+                "UpdateAvailable",
+                format!("Update {} is available", pending.id),
+                format!(
+                    "Update {} is available for {} {}/{}",
+                    pending.id, kind, namespace, name
+                ),
+            ),
+            false => (
+                "RolloutPendingApproval",
+                format!("Rollout {} is waiting for approval", pending.id),
+                format!(
+                    "Rollout of {} {}/{} is waiting for approval {}",
+                    kind, namespace, name, pending.id
+                ),
+            ),
+        };
+        publish(
+            ctx,
+            workload_reference(kind, namespace, name),
+            EventType::Normal,
+            reason,
+            "Rollout",
+            message,
+        );
+        notify::send(
+            ctx,
+            Notification {
+                title,
+                details: pending
+                    .changed_containers
+                    .iter()
+                    .map(describe_changed_container)
+                    .collect(),
+                failed: false,
+                images: Vec::new(),
+            },
+        );
+    }
+
+    let mut state = ctx.state.write().unwrap();
+    state.record_skip(
+        kind,
+        namespace,
+        name,
+        match notify_only {
+            true => SkipReason::UpdateAvailable {
+                id: pending.id.clone(),
+            },
+            false => SkipReason::AwaitingApproval {
+                id: pending.id.clone(),
+            },
+        },
+    );
+    state.remove_pending_approval(kind, namespace, name);
+    state.pending_approvals.insert(
+        pending.id,
+        PendingApproval {
+            kind: kind.to_string(),
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            changed_containers: pending.changed_containers,
+            requested_at: Utc::now(),
+            notify_only,
+            approved_by: annotated
+                .map(|annotated| annotated.approved_by)
+                .unwrap_or_default(),
+        },
+    );
+    Ok(())
+}
+
+/// Removes the pending rollout and its approval from a workload that no longer waits for approval, because it was
+/// rolled out or its digests are up to date again
+pub async fn clear<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+) {
+    ctx.state
+        .write()
+        .unwrap()
+        .remove_pending_approval(kind, namespace, name);
+    let annotations = resource.annotations();
+    if !annotations.contains_key(KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION)
+        && !annotations.contains_key(KUBE_AUTOROLLOUT_APPROVE_ANNOTATION)
+    {
+        return;
+    }
+    let result = patch_annotations(
+        api,
+        name,
+        json!({
+            KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION: Value::Null,
+            KUBE_AUTOROLLOUT_APPROVE_ANNOTATION: Value::Null,
+        }),
+    )
+    .await;
+    if let Err(err) = result {
+        warn!(
+            error = ?err,
+            kind = %kind,
+            resource = %name,
+            "Failed to remove the pending rollout annotations"
+        );
+    }
+}
+
+/// Records the approver on the pending rollout of a workload, which is rolled out by its next check once enough
+/// distinct approvers approved it. Returns the approvers so far.
+pub async fn approve<T: Rollout>(
+    api: &Api<T>,
+    name: &str,
+    id: &str,
+    approver: &str,
+) -> Result<Vec<String>> {
+    let resource = api
+        .get(name)
+        .await
+        .with_context(|| format!("Failed to get {} {}", T::kind_name(), name))?;
+    let Some(mut annotated) = pending_rollout(&resource).filter(|annotated| annotated.id == id)
+    else {
+        bail!(
+            "Rollout {} is no longer pending on {} {}",
+            id,
+            T::kind_name(),
+            name
+        );
+    };
+    if annotated.add_approver(approver) {
+        // The resource version rejects the patch if another approval was recorded in the meantime
+        patch_metadata(
+            api,
+            name,
+            json!({
+                "resourceVersion": resource.resource_version(),
+                "annotations": {
+                    KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION: serde_json::to_string(&annotated)?,
+                },
+            }),
+        )
+        .await?;
+    }
+    Ok(annotated.approved_by)
+}
+
+async fn patch_annotations<T: Rollout>(api: &Api<T>, name: &str, annotations: Value) -> Result<()> {
+    patch_metadata(api, name, json!({ "annotations": annotations })).await
+}
+
+async fn patch_metadata<T: Rollout>(api: &Api<T>, name: &str, metadata: Value) -> Result<()> {
+    let patch = json!({ "metadata": metadata });
+    api.patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to update the approval annotations of {} {}",
+                T::kind_name(),
+                name
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::apps::v1::Deployment;
+    use std::collections::BTreeMap;
+
+    fn changed(new_digest: &str) -> ChangedContainer {
+        ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:old".to_string(),
+            new_digest: new_digest.to_string(),
+            init_container: false,
+            previous_image: None,
+        }
+    }
+
+    fn deployment(annotations: &[(&str, String)]) -> Deployment {
+        let mut deployment = Deployment::default();
+        deployment.metadata.annotations = Some(
+            annotations
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect::<BTreeMap<_, _>>(),
+        );
+        deployment
+    }
+
+    #[test]
+    fn only_the_pending_digests_are_approved() {
+        let pending = PendingRollout::new("Deployment", "default", "app", &[changed("sha256:new")]);
+        let newer = PendingRollout::new("Deployment", "default", "app", &[changed("sha256:newer")]);
+        assert_ne!(pending.id, newer.id);
+
+        let annotated = serde_json::to_string(&pending).unwrap();
+        let approved = deployment(&[
+            (
+                KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION,
+                annotated.clone(),
+            ),
+            (KUBE_AUTOROLLOUT_APPROVE_ANNOTATION, "true".to_string()),
+        ]);
+        assert!(is_approved(&approved, &pending, 1));
+        assert!(!is_approved(&approved, &newer, 1));
+        assert!(!is_approved(&approved, &pending, 2));
+        assert!(!is_approved(
+            &deployment(&[(KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION, annotated)]),
+            &pending,
+            1
+        ));
+    }
+
+    #[test]
+    fn approvers_are_counted_once() {
+        let pending = PendingRollout::new("Deployment", "default", "app", &[changed("sha256:new")]);
+        let mut annotated = pending.clone();
+        assert!(annotated.add_approver("alice"));
+        assert!(!annotated.add_approver("alice"));
+        assert_eq!(annotated.approved_by, vec!["alice"]);
+        let approved_once = deployment(&[(
+            KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION,
+            serde_json::to_string(&annotated).unwrap(),
+        )]);
+        assert!(is_approved(&approved_once, &pending, 1));
+        assert!(!is_approved(&approved_once, &pending, 2));
+
+        assert!(annotated.add_approver("bob"));
+        let approved_twice = deployment(&[(
+            KUBE_AUTOROLLOUT_PENDING_ROLLOUT_ANNOTATION,
+            serde_json::to_string(&annotated).unwrap(),
+        )]);
+        assert!(is_approved(&approved_twice, &pending, 2));
+    }
+
+    #[test]
+    fn approval_requirement_is_overridden_by_annotation() {
+        assert!(is_required(&deployment(&[]), true));
+        assert!(!is_required(
+            &deployment(&[(
+                KUBE_AUTOROLLOUT_REQUIRE_APPROVAL_ANNOTATION,
+                "false".to_string()
+            )]),
+            true
+        ));
+        assert!(is_required(
+            &deployment(&[(
+                KUBE_AUTOROLLOUT_REQUIRE_APPROVAL_ANNOTATION,
+                "true".to_string()
+            )]),
+            false
+        ));
+    }
+
+    #[test]
+    fn notify_policy_always_requires_approval() {
+        let notify_only = deployment(&[
+            (KUBE_AUTOROLLOUT_POLICY_ANNOTATION, "notify".to_string()),
+            (
+                KUBE_AUTOROLLOUT_REQUIRE_APPROVAL_ANNOTATION,
+                "false".to_string(),
+            ),
+        ]);
+        assert!(is_notify_only(&notify_only));
+        assert!(is_required(&notify_only, false));
+
+        let automatic = deployment(&[(KUBE_AUTOROLLOUT_POLICY_ANNOTATION, "auto".to_string())]);
+        assert!(!is_notify_only(&automatic));
+        assert!(!is_required(&automatic, false));
+    }
+}
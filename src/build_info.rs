@@ -0,0 +1,24 @@
+/// Commit the binary was built from, passed by the image build as build argument
+pub fn git_sha() -> Option<&'static str> {
+    option_env!("KUBE_AUTOROLLOUT_GIT_SHA").filter(|sha| !sha.is_empty())
+}
+
+/// Time the binary was built, passed by the image build as build argument
+pub fn build_date() -> Option<&'static str> {
+    option_env!("KUBE_AUTOROLLOUT_BUILD_DATE").filter(|date| !date.is_empty())
+}
+
+/// Cargo features the binary was built with
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("rustls-ring", cfg!(feature = "rustls-ring")),
+        ("rustls-aws-lc", cfg!(feature = "rustls-aws-lc")),
+        ("fips", cfg!(feature = "fips")),
+        ("native-tls", cfg!(feature = "native-tls")),
+        ("mock-registry", cfg!(feature = "mock-registry")),
+        ("runtime-diagnostics", cfg!(feature = "runtime-diagnostics")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
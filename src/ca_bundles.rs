@@ -0,0 +1,202 @@
+use crate::config::{Config, ConfigMapReference};
+use crate::state::ControllerContext;
+use anyhow::{Context, Result};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+use std::fs;
+use tracing::{error, info};
+
+/// Reads the PEM encoded CA bundles from the referenced ConfigMaps in the controller's namespace
+pub async fn fetch_config_map_ca_bundles(
+    client: &Client,
+    references: &[ConfigMapReference],
+) -> Result<Vec<String>> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let mut bundles = Vec::new();
+
+    for reference in references {
+        let config_map = config_maps
+            .get(&reference.name)
+            .await
+            .with_context(|| format!("Failed to retrieve ConfigMap {}", reference.name))?;
+        bundles.extend(extract_ca_bundles(&config_map, reference)?);
+    }
+
+    Ok(bundles)
+}
+
+fn extract_ca_bundles(
+    config_map: &ConfigMap,
+    reference: &ConfigMapReference,
+) -> Result<Vec<String>> {
+    let data = config_map.data.clone().unwrap_or_default();
+
+    match &reference.key {
+        Some(key) => {
+            let bundle = data.get(key).with_context(|| {
+                format!(
+                    "Failed to get key {} from ConfigMap {}",
+                    key, reference.name
+                )
+            })?;
+            Ok(vec![bundle.clone()])
+        }
+        // BTreeMap iteration keeps the bundle order stable, so unchanged ConfigMaps compare equal
+        None => Ok(data.into_values().collect()),
+    }
+}
+
+/// Periodically re-reads the CA bundle ConfigMaps and rebuilds the registry HTTP client when their content changed
+pub async fn reload_config_map_ca_bundles(ctx: ControllerContext) {
+    let references = &ctx.config.tls.ca_certificate_config_maps;
+    let mut interval = tokio::time::interval(ctx.config.tls.ca_reload_interval);
+    // the first tick completes immediately, the bundles were just loaded on startup
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let bundles = match fetch_config_map_ca_bundles(&ctx.kube_client, references).await {
+            Ok(bundles) => bundles,
+            Err(e) => {
+                error!("Failed to reload CA bundles from ConfigMaps: {:?}", e);
+                continue;
+            }
+        };
+        if *ctx.ca_bundles.read().unwrap() == bundles {
+            continue;
+        }
+
+        info!("CA bundles in ConfigMaps changed, rebuilding registry HTTP client");
+        let previous_bundles = std::mem::replace(&mut *ctx.ca_bundles.write().unwrap(), bundles);
+        if let Err(e) = ctx.rebuild_http_client() {
+            error!(
+                "Failed to rebuild registry HTTP client, keeping the previous one: {:?}",
+                e
+            );
+            *ctx.ca_bundles.write().unwrap() = previous_bundles;
+        }
+    }
+}
+
+/// Content of the mounted CA files, globally trusted or trusted for a single registry, compared between polls to
+/// detect rotations
+fn read_ca_files(config: &Config) -> Result<Vec<Vec<u8>>> {
+    let registry_paths = config
+        .registries
+        .iter()
+        .filter_map(|registry| registry.tls.as_ref()?.ca_certificate_path.as_ref());
+
+    config
+        .tls
+        .ca_certificate_paths
+        .iter()
+        .chain(registry_paths)
+        .map(|path| {
+            fs::read(path).with_context(|| format!("Failed to read file {}", path.display()))
+        })
+        .collect()
+}
+
+/// Polls the mounted CA files every `caReloadInterval` and rebuilds the registry HTTP client when their content
+/// changed, e.g. after cert-manager rotated a CA. Requests in flight finish with the client they started with.
+pub async fn reload_rotated_ca_files(ctx: ControllerContext) {
+    let mut interval = tokio::time::interval(ctx.config.tls.ca_reload_interval);
+    // the first tick completes immediately, the files were just read on startup
+    interval.tick().await;
+    let mut current = read_ca_files(&ctx.config).ok();
+
+    loop {
+        interval.tick().await;
+
+        // the files of the reloaded configuration are watched, its client was built from them
+        let files = match read_ca_files(&ctx.latest_config.read().unwrap()) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("Failed to read CA certificate files: {:?}", e);
+                continue;
+            }
+        };
+        if current.as_ref() == Some(&files) {
+            continue;
+        }
+
+        info!("CA certificate files changed, rebuilding registry HTTP client");
+        match ctx.rebuild_http_client() {
+            Ok(()) => current = Some(files),
+            Err(e) => error!(
+                "Failed to rebuild registry HTTP client with changed CA certificates, keeping the previous one: {:?}",
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn config_map(data: &[(&str, &str)]) -> ConfigMap {
+        ConfigMap {
+            data: Some(
+                data.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn extract_ca_bundles_uses_the_referenced_key() {
+        let config_map = config_map(&[("ca.crt", "pem-a"), ("other.crt", "pem-b")]);
+        let reference = ConfigMapReference {
+            name: "corporate-ca".to_string(),
+            key: Some("ca.crt".to_string()),
+        };
+
+        let bundles = extract_ca_bundles(&config_map, &reference).unwrap();
+        assert_eq!(bundles, vec!["pem-a".to_string()]);
+    }
+
+    #[test]
+    fn extract_ca_bundles_uses_all_keys_without_reference_key() {
+        let config_map = config_map(&[("b.crt", "pem-b"), ("a.crt", "pem-a")]);
+        let reference = ConfigMapReference {
+            name: "corporate-ca".to_string(),
+            key: None,
+        };
+
+        let bundles = extract_ca_bundles(&config_map, &reference).unwrap();
+        assert_eq!(bundles, vec!["pem-a".to_string(), "pem-b".to_string()]);
+    }
+
+    #[test]
+    fn extract_ca_bundles_fails_on_missing_key() {
+        let config_map = config_map(&[("ca.crt", "pem-a")]);
+        let reference = ConfigMapReference {
+            name: "corporate-ca".to_string(),
+            key: Some("missing.crt".to_string()),
+        };
+
+        assert!(extract_ca_bundles(&config_map, &reference).is_err());
+    }
+
+    #[test]
+    fn read_ca_files_detects_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_path = dir.path().join("ca.crt");
+        fs::write(&ca_path, "ca-1").unwrap();
+        let config: Config = serde_yaml_ng::from_str(&format!(
+            "webserver:\n  port: 8080\nregistries: []\ntls:\n  caCertificatePaths: [{}]\n",
+            ca_path.display()
+        ))
+        .unwrap();
+
+        let before = read_ca_files(&config).unwrap();
+        assert_eq!(before, read_ca_files(&config).unwrap());
+        fs::write(&ca_path, "ca-2").unwrap();
+        assert_ne!(before, read_ca_files(&config).unwrap());
+    }
+}
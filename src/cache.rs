@@ -0,0 +1,250 @@
+use crate::secret_string::SecretString;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Approximate heap footprint of a cached key or value, used for memory accounting
+pub trait Weight {
+    fn weight(&self) -> usize;
+}
+
+impl Weight for String {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weight for Vec<String> {
+    fn weight(&self) -> usize {
+        self.iter().map(String::len).sum()
+    }
+}
+
+impl Weight for SecretString {
+    fn weight(&self) -> usize {
+        self.expose_secret().len()
+    }
+}
+
+struct CacheEntry<V> {
+    value: V,
+    expires_at: Option<Instant>,
+    recency: u64,
+    weight: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub(crate) entries: usize,
+    pub(crate) max_entries: usize,
+    pub(crate) approx_bytes: usize,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+}
+
+/// Size-bounded cache evicting the least recently used entry once `max_entries` is reached.
+/// Entries can optionally expire after a time-to-live.
+pub struct LruCache<K, V> {
+    max_entries: usize,
+    default_ttl: Option<Duration>,
+    entries: HashMap<K, CacheEntry<V>>,
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    weight: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone + Weight,
+    V: Weight,
+{
+    pub fn new(max_entries: usize, default_ttl: Option<Duration>) -> Self {
+        LruCache {
+            max_entries,
+            default_ttl,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            weight: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let expired = match self.entries.get(key) {
+            Some(entry) => entry.expires_at.is_some_and(|at| at <= Instant::now()),
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+        if expired {
+            self.remove(key);
+            self.misses += 1;
+            return None;
+        }
+
+        self.hits += 1;
+        self.tick += 1;
+        let entry = self.entries.get_mut(key).unwrap();
+        self.recency.remove(&entry.recency);
+        entry.recency = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        Some(&entry.value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        let ttl = self.default_ttl;
+        self.insert_with_ttl(key, value, ttl);
+    }
+
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Option<Duration>) {
+        if self.max_entries == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.max_entries {
+            self.evict_least_recently_used();
+        }
+
+        self.tick += 1;
+        let weight = key.weight() + value.weight();
+        self.weight += weight;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                expires_at: ttl.map(|ttl| Instant::now() + ttl),
+                recency: self.tick,
+                weight,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.recency);
+        self.weight -= entry.weight;
+        Some(entry.value)
+    }
+
+    /// Removes all entries whose key does not satisfy the predicate
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        let removed: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| !keep(key))
+            .cloned()
+            .collect();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.len(),
+            max_entries: self.max_entries,
+            approx_bytes: self.weight,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        if let Some((_, key)) = self.recency.pop_first() {
+            if let Some(entry) = self.entries.remove(&key) {
+                self.weight -= entry.weight;
+            }
+            self.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_entry() {
+        let mut cache: LruCache<String, String> = LruCache::new(2, None);
+        cache.insert("a".to_string(), "1".to_string());
+        cache.insert("b".to_string(), "2".to_string());
+        // touch "a" so that "b" becomes the least recently used entry
+        assert!(cache.get(&"a".to_string()).is_some());
+        cache.insert("c".to_string(), "3".to_string());
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&"b".to_string()).is_none());
+        assert_eq!(cache.get(&"a".to_string()), Some(&"1".to_string()));
+        assert_eq!(cache.get(&"c".to_string()), Some(&"3".to_string()));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn tracks_approximate_weight() {
+        let mut cache: LruCache<String, Vec<String>> = LruCache::new(10, None);
+        cache.insert(
+            "key".to_string(),
+            vec!["12345".to_string(), "67".to_string()],
+        );
+        assert_eq!(cache.stats().approx_bytes, 10);
+
+        cache.insert("key".to_string(), vec!["1".to_string()]);
+        assert_eq!(cache.stats().approx_bytes, 4);
+
+        cache.remove(&"key".to_string());
+        assert_eq!(cache.stats().approx_bytes, 0);
+    }
+
+    #[test]
+    fn expired_entries_are_misses() {
+        let mut cache: LruCache<String, String> = LruCache::new(10, Some(Duration::ZERO));
+        cache.insert("a".to_string(), "1".to_string());
+        assert!(cache.get(&"a".to_string()).is_none());
+        assert_eq!(cache.len(), 0);
+
+        cache.insert_with_ttl("b".to_string(), "2".to_string(), None);
+        assert!(cache.get(&"b".to_string()).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[test]
+    fn zero_capacity_cache_stores_nothing() {
+        let mut cache: LruCache<String, String> = LruCache::new(0, None);
+        cache.insert("a".to_string(), "1".to_string());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn retain_removes_entries_not_matching_predicate() {
+        let mut cache: LruCache<String, String> = LruCache::new(10, None);
+        cache.insert("ghcr.io/org/app:latest".to_string(), "1".to_string());
+        cache.insert("ghcr.io/org/other:latest".to_string(), "2".to_string());
+
+        cache.retain(|key| !key.contains("/app:"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&"ghcr.io/org/other:latest".to_string()).is_some());
+        assert_eq!(
+            cache.stats().approx_bytes,
+            "ghcr.io/org/other:latest".len() + 1
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use crate::config::{Canary, WaitForRollout, WorkloadKind};
+use crate::controller::{describe_changed_container, KUBE_AUTOROLLOUT_LABEL};
+use crate::events::{publish, workload_reference};
+use crate::notify::{self, Notification};
+use crate::rollback::find_failed_pod;
+use crate::rollout::{ChangedContainer, Rollout};
+use crate::rollout_wait::{wait_for_rollout, RolloutOutcome};
+use crate::state::{ControllerContext, ControllerState};
+use anyhow::Context;
+use chrono::{DateTime, SubsecRound, Utc};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::ListParams;
+use kube::runtime::events::EventType;
+use kube::{Api, ResourceExt};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Marks a workload as the canary of its images, new digests are rolled out to it before all other workloads
+pub static KUBE_AUTOROLLOUT_CANARY_ANNOTATION: &str = "kube-autorollout/canary";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CanaryStatus {
+    /// The canary is being rolled out or has not been healthy for the healthy period yet
+    Pending,
+    Healthy,
+    Failed,
+}
+
+/// Rollout of a new digest to a canary workload
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanaryRollout {
+    pub(crate) workload: String,
+    pub(crate) digest: String,
+    pub(crate) status: CanaryStatus,
+    pub(crate) reason: Option<String>,
+    pub(crate) updated_at: DateTime<Utc>,
+}
+
+pub fn is_canary<T: Rollout>(resource: &T) -> bool {
+    resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_CANARY_ANNOTATION)
+        .is_some_and(|canary| canary == "true")
+}
+
+/// Returns why the changed containers of a workload have to wait for a canary, None if they can be rolled out.
+/// Digests that failed on the canary are held back until a newer digest is pushed.
+pub async fn waiting_for_canary(
+    ctx: &ControllerContext,
+    changed_containers: &[ChangedContainer],
+) -> anyhow::Result<Option<String>> {
+    for changed_container in changed_containers {
+        let canary = ctx
+            .state
+            .read()
+            .unwrap()
+            .canaries
+            .get(&changed_container.image)
+            .filter(|canary| canary.digest == changed_container.new_digest)
+            .cloned();
+        let reason = match canary {
+            Some(canary) => match canary.status {
+                CanaryStatus::Healthy => None,
+                CanaryStatus::Pending => Some(format!("waiting for canary {}", canary.workload)),
+                CanaryStatus::Failed => Some(format!(
+                    "canary {} failed: {}",
+                    canary.workload,
+                    canary.reason.unwrap_or_default()
+                )),
+            },
+            None => find_waiting_canary(ctx, changed_container)
+                .await?
+                .map(|canary| format!("waiting for canary {}", canary)),
+        };
+        if reason.is_some() {
+            return Ok(reason);
+        }
+    }
+    Ok(None)
+}
+
+/// Finds a canary using the image that does not run the new digest yet. Canaries running it without a recorded
+/// canary rollout, e.g. rolled out before the controller restarted, do not hold back the other workloads.
+async fn find_waiting_canary(
+    ctx: &ControllerContext,
+    changed_container: &ChangedContainer,
+) -> anyhow::Result<Option<String>> {
+    for kind in &ctx.config.workload_kinds {
+        let canaries = match kind {
+            WorkloadKind::Deployment => list_canaries::<Deployment>(ctx, changed_container).await?,
+            WorkloadKind::StatefulSet => {
+                list_canaries::<StatefulSet>(ctx, changed_container).await?
+            }
+            WorkloadKind::DaemonSet => list_canaries::<DaemonSet>(ctx, changed_container).await?,
+        };
+        let state = ctx.state.read().unwrap();
+        if let Some(canary) = canaries.into_iter().find(|canary| {
+            state.workloads.get(canary).is_none_or(|workload| {
+                workload.containers.values().any(|container| {
+                    container.image == changed_container.image
+                        && container.running_digest != changed_container.new_digest
+                })
+            })
+        }) {
+            return Ok(Some(canary));
+        }
+    }
+    Ok(None)
+}
+
+/// Workload keys of the labeled canaries of a kind using the image of the changed container
+async fn list_canaries<T: Rollout>(
+    ctx: &ControllerContext,
+    changed_container: &ChangedContainer,
+) -> anyhow::Result<Vec<String>> {
+    let api: Api<T> = match ctx.config.namespaces.cluster_wide {
+        true => Api::all(ctx.kube_client.clone()),
+        false => Api::default_namespaced(ctx.kube_client.clone()),
+    };
+    let resource_list = api
+        .list(&ListParams::default().labels(KUBE_AUTOROLLOUT_LABEL))
+        .await;
+    ctx.state
+        .write()
+        .unwrap()
+        .record_kube_api_request(resource_list.is_ok());
+    Ok(resource_list
+        .with_context(|| format!("Failed to list canary {}s", T::kind_name()))?
+        .items
+        .into_iter()
+        .filter(|resource| is_canary(resource) && uses_image(resource, &changed_container.image))
+        .map(|resource| {
+            let namespace = resource
+                .namespace()
+                .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+            ControllerState::workload_key(T::kind_name(), &namespace, &resource.name_any())
+        })
+        .collect())
+}
+
+fn uses_image<T: Rollout>(resource: &T, image: &str) -> bool {
+    resource.pod_spec().is_some_and(|pod_spec| {
+        pod_spec
+            .containers
+            .iter()
+            .chain(pod_spec.init_containers.iter().flatten())
+            .any(|container| container.image.as_deref() == Some(image))
+    })
+}
+
+/// Records the rollout of the canary and follows it in the background until it completed and stayed healthy for
+/// the healthy period, the other workloads using its images are held back until then
+pub fn spawn<T: Rollout>(
+    ctx: &ControllerContext,
+    api: Api<T>,
+    resource: &T,
+    namespace: &str,
+    changed_containers: &[ChangedContainer],
+) {
+    let Some(canary) = ctx.config.canary.clone() else {
+        return;
+    };
+    if !is_canary(resource) || changed_containers.is_empty() {
+        return;
+    }
+    let ctx = ctx.clone();
+    let resource = resource.clone();
+    let kind = T::kind_name();
+    let (namespace, name) = (namespace.to_string(), resource.name_any());
+    let workload = ControllerState::workload_key(kind, &namespace, &name);
+    let changed_containers = changed_containers.to_vec();
+    record(
+        &ctx,
+        &workload,
+        &changed_containers,
+        CanaryStatus::Pending,
+        None,
+    );
+    // creation timestamps of pods only have a precision of seconds
+    let triggered_at = Utc::now().trunc_subsecs(0);
+    tokio::spawn(async move {
+        let failure = match watch(&ctx, &api, &resource, &namespace, &canary, triggered_at).await {
+            Some(failure) => failure,
+            None => {
+                info!(
+                    kind = %kind,
+                    resource = %name,
+                    namespace = %namespace,
+                    "Canary stayed healthy, rolling out its images to the other workloads"
+                );
+                record(
+                    &ctx,
+                    &workload,
+                    &changed_containers,
+                    CanaryStatus::Healthy,
+                    None,
+                );
+                publish(
+                    &ctx,
+                    workload_reference(kind, &namespace, &name),
+                    EventType::Normal,
+                    "CanaryHealthy",
+                    "Rollout",
+                    format!(
+                        "Canary stayed healthy for {}",
+                        humantime::format_duration(canary.healthy_period)
+                    ),
+                );
+                return;
+            }
+        };
+        warn!(
+            kind = %kind,
+            resource = %name,
+            namespace = %namespace,
+            reason = %failure,
+            "Canary failed, holding back its images from the other workloads"
+        );
+        record(
+            &ctx,
+            &workload,
+            &changed_containers,
+            CanaryStatus::Failed,
+            Some(&failure),
+        );
+        publish(
+            &ctx,
+            workload_reference(kind, &namespace, &name),
+            EventType::Warning,
+            "CanaryFailed",
+            "Rollout",
+            format!(
+                "Canary failed, holding back the other workloads: {}",
+                failure
+            ),
+        );
+        let mut details: Vec<String> = changed_containers
+            .iter()
+            .map(describe_changed_container)
+            .collect();
+        details.push(format!("reason: {}", failure));
+        notify::send(
+            &ctx,
+            Notification {
+                title: format!(
+                    "Canary {} {}/{} failed, the other workloads are not rolled out",
+                    kind, namespace, name
+                ),
+                details,
+                failed: true,
+                images: Vec::new(),
+            },
+        );
+    });
+}
+
+/// Returns why the canary failed, None if it completed its rollout and stayed healthy for the healthy period
+async fn watch<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    namespace: &str,
+    canary: &Canary,
+    triggered_at: DateTime<Utc>,
+) -> Option<String> {
+    let wait = WaitForRollout {
+        timeout: canary.timeout,
+        poll_interval: canary.poll_interval,
+    };
+    if let RolloutOutcome::Failed(failure) =
+        wait_for_rollout(api, &wait, &resource.name_any()).await
+    {
+        return Some(failure);
+    }
+    let pods: Api<Pod> = Api::namespaced(ctx.kube_client.clone(), namespace);
+    find_failed_pod(
+        &pods,
+        canary.healthy_period,
+        canary.poll_interval,
+        &resource.selector(),
+        triggered_at,
+    )
+    .await
+}
+
+fn record(
+    ctx: &ControllerContext,
+    workload: &str,
+    changed_containers: &[ChangedContainer],
+    status: CanaryStatus,
+    reason: Option<&str>,
+) {
+    let mut state = ctx.state.write().unwrap();
+    for changed_container in changed_containers {
+        state.canaries.insert(
+            changed_container.image.clone(),
+            CanaryRollout {
+                workload: workload.to_string(),
+                digest: changed_container.new_digest.clone(),
+                status,
+                reason: reason.map(str::to_string),
+                updated_at: Utc::now(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn canaries_are_annotated_and_use_the_image() {
+        let deployment: Deployment = serde_json::from_value(json!({
+            "metadata": {
+                "name": "app-canary",
+                "annotations": { "kube-autorollout/canary": "true" },
+            },
+            "spec": {
+                "selector": { "matchLabels": { "app": "app" } },
+                "template": {
+                    "spec": {
+                        "initContainers": [{ "name": "migrate", "image": "ghcr.io/org/migrate:latest" }],
+                        "containers": [{ "name": "app", "image": "ghcr.io/org/app:latest" }],
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        assert!(is_canary(&deployment));
+        assert!(uses_image(&deployment, "ghcr.io/org/app:latest"));
+        assert!(uses_image(&deployment, "ghcr.io/org/migrate:latest"));
+        assert!(!uses_image(&deployment, "ghcr.io/org/app:stable"));
+
+        let mut regular = deployment.clone();
+        regular.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_CANARY_ANNOTATION.to_string(),
+            "false".to_string(),
+        );
+        assert!(!is_canary(&regular));
+    }
+}
@@ -0,0 +1,69 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Performs a single reconciliation run and exits, with a non-zero exit code if it failed. Same as RUN_MODE=once
+    #[arg(long)]
+    pub once: bool,
+    /// Prints the configuration file given in CONFIG_FILE as resolved on startup, with environment variables
+    /// expanded, image pull secrets parsed and defaults applied, secrets redacted, and exits
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Validates the configuration file given in CONFIG_FILE and exits
+    ValidateConfig {
+        /// Ping every configured registry with its credentials
+        #[arg(long)]
+        check_credentials: bool,
+        /// Image to request the manifest of from the registry serving it, e.g. ghcr.io/org/app:latest. Can be repeated.
+        #[arg(long = "test-image", requires = "check_credentials")]
+        test_images: Vec<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_validate_config_with_test_images() {
+        let cli = Cli::parse_from([
+            "kube-autorollout",
+            "validate-config",
+            "--check-credentials",
+            "--test-image",
+            "ghcr.io/org/app:latest",
+            "--test-image",
+            "docker.io/library/nginx:latest",
+        ]);
+        match cli.command {
+            Some(Command::ValidateConfig {
+                check_credentials,
+                test_images,
+            }) => {
+                assert!(check_credentials);
+                assert_eq!(test_images.len(), 2);
+            }
+            other => panic!("Expected validate-config command, found: {:?}", other),
+        }
+
+        assert!(Cli::parse_from(["kube-autorollout"]).command.is_none());
+        assert!(
+            Cli::try_parse_from(["kube-autorollout", "validate-config", "--test-image", "x"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_once_flag() {
+        assert!(Cli::parse_from(["kube-autorollout", "--once"]).once);
+        assert!(!Cli::parse_from(["kube-autorollout"]).once);
+        assert!(Cli::parse_from(["kube-autorollout", "--print-config"]).print_config);
+    }
+}
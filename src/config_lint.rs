@@ -0,0 +1,108 @@
+use crate::config::Config;
+use crate::state::ControllerState;
+use globset::Glob;
+use tracing::warn;
+
+/// Registry entries whose hostname patterns can match the same registry. Entries are matched in the order of
+/// the configuration, so the later entry of each pair is shadowed for the overlapping hosts.
+pub fn find_overlapping_patterns(config: &Config) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (i, first) in config.registries.iter().enumerate() {
+        for second in &config.registries[i + 1..] {
+            if patterns_overlap(&first.hostname_pattern, &second.hostname_pattern) {
+                warnings.push(format!(
+                    "Registry pattern {} overlaps with the earlier pattern {}, which takes precedence",
+                    second.hostname_pattern, first.hostname_pattern
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Approximates glob intersection: two patterns overlap if they are equal or either one matches the other taken literally
+fn patterns_overlap(a: &str, b: &str) -> bool {
+    let matches = |pattern: &str, candidate: &str| {
+        Glob::new(pattern)
+            .map(|glob| glob.compile_matcher().is_match(candidate))
+            .unwrap_or(false)
+    };
+    a == b || matches(a, b) || matches(b, a)
+}
+
+/// Registry entries that have not matched the image of any labeled workload since the controller started
+pub fn find_unused_patterns(config: &Config, state: &ControllerState) -> Vec<String> {
+    if state.completed_cycles == 0 {
+        return Vec::new();
+    }
+    config
+        .registries
+        .iter()
+        .filter(|registry| {
+            !state
+                .registry_pattern_matches
+                .contains_key(&registry.hostname_pattern)
+        })
+        .map(|registry| {
+            format!(
+                "Registry pattern {} has not matched any workload image",
+                registry.hostname_pattern
+            )
+        })
+        .collect()
+}
+
+pub fn log_overlapping_patterns(config: &Config) {
+    for warning in find_overlapping_patterns(config) {
+        warn!("{}", warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_patterns(patterns: &[&str]) -> Config {
+        let registries = patterns
+            .iter()
+            .map(|pattern| {
+                format!(
+                    "  - hostnamePattern: \"{}\"\n    secret:\n      type: None\n",
+                    pattern
+                )
+            })
+            .collect::<String>();
+        serde_yaml_ng::from_str(&format!(
+            "webserver:\n  port: 8080\nregistries:\n{}",
+            registries
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn find_overlapping_patterns_reports_shadowed_entries() {
+        let config = config_with_patterns(&[
+            "*.example.com",
+            "registry.example.com",
+            "ghcr.io",
+            "docker.io",
+        ]);
+        let warnings = find_overlapping_patterns(&config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Registry pattern registry.example.com overlaps"));
+    }
+
+    #[test]
+    fn find_unused_patterns_waits_for_a_completed_cycle() {
+        let config = config_with_patterns(&["ghcr.io", "docker.io"]);
+        let mut state = ControllerState::default();
+        assert!(find_unused_patterns(&config, &state).is_empty());
+
+        state.completed_cycles = 1;
+        state.record_registry_pattern_match("ghcr.io");
+        assert_eq!(
+            find_unused_patterns(&config, &state),
+            vec!["Registry pattern docker.io has not matched any workload image"]
+        );
+    }
+}
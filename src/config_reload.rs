@@ -0,0 +1,159 @@
+use crate::config::{self, Config, RegistrySecret};
+use crate::state::ControllerContext;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Settings that are only read on startup, changing them requires a restart
+static RESTART_REQUIRED_SETTINGS: &[&str] = &[
+    "cronSchedule",
+    "checkInterval",
+    "webserver",
+    "watch",
+    "leaderElection",
+    "caches",
+];
+
+/// Polls the configuration file and the image pull secrets it mounts, and reloads the configuration whenever one of
+/// them changed or the process receives SIGHUP. An invalid configuration is logged and the previous one is kept.
+pub async fn reload_changed_config(ctx: ControllerContext, path: PathBuf) {
+    let mut interval = tokio::time::interval(ctx.config.config_reload_interval);
+    // the first tick completes immediately, the configuration was just loaded on startup
+    interval.tick().await;
+    let mut current = read_config_files(&ctx.config, &path).ok();
+
+    #[cfg(unix)]
+    let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(sighup) => Some(sighup),
+        Err(e) => {
+            error!(error = %e, "Failed to install SIGHUP handler, reloading on SIGHUP is disabled");
+            None
+        }
+    };
+
+    loop {
+        #[cfg(unix)]
+        let forced = tokio::select! {
+            _ = interval.tick() => false,
+            Some(()) = async { sighup.as_mut()?.recv().await } => true,
+        };
+        #[cfg(not(unix))]
+        let forced = {
+            interval.tick().await;
+            false
+        };
+        if forced {
+            info!("SIGHUP received, reloading configuration");
+        }
+
+        let files = match read_config_files(&ctx.latest_config.read().unwrap(), &path) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("Failed to read configuration files: {:?}", e);
+                continue;
+            }
+        };
+        if !forced && current.as_ref() == Some(&files) {
+            continue;
+        }
+
+        match reload_config(&ctx, &path) {
+            Ok(()) => {
+                current = Some(files);
+                ctx.state.write().unwrap().config_error = None;
+            }
+            Err(e) => {
+                error!(
+                    "Failed to reload configuration, keeping the previous one: {:?}",
+                    e
+                );
+                ctx.state.write().unwrap().config_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+}
+
+/// Contents of the configuration file and of the image pull secrets mounted for its registries
+fn read_config_files(config: &Config, path: &Path) -> Result<Vec<String>> {
+    let mut paths = vec![path.to_path_buf()];
+    paths.extend(
+        config
+            .registries
+            .iter()
+            .filter_map(|registry| match &registry.secret {
+                RegistrySecret::ImagePullSecret { mount_path, .. } => {
+                    Some(Path::new(mount_path).join(".dockerconfigjson"))
+                }
+                _ => None,
+            }),
+    );
+
+    paths
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read file {}", path.display()))
+        })
+        .collect()
+}
+
+fn reload_config(ctx: &ControllerContext, path: &Path) -> Result<()> {
+    let config = config::load_config(path)?;
+    let client = {
+        let ca_bundles = ctx.ca_bundles.read().unwrap();
+        crate::oci_registry::create_client(&config, &ca_bundles)
+            .context("Failed to build registry HTTP client for the reloaded configuration")?
+    };
+
+    let previous = ctx.latest_config.read().unwrap().clone();
+    for setting in changed_restart_required_settings(&previous, &config)? {
+        warn!(
+            setting = %setting,
+            "Configuration setting changed, the change takes effect after a restart"
+        );
+    }
+
+    *ctx.latest_config.write().unwrap() = Arc::new(config);
+    *ctx.http_client.write().unwrap() = client;
+    info!("Reloaded configuration, the next run uses the new configuration");
+    Ok(())
+}
+
+fn changed_restart_required_settings(
+    previous: &Config,
+    config: &Config,
+) -> Result<Vec<&'static str>> {
+    let previous = serde_json::to_value(previous)?;
+    let config = serde_json::to_value(config)?;
+    Ok(RESTART_REQUIRED_SETTINGS
+        .iter()
+        .copied()
+        .filter(|setting| previous.get(setting) != config.get(setting))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Config {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn reports_settings_requiring_a_restart() {
+        let previous = parse("webserver:\n  port: 8080\nregistries: []\n");
+        let config = parse(
+            "webserver:\n  port: 9090\nregistries: []\nregistryCooldown: 1m\ncronSchedule: '*/10 * * * * *'\n",
+        );
+        assert_eq!(
+            changed_restart_required_settings(&previous, &config).unwrap(),
+            vec!["cronSchedule", "webserver"]
+        );
+        assert!(changed_restart_required_settings(&previous, &previous)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,21 +1,65 @@
-use crate::config::{Config, DockerConfig, RegistrySecret};
-use crate::image_reference::ImageReference;
-use crate::oci_registry::fetch_digests_from_tag;
-use crate::rollout::Rollout;
-use crate::state::{ContainerImageReference, ControllerContext};
+use crate::approval::{self, PendingRollout};
+use crate::canary;
+use crate::config::{
+    Config, DigestComparison, DockerConfig, Registry, RegistrySecret, RestartTrigger, RolloutMode,
+    SignaturePolicy, WorkloadKind, ZeroReplicas,
+};
+use crate::config_lint::find_unused_patterns;
+use crate::error_report::{self, ErrorReport};
+use crate::events::{publish, workload_reference};
+use crate::history::{self, HistoryChange, HistoryEntry};
+use crate::hostname;
+use crate::image_reference::{
+    parse_image_id_digest, pinned_image_tag, ImageIdError, ImageReference,
+};
+use crate::in_flight::OperationKind;
+use crate::notify::{self, Notification};
+use crate::oci_registry::{
+    fetch_config_digests, fetch_digests_with_failover, fetch_platform_digest,
+    is_registry_unreachable, is_tag_not_found, list_tags,
+};
+use crate::platform::Platform;
+use crate::policy::{authorize_rollout, RolloutDecisionInput};
+use crate::rate_limit::RegistryRateLimit;
+use crate::registry_credentials::resolve_registry_secret;
+use crate::rollback;
+use crate::rollout::{ChangedContainer, Rollout};
+use crate::rollout_intent::apply_rollout_intent;
+use crate::rollout_strategy::rollout_strategy;
+use crate::rollout_wait;
+use crate::schedule::{WorkloadSchedule, KUBE_AUTOROLLOUT_SCHEDULE_ANNOTATION};
+use crate::signing::{is_signature_missing, verify_image_signature};
+use crate::skip_reason::SkipReason;
+use crate::state::{
+    CachedDigests, CachedSecret, ContainerImageReference, ControllerContext, ControllerState,
+};
+use crate::tag_policy::{TagPolicy, KUBE_AUTOROLLOUT_TAG_POLICY_ANNOTATION};
+use crate::tracked_image::{
+    resolve_tracked_images, ResolvedTrackedImage, TRACKED_IMAGE_ANNOTATION,
+};
+use crate::webhook::PushEvent;
+use crate::workload_group::{self, GroupChange, GroupTriggers};
 use anyhow::{bail, Context};
-use futures::future::try_join_all;
-use globset::Glob;
+use chrono::Utc;
+use futures::future::{join_all, try_join_all, BoxFuture};
+use futures::{stream, FutureExt, StreamExt, TryStreamExt};
 use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
-use k8s_openapi::api::core::v1::{ContainerStatus, Pod, Secret};
+use k8s_openapi::api::core::v1::{
+    Container, ContainerStatus, Node, Pod, PodStatus, Secret, ServiceAccount,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::api::ListParams;
+use kube::runtime::events::EventType;
 use kube::{Api, Client, ResourceExt};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
-use tracing::{debug, info, warn};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
 
-static KUBE_AUTOROLLOUT_LABEL: &str = "kube-autorollout/enabled=true";
+pub(crate) static KUBE_AUTOROLLOUT_LABEL: &str = "kube-autorollout/enabled=true";
 
 pub async fn create_client() -> anyhow::Result<Client> {
     info!("Initializing K8s controller");
@@ -31,175 +75,2314 @@ pub async fn create_client() -> anyhow::Result<Client> {
 }
 
 pub async fn run(ctx: ControllerContext) -> anyhow::Result<()> {
+    if !ctx.is_leader() {
+        debug!("Skipping scheduled run, this replica is not the leader");
+        // a follower has nothing to do, but its scheduler is alive
+        ctx.state.write().unwrap().last_completed_run_at = Some(Utc::now());
+        return Ok(());
+    }
     let ctx = Arc::new(ctx);
+    let Ok(_run_guard) = ctx.run_lock.try_write() else {
+        ctx.state.write().unwrap().skipped_runs += 1;
+        warn!("Skipping scheduled run, the previous run is still in progress");
+        return Ok(());
+    };
+    // A run with failed workloads still completed, the others have been checked
+    let result = reconcile_all(&ctx, None).await;
 
-    reconcile::<Deployment>(ctx.clone())
-        .await
-        .context("Failed to reconcile Deployments")?;
-    reconcile::<StatefulSet>(ctx.clone())
-        .await
-        .context("Failed to reconcile StatefulSets")?;
-    reconcile::<DaemonSet>(ctx.clone())
-        .await
-        .context("Failed to reconcile DaemonSets")?;
+    let (unused_patterns, failed_runs) = {
+        let mut state = ctx.state.write().unwrap();
+        state.completed_cycles += 1;
+        state.last_completed_run_at = Some(Utc::now());
+        let failed_runs = state.record_run(result.is_ok());
+        let unused_patterns = match state.completed_cycles {
+            1 => find_unused_patterns(&ctx.config, &state),
+            _ => Vec::new(),
+        };
+        (unused_patterns, failed_runs)
+    };
+    for warning in unused_patterns {
+        warn!("{}", warning);
+    }
+    if let Some(crash) = &ctx.config.crash_on_repeated_failure
+        && failed_runs >= crash.max_failed_runs
+    {
+        error!(
+            failed_runs,
+            "Scheduled runs failed repeatedly, exiting so that the pod is restarted"
+        );
+        ctx.crash.cancel();
+    }
 
-    Ok(())
+    result
+}
+
+/// Records the start of a scheduled run against the time its schedule expected it. Late or skipped runs are logged,
+/// warned about with exponentially growing spacing.
+pub fn record_scheduled_tick(ctx: &ControllerContext) {
+    let mut state = ctx.state.write().unwrap();
+    let since = state.started_at.unwrap_or_else(Utc::now);
+    let tick = WorkloadSchedule::of_controller(&ctx.config).and_then(|schedule| {
+        state.schedule_ticks.record(
+            &schedule,
+            since,
+            ctx.config.health.schedule_gap_tolerance,
+            Utc::now(),
+        )
+    });
+    match tick {
+        Ok(tick) if tick.gap && state.schedule_ticks.alerts_gap() => warn!(
+            expected_at = %tick.expected_at,
+            delay_seconds = tick.delay_seconds,
+            missed_ticks = tick.missed_ticks,
+            gaps = state.schedule_ticks.gaps,
+            "Scheduled run started late, the scheduler drifted or skipped runs"
+        ),
+        Ok(tick) if tick.gap => debug!(
+            expected_at = %tick.expected_at,
+            delay_seconds = tick.delay_seconds,
+            missed_ticks = tick.missed_ticks,
+            gaps = state.schedule_ticks.gaps,
+            "Scheduled run started late"
+        ),
+        Ok(_) => {}
+        Err(err) => {
+            debug!(error = %err, "Could not compute the expected start of the scheduled run")
+        }
+    }
+}
+
+/// Reconciles only the workloads whose pod template uses the pushed repository
+pub async fn run_for_push(ctx: ControllerContext, push_event: &PushEvent) -> anyhow::Result<()> {
+    if !ctx.is_leader() {
+        info!("Ignoring push event, this replica is not the leader and the leader checks on its next run");
+        return Ok(());
+    }
+    // Digests cached before the push are outdated
+    ctx.caches
+        .digests
+        .lock()
+        .unwrap()
+        .retain(|image| !push_event.matches_image(image));
+    // Push events are not dropped like scheduled runs, they wait for a run in progress to finish
+    let ctx = Arc::new(ctx);
+    let _run_guard = ctx.run_lock.write().await;
+    reconcile_all(&ctx, Some(RunFilter::PushedRepository(push_event))).await
+}
+
+/// Reconciles the selected workloads right away, bypassing the digests cached for their images by earlier runs
+pub async fn run_for_selection(
+    ctx: ControllerContext,
+    selector: &WorkloadSelector,
+) -> anyhow::Result<()> {
+    let ctx = Arc::new(ctx);
+    let _run_guard = ctx.run_lock.write().await;
+    reconcile_all(&ctx, Some(RunFilter::Selected(selector))).await
+}
+
+/// Selects labeled workloads by kind, namespace and name, unset fields match any workload
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WorkloadSelector {
+    pub kind: Option<String>,
+    pub namespace: Option<String>,
+    pub name: Option<String>,
+}
+
+impl WorkloadSelector {
+    fn matches_kind(&self, kind: &str) -> bool {
+        self.kind
+            .as_deref()
+            .is_none_or(|selected| selected.eq_ignore_ascii_case(kind))
+    }
+
+    fn matches<T: Rollout>(&self, resource: &T) -> bool {
+        self.matches_kind(T::kind_name())
+            && self
+                .namespace
+                .as_deref()
+                .is_none_or(|namespace| resource.namespace().as_deref() == Some(namespace))
+            && self
+                .name
+                .as_deref()
+                .is_none_or(|name| resource.name_any() == name)
+    }
+}
+
+/// Restricts a run to some of the labeled workloads
+#[derive(Clone, Copy)]
+enum RunFilter<'a> {
+    PushedRepository(&'a PushEvent),
+    Selected(&'a WorkloadSelector),
+}
+
+impl RunFilter<'_> {
+    fn matches_kind(&self, kind: &str) -> bool {
+        match self {
+            RunFilter::PushedRepository(_) => true,
+            RunFilter::Selected(selector) => selector.matches_kind(kind),
+        }
+    }
+
+    fn matches<T: Rollout>(&self, resource: &T, include_init_containers: bool) -> bool {
+        match self {
+            RunFilter::PushedRepository(push_event) => {
+                uses_pushed_repository(resource, push_event, include_init_containers)
+            }
+            RunFilter::Selected(selector) => selector.matches(resource),
+        }
+    }
+}
+
+async fn reconcile_all(
+    ctx: &Arc<ControllerContext>,
+    filter: Option<RunFilter<'_>>,
+) -> anyhow::Result<()> {
+    let _pass = ctx
+        .in_flight
+        .track(OperationKind::ReconcilePass, "reconcile workloads");
+    let tracked_images = if ctx.config.feature_flags.enable_tracked_images {
+        resolve_tracked_images(ctx).await.unwrap_or_else(|err| {
+            warn!(
+                error = ?err,
+                "Failed to resolve TrackedImages, falling back to per-container lookups"
+            );
+            BTreeMap::new()
+        })
+    } else {
+        BTreeMap::new()
+    };
+
+    // The kinds are reconciled concurrently, sharing one limit on the workloads checked at the same time
+    let limiter = Semaphore::new(ctx.config.reconcile_concurrency);
+    let deadline = tokio::time::Instant::now() + ctx.config.reconcile_timeouts.pass_deadline;
+    let groups = GroupTriggers::default();
+    let mut kinds = Vec::new();
+    let mut tasks: Vec<BoxFuture<'_, anyhow::Result<ReconcileSummary>>> = Vec::new();
+    for kind in &ctx.config.workload_kinds {
+        if filter.is_some_and(|filter| !filter.matches_kind(&format!("{:?}", kind))) {
+            continue;
+        }
+        let (tracked_images, groups, limiter) = (&tracked_images, &groups, &limiter);
+        let task = match kind {
+            WorkloadKind::Deployment => reconcile::<Deployment>(
+                ctx.clone(),
+                tracked_images,
+                filter,
+                groups,
+                limiter,
+                deadline,
+            )
+            .boxed(),
+            WorkloadKind::StatefulSet => reconcile::<StatefulSet>(
+                ctx.clone(),
+                tracked_images,
+                filter,
+                groups,
+                limiter,
+                deadline,
+            )
+            .boxed(),
+            WorkloadKind::DaemonSet => reconcile::<DaemonSet>(
+                ctx.clone(),
+                tracked_images,
+                filter,
+                groups,
+                limiter,
+                deadline,
+            )
+            .boxed(),
+        };
+        kinds.push(kind);
+        tasks.push(task);
+    }
+
+    let mut summary = ReconcileSummary::default();
+    for (kind, result) in kinds.into_iter().zip(join_all(tasks).await) {
+        match result {
+            Ok(kind_summary) => {
+                summary.checked += kind_summary.checked;
+                summary.timed_out += kind_summary.timed_out;
+                summary.deferred += kind_summary.deferred;
+                summary.failures.extend(kind_summary.failures);
+            }
+            Err(err) => {
+                warn!(error = ?err, kind = ?kind, "Failed to list workloads");
+                summary.failures.push(format!("{:?}: {:#}", kind, err));
+            }
+        }
+    }
+    summary
+        .failures
+        .extend(workload_group::trigger_all(ctx, groups).await);
+    summary.lowest_rate_limit = ctx.rate_limits.lowest_remaining();
+    summary.finish()
+}
+
+/// Outcome of a reconciliation run. A failing workload is recorded here and does not stop the remaining ones.
+#[derive(Debug, Default)]
+struct ReconcileSummary {
+    checked: usize,
+    failures: Vec<String>,
+    /// Failed workloads whose check exceeded the resource timeout
+    timed_out: usize,
+    /// Workloads not checked before the deadline of the pass, checked first in the next pass
+    deferred: usize,
+    /// Registry with the fewest requests left in its rate limit
+    lowest_rate_limit: Option<(String, RegistryRateLimit)>,
+}
+
+impl ReconcileSummary {
+    fn finish(self) -> anyhow::Result<()> {
+        info!(
+            checked = %self.checked,
+            failed = %self.failures.len(),
+            timed_out = %self.timed_out,
+            deferred = %self.deferred,
+            "Finished reconciliation run"
+        );
+        if self.deferred > 0 {
+            warn!(
+                deferred = %self.deferred,
+                "Reconciliation run hit its deadline, the remaining workloads are checked first in the next run"
+            );
+        }
+        if let Some((registry, rate_limit)) = &self.lowest_rate_limit {
+            info!(
+                registry = %registry,
+                limit = %rate_limit.limit,
+                remaining = %rate_limit.remaining,
+                "Registry with the fewest requests left in its rate limit"
+            );
+        }
+        if !self.failures.is_empty() {
+            bail!(
+                "Failed to reconcile {} of {} workloads: {}",
+                self.failures.len(),
+                self.checked,
+                self.failures.join("; ")
+            );
+        }
+        Ok(())
+    }
 }
 
-async fn reconcile<T>(ctx: Arc<ControllerContext>) -> anyhow::Result<()>
+/// Whether a workload was checked within the deadline of the pass
+enum PassOutcome {
+    Checked,
+    Deferred,
+}
+
+/// Lists the labeled workloads of a kind and reconciles each of them, holding a permit of the limiter while a
+/// workload is checked. Only a failure to list them is returned, failures of single workloads are collected in the
+/// summary of the kind. Workloads deferred by an earlier pass are checked first, workloads not checked by the
+/// deadline are cancelled and deferred to the next pass.
+async fn reconcile<T>(
+    ctx: Arc<ControllerContext>,
+    tracked_images: &BTreeMap<String, ResolvedTrackedImage>,
+    filter: Option<RunFilter<'_>>,
+    groups: &GroupTriggers,
+    limiter: &Semaphore,
+    deadline: tokio::time::Instant,
+) -> anyhow::Result<ReconcileSummary>
 where
     T: Rollout,
 {
     let kind_name = T::kind_name();
-    let api: Api<T> = Api::default_namespaced(ctx.kube_client.clone());
-    let pods: Api<Pod> = Api::default_namespaced(ctx.kube_client.clone());
+    let namespaces = &ctx.config.namespaces;
+    let list_api: Api<T> = match namespaces.cluster_wide {
+        true => Api::all(ctx.kube_client.clone()),
+        false => Api::default_namespaced(ctx.kube_client.clone()),
+    };
     let lp = ListParams::default().labels(KUBE_AUTOROLLOUT_LABEL);
-    let secrets: Api<Secret> = Api::default_namespaced(ctx.kube_client.clone());
 
     // List the resources based on label selector (server-side filtering)
-    let resource_list = api.list(&lp).await?;
+    let resource_list = {
+        let _list = ctx
+            .in_flight
+            .track(OperationKind::KubeApi, format!("list {}s", kind_name));
+        list_api.list(&lp).await
+    };
+    ctx.state
+        .write()
+        .unwrap()
+        .record_kube_api_request(resource_list.is_ok());
+    let resource_list = resource_list.with_context(|| format!("Failed to list {}s", kind_name))?;
 
     info!(
         resource_count = %resource_list.items.len(),
         kind = %kind_name,
         label = %KUBE_AUTOROLLOUT_LABEL,
+        cluster_wide = %namespaces.cluster_wide,
         "Scanning for digest changes in resources"
     );
 
-    for resource in resource_list.items {
-        let resource_name = resource.name_any();
+    let workload_key = |resource: &T| {
+        let namespace = resource
+            .namespace()
+            .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+        ControllerState::workload_key(kind_name, &namespace, &resource.name_any())
+    };
+    let mut resources = resource_list.items;
+    {
+        let state = ctx.state.read().unwrap();
+        resources
+            .sort_by_key(|resource| !state.deferred_workloads.contains(&workload_key(resource)));
+    }
+
+    let results: Vec<anyhow::Result<PassOutcome>> =
+        stream::iter(resources)
+            .filter(|resource| {
+                std::future::ready(filter.is_none_or(|filter| {
+                    filter.matches(resource, ctx.config.feature_flags.enable_init_containers)
+                }))
+            })
+            .map(|resource| {
+                let ctx = &ctx;
+                async move {
+                    // workloads reconciled through the API are checked even if they are backing off
+                    let key = workload_key(&resource);
+                    if let Some(RunFilter::Selected(_)) = filter {
+                        ctx.state.write().unwrap().work_queue.forget(&key);
+                        evict_cached_digests(ctx, &resource);
+                    }
+                    let _permit = limiter
+                        .acquire()
+                        .await
+                        .expect("the limiter is never closed");
+                    let Ok(result) = tokio::time::timeout_at(
+                        deadline,
+                        reconcile_resource(ctx, &resource, tracked_images, groups),
+                    )
+                    .await
+                    else {
+                        let namespace = resource
+                            .namespace()
+                            .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+                        let mut state = ctx.state.write().unwrap();
+                        state.deferred_workloads.insert(key);
+                        state.record_skip(
+                            kind_name,
+                            &namespace,
+                            &resource.name_any(),
+                            SkipReason::Deferred,
+                        );
+                        return Ok(PassOutcome::Deferred);
+                    };
+                    ctx.state.write().unwrap().deferred_workloads.remove(&key);
+                    result
+                    .map(|()| PassOutcome::Checked)
+                    .map_err(|err| {
+                        let resource_namespace = resource.namespace().unwrap_or_default();
+                        let resource_name = resource.name_any();
+                        warn!(
+                            error = ?err,
+                            kind = %kind_name,
+                            resource = %resource_name,
+                            namespace = %resource_namespace,
+                            "Failed to reconcile resource, continuing with the remaining resources"
+                        );
+                        err.context(format!(
+                            "{} {}/{}",
+                            kind_name, resource_namespace, resource_name
+                        ))
+                    })
+                }
+            })
+            .buffer_unordered(ctx.config.reconcile_concurrency)
+            .collect()
+            .await;
+
+    let deferred = results
+        .iter()
+        .filter(|result| matches!(result, Ok(PassOutcome::Deferred)))
+        .count();
+    let checked = results.len() - deferred;
+    let failures: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+    Ok(ReconcileSummary {
+        checked,
+        timed_out: failures
+            .iter()
+            .filter(|err| is_check_timed_out(err))
+            .count(),
+        deferred,
+        failures: failures.iter().map(|err| format!("{:#}", err)).collect(),
+        ..Default::default()
+    })
+}
+
+fn uses_pushed_repository<T: Rollout>(
+    resource: &T,
+    push_event: &PushEvent,
+    include_init_containers: bool,
+) -> bool {
+    resource.pod_spec().is_some_and(|pod_spec| {
+        let init_containers = match &pod_spec.init_containers {
+            Some(init_containers) if include_init_containers => init_containers.as_slice(),
+            _ => &[],
+        };
+        pod_spec
+            .containers
+            .iter()
+            .chain(init_containers)
+            .filter_map(|container| container.image.as_deref())
+            .any(|image| push_event.matches_image(image))
+    })
+}
+
+/// Registries of the images of a workload's pod template
+fn image_registries<T: Rollout>(resource: &T, include_init_containers: bool) -> Vec<String> {
+    let Some(pod_spec) = resource.pod_spec() else {
+        return Vec::new();
+    };
+    let init_containers = match &pod_spec.init_containers {
+        Some(init_containers) if include_init_containers => init_containers.as_slice(),
+        _ => &[],
+    };
+    let registries: BTreeSet<String> = pod_spec
+        .containers
+        .iter()
+        .chain(init_containers)
+        .filter_map(|container| container.image.as_deref())
+        .filter_map(|image| ImageReference::parse(image).ok())
+        .map(|image_reference| image_reference.registry)
+        .collect();
+    registries.into_iter().collect()
+}
+
+/// Removes the cached digests of the repositories a workload's pod template uses, so that a check on request asks the
+/// registry while the digests cached for other workloads are kept. Tags and registries are not compared, as the tag
+/// followed and the registry looked up may differ from the pod template.
+fn evict_cached_digests<T: Rollout>(ctx: &ControllerContext, resource: &T) {
+    let Some(pod_spec) = resource.pod_spec() else {
+        return;
+    };
+    let repositories: BTreeSet<String> = pod_spec
+        .containers
+        .iter()
+        .chain(pod_spec.init_containers.iter().flatten())
+        .filter_map(|container| container.image.as_deref())
+        .filter_map(|image| ImageReference::parse(image).ok())
+        .map(|image_reference| image_reference.repository)
+        .collect();
+    ctx.caches.digests.lock().unwrap().retain(|image| {
+        ImageReference::parse(image)
+            .is_ok_and(|image_reference| !repositories.contains(&image_reference.repository))
+    });
+}
+
+/// Checks a single labeled workload unless it is backing off from failed checks. Failures are recorded in the work
+/// queue, which dead-letters the workload once it used up its attempts. A `WorkloadSkipped` event is published
+/// whenever the reason the workload is not rolled out changes.
+pub(crate) async fn reconcile_resource<T>(
+    ctx: &ControllerContext,
+    resource: &T,
+    tracked_images: &BTreeMap<String, ResolvedTrackedImage>,
+    groups: &GroupTriggers,
+) -> anyhow::Result<()>
+where
+    T: Rollout,
+{
+    let kind_name = T::kind_name();
+    let resource_name = resource.name_any();
+    let resource_namespace = resource
+        .namespace()
+        .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+    let key = ControllerState::workload_key(kind_name, &resource_namespace, &resource_name);
+    let previous_skip = ctx
+        .state
+        .read()
+        .unwrap()
+        .workloads
+        .get(&key)
+        .and_then(|workload| workload.skipped.clone());
+
+    let result = check_queued_resource(ctx, resource, tracked_images, groups, &key).await;
+
+    let skip = ctx
+        .state
+        .read()
+        .unwrap()
+        .workloads
+        .get(&key)
+        .and_then(|workload| workload.skipped.clone());
+    if let Some(skip) = skip
+        && Some(&skip) != previous_skip.as_ref()
+        && !skip.has_own_event()
+    {
+        publish(
+            ctx,
+            workload_reference(kind_name, &resource_namespace, &resource_name),
+            EventType::Normal,
+            "WorkloadSkipped",
+            "Reconcile",
+            format!("Workload not rolled out: {}", skip),
+        );
+    }
+    result
+}
+
+async fn check_queued_resource<T>(
+    ctx: &ControllerContext,
+    resource: &T,
+    tracked_images: &BTreeMap<String, ResolvedTrackedImage>,
+    groups: &GroupTriggers,
+    key: &str,
+) -> anyhow::Result<()>
+where
+    T: Rollout,
+{
+    let kind_name = T::kind_name();
+    let resource_name = resource.name_any();
+    let resource_namespace = resource
+        .namespace()
+        .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+    let generation = resource.meta().generation;
+    {
+        let mut state = ctx.state.write().unwrap();
+        if let Some(reason) = state.work_queue.hold(key, generation, Utc::now()) {
+            debug!(workload = %key, reason = %reason, "Skipping workload backing off from failed checks");
+            state.record_skip(kind_name, &resource_namespace, &resource_name, reason);
+            return Ok(());
+        }
+    }
+
+    let timeout = ctx.config.reconcile_timeouts.resource_timeout;
+    let result = tokio::time::timeout(
+        timeout,
+        check_resource(ctx, resource, tracked_images, groups),
+    )
+    .await
+    .unwrap_or_else(|_| Err(CheckTimedOut { timeout }.into()));
+    let Err(err) = &result else {
+        ctx.state.write().unwrap().work_queue.forget(key);
+        return result;
+    };
+    let error = format!("{:#}", err);
+    let failure = {
+        let mut state = ctx.state.write().unwrap();
+        state.record_skip(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            SkipReason::CheckFailed {
+                error: error.clone(),
+            },
+        );
+        state.work_queue.record_failure(
+            key,
+            generation,
+            &error,
+            &ctx.config.workload_retry,
+            Utc::now(),
+        )
+    };
+    error_report::report(
+        ctx,
+        ErrorReport::new(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            image_registries(resource, ctx.config.feature_flags.enable_init_containers),
+            err,
+        ),
+    );
+    if failure.retry_at.is_none() {
+        warn!(
+            workload = %key,
+            attempts = %failure.attempts,
+            "Dead-lettering workload, it is checked again once its spec changes or it is reconciled through the API"
+        );
+        publish(
+            ctx,
+            workload_reference(kind_name, &resource_namespace, &resource_name),
+            EventType::Warning,
+            "CheckDeadLettered",
+            "Reconcile",
+            format!(
+                "Stopped checking the workload after {} failed attempts: {}",
+                failure.attempts, error
+            ),
+        );
+    }
+    result
+}
+
+/// The check of a workload took longer than the resource timeout and was cancelled
+#[derive(Debug)]
+pub struct CheckTimedOut {
+    pub timeout: std::time::Duration,
+}
+
+impl std::fmt::Display for CheckTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Check timed out after {}",
+            humantime::format_duration(self.timeout)
+        )
+    }
+}
+
+impl std::error::Error for CheckTimedOut {}
+
+fn is_check_timed_out(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.downcast_ref::<CheckTimedOut>().is_some())
+}
+
+/// Checks the digests of a single labeled workload and triggers its rollout if any container image changed
+async fn check_resource<T>(
+    ctx: &ControllerContext,
+    resource: &T,
+    tracked_images: &BTreeMap<String, ResolvedTrackedImage>,
+    groups: &GroupTriggers,
+) -> anyhow::Result<()>
+where
+    T: Rollout,
+{
+    let kind_name = T::kind_name();
+    let namespaces = &ctx.config.namespaces;
+    let resource_name = resource.name_any();
+    let resource_namespace = resource
+        .namespace()
+        .unwrap_or_else(|| ctx.kube_client.default_namespace().to_string());
+    if namespaces.cluster_wide && !namespaces.is_selected(&resource_namespace) {
+        debug!(
+            kind = %kind_name,
+            resource = %resource_name,
+            namespace = %resource_namespace,
+            "Skipping resource in namespace not selected by the namespace patterns"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            SkipReason::NamespaceNotSelected,
+        );
+        return Ok(());
+    }
+    if !is_scheduled_check_due(
+        ctx,
+        kind_name,
+        &resource_namespace,
+        &resource_name,
+        resource,
+    ) {
+        return Ok(());
+    }
+    if resource.is_paused() {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            "Skipping paused resource"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            SkipReason::Paused,
+        );
+        return Ok(());
+    }
+    if let Some(until) = resource.rollout_cooldown_until(ctx.config.rollout_cooldown, Utc::now()) {
         info!(
             kind = %kind_name,
             resource = %resource_name,
-            "Found resource with label"
+            until = %until,
+            "Skipping resource because it was rolled out recently and is in its cooldown"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            SkipReason::Cooldown { until },
+        );
+        return Ok(());
+    }
+    let api: Api<T> = Api::namespaced(ctx.kube_client.clone(), &resource_namespace);
+    let pods: Api<Pod> = Api::namespaced(ctx.kube_client.clone(), &resource_namespace);
+    let secrets: Api<Secret> = Api::namespaced(ctx.kube_client.clone(), &resource_namespace);
+    info!(
+        kind = %kind_name,
+        resource = %resource_name,
+        namespace = %resource_namespace,
+        "Found resource with label"
+    );
+    if let Err(err) = T::restore_rolling_update(&api, resource).await {
+        warn!(
+            error = ?err,
+            kind = %kind_name,
+            resource = %resource_name,
+            "Failed to restore rolling update parameters"
         );
-        let desired_replicas = resource.desired_replicas();
-        let actual_replicas = resource.actual_replicas();
+    }
 
-        if desired_replicas > 0 && actual_replicas > 0 {
-            let selector = resource.selector();
-            let pod = match get_associated_pod(&pods, &selector).await {
-                Ok(pod) => pod,
-                Err(err) => {
-                    warn!(
-                        error = %err,
+    let desired_replicas = resource.desired_replicas();
+    let actual_replicas = resource.actual_replicas();
+    let scaled_to_zero = desired_replicas == 0 || actual_replicas == 0;
+    // workloads without pods are checked against the digests they ran at their last check
+    let remembered_pod = match ctx.config.zero_replicas {
+        ZeroReplicas::Skip => None,
+        _ if scaled_to_zero => remembered_pod(ctx, resource, &resource_namespace, &resource_name),
+        _ => None,
+    };
+
+    if !scaled_to_zero || remembered_pod.is_some() {
+        let selector = resource.selector();
+        let associated_pod = match remembered_pod {
+            Some(pod) => Ok(pod),
+            None => {
+                let _list_pods = ctx.in_flight.track(
+                    OperationKind::KubeApi,
+                    format!(
+                        "list pods of {} {}/{}",
+                        kind_name, resource_namespace, resource_name
+                    ),
+                );
+                get_associated_pod(&pods, &selector).await
+            }
+        };
+        let pod = match associated_pod {
+            Ok(pod) => pod,
+            Err(err) => {
+                warn!(
+                    error = %err,
+                    kind = %kind_name,
+                    resource = %resource_name,
+                    "Skipping resource because its pods/containers are not scheduled or ready yet"
+                );
+                ctx.state.write().unwrap().record_skip(
+                    kind_name,
+                    &resource_namespace,
+                    &resource_name,
+                    SkipReason::PodsNotReady {
+                        message: format!("{:#}", err),
+                    },
+                );
+                return Ok(());
+            }
+        };
+        let pod_name = pod.metadata.name.as_ref().unwrap();
+
+        let mut restart_trigger = resource.restart_trigger(ctx.config.restart_trigger);
+        // rolled back containers and images pinned in the spec stay on their digest, only pinning rolls them out
+        if restart_trigger != RestartTrigger::PinDigest
+            && (!resource.rolled_back_digests().is_empty() || has_pinned_images(&pod))
+        {
+            restart_trigger = RestartTrigger::PinDigest;
+        }
+        // pinned images are pulled by digest, so their pull policy does not matter
+        if restart_trigger != RestartTrigger::PinDigest {
+            warn_misconfigured_container_image_pull_policies(&pod);
+        }
+
+        let container_image_references: Vec<_> = get_pod_container_image_references(
+            &pod,
+            ctx.config.feature_flags.enable_init_containers,
+            resource.track_tag(),
+        )
+        .with_context(|| {
+            format!(
+                "Could not retrieve container image references for pod {}",
+                pod_name
+            )
+        })?
+        .into_iter()
+        .filter(|reference| {
+            if !resource.is_container_checked(&reference.container_name) {
+                debug!(
+                    kind = %kind_name,
+                    resource = %resource_name,
+                    container = %reference.container_name,
+                    "Skipping container excluded by annotation"
+                );
+                return false;
+            }
+            let repository = format!(
+                "{}/{}",
+                reference.image_reference.registry, reference.image_reference.repository
+            );
+            if !ctx.config.image_filters.is_selected(&repository) {
+                debug!(
+                    kind = %kind_name,
+                    resource = %resource_name,
+                    container = %reference.container_name,
+                    repository = %repository,
+                    "Skipping container whose repository is excluded by the image filters"
+                );
+                return false;
+            }
+            true
+        })
+        .collect();
+
+        let image_pull_secret_names = resource.image_pull_secrets();
+        debug!(
+            secrets = ?image_pull_secret_names,
+            resource = %resource_name,
+            "Parsed image pull secrets for resource"
+        );
+
+        let mut image_pull_secrets = collect_image_pull_secrets(
+            ctx,
+            &secrets,
+            &resource_namespace,
+            &image_pull_secret_names,
+        )
+        .await
+        .with_context(|| format!("Failed to collect image pull secrets for pod {}", pod_name))?;
+        image_pull_secrets.extend(
+            collect_service_account_image_pull_secrets(
+                ctx,
+                &secrets,
+                &resource_namespace,
+                &pod,
+                &image_pull_secret_names,
+            )
+            .await,
+        );
+
+        ctx.state.write().unwrap().clear_unmatched_images(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+        );
+
+        let tracked_image_name = resource
+            .annotations()
+            .get(TRACKED_IMAGE_ANNOTATION)
+            .map(String::as_str);
+
+        let tag_updates = match resource
+            .annotations()
+            .get(KUBE_AUTOROLLOUT_TAG_POLICY_ANNOTATION)
+        {
+            Some(tag_policy) => {
+                find_tag_updates(
+                    ctx,
+                    kind_name,
+                    &resource_namespace,
+                    &resource_name,
+                    tag_policy,
+                    &container_image_references,
+                    &image_pull_secrets,
+                )
+                .await
+            }
+            None => Vec::new(),
+        };
+        if !tag_updates.is_empty() {
+            info!(
+                kind = %kind_name,
+                resource = %resource_name,
+                tag_updates = %describe_changed_containers(&tag_updates),
+                "Found newer tags for containers"
+            );
+        }
+
+        // Changes are collected across all containers, so the workload is restarted only once per cycle. The
+        // containers are checked concurrently, their changes are kept in the order of the pod spec. Containers moving
+        // to a newer tag are not checked for digest changes of their current tag.
+        let (resource_namespace, resource_name) = (&resource_namespace, &resource_name);
+        let image_pull_secrets = &image_pull_secrets;
+        let rolled_back_digests = &resource.rolled_back_digests();
+        let registry_override = resource.registry_override();
+        let platform = &get_pod_platform(ctx, &pod).await;
+        let tag_updates = &tag_updates;
+        let container_checks: Vec<_> = container_image_references
+            .iter()
+            .map(|reference| async move {
+                if let Some(tag_update) = tag_updates
+                    .iter()
+                    .find(|tag_update| tag_update.container_name == reference.container_name)
+                {
+                    return anyhow::Ok(Some(tag_update.clone()));
+                }
+                info!(
+                    pod = %pod_name,
+                    container = %reference.container_name,
+                    image = %reference.image_reference,
+                    current_digest = %reference.digest,
+                    "Found container with image and current digest"
+                );
+                // the image is looked up, and its credentials resolved, at the host of the registry annotation
+                let overridden = registry_override.map(|registry| reference.with_registry(registry));
+                let lookup_reference = overridden.as_ref().unwrap_or(reference);
+
+                let tracked_image = tracked_image_name
+                    .and_then(|name| tracked_images.get(name))
+                    .filter(|tracked_image| tracked_image.tracks(&reference.image_reference));
+                let lookup = match tracked_image {
+                    Some(tracked_image) if tracked_image.paused => {
+                        info!(
+                            pod = %pod_name,
+                            container = %reference.container_name,
+                            tracked_image = %tracked_image_name.unwrap_or_default(),
+                            "Deferring container because its TrackedImage is paused"
+                        );
+                        ctx.state.write().unwrap().record_skip(
+                            kind_name,
+                            resource_namespace,
+                            resource_name,
+                            SkipReason::TrackedImagePaused,
+                        );
+                        None
+                    }
+                    Some(tracked_image) => Some((
+                        tracked_image.digests.clone(),
+                        tracked_image.registry_host.clone(),
+                    )),
+                    None => {
+                        lookup_recent_digests(
+                            ctx,
+                            kind_name,
+                            resource_namespace,
+                            resource_name,
+                            pod_name,
+                            image_pull_secrets,
+                            lookup_reference,
+                        )
+                        .await?
+                    }
+                };
+                let Some((recent_digests, registry_host)) = lookup else {
+                    return anyhow::Ok(None);
+                };
+
+                info!(
+                    recent_digests = %recent_digests.join(","),
+                    registry_host = %registry_host,
+                    "Found recent image digests"
+                );
+
+                ctx.state.write().unwrap().record_container_check(
+                    kind_name,
+                    resource_namespace,
+                    resource_name,
+                    reference,
+                    &registry_host,
+                    &recent_digests,
+                );
+
+                if !recent_digests.contains(&reference.digest) {
+                    let new_digest = recent_digests.last().cloned().unwrap_or_default();
+                    // an index changes with any of its platforms, the node only runs the manifest of its own
+                    if let Some(platform) = platform
+                        && recent_digests.len() > 1
+                        && is_platform_manifest_unchanged(
+                            ctx,
+                            image_pull_secrets,
+                            lookup_reference,
+                            platform,
+                            &new_digest,
+                            &registry_host,
+                        )
+                        .await
+                    {
+                        info!(
+                            kind = %kind_name,
+                            resource = %resource_name,
+                            container = %reference.container_name,
+                            platform = %platform,
+                            digest = %new_digest,
+                            "Skipping container, the manifest of its node's platform is unchanged"
+                        );
+                        return Ok(None);
+                    }
+                    let compares_config_digests = ctx
+                        .config
+                        .find_registry_for_hostname(&lookup_reference.image_reference.registry)
+                        .is_some_and(|registry| {
+                            registry.digest_comparison == DigestComparison::Config
+                        });
+                    if compares_config_digests
+                        && is_image_config_unchanged(
+                            ctx,
+                            image_pull_secrets,
+                            lookup_reference,
+                            &new_digest,
+                            &registry_host,
+                        )
+                        .await
+                    {
+                        info!(
+                            kind = %kind_name,
+                            resource = %resource_name,
+                            container = %reference.container_name,
+                            digest = %new_digest,
+                            "Skipping container, only its manifest changed while the image config is up to date"
+                        );
+                        return Ok(None);
+                    }
+                    info!(
                         kind = %kind_name,
                         resource = %resource_name,
-                        "Skipping resource because its pods/containers are not scheduled or ready yet"
+                        container = %reference.container_name,
+                        "Container digest has changed"
+                    );
+                    if rolled_back_digests.get(&reference.container_name) == Some(&new_digest) {
+                        info!(
+                            kind = %kind_name,
+                            resource = %resource_name,
+                            container = %reference.container_name,
+                            digest = %new_digest,
+                            "Skipping container, its latest digest was rolled back"
+                        );
+                        return Ok(None);
+                    }
+                    if let Some(signature_policy) = &ctx.config.signature_policy
+                        && !is_signature_accepted(
+                            ctx,
+                            signature_policy,
+                            kind_name,
+                            resource_namespace,
+                            resource_name,
+                            image_pull_secrets,
+                            lookup_reference,
+                            &recent_digests,
+                            &registry_host,
+                        )
+                        .await
+                    {
+                        return Ok(None);
+                    }
+                    Ok(Some(ChangedContainer {
+                        container_name: reference.container_name.clone(),
+                        image: reference.image_reference.to_string(),
+                        previous_digest: reference.digest.clone(),
+                        new_digest,
+                        init_container: reference.init_container,
+                        previous_image: None,
+                    }))
+                } else {
+                    info!(
+                        kind = %kind_name,
+                        resource = %resource_name,
+                        container = %reference.container_name,
+                        "Skipping container, digest is up to date"
+                    );
+                    Ok(None)
+                }
+            })
+            .collect();
+        let changed_containers: Vec<ChangedContainer> = stream::iter(container_checks)
+            .buffered(ctx.config.reconcile_concurrency)
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        if changed_containers.is_empty() {
+            info!(
+                kind = %kind_name,
+                resource = %resource_name,
+                "Skipping resource, all digests are up to date"
+            );
+            approval::clear(
+                ctx,
+                &api,
+                resource,
+                kind_name,
+                resource_namespace,
+                resource_name,
+            )
+            .await;
+            return Ok(());
+        }
+
+        roll_out_changes(
+            ctx,
+            &api,
+            resource,
+            resource_namespace,
+            changed_containers,
+            restart_trigger,
+            groups,
+        )
+        .await?;
+    } else {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            desired_replicas = %desired_replicas,
+            actual_replicas = %actual_replicas,
+            "Skipping resource as desired and actual replicas are zero"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            &resource_namespace,
+            &resource_name,
+            SkipReason::ZeroReplicas {
+                pending_containers: Vec::new(),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Rolls out the changed containers of the workload once they passed the gates every rollout is subject to: the
+/// policy, dry run, canary, approval, intent mode and workload groups. Digest changes and tag updates take the same
+/// gates.
+async fn roll_out_changes<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    resource_namespace: &str,
+    changed_containers: Vec<ChangedContainer>,
+    restart_trigger: RestartTrigger,
+    groups: &GroupTriggers,
+) -> anyhow::Result<()> {
+    let kind_name = T::kind_name();
+    let resource_name = &resource.name_any();
+    if resource.is_rollout_triggered(&changed_containers) {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            changed_containers = %describe_changed_containers(&changed_containers),
+            "Skipping rollout, the workload was already patched for these digests and its pods are being replaced"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::RolloutInProgress,
+        );
+        return Ok(());
+    }
+
+    if ctx.config.zero_replicas == ZeroReplicas::PatchOnScaleUp && resource.desired_replicas() == 0
+    {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            changed_containers = %describe_changed_containers(&changed_containers),
+            "Deferring rollout of the workload scaled to zero until it is scaled up"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::ZeroReplicas {
+                pending_containers: changed_containers
+                    .iter()
+                    .map(|changed| changed.container_name.clone())
+                    .collect(),
+            },
+        );
+        return Ok(());
+    }
+
+    if let Some(policy) = &ctx.config.policy {
+        let input = RolloutDecisionInput {
+            kind: kind_name,
+            namespace: resource_namespace,
+            name: resource_name,
+            changed_containers: &changed_containers,
+        };
+        let decision = authorize_rollout(&ctx.http_client(), policy, &input).await;
+        if !decision.allowed {
+            let reason = decision
+                .reason
+                .unwrap_or_else(|| "denied by policy".to_string());
+            info!(
+                kind = %kind_name,
+                resource = %resource_name,
+                reason = %reason,
+                "Skipping rollout because the policy denied it"
+            );
+            ctx.state.write().unwrap().record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::PolicyDenied {
+                    message: reason.clone(),
+                },
+            );
+            publish(
+                ctx,
+                workload_reference(kind_name, resource_namespace, resource_name),
+                EventType::Normal,
+                "RolloutSkipped",
+                "Rollout",
+                format!("Rollout denied by policy: {}", reason),
+            );
+            return Ok(());
+        }
+    }
+
+    if resource.is_dry_run(ctx.config.dry_run) {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            changed_containers = %describe_changed_containers(&changed_containers),
+            "Dry run, would trigger rollout"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::DryRun,
+        );
+        publish(
+            ctx,
+            workload_reference(kind_name, resource_namespace, resource_name),
+            EventType::Normal,
+            "RolloutDryRun",
+            "Rollout",
+            format!(
+                "Dry run, would trigger rollout for {}",
+                describe_changed_containers(&changed_containers)
+            ),
+        );
+        return Ok(());
+    }
+
+    if ctx.config.canary.is_some()
+        && !canary::is_canary(resource)
+        && let Some(reason) = canary::waiting_for_canary(ctx, &changed_containers).await?
+    {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            reason = %reason,
+            "Deferring rollout until the canary is healthy"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::WaitingForCanary { message: reason },
+        );
+        return Ok(());
+    }
+
+    let requires_approval = approval::is_required(resource, ctx.config.require_approval);
+    if requires_approval {
+        let pending = PendingRollout::new(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            &changed_containers,
+        );
+        if !approval::is_approved(resource, &pending, ctx.config.required_approvers) {
+            approval::request(
+                ctx,
+                api,
+                resource,
+                kind_name,
+                resource_namespace,
+                resource_name,
+                pending,
+            )
+            .await?;
+            return Ok(());
+        }
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            approval = %pending.id,
+            "Rolling out approved changes"
+        );
+    }
+
+    if ctx.config.rollout_mode == RolloutMode::Intent {
+        apply_rollout_intent(
+            &ctx.kube_client,
+            resource_namespace,
+            kind_name,
+            resource_name,
+            &changed_containers,
+        )
+        .await?;
+        publish(
+            ctx,
+            workload_reference(kind_name, resource_namespace, resource_name),
+            EventType::Normal,
+            "RolloutIntentCreated",
+            "Rollout",
+            format!(
+                "Described the rollout for {} as a RolloutIntent",
+                describe_changed_containers(&changed_containers)
+            ),
+        );
+        if requires_approval {
+            approval::clear(
+                ctx,
+                api,
+                resource,
+                kind_name,
+                resource_namespace,
+                resource_name,
+            )
+            .await;
+        }
+        return Ok(());
+    }
+
+    if let Some(group) = workload_group::group_name(resource) {
+        info!(
+            kind = %kind_name,
+            resource = %resource_name,
+            group = %group,
+            "Deferring rollout to trigger the workload group together"
+        );
+        groups.add(
+            resource_namespace,
+            group,
+            GroupChange {
+                kind: kind_name,
+                name: resource_name.clone(),
+                changed_containers,
+                restart_trigger,
+                requires_approval,
+            },
+        );
+        return Ok(());
+    }
+    trigger_rollout(
+        ctx,
+        api,
+        resource,
+        resource_namespace,
+        &changed_containers,
+        restart_trigger,
+        requires_approval,
+    )
+    .await
+}
+
+/// Patches the workload to roll out the changed containers and reports the rollout through notifications, the
+/// history and events. Tag updates set the newer images, digest changes restart the workload with the strategy of its
+/// restart trigger. Workloads restarted along with their group may have no changed containers.
+pub(crate) async fn trigger_rollout<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    resource_namespace: &str,
+    changed_containers: &[ChangedContainer],
+    restart_trigger: RestartTrigger,
+    requires_approval: bool,
+) -> anyhow::Result<()> {
+    let kind_name = T::kind_name();
+    let resource_name = &resource.name_any();
+    let (tag_updates, digest_changes): (Vec<_>, Vec<_>) = changed_containers
+        .iter()
+        .cloned()
+        .partition(ChangedContainer::is_tag_update);
+    // group members without changes of their own are restarted like digest changes
+    let only_tag_updates = !tag_updates.is_empty() && digest_changes.is_empty();
+    info!(
+        kind = %kind_name,
+        resource = %resource_name,
+        changed_containers = %changed_containers.len(),
+        tag_updates = %tag_updates.len(),
+        "Triggering rollout for resource"
+    );
+    if let Some(overrides) = &ctx.config.rolling_update_overrides {
+        T::apply_rolling_update_overrides(api, resource, overrides).await?;
+    }
+    let rollout_changes: Vec<_> = changed_containers
+        .iter()
+        .map(HistoryChange::from_changed_container)
+        .collect();
+    let patched = {
+        let _patch = ctx.in_flight.track(
+            OperationKind::KubeApi,
+            format!(
+                "patch {} {}/{}",
+                kind_name, resource_namespace, resource_name
+            ),
+        );
+        async {
+            if !tag_updates.is_empty() {
+                T::patch_container_images(api, resource_name, &tag_updates).await?;
+            }
+            if only_tag_updates {
+                return Ok(());
+            }
+            let strategy = rollout_strategy(restart_trigger, &ctx.config)?;
+            T::patch_rollout(api, resource, &digest_changes, strategy.as_ref()).await
+        }
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to patch {} resource {} to trigger rollout",
+                kind_name, resource_name
+            )
+        })
+    };
+    if let Err(err) = patched {
+        notify_rollout_failed(
+            ctx,
+            kind_name,
+            resource_namespace,
+            resource_name,
+            changed_containers
+                .iter()
+                .map(describe_changed_container)
+                .collect(),
+            &err,
+        );
+        history::record(
+            ctx,
+            kind_name,
+            resource_namespace,
+            resource_name,
+            HistoryEntry::new(rollout_changes, Some(&err)),
+        )
+        .await;
+        return Err(err);
+    }
+    ctx.state
+        .write()
+        .unwrap()
+        .record_rollout(kind_name, resource_namespace, resource_name);
+    if requires_approval {
+        approval::clear(
+            ctx,
+            api,
+            resource,
+            kind_name,
+            resource_namespace,
+            resource_name,
+        )
+        .await;
+    }
+    notify::send(
+        ctx,
+        Notification {
+            title: match only_tag_updates {
+                true => format!(
+                    "Updated image tags of {} {}/{}",
+                    kind_name, resource_namespace, resource_name
+                ),
+                false => format!(
+                    "Triggered rollout of {} {}/{}",
+                    kind_name, resource_namespace, resource_name
+                ),
+            },
+            details: changed_containers
+                .iter()
+                .map(describe_changed_container)
+                .collect(),
+            failed: false,
+            images: changed_containers
+                .iter()
+                .map(|changed| changed.image.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        },
+    );
+    history::record(
+        ctx,
+        kind_name,
+        resource_namespace,
+        resource_name,
+        HistoryEntry::new(rollout_changes.clone(), None),
+    )
+    .await;
+    rollout_wait::spawn(
+        ctx,
+        api.clone(),
+        kind_name,
+        resource_namespace,
+        resource_name,
+        rollout_changes,
+    );
+    canary::spawn(
+        ctx,
+        api.clone(),
+        resource,
+        resource_namespace,
+        changed_containers,
+    );
+    // rollbacks pin the previous digest of the tag, which does not revert a tag update
+    if !digest_changes.is_empty() {
+        rollback::spawn(
+            ctx,
+            api.clone(),
+            resource,
+            kind_name,
+            resource_namespace,
+            digest_changes,
+        );
+    }
+    publish(
+        ctx,
+        workload_reference(kind_name, resource_namespace, resource_name),
+        EventType::Normal,
+        match only_tag_updates {
+            true => "TagUpdated",
+            false => "RolloutTriggered",
+        },
+        "Rollout",
+        match changed_containers.is_empty() {
+            true => "Triggered rollout along with its workload group".to_string(),
+            false if only_tag_updates => {
+                format!(
+                    "Updated {}",
+                    describe_changed_containers(changed_containers)
+                )
+            }
+            false => format!(
+                "Triggered rollout for {}",
+                describe_changed_containers(changed_containers)
+            ),
+        },
+    );
+    info!(
+        kind = %kind_name,
+        resource = %resource_name,
+        "Successfully triggered rollout"
+    );
+    Ok(())
+}
+
+/// Workloads with a schedule annotation are only checked when their own schedule is due, all other workloads on
+/// every run. Invalid schedules fall back to the global schedule.
+fn is_scheduled_check_due<T: Rollout>(
+    ctx: &ControllerContext,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    resource: &T,
+) -> bool {
+    let Some(value) = resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_SCHEDULE_ANNOTATION)
+    else {
+        return true;
+    };
+    let schedule = match WorkloadSchedule::parse(value) {
+        Ok(schedule) => schedule,
+        Err(err) => {
+            warn!(
+                error = ?err,
+                kind = %kind_name,
+                resource = %resource_name,
+                "Ignoring invalid schedule annotation, using the global schedule"
+            );
+            return true;
+        }
+    };
+
+    let mut state = ctx.state.write().unwrap();
+    let workload = state.workload_mut(kind_name, resource_namespace, resource_name);
+    let now = Utc::now();
+    match schedule.is_due(workload.last_scheduled_check_at, now) {
+        Ok(true) => {
+            workload.last_scheduled_check_at = Some(now);
+            true
+        }
+        Ok(false) => {
+            debug!(
+                kind = %kind_name,
+                resource = %resource_name,
+                schedule = %value,
+                "Skipping resource until its own schedule is due"
+            );
+            workload.skipped = Some(SkipReason::ScheduleNotDue {
+                schedule: value.to_string(),
+            });
+            false
+        }
+        Err(err) => {
+            warn!(
+                error = ?err,
+                kind = %kind_name,
+                resource = %resource_name,
+                "Failed to evaluate schedule annotation, using the global schedule"
+            );
+            true
+        }
+    }
+}
+
+/// Finds the newest tag allowed by the workload's tag policy for every container. In intent mode the newer tag is
+/// described in the RolloutIntent for the actuator. New tags are subject to the signature policy like new digests.
+async fn find_tag_updates(
+    ctx: &ControllerContext,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    tag_policy: &str,
+    references: &[ContainerImageReference],
+    image_pull_secrets: &[DockerConfig],
+) -> Vec<ChangedContainer> {
+    let tag_policy = match TagPolicy::parse(tag_policy) {
+        Ok(tag_policy) => tag_policy,
+        Err(err) => {
+            warn!(
+                error = %err,
+                kind = %kind_name,
+                resource = %resource_name,
+                "Ignoring invalid tag policy annotation"
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut tag_updates = Vec::new();
+    for reference in references {
+        let image_reference = &reference.image_reference;
+        let Ok(registry_secret) = find_registry_secret(ctx, image_pull_secrets, reference).await
+        else {
+            continue;
+        };
+        let tags = match list_tags(
+            &ctx.registry_http_client(&image_reference.registry),
+            &ctx.caches.tokens,
+            &ctx.config.registry_retry,
+            &ctx.registry_requests,
+            &ctx.config.registry_endpoint(image_reference),
+            &registry_secret,
+        )
+        .await
+        {
+            Ok(tags) => tags,
+            Err(err) => {
+                warn!(
+                    error = %format!("{:#}", err),
+                    kind = %kind_name,
+                    resource = %resource_name,
+                    image = %image_reference,
+                    "Failed to list tags of image"
+                );
+                continue;
+            }
+        };
+        let Some(tag) = tag_policy.select_newest_tag(&image_reference.tag, &tags) else {
+            continue;
+        };
+
+        let updated_reference = ContainerImageReference {
+            container_name: reference.container_name.clone(),
+            image_reference: ImageReference {
+                tag,
+                ..image_reference.clone()
+            },
+            digest: reference.digest.clone(),
+            init_container: reference.init_container,
+        };
+        if let Some(signature_policy) = &ctx.config.signature_policy {
+            let digests = fetch_digests_with_failover(
+                &ctx.config
+                    .registry_endpoint(&updated_reference.image_reference),
+                &[],
+                &registry_secret,
+                &ctx.registry_http_client(&image_reference.registry),
+                &ctx.caches.tokens,
+                &ctx.rate_limits,
+                &ctx.config.registry_retry,
+                &ctx.registry_requests,
+                ctx.config.feature_flags.enable_jfrog_artifactory_fallback,
+            )
+            .await;
+            let accepted = match digests {
+                Ok((digests, registry_host)) => {
+                    is_signature_accepted(
+                        ctx,
+                        signature_policy,
+                        kind_name,
+                        resource_namespace,
+                        resource_name,
+                        image_pull_secrets,
+                        &updated_reference,
+                        &digests,
+                        &registry_host,
+                    )
+                    .await
+                }
+                Err(err) => {
+                    warn!(
+                        error = %format!("{:#}", err),
+                        image = %updated_reference.image_reference,
+                        "Failed to resolve the digest of the newer tag for signature verification"
                     );
-                    continue;
+                    false
                 }
             };
-            let pod_name = pod.metadata.name.as_ref().unwrap();
+            if !accepted {
+                continue;
+            }
+        }
 
-            warn_misconfigured_container_image_pull_policies(&pod);
+        tag_updates.push(ChangedContainer {
+            container_name: reference.container_name.clone(),
+            image: updated_reference.image_reference.to_string(),
+            previous_digest: reference.digest.clone(),
+            new_digest: String::new(),
+            init_container: reference.init_container,
+            previous_image: Some(image_reference.to_string()),
+        });
+    }
+    tag_updates
+}
 
-            let container_image_references = get_pod_container_image_references(&pod)
-                .with_context(|| {
-                    format!(
-                        "Could not retrieve container image references for pod {}",
-                        pod_name
-                    )
-                })?;
+/// Verifies the signature of the digest the tag resolves to, which is the last of the recent digests. Rejected
+/// containers are recorded as deferred, so the workload is not rolled out to an unsigned image.
+#[allow(clippy::too_many_arguments)]
+async fn is_signature_accepted(
+    ctx: &ControllerContext,
+    signature_policy: &SignaturePolicy,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    image_pull_secrets: &[DockerConfig],
+    reference: &ContainerImageReference,
+    recent_digests: &[String],
+    registry_host: &str,
+) -> bool {
+    let image = reference.image_reference.to_string();
+    let Some(digest) = recent_digests.last() else {
+        return false;
+    };
+    let host_reference = ImageReference {
+        registry: registry_host.to_string(),
+        repository: reference.image_reference.repository.clone(),
+        tag: reference.image_reference.tag.clone(),
+    };
+
+    let result = match find_registry_secret(ctx, image_pull_secrets, reference).await {
+        Ok(registry_secret) => {
+            verify_image_signature(
+                &ctx.registry_http_client(&reference.image_reference.registry),
+                &ctx.caches.tokens,
+                &ctx.config.registry_retry,
+                &ctx.registry_requests,
+                signature_policy,
+                &host_reference,
+                digest,
+                &registry_secret,
+            )
+            .await
+        }
+        Err(err) => Err(err),
+    };
+    let mut state = ctx.state.write().unwrap();
+    match result {
+        Ok(()) => {
+            state.clear_signature_failure(&image);
+            true
+        }
+        Err(err) => {
+            let reason = match is_signature_missing(&err) {
+                true => "image not signed",
+                false => "signature verification failed",
+            };
+            warn!(
+                error = %format!("{:#}", err),
+                kind = %kind_name,
+                resource = %resource_name,
+                container = %reference.container_name,
+                image = %image,
+                digest = %digest,
+                "Skipping container because its new digest is not signed according to the signature policy"
+            );
+            state.record_signature_failure(&image, &format!("{}: {:#}", reason, err));
+            state.record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::SignatureRejected {
+                    message: reason.to_string(),
+                },
+            );
+            publish(
+                ctx,
+                workload_reference(kind_name, resource_namespace, resource_name),
+                EventType::Warning,
+                "SignatureVerificationFailed",
+                "VerifySignature",
+                format!(
+                    "Skipped container {} because {}@{}: {} ({:#})",
+                    reference.container_name, image, digest, reason, err
+                ),
+            );
+            false
+        }
+    }
+}
+
+/// Compares the config blobs of the running and the new digest for registries with `digestComparison: config`, so
+/// index annotations or attestations changed by the registry do not trigger a rollout. Failing to fetch the configs
+/// counts as a change, like with the manifest digest comparison.
+async fn is_image_config_unchanged(
+    ctx: &ControllerContext,
+    image_pull_secrets: &[DockerConfig],
+    reference: &ContainerImageReference,
+    new_digest: &str,
+    registry_host: &str,
+) -> bool {
+    let host_reference = ImageReference {
+        registry: registry_host.to_string(),
+        repository: reference.image_reference.repository.clone(),
+        tag: reference.image_reference.tag.clone(),
+    };
+    let result = async {
+        let registry_secret = find_registry_secret(ctx, image_pull_secrets, reference).await?;
+        let client = ctx.registry_http_client(&reference.image_reference.registry);
+        let fetch = |digest| {
+            fetch_config_digests(
+                &client,
+                &ctx.caches.tokens,
+                &ctx.config.registry_retry,
+                &ctx.registry_requests,
+                &host_reference,
+                digest,
+                &registry_secret,
+            )
+        };
+        let running = fetch(&reference.digest).await?;
+        let new = fetch(new_digest).await?;
+        anyhow::Ok(running.is_subset(&new))
+    }
+    .await;
+    match result {
+        Ok(unchanged) => unchanged,
+        Err(err) => {
+            warn!(
+                error = %format!("{:#}", err),
+                container = %reference.container_name,
+                image = %reference.image_reference,
+                "Failed to compare image config digests, comparing manifest digests instead"
+            );
+            false
+        }
+    }
+}
 
-            let image_pull_secrets = resource.image_pull_secrets();
+/// Compares the manifests the pod's platform resolves to in the running and the new index, so heterogeneous
+/// clusters do not roll out containers whose platform was not rebuilt
+async fn is_platform_manifest_unchanged(
+    ctx: &ControllerContext,
+    image_pull_secrets: &[DockerConfig],
+    reference: &ContainerImageReference,
+    platform: &Platform,
+    new_digest: &str,
+    registry_host: &str,
+) -> bool {
+    let host_reference = ImageReference {
+        registry: registry_host.to_string(),
+        repository: reference.image_reference.repository.clone(),
+        tag: reference.image_reference.tag.clone(),
+    };
+    let result = async {
+        let registry_secret = find_registry_secret(ctx, image_pull_secrets, reference).await?;
+        let client = ctx.registry_http_client(&reference.image_reference.registry);
+        let fetch = |digest| {
+            fetch_platform_digest(
+                &client,
+                &ctx.caches.tokens,
+                &ctx.config.registry_retry,
+                &ctx.registry_requests,
+                &host_reference,
+                digest,
+                &registry_secret,
+                platform,
+            )
+        };
+        let running = fetch(&reference.digest).await?;
+        let new = fetch(new_digest).await?;
+        anyhow::Ok(running == new)
+    }
+    .await;
+    match result {
+        Ok(unchanged) => unchanged,
+        Err(err) => {
             debug!(
-                secrets = ?image_pull_secrets,
+                error = %format!("{:#}", err),
+                container = %reference.container_name,
+                image = %reference.image_reference,
+                platform = %platform,
+                "Failed to compare platform manifests, comparing index digests instead"
+            );
+            false
+        }
+    }
+}
+
+/// Platform of the node the pod runs on, from the node's well-known labels. Falls back to the node selector and
+/// required node affinity of the pod if the node can not be read, e.g. without RBAC permissions for nodes.
+async fn get_pod_platform(ctx: &ControllerContext, pod: &Pod) -> Option<Platform> {
+    let spec = pod.spec.as_ref()?;
+    if let Some(node_name) = &spec.node_name {
+        let nodes: Api<Node> = Api::all(ctx.kube_client.clone());
+        match nodes.get(node_name).await {
+            Ok(node) => {
+                if let Some(platform) = Platform::from_node_labels(node.labels()) {
+                    return Some(platform);
+                }
+            }
+            Err(err) => debug!(
+                error = %err,
+                node = %node_name,
+                "Could not read the node of the pod, taking its platform from the pod spec"
+            ),
+        }
+    }
+    Platform::from_pod_spec(spec)
+}
+
+/// Looks up the recent digests of a container's image in its registry.
+/// Returns `None` when the container has to be skipped for this cycle.
+async fn lookup_recent_digests(
+    ctx: &ControllerContext,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    pod_name: &str,
+    image_pull_secrets: &[DockerConfig],
+    reference: &ContainerImageReference,
+) -> anyhow::Result<Option<(Vec<String>, String)>> {
+    let registry_secret = match find_matching_image_pull_secret(image_pull_secrets, reference)
+        .or_else(|_| get_registry_secret_from_config(&ctx.config, reference))
+    {
+        Ok(registry_secret) => registry_secret,
+        Err(err) => {
+            warn!(
+                error = %err,
+                kind = %kind_name,
                 resource = %resource_name,
-                "Parsed image pull secrets for resource"
+                container = %reference.container_name,
+                image = %reference.image_reference,
+                "Labeled workload uses an image from a registry matching no registry configuration or image pull secret"
             );
+            let image = reference.image_reference.to_string();
+            let mut state = ctx.state.write().unwrap();
+            state.record_unmatched_image(kind_name, resource_namespace, resource_name, &image);
+            state.record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::NoMatchingRegistry { image },
+            );
+            return Ok(None);
+        }
+    };
 
-            let image_pull_secrets = collect_image_pull_secrets(&secrets, &image_pull_secrets)
-                .await
-                .with_context(|| {
-                    format!("Failed to collect image pull secrets for pod {}", pod_name)
-                })?;
+    let registry_secret = match resolve_registry_secret(
+        &ctx.http_client(),
+        &ctx.caches.credentials,
+        &reference.image_reference.registry,
+        registry_secret,
+    )
+    .await
+    {
+        Ok(registry_secret) => registry_secret,
+        Err(err) => {
+            warn!(
+                error = %format!("{:#}", err),
+                kind = %kind_name,
+                resource = %resource_name,
+                container = %reference.container_name,
+                image = %reference.image_reference,
+                "Skipping container because registry credentials could not be obtained"
+            );
+            ctx.state.write().unwrap().record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::CredentialsUnavailable {
+                    image: reference.image_reference.to_string(),
+                },
+            );
+            publish_digest_fetch_failure(
+                ctx,
+                kind_name,
+                resource_namespace,
+                resource_name,
+                reference,
+                &err,
+            );
+            return Ok(None);
+        }
+    };
 
-            for reference in container_image_references.iter() {
-                info!(
-                    pod = %pod_name,
-                    container = %reference.container_name,
-                    image = %reference.image_reference,
-                    current_digest = %reference.digest,
-                    "Found container with image and current digest"
-                );
+    let registry = &reference.image_reference.registry;
+    if let Some(matched) = ctx.config.find_registry_for_hostname(registry) {
+        ctx.state
+            .write()
+            .unwrap()
+            .record_registry_pattern_match(&matched.hostname_pattern);
+    }
 
-                let registry_secret =
-                    find_matching_image_pull_secret(&image_pull_secrets, reference)
-                        .or_else(|_| get_registry_secret_from_config(&ctx.config, reference))?;
+    let unavailable_until = ctx
+        .state
+        .read()
+        .unwrap()
+        .registry_unavailable_until(registry);
+    if let Some(until) = unavailable_until {
+        info!(
+            pod = %pod_name,
+            container = %reference.container_name,
+            registry = %registry,
+            until = %until,
+            "Deferring container because its registry is in cooldown after being unavailable"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::RegistryUnavailable,
+        );
+        return Ok(None);
+    }
 
-                let recent_digests = match fetch_digests_from_tag(
-                    &reference.image_reference,
-                    &registry_secret,
-                    &ctx.http_client,
-                    ctx.config.feature_flags.enable_jfrog_artifactory_fallback,
-                )
-                .await
-                .context("Failed to retrieve recent digests from registry")
-                {
-                    Ok(digests) => digests,
-                    Err(err) => {
-                        warn!(
-                            error = %err,
-                            pod = %pod_name,
-                            container = %reference.container_name,
-                            image = %reference.image_reference,
-                            "Skipping container because registry lookup failed"
-                        );
-                        continue;
-                    }
-                };
+    let image = reference.image_reference.to_string();
+    let removed_until = ctx.state.read().unwrap().tag_removed_until(&image);
+    if let Some(until) = removed_until {
+        debug!(
+            pod = %pod_name,
+            container = %reference.container_name,
+            image = %image,
+            until = %until,
+            "Deferring container because its tag was removed upstream"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::TagRemoved,
+        );
+        return Ok(None);
+    }
 
-                info!(
-                    recent_digests = %recent_digests.join(","),
-                    "Found recent image digests"
-                );
+    let pass = ctx.state.read().unwrap().completed_cycles;
+    let cached = ctx
+        .caches
+        .digests
+        .lock()
+        .unwrap()
+        .get(&image)
+        .filter(|cached| cached.is_fresh(pass, ctx.config.caches.digest_ttl))
+        .map(|cached| (cached.digests.clone(), cached.registry_host.clone()));
+    if let Some(cached) = cached {
+        debug!(
+            image = %image,
+            "Using cached digests resolved earlier"
+        );
+        return Ok(Some(cached));
+    }
 
-                if !recent_digests.contains(&reference.digest) {
-                    info!(
-                        kind = %kind_name,
-                        resource = %resource_name,
-                        "Triggering rollout for resource"
-                    );
+    let fallback_hosts = ctx
+        .config
+        .find_registry_for_hostname(registry)
+        .map(Registry::fallback_endpoints)
+        .unwrap_or_default();
 
-                    T::patch_rollout_annotation(
-                        &api,
-                        &resource_name,
-                        ctx.config.feature_flags.enable_kubectl_annotation,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to patch {} resource {} to trigger rollout",
-                            kind_name, resource_name
-                        )
-                    })?;
-                    info!(
-                        kind = %kind_name,
-                        resource = %resource_name,
-                        "Successfully triggered rollout"
-                    );
-                    continue;
-                } else {
-                    info!(
-                        kind = %kind_name,
-                        resource = %resource_name,
-                        "Skipping resource, digest is up to date"
-                    );
-                }
-            }
-        } else {
-            info!(
+    let throttled_until = ctx
+        .rate_limits
+        .get(registry)
+        .and_then(|rate_limit| rate_limit.throttled_until(ctx.config.rate_limit_min_remaining));
+    if let Some(until) = throttled_until {
+        info!(
+            pod = %pod_name,
+            container = %reference.container_name,
+            registry = %registry,
+            until = %until,
+            "Deferring container because its registry has only few requests left in its rate limit"
+        );
+        ctx.state.write().unwrap().record_skip(
+            kind_name,
+            resource_namespace,
+            resource_name,
+            SkipReason::RateLimited,
+        );
+        return Ok(None);
+    }
+
+    let fetched = {
+        let _fetch = ctx.in_flight.track(
+            OperationKind::Registry,
+            format!("fetch digests of {}", reference.image_reference),
+        );
+        fetch_digests_with_failover(
+            &ctx.config.registry_endpoint(&reference.image_reference),
+            &fallback_hosts,
+            &registry_secret,
+            &ctx.registry_http_client(registry),
+            &ctx.caches.tokens,
+            &ctx.rate_limits,
+            &ctx.config.registry_retry,
+            &ctx.registry_requests,
+            ctx.config.feature_flags.enable_jfrog_artifactory_fallback,
+        )
+        .await
+        .context("Failed to retrieve recent digests from registry")
+    };
+    match fetched {
+        Ok(fetched) => {
+            let mut state = ctx.state.write().unwrap();
+            state.mark_registry_available(registry);
+            state.mark_tag_present(&image);
+            ctx.caches.digests.lock().unwrap().insert(
+                image,
+                CachedDigests {
+                    digests: fetched.0.clone(),
+                    registry_host: fetched.1.clone(),
+                    fetched_at: Instant::now(),
+                    pass,
+                },
+            );
+            Ok(Some(fetched))
+        }
+        Err(err) if is_tag_not_found(&err) => {
+            warn!(
+                error = %err,
                 kind = %kind_name,
                 resource = %resource_name,
-                desired_replicas = %desired_replicas,
-                actual_replicas = %actual_replicas,
-                "Skipping resource as desired and actual replicas are zero"
+                container = %reference.container_name,
+                image = %image,
+                backoff = %humantime::format_duration(ctx.config.removed_tag_backoff),
+                "Tag was removed upstream, backing off checks of the image"
+            );
+            let mut state = ctx.state.write().unwrap();
+            state.mark_tag_removed(
+                &image,
+                chrono::Duration::from_std(ctx.config.removed_tag_backoff)
+                    .unwrap_or(chrono::Duration::MAX),
+            );
+            state.record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::TagRemoved,
+            );
+            publish_digest_fetch_failure(
+                ctx,
+                kind_name,
+                resource_namespace,
+                resource_name,
+                reference,
+                &err,
+            );
+            Ok(None)
+        }
+        Err(err) if is_registry_unreachable(&err) => {
+            warn!(
+                error = %err,
+                registry = %registry,
+                cooldown = %humantime::format_duration(ctx.config.registry_cooldown),
+                "Registry is unavailable, deferring all containers using it until the cooldown expires"
             );
+            let mut state = ctx.state.write().unwrap();
+            state.mark_registry_unavailable(
+                registry,
+                chrono::Duration::from_std(ctx.config.registry_cooldown)
+                    .unwrap_or(chrono::Duration::MAX),
+            );
+            state.record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::RegistryUnavailable,
+            );
+            publish_digest_fetch_failure(
+                ctx,
+                kind_name,
+                resource_namespace,
+                resource_name,
+                reference,
+                &err,
+            );
+            Ok(None)
+        }
+        Err(err) => {
+            warn!(
+                error = %err,
+                pod = %pod_name,
+                container = %reference.container_name,
+                image = %reference.image_reference,
+                "Skipping container because registry lookup failed"
+            );
+            ctx.state.write().unwrap().record_skip(
+                kind_name,
+                resource_namespace,
+                resource_name,
+                SkipReason::RegistryLookupFailed {
+                    image: reference.image_reference.to_string(),
+                },
+            );
+            publish_digest_fetch_failure(
+                ctx,
+                kind_name,
+                resource_namespace,
+                resource_name,
+                reference,
+                &err,
+            );
+            Ok(None)
         }
     }
+}
 
-    Ok(())
+fn publish_digest_fetch_failure(
+    ctx: &ControllerContext,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    reference: &ContainerImageReference,
+    err: &anyhow::Error,
+) {
+    publish(
+        ctx,
+        workload_reference(kind_name, resource_namespace, resource_name),
+        EventType::Warning,
+        "DigestFetchFailed",
+        "FetchDigests",
+        format!(
+            "Failed to fetch digests of {} for container {}: {:#}",
+            reference.image_reference, reference.container_name, err
+        ),
+    );
+}
+
+pub(crate) fn describe_changed_container(changed: &ChangedContainer) -> String {
+    match &changed.previous_image {
+        Some(previous_image) => format!(
+            "container {} from {} to {}",
+            changed.container_name, previous_image, changed.image
+        ),
+        None => format!(
+            "container {} ({} {} -> {})",
+            changed.container_name, changed.image, changed.previous_digest, changed.new_digest
+        ),
+    }
+}
+
+fn describe_changed_containers(changed_containers: &[ChangedContainer]) -> String {
+    changed_containers
+        .iter()
+        .map(describe_changed_container)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn notify_rollout_failed(
+    ctx: &ControllerContext,
+    kind_name: &str,
+    resource_namespace: &str,
+    resource_name: &str,
+    mut details: Vec<String>,
+    err: &anyhow::Error,
+) {
+    details.push(format!("error: {:#}", err));
+    notify::send(
+        ctx,
+        Notification {
+            title: format!(
+                "Rollout of {} {}/{} failed",
+                kind_name, resource_namespace, resource_name
+            ),
+            details,
+            failed: true,
+            images: Vec::new(),
+        },
+    );
 }
 
 async fn get_associated_pod(
@@ -254,36 +2437,164 @@ fn sort_pods_by_creation_timestamp(a: &Pod, b: &Pod) -> Ordering {
     b.cmp(&a)
 }
 
-fn get_pod_container_image_references(pod: &Pod) -> anyhow::Result<Vec<ContainerImageReference>> {
-    let container_statuses = pod
-        .status
-        .as_ref()
+/// Init and sidecar (restartable init) containers are only included with `enableInitContainers`
+fn get_pod_container_image_references(
+    pod: &Pod,
+    include_init_containers: bool,
+    track_tag: Option<&str>,
+) -> anyhow::Result<Vec<ContainerImageReference>> {
+    let status = pod.status.as_ref();
+    let container_statuses = status
         .and_then(|s| s.container_statuses.as_ref())
         .context("Failed to get container status")?;
+    let init_container_statuses = match status.and_then(|s| s.init_container_statuses.as_ref()) {
+        Some(statuses) if include_init_containers => statuses.as_slice(),
+        _ => &[],
+    };
+
+    let spec = pod.spec.as_ref();
+    let spec_containers = spec
+        .map(|spec| spec.containers.as_slice())
+        .unwrap_or_default();
+    let spec_init_containers = spec
+        .and_then(|spec| spec.init_containers.as_deref())
+        .unwrap_or_default();
+    let spec_image = |containers: &'_ [Container], name: &str| -> Option<String> {
+        containers
+            .iter()
+            .find(|container| container.name == name)
+            .and_then(|container| container.image.clone())
+    };
 
-    let references: Result<Vec<_>, _> = container_statuses
+    let statuses = container_statuses
         .iter()
-        .map(|container_status| get_container_image_reference(container_status))
-        .collect();
+        .map(|status| (status, spec_containers, false))
+        .chain(
+            init_container_statuses
+                .iter()
+                .map(|status| (status, spec_init_containers, true)),
+        );
+    let mut references = Vec::new();
+    for (container_status, spec_containers, init_container) in statuses {
+        let digest = match parse_image_id_digest(&container_status.image_id) {
+            Ok(digest) => digest,
+            // init containers that have not been started yet have no image ID to compare against
+            Err(ImageIdError::Empty) if init_container => continue,
+            Err(err) => {
+                warn!(
+                    container = %container_status.name,
+                    reason = %err,
+                    "Skipping container, the digest of its running image is unknown"
+                );
+                continue;
+            }
+        };
+        let spec_image = spec_image(spec_containers, &container_status.name);
+        references.push(ContainerImageReference {
+            init_container,
+            ..get_container_image_reference(
+                container_status,
+                spec_image.as_deref(),
+                track_tag,
+                digest,
+            )?
+        });
+    }
 
-    Ok(references?)
+    Ok(references)
 }
 
+/// Container runtimes report images pinned to a digest without their tag, so the tag of pinned images is taken
+/// from the track-tag annotation or the pod spec
 fn get_container_image_reference(
     container_status: &ContainerStatus,
+    spec_image: Option<&str>,
+    track_tag: Option<&str>,
+    digest: String,
 ) -> anyhow::Result<ContainerImageReference> {
     let container_name = container_status.name.clone();
-    let image = container_status.image.clone();
-    let image_id = container_status.image_id.clone();
+    let image = match spec_image {
+        Some(spec_image) if spec_image.contains('@') => pinned_image_tag(spec_image, track_tag)
+            .with_context(|| {
+                format!(
+                    "Image {} is pinned to a digest without tag, set the annotation kube-autorollout/track-tag to the tag to follow",
+                    spec_image
+                )
+            })?,
+        _ => container_status.image.clone(),
+    };
 
     let image_reference: ImageReference =
         ImageReference::parse(&image).context("Failed to parse image reference")?;
-    let digest = image_id.split("@").collect::<Vec<&str>>()[1].to_string();
 
     Ok(ContainerImageReference {
         container_name,
         image_reference,
         digest,
+        init_container: false,
+    })
+}
+
+/// Stands in for the pods of a workload scaled to zero replicas, running the digests of the workload's last check.
+/// Containers whose image changed since are left out, None if no digests of the workload are known.
+fn remembered_pod<T: Rollout>(
+    ctx: &ControllerContext,
+    resource: &T,
+    resource_namespace: &str,
+    resource_name: &str,
+) -> Option<Pod> {
+    let spec = resource.pod_spec()?.clone();
+    let key = ControllerState::workload_key(T::kind_name(), resource_namespace, resource_name);
+    let state = ctx.state.read().unwrap();
+    let workload = state.workloads.get(&key)?;
+    let statuses = |containers: &[Container]| -> Vec<ContainerStatus> {
+        containers
+            .iter()
+            .filter_map(|container| {
+                let tracked = workload.containers.get(&container.name)?;
+                let spec_image = container.image.as_deref()?;
+                // pinned images are tracked by the tag they follow
+                let unchanged = spec_image.contains('@')
+                    || ImageReference::parse(spec_image)
+                        .is_ok_and(|image| image.to_string() == tracked.image);
+                unchanged.then(|| ContainerStatus {
+                    name: container.name.clone(),
+                    image: tracked.image.clone(),
+                    image_id: format!("{}@{}", tracked.image, tracked.running_digest),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    };
+    let container_statuses = statuses(&spec.containers);
+    if container_statuses.is_empty() {
+        return None;
+    }
+    let init_container_statuses = statuses(spec.init_containers.as_deref().unwrap_or_default());
+
+    Some(Pod {
+        metadata: ObjectMeta {
+            name: Some(resource_name.to_string()),
+            namespace: Some(resource_namespace.to_string()),
+            ..Default::default()
+        },
+        spec: Some(spec),
+        status: Some(PodStatus {
+            container_statuses: Some(container_statuses),
+            init_container_statuses: Some(init_container_statuses),
+            ..Default::default()
+        }),
+    })
+}
+
+/// Whether a container image of the pod is pinned to a digest, such images only change when the digest is re-pinned
+fn has_pinned_images(pod: &Pod) -> bool {
+    pod.spec.as_ref().is_some_and(|spec| {
+        spec.containers
+            .iter()
+            .chain(spec.init_containers.iter().flatten())
+            .filter_map(|container| container.image.as_deref())
+            .any(|image| image.contains('@'))
     })
 }
 
@@ -303,60 +2614,34 @@ fn warn_misconfigured_container_image_pull_policies(pod: &Pod) {
         });
 }
 
-fn normalize_image_registry_name(registry_name: &str) -> String {
-    let registry_name = registry_name
-        .trim_start_matches("https://")
-        .trim_start_matches("http://");
-
-    //docker.io is internally wrapped to registry-1.docker.io
-    if registry_name.eq("docker.io") {
-        return format!("*.{}", registry_name);
-    }
-    registry_name.to_string()
-}
-
 fn find_matching_image_pull_secret(
-    image_pull_secrets: &Vec<DockerConfig>,
+    image_pull_secrets: &[DockerConfig],
     container_image_reference: &ContainerImageReference,
 ) -> anyhow::Result<RegistrySecret> {
-    let normalized_pod_registry_name =
-        normalize_image_registry_name(&container_image_reference.image_reference.registry);
-    for image_pull_secret in image_pull_secrets {
-        for auth in &image_pull_secret.auths {
-            let pull_secret_hostname_pattern = normalize_image_registry_name(auth.0);
-
-            //As opposed to Docker's config json, the Kubernetes .dockerconfigjson can include * wildcards in the keys, so it needs to be glob'ed: [https://kubernetes.io/docs/concepts/containers/images/#config-json](https://kubernetes.io/docs/concepts/containers/images/#config-json)
-            let glob = Glob::new(&pull_secret_hostname_pattern)
-                .with_context(|| {
-                    format!("invalid hostname pattern {}", pull_secret_hostname_pattern)
-                })?
-                .compile_matcher();
-
-            if glob.is_match(&normalized_pod_registry_name) {
-                let registry_secret = RegistrySecret::ImagePullSecret {
-                    mount_path: String::new(),
-                    docker_config: image_pull_secret.clone(),
-                };
-
-                info!(
-                    registry = %normalized_pod_registry_name,
-                    "Found matching image pull secret for pod registry"
-                );
-
-                return Ok(registry_secret);
-            }
-        }
-    }
-    bail!("No matching image pull secret found");
+    let registry = &container_image_reference.image_reference.registry;
+    let image_pull_secret = image_pull_secrets
+        .iter()
+        .find(|image_pull_secret| image_pull_secret.find_auth(registry).is_some())
+        .context("No matching image pull secret found")?;
+    info!(
+        registry = %hostname::normalize(registry),
+        "Found matching image pull secret for pod registry"
+    );
+    Ok(RegistrySecret::ImagePullSecret {
+        mount_path: String::new(),
+        docker_config: image_pull_secret.clone(),
+    })
 }
 
 async fn collect_image_pull_secrets(
+    ctx: &ControllerContext,
     secrets: &Api<Secret>,
+    namespace: &str,
     image_pull_secrets: &Vec<String>,
 ) -> anyhow::Result<Vec<DockerConfig>> {
     let futures_vec = image_pull_secrets
         .iter()
-        .map(|name| get_image_pull_secret_content(secrets, name))
+        .map(|name| get_image_pull_secret_content(ctx, secrets, namespace, name))
         .collect::<Vec<_>>();
 
     let configs: Vec<DockerConfig> = try_join_all(futures_vec).await?;
@@ -364,10 +2649,113 @@ async fn collect_image_pull_secrets(
     Ok(configs)
 }
 
+/// Collects the image pull secrets attached to the pod's ServiceAccount, which the kubelet uses in addition to the
+/// ones of the pod spec. As these are not part of the workload, missing permissions or secrets are only logged.
+async fn collect_service_account_image_pull_secrets(
+    ctx: &ControllerContext,
+    secrets: &Api<Secret>,
+    namespace: &str,
+    pod: &Pod,
+    pod_image_pull_secrets: &[String],
+) -> Vec<DockerConfig> {
+    let service_account_name = pod
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.service_account_name.as_deref())
+        .unwrap_or("default");
+    let service_accounts: Api<ServiceAccount> = Api::namespaced(ctx.kube_client.clone(), namespace);
+    let result = service_accounts.get_opt(service_account_name).await;
+    ctx.state
+        .write()
+        .unwrap()
+        .record_kube_api_request(result.is_ok());
+    let service_account = match result {
+        Ok(Some(service_account)) => service_account,
+        Ok(None) => return Vec::new(),
+        Err(err) => {
+            debug!(
+                error = ?err,
+                service_account = %service_account_name,
+                namespace = %namespace,
+                "Failed to get ServiceAccount for its image pull secrets"
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut configs = Vec::new();
+    for name in service_account_image_pull_secrets(&service_account, pod_image_pull_secrets) {
+        match get_image_pull_secret_content(ctx, secrets, namespace, &name).await {
+            Ok(config) => configs.push(config),
+            Err(err) => warn!(
+                error = %format!("{:#}", err),
+                service_account = %service_account_name,
+                namespace = %namespace,
+                "Skipping image pull secret of ServiceAccount"
+            ),
+        }
+    }
+    configs
+}
+
+/// Names of the ServiceAccount's image pull secrets that are not already referenced by the pod spec
+fn service_account_image_pull_secrets(
+    service_account: &ServiceAccount,
+    pod_image_pull_secrets: &[String],
+) -> Vec<String> {
+    service_account
+        .image_pull_secrets
+        .iter()
+        .flatten()
+        .map(|secret| secret.name.clone())
+        .filter(|name| !name.is_empty() && !pod_image_pull_secrets.contains(name))
+        .collect()
+}
+
+/// Decodes the image pull secret, reusing the decoded config of earlier checks. Once `caches.secretTtl` expired,
+/// only the metadata of the Secret is read to confirm its resourceVersion did not change.
 async fn get_image_pull_secret_content(
+    ctx: &ControllerContext,
     secrets: &Api<Secret>,
+    namespace: &str,
     secret_name: &str,
 ) -> anyhow::Result<DockerConfig> {
+    let key = format!("{}/{}", namespace, secret_name);
+    let ttl = ctx.config.caches.secret_ttl;
+    let cached = ctx.caches.secrets.lock().unwrap().get(&key).map(|cached| {
+        (
+            cached.docker_config.clone(),
+            cached.resource_version.clone(),
+            cached.is_fresh(ttl),
+        )
+    });
+    if let Some((docker_config, resource_version, fresh)) = cached {
+        if fresh {
+            return Ok(docker_config);
+        }
+        let unchanged = resource_version.is_some()
+            && secrets
+                .get_metadata(secret_name)
+                .await
+                .is_ok_and(|secret| secret.metadata.resource_version == resource_version);
+        if unchanged {
+            debug!(
+                secret = %secret_name,
+                namespace = %namespace,
+                "Reusing decoded secret, its resourceVersion is unchanged"
+            );
+            ctx.caches.secrets.lock().unwrap().insert(
+                key,
+                CachedSecret {
+                    docker_config: docker_config.clone(),
+                    resource_version,
+                    validated_at: Instant::now(),
+                },
+            );
+            return Ok(docker_config);
+        }
+    }
+
     debug!(
         secret = %secret_name,
         "Getting secret content"
@@ -377,6 +2765,7 @@ async fn get_image_pull_secret_content(
         .get(secret_name)
         .await
         .with_context(|| format!("Failed to retrieve secret {}", secret_name))?;
+    let resource_version = secret.metadata.resource_version.clone();
 
     let data = secret
         .data
@@ -403,9 +2792,35 @@ async fn get_image_pull_secret_content(
             )
         })?;
 
+    ctx.caches.secrets.lock().unwrap().insert(
+        key,
+        CachedSecret {
+            docker_config: docker_config.clone(),
+            resource_version,
+            validated_at: Instant::now(),
+        },
+    );
+
     Ok(docker_config)
 }
 
+/// Finds the credentials of the container's registry and exchanges cloud provider credentials for registry ones
+async fn find_registry_secret(
+    ctx: &ControllerContext,
+    image_pull_secrets: &[DockerConfig],
+    reference: &ContainerImageReference,
+) -> anyhow::Result<RegistrySecret> {
+    let registry_secret = find_matching_image_pull_secret(image_pull_secrets, reference)
+        .or_else(|_| get_registry_secret_from_config(&ctx.config, reference))?;
+    resolve_registry_secret(
+        &ctx.http_client(),
+        &ctx.caches.credentials,
+        &reference.image_reference.registry,
+        registry_secret,
+    )
+    .await
+}
+
 fn get_registry_secret_from_config(
     config: &Config,
     reference: &ContainerImageReference,
@@ -419,7 +2834,260 @@ fn get_registry_secret_from_config(
                 registry_name
             )
         })?
-        .secret
-        .clone();
+        .auth_secret();
     Ok(secret)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Policy};
+    use crate::oci_registry;
+    use crate::registry_requests::RegistryRequestLog;
+    use crate::state::ControllerCaches;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::sync::RwLock;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
+
+    /// Context whose Kubernetes API server can not be reached, so that any rollout passing the gates fails
+    fn unreachable_cluster_context(config: Config) -> ControllerContext {
+        let kube_config = kube::Config::new("http://127.0.0.1:1".parse().unwrap());
+        let http_client = oci_registry::create_client(&config, &[]).unwrap();
+        ControllerContext {
+            kube_client: Client::try_from(kube_config).unwrap(),
+            caches: Arc::new(ControllerCaches::new(&config.caches)),
+            registry_requests: Arc::new(RegistryRequestLog::new(10)),
+            latest_config: Arc::new(RwLock::new(Arc::new(config.clone()))),
+            config: Arc::new(config),
+            http_client: Arc::new(RwLock::new(http_client)),
+            ca_bundles: Arc::default(),
+            state: Arc::default(),
+            events: None,
+            run_lock: Arc::default(),
+            leader: Arc::new(AtomicBool::new(true)),
+            rate_limits: Arc::default(),
+            notification_batch: Arc::default(),
+            in_flight: Arc::default(),
+            reported_errors: Arc::default(),
+            crash: CancellationToken::new(),
+        }
+    }
+
+    fn config() -> Config {
+        serde_yaml_ng::from_str("webserver:\n  port: 8080\nregistries: []\n").unwrap()
+    }
+
+    fn tag_update() -> ChangedContainer {
+        ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:1.3.0".to_string(),
+            previous_digest: "sha256:old".to_string(),
+            new_digest: String::new(),
+            init_container: false,
+            previous_image: Some("ghcr.io/org/app:1.2.0".to_string()),
+        }
+    }
+
+    fn deployment(annotations: &[(&str, String)]) -> Deployment {
+        let mut deployment = Deployment::default();
+        deployment.metadata.name = Some("app".to_string());
+        deployment.metadata.namespace = Some("default".to_string());
+        deployment.metadata.annotations = Some(
+            annotations
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        );
+        deployment
+    }
+
+    async fn roll_out_tag_update(
+        ctx: &ControllerContext,
+        resource: &Deployment,
+    ) -> anyhow::Result<()> {
+        let api: Api<Deployment> = Api::namespaced(ctx.kube_client.clone(), "default");
+        roll_out_changes(
+            ctx,
+            &api,
+            resource,
+            "default",
+            vec![tag_update()],
+            RestartTrigger::Annotation,
+            &GroupTriggers::default(),
+        )
+        .await
+    }
+
+    fn skip_reason(ctx: &ControllerContext) -> Option<SkipReason> {
+        let state = ctx.state.read().unwrap();
+        state
+            .workloads
+            .get("Deployment/default/app")?
+            .skipped
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn tag_updates_wait_for_approval() {
+        let ctx = unreachable_cluster_context(config());
+        let pending = PendingRollout::new("Deployment", "default", "app", &[tag_update()]);
+        let mut annotations = vec![
+            ("kube-autorollout/require-approval", "true".to_string()),
+            (
+                "kube-autorollout/pending-rollout",
+                serde_json::to_string(&pending).unwrap(),
+            ),
+        ];
+
+        roll_out_tag_update(&ctx, &deployment(&annotations))
+            .await
+            .unwrap();
+        assert_eq!(
+            skip_reason(&ctx),
+            Some(SkipReason::AwaitingApproval {
+                id: pending.id.clone()
+            })
+        );
+        assert!(
+            ctx.state
+                .read()
+                .unwrap()
+                .pending_approvals
+                .contains_key(&pending.id)
+        );
+
+        // once approved, the tag update passes the gates and is patched
+        annotations.push(("kube-autorollout/approve", "true".to_string()));
+        let err = roll_out_tag_update(&ctx, &deployment(&annotations))
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to patch Deployment resource app"));
+    }
+
+    #[tokio::test]
+    async fn tag_updates_denied_by_policy_are_skipped() {
+        let app = Router::new().route(
+            "/v1/data/autorollout/allow",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                let image = body["input"]["changedContainers"][0]["image"]
+                    .as_str()
+                    .unwrap_or_default();
+                let reason = format!("{} is frozen", image);
+                Json(json!({ "result": { "allow": false, "reason": reason } }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut config = config();
+        config.policy = Some(Policy {
+            opa_url: format!("http://{}/v1/data/autorollout/allow", addr),
+            timeout: Duration::from_secs(5),
+            fail_open: false,
+        });
+        let ctx = unreachable_cluster_context(config);
+
+        roll_out_tag_update(&ctx, &deployment(&[])).await.unwrap();
+        assert_eq!(
+            skip_reason(&ctx),
+            Some(SkipReason::PolicyDenied {
+                message: "ghcr.io/org/app:1.3.0 is frozen".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn tag_policies_are_followed_in_intent_mode() {
+        let app = Router::new().route(
+            "/v2/org/app/tags/list",
+            get(|| async { Json(json!({ "name": "org/app", "tags": ["1.2.0", "1.3.0"] })) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut config: Config = serde_yaml_ng::from_str(
+            r#"
+        webserver:
+          port: 8080
+        rolloutMode: intent
+        registries:
+          - hostnamePattern: "127.0.0.1:*"
+            secret:
+              type: None
+        "#,
+        )
+        .unwrap();
+        config.setup_glob_set().unwrap();
+        let ctx = unreachable_cluster_context(config);
+        let reference = ContainerImageReference {
+            container_name: "app".to_string(),
+            image_reference: ImageReference::parse(&format!("{}/org/app:1.2.0", addr)).unwrap(),
+            digest: "sha256:old".to_string(),
+            init_container: false,
+        };
+
+        let tag_updates = find_tag_updates(
+            &ctx,
+            "Deployment",
+            "default",
+            "app",
+            "minor",
+            &[reference],
+            &[],
+        )
+        .await;
+        assert_eq!(tag_updates.len(), 1);
+        assert_eq!(tag_updates[0].image, format!("{}/org/app:1.3.0", addr));
+        assert_eq!(
+            tag_updates[0].previous_image,
+            Some(format!("{}/org/app:1.2.0", addr))
+        );
+
+        let err = roll_out_tag_update(&ctx, &deployment(&[]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("RolloutIntent"));
+    }
+
+    #[tokio::test]
+    async fn checks_on_request_evict_only_the_digests_of_the_selected_workload() {
+        let ctx = unreachable_cluster_context(config());
+        for image in [
+            "ghcr.io/org/app:latest",
+            "ghcr.io/org/app:1.2.0",
+            "ghcr.io/org/other:latest",
+        ] {
+            ctx.caches.digests.lock().unwrap().insert(
+                image.to_string(),
+                CachedDigests {
+                    digests: vec!["sha256:new".to_string()],
+                    registry_host: "ghcr.io".to_string(),
+                    fetched_at: Instant::now(),
+                    pass: 0,
+                },
+            );
+        }
+        let mut resource = deployment(&[]);
+        resource.spec = serde_json::from_value(json!({
+            "selector": {},
+            "template": { "spec": { "containers": [{ "name": "app", "image": "ghcr.io/org/app:latest" }] } },
+        }))
+        .unwrap();
+
+        evict_cached_digests(&ctx, &resource);
+        let mut digests = ctx.caches.digests.lock().unwrap();
+        assert!(digests.get(&"ghcr.io/org/app:latest".to_string()).is_none());
+        assert!(digests.get(&"ghcr.io/org/app:1.2.0".to_string()).is_none());
+        assert!(
+            digests
+                .get(&"ghcr.io/org/other:latest".to_string())
+                .is_some()
+        );
+    }
+}
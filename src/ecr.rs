@@ -0,0 +1,464 @@
+use crate::secret_string::SecretString;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::header::AUTHORIZATION;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use tracing::{debug, info};
+
+static ECR_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+static ECR_GET_AUTHORIZATION_TOKEN_TARGET: &str =
+    "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken";
+static STS_API_VERSION: &str = "2011-06-15";
+static STS_ROLE_SESSION_NAME: &str = "kube-autorollout";
+static INSTANCE_METADATA_ENDPOINT: &str = "http://169.254.169.254";
+const INSTANCE_METADATA_TOKEN_TTL_SECONDS: u32 = 300;
+
+/// ECR authorization token, already in the `base64(AWS:<password>)` form expected for basic authentication
+pub struct EcrToken {
+    pub authorization: SecretString,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// AWS credentials of the controller, resolved from the environment, IRSA, EKS Pod Identity or the instance profile
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: SecretString,
+    session_token: Option<SecretString>,
+}
+
+/// Credentials as served by the EKS Pod Identity agent and the EC2 instance metadata service
+#[derive(Deserialize)]
+struct ProviderCredentials {
+    #[serde(rename = "AccessKeyId")]
+    access_key_id: String,
+    #[serde(rename = "SecretAccessKey")]
+    secret_access_key: SecretString,
+    #[serde(rename = "Token")]
+    token: Option<SecretString>,
+}
+
+impl From<ProviderCredentials> for AwsCredentials {
+    fn from(credentials: ProviderCredentials) -> Self {
+        AwsCredentials {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: credentials.token,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GetAuthorizationTokenResponse {
+    #[serde(rename = "authorizationData")]
+    authorization_data: Vec<AuthorizationData>,
+}
+
+#[derive(Deserialize)]
+struct AuthorizationData {
+    #[serde(rename = "authorizationToken")]
+    authorization_token: SecretString,
+    /// Seconds since the epoch
+    #[serde(rename = "expiresAt")]
+    expires_at: f64,
+}
+
+/// Returns the region of an ECR registry hostname such as `123456789012.dkr.ecr.eu-central-1.amazonaws.com`
+pub fn region_from_hostname(hostname: &str) -> Option<&str> {
+    let mut labels = hostname.split('.');
+    let _registry_id = labels.next()?;
+    if labels.next()? != "dkr" || !labels.next()?.starts_with("ecr") {
+        return None;
+    }
+    let region = labels.next()?;
+    (labels.next()? == "amazonaws").then_some(region)
+}
+
+fn aws_domain(region: &str) -> &'static str {
+    match region.starts_with("cn-") {
+        true => "amazonaws.com.cn",
+        false => "amazonaws.com",
+    }
+}
+
+/// Exchanges the controller's AWS credentials for an ECR authorization token through `GetAuthorizationToken`.
+/// The token grants access to all registries of the region the credentials are allowed to pull from.
+pub async fn get_authorization_token(client: &Client, region: &str) -> Result<EcrToken> {
+    let credentials = load_credentials(client, region)
+        .await
+        .context("Failed to load AWS credentials")?;
+
+    let host = format!("api.ecr.{}.{}", region, aws_domain(region));
+    let mut headers = BTreeMap::from([
+        ("content-type", ECR_CONTENT_TYPE.to_string()),
+        ("host", host.clone()),
+        (
+            "x-amz-date",
+            Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+        ),
+        (
+            "x-amz-target",
+            ECR_GET_AUTHORIZATION_TOKEN_TARGET.to_string(),
+        ),
+    ]);
+    if let Some(session_token) = &credentials.session_token {
+        headers.insert(
+            "x-amz-security-token",
+            session_token.expose_secret().to_string(),
+        );
+    }
+    let request = SignableRequest {
+        method: "POST",
+        path: "/",
+        query: "",
+        headers,
+        body: b"{}",
+    };
+    let authorization = authorization_header(&credentials, region, "ecr", &request);
+
+    let mut builder = client
+        .post(format!("https://{}/", host))
+        .header(AUTHORIZATION, authorization)
+        .body(request.body);
+    for (name, value) in request.headers.iter().filter(|(name, _)| **name != "host") {
+        builder = builder.header(*name, value);
+    }
+    let response = builder
+        .send()
+        .await
+        .with_context(|| format!("Failed to request ECR authorization token from {}", host))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "ECR GetAuthorizationToken in {} failed with status {}: {}",
+            region,
+            status,
+            body
+        );
+    }
+
+    let authorization_data = response
+        .json::<GetAuthorizationTokenResponse>()
+        .await
+        .context("Failed to parse ECR GetAuthorizationToken response")?
+        .authorization_data
+        .into_iter()
+        .next()
+        .context("ECR GetAuthorizationToken response contains no authorization data")?;
+    let expires_at = Utc
+        .timestamp_opt(authorization_data.expires_at as i64, 0)
+        .single()
+        .context("ECR authorization token has an invalid expiry")?;
+    info!(
+        region = %region,
+        expires_at = %expires_at,
+        "Obtained ECR authorization token"
+    );
+    Ok(EcrToken {
+        authorization: authorization_data.authorization_token,
+        expires_at,
+    })
+}
+
+/// Resolves credentials in the order of the AWS SDKs: static environment variables, IRSA web identity, EKS Pod
+/// Identity and finally the EC2 instance profile
+async fn load_credentials(client: &Client, region: &str) -> Result<AwsCredentials> {
+    if let (Ok(access_key_id), Ok(secret_access_key)) = (
+        env::var("AWS_ACCESS_KEY_ID"),
+        env::var("AWS_SECRET_ACCESS_KEY"),
+    ) {
+        debug!("Using AWS credentials from the environment");
+        return Ok(AwsCredentials {
+            access_key_id,
+            secret_access_key: SecretString::new(secret_access_key),
+            session_token: env::var("AWS_SESSION_TOKEN").ok().map(SecretString::new),
+        });
+    }
+    if let (Ok(token_file), Ok(role_arn)) = (
+        env::var("AWS_WEB_IDENTITY_TOKEN_FILE"),
+        env::var("AWS_ROLE_ARN"),
+    ) {
+        debug!(role_arn = %role_arn, "Assuming AWS role with web identity");
+        return assume_role_with_web_identity(client, region, &token_file, &role_arn).await;
+    }
+    if let Ok(uri) = env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI") {
+        debug!(uri = %uri, "Using AWS container credentials");
+        return container_credentials(client, &uri).await;
+    }
+    debug!("Using AWS credentials of the instance profile");
+    instance_profile_credentials(client).await
+}
+
+async fn assume_role_with_web_identity(
+    client: &Client,
+    region: &str,
+    token_file: &str,
+    role_arn: &str,
+) -> Result<AwsCredentials> {
+    let web_identity_token = tokio::fs::read_to_string(token_file)
+        .await
+        .with_context(|| format!("Failed to read web identity token file {}", token_file))?;
+    let url = format!("https://sts.{}.{}/", region, aws_domain(region));
+    let response = client
+        .post(&url)
+        .form(&[
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", STS_API_VERSION),
+            ("RoleArn", role_arn),
+            ("RoleSessionName", STS_ROLE_SESSION_NAME),
+            ("WebIdentityToken", web_identity_token.trim()),
+        ])
+        .send()
+        .await
+        .with_context(|| format!("Failed to assume role {} with web identity", role_arn))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!(
+            "STS AssumeRoleWithWebIdentity for {} failed with status {}: {}",
+            role_arn,
+            status,
+            body
+        );
+    }
+
+    let element = |name: &str| {
+        xml_element(&body, name).with_context(|| {
+            format!(
+                "STS AssumeRoleWithWebIdentity response contains no {}",
+                name
+            )
+        })
+    };
+    Ok(AwsCredentials {
+        access_key_id: element("AccessKeyId")?.to_string(),
+        secret_access_key: SecretString::new(element("SecretAccessKey")?.to_string()),
+        session_token: Some(SecretString::new(element("SessionToken")?.to_string())),
+    })
+}
+
+async fn container_credentials(client: &Client, uri: &str) -> Result<AwsCredentials> {
+    let authorization = match env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE") {
+        Ok(token_file) => Some(tokio::fs::read_to_string(&token_file).await.with_context(
+            || {
+                format!(
+                    "Failed to read container authorization token {}",
+                    token_file
+                )
+            },
+        )?),
+        Err(_) => env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN").ok(),
+    };
+    let mut request = client.get(uri);
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization.trim());
+    }
+    let credentials = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to get AWS container credentials from {}", uri))?
+        .json::<ProviderCredentials>()
+        .await
+        .context("Failed to parse AWS container credentials")?;
+    Ok(credentials.into())
+}
+
+/// Reads the instance profile credentials through IMDSv2
+async fn instance_profile_credentials(client: &Client) -> Result<AwsCredentials> {
+    let session_token = client
+        .put(format!("{}/latest/api/token", INSTANCE_METADATA_ENDPOINT))
+        .header(
+            "x-aws-ec2-metadata-token-ttl-seconds",
+            INSTANCE_METADATA_TOKEN_TTL_SECONDS,
+        )
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("Failed to get instance metadata session token")?
+        .text()
+        .await
+        .context("Failed to read instance metadata session token")?;
+    let credentials_url = format!(
+        "{}/latest/meta-data/iam/security-credentials/",
+        INSTANCE_METADATA_ENDPOINT
+    );
+    let get = |url: String| {
+        client
+            .get(url)
+            .header("x-aws-ec2-metadata-token", &session_token)
+            .send()
+    };
+
+    let roles = get(credentials_url.clone())
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("Failed to get the instance profile role")?
+        .text()
+        .await
+        .context("Failed to read the instance profile role")?;
+    let role = roles
+        .lines()
+        .next()
+        .filter(|role| !role.is_empty())
+        .context("Instance has no instance profile role")?;
+    let credentials = get(format!("{}{}", credentials_url, role))
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| {
+            format!(
+                "Failed to get credentials of instance profile role {}",
+                role
+            )
+        })?
+        .json::<ProviderCredentials>()
+        .await
+        .context("Failed to parse instance profile credentials")?;
+    Ok(credentials.into())
+}
+
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
+
+/// Request signed with AWS Signature Version 4, all headers are signed and must include `host` and `x-amz-date`
+struct SignableRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    query: &'a str,
+    /// Lowercase header names
+    headers: BTreeMap<&'a str, String>,
+    body: &'static [u8],
+}
+
+fn authorization_header(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    request: &SignableRequest,
+) -> String {
+    let amz_date = request
+        .headers
+        .get("x-amz-date")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let date = amz_date.get(..8).unwrap_or_default();
+    let canonical_headers: String = request
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = request
+        .headers
+        .keys()
+        .copied()
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method,
+        request.path,
+        request.query,
+        canonical_headers,
+        signed_headers,
+        sha256_hex(request.body)
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let secret = format!("AWS4{}", credentials.secret_access_key.expose_secret());
+    let signing_key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(secret.into_bytes(), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    ring::hmac::sign(&key, data).as_ref().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_requests_with_signature_version_4() {
+        // example request of the AWS Signature Version 4 documentation
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: SecretString::new(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            ),
+            session_token: None,
+        };
+        let request = SignableRequest {
+            method: "GET",
+            path: "/",
+            query: "Action=ListUsers&Version=2010-05-08",
+            headers: BTreeMap::from([
+                (
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+                ),
+                ("host", "iam.amazonaws.com".to_string()),
+                ("x-amz-date", "20150830T123600Z".to_string()),
+            ]),
+            body: b"",
+        };
+
+        assert_eq!(
+            authorization_header(&credentials, "us-east-1", "iam", &request),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn parses_region_and_sts_credentials() {
+        assert_eq!(
+            region_from_hostname("123456789012.dkr.ecr.eu-central-1.amazonaws.com"),
+            Some("eu-central-1")
+        );
+        assert_eq!(
+            region_from_hostname("123456789012.dkr.ecr-fips.us-east-1.amazonaws.com"),
+            Some("us-east-1")
+        );
+        assert_eq!(
+            region_from_hostname("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn"),
+            Some("cn-north-1")
+        );
+        assert_eq!(region_from_hostname("ghcr.io"), None);
+
+        let response = "<AssumeRoleWithWebIdentityResult><Credentials>\
+            <AccessKeyId>ASIAEXAMPLE</AccessKeyId><SessionToken>session</SessionToken>\
+            </Credentials></AssumeRoleWithWebIdentityResult>";
+        assert_eq!(xml_element(response, "AccessKeyId"), Some("ASIAEXAMPLE"));
+        assert_eq!(xml_element(response, "SecretAccessKey"), None);
+    }
+}
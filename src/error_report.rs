@@ -0,0 +1,299 @@
+use crate::config::ErrorReportTarget;
+use crate::state::{ControllerContext, ControllerState};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Error of a failed workload check, reported along with the workload and the registries of its images
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub registries: Vec<String>,
+    /// Messages of the error and its causes, the outermost first
+    pub chain: Vec<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl ErrorReport {
+    pub fn new(
+        kind: &str,
+        namespace: &str,
+        name: &str,
+        registries: Vec<String>,
+        err: &anyhow::Error,
+    ) -> Self {
+        ErrorReport {
+            kind: kind.to_string(),
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            registries,
+            chain: err.chain().map(|cause| cause.to_string()).collect(),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Identifies repeated reports of the same error of a workload
+    fn fingerprint(&self) -> String {
+        format!(
+            "{}/{}/{}: {}",
+            self.kind,
+            self.namespace,
+            self.name,
+            self.chain.join(": ")
+        )
+    }
+
+    fn webhook_payload(&self) -> Value {
+        json!({
+            "kind": self.kind,
+            "namespace": self.namespace,
+            "name": self.name,
+            "registries": self.registries,
+            "error": self.chain.join(": "),
+            "chain": self.chain,
+            "occurredAt": self.occurred_at,
+        })
+    }
+
+    /// Event as accepted by Sentry's store endpoint, grouped into one issue per fingerprint
+    fn sentry_payload(&self) -> Value {
+        json!({
+            "timestamp": self.occurred_at.to_rfc3339(),
+            "level": "error",
+            "logger": "kube-autorollout",
+            "platform": "other",
+            "release": concat!("kube-autorollout@", env!("CARGO_PKG_VERSION")),
+            "message": {
+                "formatted": format!(
+                    "Failed to check {} {}/{}: {}",
+                    self.kind,
+                    self.namespace,
+                    self.name,
+                    self.chain.join(": ")
+                ),
+            },
+            "fingerprint": [self.fingerprint()],
+            "tags": {
+                "kind": self.kind,
+                "namespace": self.namespace,
+                "workload": self.name,
+                "registry": self.registries.join(","),
+            },
+            // Sentry lists the causes first and the outermost error last
+            "exception": {
+                "values": self
+                    .chain
+                    .iter()
+                    .rev()
+                    .map(|message| json!({ "type": "Error", "value": message }))
+                    .collect::<Vec<_>>(),
+            },
+        })
+    }
+}
+
+/// Sentry DSN of the form `https://<public key>@<host>/<project id>`
+#[derive(Debug, PartialEq)]
+pub struct SentryDsn {
+    store_url: Url,
+    public_key: String,
+}
+
+impl SentryDsn {
+    pub fn parse(dsn: &str) -> Result<Self> {
+        let mut url = Url::parse(dsn).context("DSN is not a URL")?;
+        let public_key = url.username().to_string();
+        if public_key.is_empty() {
+            bail!("DSN lacks the public key");
+        }
+        let mut segments: Vec<String> = url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|segment| !segment.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let project_id = segments.pop().context("DSN lacks the project id")?;
+        let prefix: String = segments
+            .iter()
+            .map(|segment| format!("/{}", segment))
+            .collect();
+        url.set_path(&format!("{}/api/{}/store/", prefix, project_id));
+        // the URL was parsed with a username, so it can hold credentials
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+        Ok(SentryDsn {
+            store_url: url,
+            public_key,
+        })
+    }
+
+    fn auth_header(&self) -> String {
+        format!(
+            "Sentry sentry_version=7, sentry_key={}, sentry_client=kube-autorollout/{}",
+            self.public_key,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+}
+
+/// Errors reported recently, by fingerprint
+#[derive(Default)]
+pub struct ReportedErrors(Mutex<BTreeMap<String, DateTime<Utc>>>);
+
+impl ReportedErrors {
+    /// Whether an error is due to be reported, i.e. it was not reported within the window. The error is recorded as
+    /// reported, errors reported before the window are forgotten.
+    fn is_due(&self, fingerprint: &str, window: Duration, now: DateTime<Utc>) -> bool {
+        let window = chrono::Duration::from_std(window).unwrap_or(chrono::Duration::MAX);
+        let mut reported = self.0.lock().unwrap();
+        reported.retain(|_, reported_at| now.signed_duration_since(*reported_at) < window);
+        if reported.contains_key(fingerprint) {
+            return false;
+        }
+        reported.insert(fingerprint.to_string(), now);
+        true
+    }
+}
+
+/// Reports a failed workload check to the configured error-reporting service. Reports are sent in the background and
+/// failures are only logged, an error already reported within the deduplication window is not reported again.
+pub fn report(ctx: &ControllerContext, report: ErrorReport) {
+    let Some(error_reporting) = &ctx.config.error_reporting else {
+        return;
+    };
+    if !ctx.reported_errors.is_due(
+        &report.fingerprint(),
+        error_reporting.deduplication_window,
+        report.occurred_at,
+    ) {
+        debug!(
+            workload = %ControllerState::workload_key(&report.kind, &report.namespace, &report.name),
+            "Not reporting error, it was reported within the deduplication window"
+        );
+        return;
+    }
+    let client = ctx.http_client();
+    let target = error_reporting.target.clone();
+    tokio::spawn(async move {
+        if let Err(err) = send(&client, &target, &report).await {
+            warn!(
+                error = ?err,
+                kind = %report.kind,
+                namespace = %report.namespace,
+                resource = %report.name,
+                "Failed to report error"
+            );
+        }
+    });
+}
+
+async fn send(client: &Client, target: &ErrorReportTarget, report: &ErrorReport) -> Result<()> {
+    let request = match target {
+        ErrorReportTarget::Sentry { dsn } => {
+            let dsn = SentryDsn::parse(dsn.expose_secret())?;
+            client
+                .post(dsn.store_url.clone())
+                .header("X-Sentry-Auth", dsn.auth_header())
+                .json(&report.sentry_payload())
+        }
+        ErrorReportTarget::Webhook { url } => client
+            .post(url.expose_secret())
+            .json(&report.webhook_payload()),
+    };
+    let response = request
+        .send()
+        .await
+        .context("Failed to send error report")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "Error reporting service answered with status {}: {}",
+            status,
+            body
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentry_dsn_is_parsed_into_store_url_and_key() {
+        let dsn = SentryDsn::parse("https://abc123@o42.ingest.sentry.io/4711").unwrap();
+        assert_eq!(
+            dsn.store_url.as_str(),
+            "https://o42.ingest.sentry.io/api/4711/store/"
+        );
+        assert_eq!(dsn.public_key, "abc123");
+
+        let self_hosted = SentryDsn::parse("http://key@sentry.example.com:9000/sentry/7").unwrap();
+        assert_eq!(
+            self_hosted.store_url.as_str(),
+            "http://sentry.example.com:9000/sentry/api/7/store/"
+        );
+
+        assert!(SentryDsn::parse("https://o42.ingest.sentry.io/4711").is_err());
+        assert!(SentryDsn::parse("https://abc123@o42.ingest.sentry.io/").is_err());
+    }
+
+    #[test]
+    fn repeated_errors_are_reported_once_per_window() {
+        let reported = ReportedErrors::default();
+        let window = Duration::from_secs(3600);
+        let now = Utc::now();
+
+        assert!(reported.is_due("Deployment/default/app: timeout", window, now));
+        assert!(!reported.is_due(
+            "Deployment/default/app: timeout",
+            window,
+            now + chrono::Duration::minutes(30)
+        ));
+        assert!(reported.is_due("Deployment/default/web: timeout", window, now));
+        assert!(reported.is_due(
+            "Deployment/default/app: timeout",
+            window,
+            now + chrono::Duration::minutes(61)
+        ));
+    }
+
+    #[test]
+    fn sentry_payload_lists_causes_first() {
+        let err = anyhow::anyhow!("connection refused")
+            .context("Failed to retrieve recent digests from registry");
+        let report = ErrorReport::new(
+            "Deployment",
+            "default",
+            "app",
+            vec!["ghcr.io".to_string()],
+            &err,
+        );
+
+        let payload = report.sentry_payload();
+        assert_eq!(
+            payload["exception"]["values"][0]["value"],
+            "connection refused"
+        );
+        assert_eq!(
+            payload["exception"]["values"][1]["value"],
+            "Failed to retrieve recent digests from registry"
+        );
+        assert_eq!(payload["tags"]["registry"], "ghcr.io");
+        assert_eq!(
+            report.webhook_payload()["error"],
+            "Failed to retrieve recent digests from registry: connection refused"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::state::ControllerContext;
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::runtime::events::{Event, EventType, Recorder};
+use tracing::warn;
+
+static KUBE_AUTOROLLOUT_REPORTER: &str = "kube-autorollout";
+/// Upper bound of the event note enforced by the API server
+const MAX_NOTE_BYTES: usize = 1024;
+
+pub fn create_recorder(client: kube::Client) -> Recorder {
+    Recorder::new(client, KUBE_AUTOROLLOUT_REPORTER.into())
+}
+
+/// Reference to a workload of one of the supported kinds, all of which are served by the apps/v1 API
+pub fn workload_reference(kind: &str, namespace: &str, name: &str) -> ObjectReference {
+    ObjectReference {
+        api_version: Some("apps/v1".to_string()),
+        kind: Some(kind.to_string()),
+        namespace: Some(namespace.to_string()),
+        name: Some(name.to_string()),
+        ..Default::default()
+    }
+}
+
+fn truncate_note(mut note: String) -> String {
+    if note.len() > MAX_NOTE_BYTES {
+        let mut end = MAX_NOTE_BYTES - 3;
+        while !note.is_char_boundary(end) {
+            end -= 1;
+        }
+        note.truncate(end);
+        note.push_str("...");
+    }
+    note
+}
+
+/// Publishes an event on the workload, shown by `kubectl describe`. Events are published in the background and
+/// failures are only logged, so they never hold up or fail the reconciliation.
+pub fn publish(
+    ctx: &ControllerContext,
+    reference: ObjectReference,
+    type_: EventType,
+    reason: &str,
+    action: &str,
+    note: String,
+) {
+    let Some(recorder) = ctx.events.clone() else {
+        return;
+    };
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(truncate_note(note)),
+        action: action.to_string(),
+        secondary: None,
+    };
+    tokio::spawn(async move {
+        if let Err(err) = recorder.publish(&event, &reference).await {
+            warn!(
+                error = %err,
+                kind = ?reference.kind,
+                resource = ?reference.name,
+                reason = %event.reason,
+                "Failed to publish Kubernetes event"
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_notes_are_truncated_at_a_char_boundary() {
+        let note = truncate_note("ä".repeat(600));
+        assert!(note.len() <= MAX_NOTE_BYTES);
+        assert!(note.ends_with("..."));
+        assert_eq!(truncate_note("short".to_string()), "short");
+    }
+}
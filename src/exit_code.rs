@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// Failure ending the process with its own exit code, so that automation wrapping the binary can tell a configuration
+/// to fix apart from an unavailable dependency. Attached to an error as its context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The configuration file is missing, can not be parsed or is invalid
+    Config,
+    /// The Kubernetes API server can not be reached or rejects the credentials
+    KubeConnection,
+    /// The webserver can not listen on its port
+    WebserverBind,
+    /// Scheduled runs failed in a row as often as `crashOnRepeatedFailure` allows
+    RepeatedFailures,
+}
+
+/// Exit code of errors without a specific exit code, such as a failed one-shot run
+pub const GENERIC_FAILURE: u8 = 1;
+
+impl Exit {
+    pub fn code(self) -> u8 {
+        match self {
+            Exit::Config => 3,
+            Exit::KubeConnection => 4,
+            Exit::WebserverBind => 5,
+            Exit::RepeatedFailures => 6,
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exit::Config => write!(f, "Invalid configuration"),
+            Exit::KubeConnection => write!(f, "Failed to connect to the Kubernetes API server"),
+            Exit::WebserverBind => write!(f, "Failed to bind the webserver"),
+            Exit::RepeatedFailures => write!(f, "Stopping after repeated failed runs"),
+        }
+    }
+}
+
+/// Exit code of the error, given by the outermost `Exit` context
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<Exit>()
+        .map_or(GENERIC_FAILURE, |exit| exit.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn exit_code_is_taken_from_the_context() {
+        let err = anyhow::anyhow!("missing field `registries`")
+            .context("Failed to parse YAML config")
+            .context(Exit::Config)
+            .context("Failed to start");
+        assert_eq!(exit_code(&err), 3);
+        assert!(
+            format!("{:#}", err).contains("Invalid configuration: Failed to parse YAML config")
+        );
+
+        let result: anyhow::Result<()> = Err(anyhow::anyhow!("connection refused"));
+        assert_eq!(
+            exit_code(&result.context(Exit::KubeConnection).unwrap_err()),
+            4
+        );
+        assert_eq!(exit_code(&anyhow::anyhow!("run failed")), GENERIC_FAILURE);
+    }
+}
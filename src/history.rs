@@ -0,0 +1,257 @@
+use crate::config::History;
+use crate::rollout::ChangedContainer;
+use crate::state::ControllerContext;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::PostParams;
+use kube::Api;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Attempts to write the history ConfigMap when concurrent checks of other workloads updated it in between
+const MAX_WRITE_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum RolloutResult {
+    /// The workload was patched
+    Succeeded,
+    /// Patching the workload failed
+    Failed,
+    /// All replicas run the new pod template, recorded with `waitForRollout`
+    Completed,
+    /// The rollout did not complete in time or failed to progress, recorded with `waitForRollout`
+    NotCompleted,
+    /// The new pods failed to start and the workload was reverted, recorded with `autoRollback`
+    RolledBack,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub result: RolloutResult,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub containers: Vec<HistoryChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryChange {
+    pub container: String,
+    pub image: String,
+    /// Digest before the rollout, or the previous image for tag updates
+    pub previous: String,
+    /// Digest rolled out, or the new image for tag updates
+    pub new: String,
+}
+
+impl HistoryEntry {
+    pub fn new(containers: Vec<HistoryChange>, error: Option<&anyhow::Error>) -> Self {
+        HistoryEntry {
+            timestamp: Utc::now(),
+            result: match error {
+                Some(_) => RolloutResult::Failed,
+                None => RolloutResult::Succeeded,
+            },
+            error: error.map(|err| format!("{:#}", err)),
+            containers,
+        }
+    }
+}
+
+impl HistoryChange {
+    /// Tag updates record the previous and the newer image, digest changes the previous and the new digest
+    pub fn from_changed_container(changed: &ChangedContainer) -> Self {
+        HistoryChange {
+            container: changed.container_name.clone(),
+            image: changed.image.clone(),
+            previous: changed
+                .previous_image
+                .clone()
+                .unwrap_or_else(|| changed.previous_digest.clone()),
+            new: changed.target().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkloadHistory {
+    kind: String,
+    namespace: String,
+    name: String,
+    entries: Vec<HistoryEntry>,
+}
+
+/// ConfigMap keys only allow alphanumerics, `-`, `_` and `.`, which kinds, namespaces and names stay within
+fn history_key(kind: &str, namespace: &str, name: &str) -> String {
+    format!("{}.{}.{}", kind, namespace, name)
+}
+
+/// Appends the entry to the history of the workload. Failing to persist the history is only logged, so it never
+/// fails the reconciliation.
+pub async fn record(
+    ctx: &ControllerContext,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+    entry: HistoryEntry,
+) {
+    let Some(history) = &ctx.config.history else {
+        return;
+    };
+    let result = append_entry(ctx, history, &history_key(kind, namespace, name), entry).await;
+    ctx.state
+        .write()
+        .unwrap()
+        .record_kube_api_request(result.is_ok());
+    if let Err(err) = result {
+        warn!(
+            error = ?err,
+            kind = %kind,
+            resource = %name,
+            namespace = %namespace,
+            "Failed to record rollout history"
+        );
+    }
+}
+
+async fn append_entry(
+    ctx: &ControllerContext,
+    history: &History,
+    key: &str,
+    entry: HistoryEntry,
+) -> Result<()> {
+    let api: Api<ConfigMap> = Api::default_namespaced(ctx.kube_client.clone());
+    let mut attempt = 1;
+    loop {
+        let existing = api
+            .get_opt(&history.config_map_name)
+            .await
+            .with_context(|| format!("Failed to get ConfigMap {}", history.config_map_name))?;
+        let mut config_map = existing.clone().unwrap_or_else(|| ConfigMap {
+            metadata: kube::api::ObjectMeta {
+                name: Some(history.config_map_name.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let data = config_map.data.get_or_insert_with(BTreeMap::new);
+        let entries = append(
+            data.get(key).map(String::as_str),
+            entry.clone(),
+            history.max_entries_per_workload,
+        )?;
+        data.insert(key.to_string(), entries);
+
+        // the resource version of the ConfigMap that was read makes concurrent writes fail with a conflict
+        let result = match existing {
+            Some(_) => {
+                api.replace(
+                    &history.config_map_name,
+                    &PostParams::default(),
+                    &config_map,
+                )
+                .await
+            }
+            None => api.create(&PostParams::default(), &config_map).await,
+        };
+        match result {
+            Ok(_) => return Ok(()),
+            Err(kube::Error::Api(response))
+                if response.code == 409 && attempt < MAX_WRITE_ATTEMPTS =>
+            {
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to write ConfigMap {}", history.config_map_name)
+                });
+            }
+        }
+    }
+}
+
+/// Appends the entry to the serialized entries and drops the oldest ones beyond the limit
+fn append(entries: Option<&str>, entry: HistoryEntry, max_entries: usize) -> Result<String> {
+    let mut entries: Vec<HistoryEntry> = match entries {
+        // a corrupted history is started over instead of blocking all further entries
+        Some(entries) => serde_json::from_str(entries).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    entries.push(entry);
+    let excess = entries.len().saturating_sub(max_entries);
+    entries.drain(..excess);
+    serde_json::to_string(&entries).context("Failed to serialize rollout history")
+}
+
+/// Reads the history of all workloads from the ConfigMap
+pub async fn read(ctx: &ControllerContext, history: &History) -> Result<Vec<WorkloadHistory>> {
+    let api: Api<ConfigMap> = Api::default_namespaced(ctx.kube_client.clone());
+    let config_map = api
+        .get_opt(&history.config_map_name)
+        .await
+        .with_context(|| format!("Failed to get ConfigMap {}", history.config_map_name))?;
+    Ok(config_map
+        .and_then(|config_map| config_map.data)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|(key, entries)| parse_workload_history(key, entries))
+        .collect())
+}
+
+fn parse_workload_history(key: &str, entries: &str) -> Option<WorkloadHistory> {
+    // kinds and namespaces contain no dots, names may
+    let mut parts = key.splitn(3, '.');
+    let (kind, namespace, name) = (parts.next()?, parts.next()?, parts.next()?);
+    Some(WorkloadHistory {
+        kind: kind.to_string(),
+        namespace: namespace.to_string(),
+        name: name.to_string(),
+        entries: serde_json::from_str(entries).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(new: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc::now(),
+            result: RolloutResult::Succeeded,
+            error: None,
+            containers: vec![HistoryChange {
+                container: "app".to_string(),
+                image: "ghcr.io/org/app:latest".to_string(),
+                previous: "sha256:old".to_string(),
+                new: new.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn history_keeps_the_most_recent_entries() {
+        let mut entries = None;
+        for digest in ["sha256:a", "sha256:b", "sha256:c"] {
+            entries = Some(append(entries.as_deref(), entry(digest), 2).unwrap());
+        }
+
+        let history = parse_workload_history(
+            &history_key("Deployment", "default", "app.v2"),
+            &entries.unwrap(),
+        )
+        .unwrap();
+        assert_eq!(history.kind, "Deployment");
+        assert_eq!(history.namespace, "default");
+        assert_eq!(history.name, "app.v2");
+        assert_eq!(
+            history
+                .entries
+                .iter()
+                .map(|entry| entry.containers[0].new.as_str())
+                .collect::<Vec<_>>(),
+            vec!["sha256:b", "sha256:c"]
+        );
+    }
+}
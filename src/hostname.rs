@@ -0,0 +1,160 @@
+use tracing::debug;
+
+/// Canonical name of Docker Hub, as used in image references
+pub const DOCKER_HUB: &str = "docker.io";
+
+/// Host serving the registry API of Docker Hub, containerd resolves docker.io to it as well
+const DOCKER_HUB_API_HOST: &str = "registry-1.docker.io";
+
+/// Further names of Docker Hub found in image references, pull secrets and webhook payloads
+const DOCKER_HUB_ALIASES: &[&str] = &[
+    "index.docker.io",
+    "registry-1.docker.io",
+    "registry.hub.docker.com",
+];
+
+/// Normalizes a registry hostname or hostname pattern for matching. Image references, pull secret keys such as
+/// `https://index.docker.io/v1/`, registry configurations and webhook payloads name the same registry differently,
+/// so the scheme and path are removed, the host is lowercased, ports that are the default of the scheme are dropped
+/// and the aliases of Docker Hub become `docker.io`. Other ports are kept, `registry:5000` is another registry than
+/// `registry`.
+pub fn normalize(host: &str) -> String {
+    let (scheme, rest) = match host.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+        None => (None, host),
+    };
+    let authority = rest
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let default_port = match scheme.as_deref() {
+        Some("https") => Some("443"),
+        Some("http") => Some("80"),
+        _ => None,
+    };
+    let host = match port(&authority) {
+        Some(port) if Some(port) == default_port => strip_port(&authority),
+        _ => &authority,
+    };
+
+    if DOCKER_HUB_ALIASES.contains(&host) {
+        DOCKER_HUB.to_string()
+    } else {
+        host.to_string()
+    }
+}
+
+/// Whether the host names Docker Hub
+pub fn is_docker_hub(host: &str) -> bool {
+    normalize(host) == DOCKER_HUB
+}
+
+/// Host the registry API is requested from. Docker Hub is rewritten to `registry-1.docker.io` to mimic containerd,
+/// other hosts are used as they are.
+pub fn api_host(registry: &str) -> &str {
+    if is_docker_hub(registry) {
+        debug!(
+            registry = %registry,
+            rewrite_to = DOCKER_HUB_API_HOST,
+            "Rewriting Docker Hub to its registry API host"
+        );
+        return DOCKER_HUB_API_HOST;
+    }
+    registry
+}
+
+/// Removes the port of a `host:port` or `[ipv6]:port` host
+pub fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.find(']').map_or(host, |end| &host[..=end]);
+    }
+    host.split_once(':').map_or(host, |(host, _port)| host)
+}
+
+/// Port of a `host:port` or `[ipv6]:port` host
+fn port(host: &str) -> Option<&str> {
+    host[strip_port(host).len()..].strip_prefix(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_strips_scheme_and_path() {
+        assert_eq!(normalize("ghcr.io"), "ghcr.io");
+        assert_eq!(normalize("https://ghcr.io"), "ghcr.io");
+        assert_eq!(normalize("http://ghcr.io/"), "ghcr.io");
+        assert_eq!(normalize("HTTPS://GHCR.io/v2/"), "ghcr.io");
+        assert_eq!(normalize("*.Example.com"), "*.example.com");
+    }
+
+    #[test]
+    fn normalize_keeps_ports_besides_the_scheme_default() {
+        assert_eq!(normalize("localhost:5000"), "localhost:5000");
+        assert_eq!(
+            normalize("https://registry.example.com:443"),
+            "registry.example.com"
+        );
+        assert_eq!(
+            normalize("http://registry.example.com:80/v2/"),
+            "registry.example.com"
+        );
+        assert_eq!(
+            normalize("http://registry.example.com:443"),
+            "registry.example.com:443"
+        );
+        assert_eq!(
+            normalize("registry.example.com:443"),
+            "registry.example.com:443"
+        );
+        assert_eq!(normalize("https://[::1]:443"), "[::1]");
+        assert_eq!(normalize("[::1]:5000"), "[::1]:5000");
+    }
+
+    #[test]
+    fn normalize_aliases_docker_hub() {
+        for host in [
+            "docker.io",
+            "index.docker.io",
+            "registry-1.docker.io",
+            "registry.hub.docker.com",
+            "https://index.docker.io/v1/",
+            "https://registry-1.docker.io:443",
+            "Docker.IO",
+        ] {
+            assert_eq!(normalize(host), DOCKER_HUB, "{}", host);
+            assert!(is_docker_hub(host), "{}", host);
+        }
+        assert_eq!(normalize("*.docker.io"), "*.docker.io");
+        assert_eq!(normalize("docker.io:5000"), "docker.io:5000");
+        assert!(!is_docker_hub("mirror.docker.io"));
+        assert!(!is_docker_hub("docker.io.example.com"));
+    }
+
+    #[test]
+    fn api_host_rewrites_docker_hub_only() {
+        assert_eq!(api_host("docker.io"), "registry-1.docker.io");
+        assert_eq!(api_host("index.docker.io"), "registry-1.docker.io");
+        assert_eq!(api_host("registry-1.docker.io"), "registry-1.docker.io");
+        assert_eq!(api_host("ghcr.io"), "ghcr.io");
+        assert_eq!(api_host("http://localhost:5000"), "http://localhost:5000");
+    }
+
+    #[test]
+    fn strip_port_and_port() {
+        assert_eq!(
+            strip_port("registry.example.com:5000"),
+            "registry.example.com"
+        );
+        assert_eq!(strip_port("registry.example.com"), "registry.example.com");
+        assert_eq!(strip_port("[::1]:5000"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+        assert_eq!(port("registry.example.com:5000"), Some("5000"));
+        assert_eq!(port("registry.example.com"), None);
+        assert_eq!(port("[::1]:5000"), Some("5000"));
+        assert_eq!(port("[::1]"), None);
+    }
+}
@@ -1,6 +1,7 @@
+use crate::hostname;
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ImageReference {
     pub registry: String,
     pub repository: String,
@@ -29,52 +30,120 @@ impl fmt::Display for ParseError {
     }
 }
 
+/// Reasons the image ID reported in a container status does not identify a manifest digest
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImageIdError {
+    /// The container has not been started yet
+    Empty,
+    /// Only the local image ID is known, e.g. for images that were loaded into the node instead of being pulled
+    MissingDigest(String),
+    InvalidDigest(String),
+}
+
+impl std::error::Error for ImageIdError {}
+impl fmt::Display for ImageIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageIdError::Empty => write!(f, "image ID is empty"),
+            ImageIdError::MissingDigest(image_id) => {
+                write!(f, "image ID {} contains no repository digest", image_id)
+            }
+            ImageIdError::InvalidDigest(image_id) => {
+                write!(f, "image ID {} contains an invalid digest", image_id)
+            }
+        }
+    }
+}
+
+/// Returns the repository digest of an image ID as reported in container statuses. Runtimes report it as
+/// `repo@sha256:...`, optionally with a scheme such as `docker-pullable://`.
+pub fn parse_image_id_digest(image_id: &str) -> Result<String, ImageIdError> {
+    if image_id.is_empty() {
+        return Err(ImageIdError::Empty);
+    }
+    let reference = image_id
+        .split_once("://")
+        .map_or(image_id, |(_scheme, reference)| reference);
+    let Some((_repository, digest)) = reference.rsplit_once('@') else {
+        return Err(ImageIdError::MissingDigest(image_id.to_string()));
+    };
+    let valid = digest.split_once(':').is_some_and(|(algorithm, hex)| {
+        !algorithm.is_empty() && !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if !valid {
+        return Err(ImageIdError::InvalidDigest(image_id.to_string()));
+    }
+    Ok(digest.to_string())
+}
+
 impl fmt::Display for ImageReference {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}/{}:{}", self.registry, self.repository, self.tag)
     }
 }
 
+/// Returns the `name:tag` to track for an image pinned to a digest as `name@digest` or `name:tag@digest`. A tag hint
+/// takes precedence over the co-declared tag. None for images that are not pinned, or pinned without tag and hint.
+pub fn pinned_image_tag(image: &str, tag_hint: Option<&str>) -> Option<String> {
+    let (name, _digest) = image.split_once('@')?;
+    let (name, tag) = match name.rfind(':') {
+        Some(pos) if pos > name.rfind('/').unwrap_or(0) => (&name[..pos], Some(&name[pos + 1..])),
+        _ => (name, None),
+    };
+    Some(format!("{}:{}", name, tag_hint.or(tag)?))
+}
+
+/// Registry of images whose name has no registry part
+pub const DEFAULT_REGISTRY: &str = hostname::DOCKER_HUB;
+/// Tag of images whose reference has no tag
+pub const DEFAULT_TAG: &str = "latest";
+
+/// Splits an image name without tag into registry and repository following Docker's normalization rules: the first
+/// path component is only a registry if it looks like a host, other names refer to Docker Hub, where single
+/// component names are official images in the `library` namespace
+pub fn split_registry(name: &str) -> (&str, String) {
+    match name.split_once('/') {
+        Some((host, repository))
+            if host.contains('.') || host.contains(':') || host == "localhost" =>
+        {
+            (host, repository.to_string())
+        }
+        Some(_) => (DEFAULT_REGISTRY, name.to_string()),
+        None => (DEFAULT_REGISTRY, format!("library/{}", name)),
+    }
+}
+
 impl ImageReference {
     pub fn parse(s: &str) -> Result<Self, ParseError> {
         // digest references are not supported
         if s.contains('@') {
             return Err(ParseError::DigestNotAllowed);
         }
-
-        // Must contain a tag (colon after last slash)
-        let (without_tag, tag) = if let Some(pos) = s.rfind(':') {
-            let last_slash = s.rfind('/').unwrap_or(0);
-            if pos > last_slash {
-                (&s[..pos], Some(s[pos + 1..].to_string()))
-            } else {
-                (s, None)
-            }
-        } else {
-            (s, None)
-        };
-        let tag = tag.ok_or(ParseError::MissingTag)?;
-
-        // Split into registry and repository by the first slash
-        let parts: Vec<&str> = without_tag.splitn(2, '/').collect();
-        if parts.len() != 2 {
+        if s.is_empty() || s.contains(char::is_whitespace) {
             return Err(ParseError::InvalidFormat(s.to_string()));
         }
 
-        let registry = parts[0];
-        let repository = parts[1];
-
-        if registry.is_empty() {
+        // A colon after the last slash separates the tag, other colons belong to the registry port
+        let (name, tag) = match s.rfind(':') {
+            Some(pos) if pos > s.rfind('/').unwrap_or(0) => (&s[..pos], &s[pos + 1..]),
+            _ => (s, DEFAULT_TAG),
+        };
+        if tag.is_empty() {
+            return Err(ParseError::MissingTag);
+        }
+        if name.starts_with('/') {
             return Err(ParseError::MissingRegistry);
         }
-        if repository.is_empty() {
+
+        let (registry, repository) = split_registry(name);
+        if repository.is_empty() || repository.ends_with('/') {
             return Err(ParseError::MissingRepository);
         }
 
         Ok(Self {
             registry: registry.to_string(),
-            repository: repository.to_string(),
-            tag,
+            repository,
+            tag: tag.to_string(),
         })
     }
 }
@@ -98,14 +167,101 @@ mod tests {
 
     #[test]
     fn parse_valid_image_reference_single_level_repo() {
-        let input = "registry/repo:latest";
+        let input = "registry.example.com/repo:latest";
         let result = ImageReference::parse(input).unwrap();
-        assert_eq!(result.registry, "registry");
+        assert_eq!(result.registry, "registry.example.com");
         assert_eq!(result.repository, "repo");
         assert_eq!(result.tag, "latest");
         assert_eq!(result.to_string(), input);
     }
 
+    #[test]
+    fn parse_registries_with_ports() {
+        let result = ImageReference::parse("localhost:5000/myapp:1.2").unwrap();
+        assert_eq!(result.registry, "localhost:5000");
+        assert_eq!(result.repository, "myapp");
+        assert_eq!(result.tag, "1.2");
+
+        let result = ImageReference::parse("registry.internal:8443/team/app:tag").unwrap();
+        assert_eq!(result.registry, "registry.internal:8443");
+        assert_eq!(result.repository, "team/app");
+        assert_eq!(result.tag, "tag");
+
+        let result = ImageReference::parse("registry.internal:8443/team/app").unwrap();
+        assert_eq!(result.registry, "registry.internal:8443");
+        assert_eq!(result.repository, "team/app");
+        assert_eq!(result.tag, "latest");
+
+        let result = ImageReference::parse("[::1]:5000/app:dev").unwrap();
+        assert_eq!(result.registry, "[::1]:5000");
+        assert_eq!(result.repository, "app");
+    }
+
+    #[test]
+    fn parse_normalizes_docker_hub_images() {
+        for (input, expected) in [
+            ("nginx:1.27", "docker.io/library/nginx:1.27"),
+            ("nginx", "docker.io/library/nginx:latest"),
+            ("library/nginx:latest", "docker.io/library/nginx:latest"),
+            ("bitnami/redis:7.4", "docker.io/bitnami/redis:7.4"),
+            ("docker.io/bitnami/redis", "docker.io/bitnami/redis:latest"),
+            ("localhost/app:dev", "localhost/app:dev"),
+        ] {
+            assert_eq!(
+                ImageReference::parse(input).unwrap().to_string(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn pinned_image_tag_keeps_the_tag_or_takes_the_hint() {
+        assert_eq!(
+            pinned_image_tag("registry:5000/repo:v1@sha256:123abc", None).as_deref(),
+            Some("registry:5000/repo:v1")
+        );
+        assert_eq!(
+            pinned_image_tag("registry:5000/repo@sha256:123abc", None),
+            None
+        );
+        assert_eq!(
+            pinned_image_tag("registry:5000/repo@sha256:123abc", Some("v2")).as_deref(),
+            Some("registry:5000/repo:v2")
+        );
+        assert_eq!(
+            pinned_image_tag("registry:5000/repo:v1@sha256:123abc", Some("v2")).as_deref(),
+            Some("registry:5000/repo:v2")
+        );
+        assert_eq!(pinned_image_tag("registry/repo:v1", Some("v2")), None);
+    }
+
+    #[test]
+    fn parse_image_id_digest_handles_runtime_formats() {
+        assert_eq!(
+            parse_image_id_digest("ghcr.io/org/app@sha256:123abc").unwrap(),
+            "sha256:123abc"
+        );
+        assert_eq!(
+            parse_image_id_digest("docker-pullable://org/app@sha256:123abc").unwrap(),
+            "sha256:123abc"
+        );
+        assert_eq!(parse_image_id_digest(""), Err(ImageIdError::Empty));
+        assert_eq!(
+            parse_image_id_digest("docker://sha256:123abc"),
+            Err(ImageIdError::MissingDigest(
+                "docker://sha256:123abc".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_image_id_digest("ghcr.io/org/app@latest"),
+            Err(ImageIdError::InvalidDigest(
+                "ghcr.io/org/app@latest".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn parse_error_digest_not_allowed() {
         let input = "registry/repo@sha256:123abc";
@@ -118,7 +274,7 @@ mod tests {
 
     #[test]
     fn parse_error_missing_tag() {
-        let input = "registry/repo";
+        let input = "registry.example.com/repo:";
         let err = ImageReference::parse(input).unwrap_err();
         match err {
             ParseError::MissingTag => {}
@@ -128,8 +284,8 @@ mod tests {
 
     #[test]
     fn parse_error_invalid_format() {
-        // No slash after registry means invalid format
-        let input = "registryrepo:tag";
+        // Whitespace is never part of an image reference
+        let input = "registry.example.com/my repo:tag";
         let err = ImageReference::parse(input).unwrap_err();
         match err {
             ParseError::InvalidFormat(s) => assert_eq!(s, input),
@@ -151,7 +307,7 @@ mod tests {
     #[test]
     fn parse_error_missing_repository() {
         // Trailing slash after registry
-        let input = "registry/:tag";
+        let input = "registry.example.com/:tag";
         let err = ImageReference::parse(input).unwrap_err();
         match err {
             ParseError::MissingRepository => {}
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Operation of a reconcile pass that may stall
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OperationKind {
+    ReconcilePass,
+    KubeApi,
+    Registry,
+}
+
+/// Operation in flight, as reported by `/debug/tasks` and the state dump
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InFlightOperation {
+    pub(crate) kind: OperationKind,
+    pub(crate) description: String,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) elapsed_ms: i64,
+}
+
+/// Reconcile passes, Kubernetes API requests and registry requests currently in flight, to tell which one stalls
+#[derive(Default)]
+pub struct InFlightOperations {
+    next_id: AtomicU64,
+    operations: Mutex<BTreeMap<u64, TrackedOperation>>,
+}
+
+struct TrackedOperation {
+    kind: OperationKind,
+    description: String,
+    started_at: DateTime<Utc>,
+}
+
+impl InFlightOperations {
+    /// Tracks an operation until the returned guard is dropped
+    pub fn track(
+        self: &Arc<Self>,
+        kind: OperationKind,
+        description: impl Into<String>,
+    ) -> OperationGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.operations.lock().unwrap().insert(
+            id,
+            TrackedOperation {
+                kind,
+                description: description.into(),
+                started_at: Utc::now(),
+            },
+        );
+        OperationGuard {
+            operations: self.clone(),
+            id,
+        }
+    }
+
+    /// Operations in flight, the longest running first
+    pub fn snapshot(&self) -> Vec<InFlightOperation> {
+        let now = Utc::now();
+        let mut operations: Vec<InFlightOperation> = self
+            .operations
+            .lock()
+            .unwrap()
+            .values()
+            .map(|operation| InFlightOperation {
+                kind: operation.kind,
+                description: operation.description.clone(),
+                started_at: operation.started_at,
+                elapsed_ms: (now - operation.started_at).num_milliseconds(),
+            })
+            .collect();
+        operations.sort_by_key(|operation| operation.started_at);
+        operations
+    }
+}
+
+/// Removes its operation from the operations in flight when dropped, also when the operation's future is cancelled
+pub struct OperationGuard {
+    operations: Arc<InFlightOperations>,
+    id: u64,
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.operations.operations.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_are_tracked_until_their_guard_is_dropped() {
+        let operations = Arc::new(InFlightOperations::default());
+        let pass = operations.track(OperationKind::ReconcilePass, "reconcile all workloads");
+        let request = operations.track(OperationKind::Registry, "ghcr.io/org/app:latest");
+
+        let snapshot = operations.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].kind, OperationKind::ReconcilePass);
+        assert_eq!(snapshot[1].description, "ghcr.io/org/app:latest");
+
+        drop(request);
+        assert_eq!(operations.snapshot().len(), 1);
+        drop(pass);
+        assert!(operations.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::config::LeaderElection;
+use crate::state::ControllerContext;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use k8s_openapi::api::coordination::v1::Lease;
+use kube::api::{Patch, PatchParams, PostParams};
+use kube::Api;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::atomic::Ordering;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, PartialEq)]
+enum LeaseAction {
+    Renew,
+    Acquire,
+    Follow,
+}
+
+/// Holder and renewal of a lease, read from its JSON representation as the time types differ between API versions
+#[derive(Debug)]
+struct LeaseRecord {
+    holder: Option<String>,
+    renew_time: Option<DateTime<Utc>>,
+    lease_duration_seconds: i64,
+    lease_transitions: i64,
+}
+
+impl LeaseRecord {
+    fn from_lease(lease: &Lease) -> Result<Self> {
+        let spec = serde_json::to_value(&lease.spec).context("Failed to serialize lease")?;
+        Ok(LeaseRecord {
+            holder: spec["holderIdentity"]
+                .as_str()
+                .filter(|holder| !holder.is_empty())
+                .map(str::to_string),
+            renew_time: spec["renewTime"]
+                .as_str()
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc)),
+            lease_duration_seconds: spec["leaseDurationSeconds"].as_i64().unwrap_or_default(),
+            lease_transitions: spec["leaseTransitions"].as_i64().unwrap_or_default(),
+        })
+    }
+
+    fn next_action(&self, identity: &str, now: DateTime<Utc>) -> LeaseAction {
+        match (&self.holder, self.renew_time) {
+            (Some(holder), _) if holder == identity => LeaseAction::Renew,
+            (None, _) | (_, None) => LeaseAction::Acquire,
+            (Some(_), Some(renew_time))
+                if renew_time + chrono::Duration::seconds(self.lease_duration_seconds) < now =>
+            {
+                LeaseAction::Acquire
+            }
+            _ => LeaseAction::Follow,
+        }
+    }
+}
+
+/// Name of this replica in the lease, the pod name set through the downward API or the hostname
+pub fn identity() -> Result<String> {
+    env::var("POD_NAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .context("Leader election requires POD_NAME or HOSTNAME to be set")
+}
+
+/// Takes part in the leader election until cancelled and keeps the leadership flag of the context up to date. The
+/// leader keeps its leadership while renewals fail until the lease expired, and releases the lease on shutdown so
+/// that a follower takes over right away.
+pub async fn run(
+    ctx: ControllerContext,
+    config: LeaderElection,
+    identity: String,
+    cancellation_token: CancellationToken,
+) {
+    let api: Api<Lease> = Api::default_namespaced(ctx.kube_client.clone());
+    info!(
+        lease = %config.lease_name,
+        identity = %identity,
+        "Starting leader election"
+    );
+    let mut last_renewed_at: Option<DateTime<Utc>> = None;
+    loop {
+        let now = Utc::now();
+        let result = try_acquire_or_renew(&api, &config, &identity, now).await;
+        ctx.state
+            .write()
+            .unwrap()
+            .record_kube_api_request(result.is_ok());
+        let is_leader = match result {
+            Ok(true) => {
+                last_renewed_at = Some(now);
+                true
+            }
+            Ok(false) => false,
+            Err(err) => {
+                warn!(error = ?err, lease = %config.lease_name, "Failed to acquire or renew lease");
+                last_renewed_at.is_some_and(|renewed_at| {
+                    now - renewed_at
+                        < chrono::Duration::from_std(config.lease_duration).unwrap_or_default()
+                })
+            }
+        };
+        if !is_leader {
+            last_renewed_at = None;
+        }
+        let was_leader = ctx.leader.swap(is_leader, Ordering::SeqCst);
+        match (was_leader, is_leader) {
+            (false, true) => info!(identity = %identity, "Acquired leadership"),
+            (true, false) => warn!(identity = %identity, "Lost leadership"),
+            _ => {}
+        }
+
+        tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+            _ = tokio::time::sleep(config.renew_interval) => {}
+        }
+    }
+
+    if ctx.leader.swap(false, Ordering::SeqCst) {
+        match release(&api, &config).await {
+            Ok(()) => info!(lease = %config.lease_name, "Released lease"),
+            Err(err) => warn!(error = ?err, lease = %config.lease_name, "Failed to release lease"),
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Returns whether this replica holds the lease afterwards. Updates carry the resource version of the lease that was
+/// read, so that only one of several replicas racing for an expired lease wins.
+async fn try_acquire_or_renew(
+    api: &Api<Lease>,
+    config: &LeaderElection,
+    identity: &str,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let lease_duration_seconds = config.lease_duration.as_secs().max(1);
+    let Some(lease) = api.get_opt(&config.lease_name).await? else {
+        let lease: Lease = serde_json::from_value(json!({
+            "metadata": { "name": config.lease_name },
+            "spec": {
+                "holderIdentity": identity,
+                "leaseDurationSeconds": lease_duration_seconds,
+                "acquireTime": format_time(now),
+                "renewTime": format_time(now),
+                "leaseTransitions": 0,
+            },
+        }))?;
+        return match api.create(&PostParams::default(), &lease).await {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(response)) if response.code == 409 => Ok(false),
+            Err(err) => Err(err).context("Failed to create lease"),
+        };
+    };
+
+    let record = LeaseRecord::from_lease(&lease)?;
+    let spec = match record.next_action(identity, now) {
+        LeaseAction::Renew => json!({
+            "renewTime": format_time(now),
+            "leaseDurationSeconds": lease_duration_seconds,
+        }),
+        LeaseAction::Acquire => {
+            debug!(previous_holder = ?record.holder, "Lease expired, trying to acquire it");
+            json!({
+                "holderIdentity": identity,
+                "leaseDurationSeconds": lease_duration_seconds,
+                "acquireTime": format_time(now),
+                "renewTime": format_time(now),
+                "leaseTransitions": record.lease_transitions + 1,
+            })
+        }
+        LeaseAction::Follow => return Ok(false),
+    };
+    let patch = json!({
+        "metadata": { "resourceVersion": lease.metadata.resource_version },
+        "spec": spec,
+    });
+    match api
+        .patch(
+            &config.lease_name,
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(response)) if response.code == 409 => Ok(false),
+        Err(err) => Err(err).context("Failed to update lease"),
+    }
+}
+
+async fn release(api: &Api<Lease>, config: &LeaderElection) -> Result<()> {
+    let patch = json!({ "spec": { "holderIdentity": Value::Null } });
+    api.patch(
+        &config.lease_name,
+        &PatchParams::default(),
+        &Patch::Merge(&patch),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(holder: Option<&str>, renewed_seconds_ago: i64, now: DateTime<Utc>) -> LeaseRecord {
+        LeaseRecord {
+            holder: holder.map(str::to_string),
+            renew_time: Some(now - chrono::Duration::seconds(renewed_seconds_ago)),
+            lease_duration_seconds: 15,
+            lease_transitions: 2,
+        }
+    }
+
+    #[test]
+    fn expired_or_released_leases_are_acquired() {
+        let now = Utc::now();
+        assert_eq!(
+            record(Some("pod-a"), 5, now).next_action("pod-a", now),
+            LeaseAction::Renew
+        );
+        assert_eq!(
+            record(Some("pod-b"), 5, now).next_action("pod-a", now),
+            LeaseAction::Follow
+        );
+        assert_eq!(
+            record(Some("pod-b"), 20, now).next_action("pod-a", now),
+            LeaseAction::Acquire
+        );
+        assert_eq!(
+            record(None, 5, now).next_action("pod-a", now),
+            LeaseAction::Acquire
+        );
+    }
+
+    #[test]
+    fn lease_record_is_read_from_the_lease_spec() {
+        let lease: Lease = serde_json::from_value(json!({
+            "metadata": { "name": "kube-autorollout" },
+            "spec": {
+                "holderIdentity": "pod-a",
+                "leaseDurationSeconds": 15,
+                "renewTime": "2026-10-16T10:00:00.123456Z",
+                "leaseTransitions": 3,
+            },
+        }))
+        .unwrap();
+        let record = LeaseRecord::from_lease(&lease).unwrap();
+        assert_eq!(record.holder.as_deref(), Some("pod-a"));
+        assert_eq!(
+            record.renew_time.map(format_time).as_deref(),
+            Some("2026-10-16T10:00:00.123456Z")
+        );
+        assert_eq!(record.lease_duration_seconds, 15);
+        assert_eq!(record.lease_transitions, 3);
+    }
+}
@@ -1,19 +1,74 @@
-use crate::state::ControllerContext;
-use anyhow::Context;
+use crate::cli::{Cli, Command};
+use crate::exit_code::Exit;
+use crate::image_reference::ImageReference;
+use crate::registry_requests::RegistryRequestLog;
+use crate::state::{ControllerCaches, ControllerContext, ControllerState};
+use anyhow::{bail, Context};
+use chrono::Utc;
+use clap::Parser;
 use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock};
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
-use tracing_subscriber;
 
+mod acr;
+mod api;
+mod approval;
+mod build_info;
+mod ca_bundles;
+mod cache;
+mod canary;
+mod cli;
 mod config;
+mod config_lint;
+mod config_reload;
 mod controller;
+mod ecr;
+mod error_report;
+mod events;
+mod exit_code;
+mod history;
+mod hostname;
 mod image_reference;
+mod in_flight;
+mod leader_election;
+#[cfg(any(test, feature = "mock-registry"))]
+mod mock_registry;
+mod notify;
 mod oci_registry;
+mod platform;
+mod policy;
+mod rate_limit;
+mod registry_credentials;
+mod registry_probe;
+mod registry_requests;
+mod retry;
+mod rollback;
 mod rollout;
+mod rollout_intent;
+mod rollout_strategy;
+mod rollout_wait;
+mod schedule;
 mod secret_string;
+mod signing;
+mod skip_reason;
 mod state;
+mod state_dump;
+mod svid_files;
+mod tag_policy;
+mod tls;
+mod token_cache;
+mod tracked_image;
+mod webhook;
 mod webserver;
+mod workload_group;
+mod workload_watcher;
+mod workqueue;
 
 // Avoid musl's default allocator due to lackluster performance
 // https://nickb.dev/blog/default-musl-allocator-considered-harmful-to-performance
@@ -22,54 +77,203 @@ mod webserver;
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(exit_code::exit_code(&err))
+        }
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    // printed before logging is set up, so the output is the configuration only
+    if cli.print_config {
+        return print_config();
+    }
+    #[cfg(feature = "runtime-diagnostics")]
+    console_subscriber::init();
+    #[cfg(not(feature = "runtime-diagnostics"))]
     tracing_subscriber::fmt::init();
     info!("Starting kube-autorollout {} 🚀", env!("CARGO_PKG_VERSION"));
+    tls::install_crypto_provider()?;
+
+    if let Some(Command::ValidateConfig {
+        check_credentials,
+        test_images,
+    }) = cli.command
+    {
+        return validate_config(check_credentials, &test_images).await;
+    }
 
-    let config_file = env::var("CONFIG_FILE").context("CONFIG_FILE is not set")?;
-    let config = config::load_config(config_file)?;
+    #[cfg(feature = "mock-registry")]
+    let _mock_registry = match env::var("MOCK_REGISTRY_CONFIG") {
+        Ok(path) => Some(start_mock_registry(&path).await?),
+        Err(_) => None,
+    };
+
+    let run_once = cli.once || env::var("RUN_MODE").is_ok_and(|run_mode| run_mode == "once");
+    let config_file = env::var("CONFIG_FILE")
+        .context("CONFIG_FILE is not set")
+        .context(Exit::Config)?;
+    let config = config::load_config(&config_file).context(Exit::Config)?;
+    config_lint::log_overlapping_patterns(&config);
 
-    let kube_client = controller::create_client().await?;
-    let http_client = oci_registry::create_client(&config)?;
+    let kube_client = controller::create_client()
+        .await
+        .context(Exit::KubeConnection)?;
+    let ca_bundles = ca_bundles::fetch_config_map_ca_bundles(
+        &kube_client,
+        &config.tls.ca_certificate_config_maps,
+    )
+    .await
+    .context("Failed to load CA bundles from ConfigMaps")?;
+    let http_client = oci_registry::create_client(&config, &ca_bundles)?;
 
     let ctx = ControllerContext {
         kube_client: kube_client.clone(),
-        config: config.clone(),
-        http_client,
+        config: Arc::new(config.clone()),
+        latest_config: Arc::new(RwLock::new(Arc::new(config.clone()))),
+        http_client: Arc::new(RwLock::new(http_client)),
+        ca_bundles: Arc::new(RwLock::new(ca_bundles)),
+        state: Arc::new(RwLock::new(ControllerState {
+            started_at: Some(Utc::now()),
+            ..Default::default()
+        })),
+        caches: Arc::new(ControllerCaches::new(&config.caches)),
+        events: config
+            .feature_flags
+            .enable_kubernetes_events
+            .then(|| events::create_recorder(kube_client.clone())),
+        run_lock: Arc::default(),
+        // A one-shot run is not elected, overlapping runs are prevented by the CronJob's concurrency policy
+        leader: Arc::new(AtomicBool::new(
+            config.leader_election.is_none() || run_once,
+        )),
+        rate_limits: Arc::default(),
+        notification_batch: Arc::default(),
+        in_flight: Arc::default(),
+        reported_errors: Arc::default(),
+        registry_requests: Arc::new(RegistryRequestLog::new(
+            config.diagnostics.registry_request_log_size,
+        )),
+        crash: CancellationToken::new(),
     };
 
-    info!(
-        "Executing job scheduler at cron schedule {}",
-        config.cron_schedule
-    );
-    let mut scheduler = JobScheduler::new().await?;
+    if run_once {
+        info!("Running a single reconciliation in one-shot mode");
+        let result = controller::run(ctx.clone()).await;
+        notify::flush(&ctx).await;
+        return result;
+    }
+
     let main_cancellation_token = CancellationToken::new();
+
+    #[cfg(unix)]
+    tokio::spawn(state_dump::listen_for_dump_signal(ctx.clone()));
+    let status_ctx = ctx.clone();
+    let crash = ctx.crash.clone();
+
+    if !config.tls.ca_certificate_config_maps.is_empty() {
+        tokio::spawn(ca_bundles::reload_config_map_ca_bundles(ctx.clone()));
+    }
+    tokio::spawn(ca_bundles::reload_rotated_ca_files(ctx.clone()));
+    if config.tls.svid_files.is_some() {
+        tokio::spawn(svid_files::reload_rotated_svids(ctx.clone()));
+    }
+    tokio::spawn(registry_probe::probe_registries(ctx.clone()));
+    tokio::spawn(config_reload::reload_changed_config(
+        ctx.clone(),
+        config_file.into(),
+    ));
+
+    let leader_election = match config.leader_election.clone() {
+        Some(leader_election) => Some(tokio::spawn(leader_election::run(
+            ctx.clone(),
+            leader_election,
+            leader_election::identity()?,
+            main_cancellation_token.clone(),
+        ))),
+        None => None,
+    };
+
+    let mut scheduler = JobScheduler::new().await?;
     let cronjob_cancellation_token = main_cancellation_token.clone();
 
-    // Add a job scheduled to run
-    let job = Job::new_async(config.cron_schedule, move |_uuid, _l| {
-        let ctx = ctx.clone();
-        let cronjob_cancellation_token = cronjob_cancellation_token.clone();
-        Box::pin(async move {
-            tokio::select! {
-            _ = cronjob_cancellation_token.cancelled() => {
-                info!("Shutdown signal received, stopping controller job scheduler");
-            }
-            result = controller::run(ctx) => {
-                if let Err(e) = result {
-                    error!("Error while running controller job: {:?}", e);
+    match config.watch.clone() {
+        Some(watch) => {
+            let watch_cancellation_token = main_cancellation_token.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                _ = watch_cancellation_token.cancelled() => {
+                    info!("Shutdown signal received, stopping workload watcher");
                 }
-            }
-            }
-        })
-    })?;
-    scheduler.add(job).await?;
+                _ = workload_watcher::run(ctx, watch) => {}
+                }
+            });
+        }
+        None => {
+            let run_controller = move |_uuid, _l| {
+                let ctx = ctx.with_latest_config();
+                controller::record_scheduled_tick(&ctx);
+                let cronjob_cancellation_token = cronjob_cancellation_token.clone();
+                Box::pin(async move {
+                    tokio::select! {
+                    _ = cronjob_cancellation_token.cancelled() => {
+                        info!("Shutdown signal received, stopping controller job scheduler");
+                    }
+                    result = controller::run(ctx) => {
+                        if let Err(e) = result {
+                            error!("Error while running controller job: {:?}", e);
+                        }
+                    }
+                    }
+                }) as Pin<Box<dyn Future<Output = ()> + Send>>
+            };
+            let job = match config.check_interval {
+                Some(interval) => {
+                    info!(
+                        "Executing job scheduler every {}",
+                        humantime_serde::re::humantime::format_duration(interval)
+                    );
+                    Job::new_repeated_async(interval, run_controller)?
+                }
+                None => {
+                    info!(
+                        "Executing job scheduler at cron schedule {}",
+                        config.cron_schedule
+                    );
+                    Job::new_async(config.cron_schedule.as_str(), run_controller)?
+                }
+            };
+            scheduler.add(job).await?;
+        }
+    }
     scheduler.start().await?;
 
-    let app = webserver::create_app();
-    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], config.webserver.port));
+    if let Some(health) = &config.webserver.health {
+        let addr = health.address();
+        info!("Starting health webserver on {}", addr);
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))
+            .context(Exit::WebserverBind)?;
+        let app = webserver::create_health_app(status_ctx.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                error!("Health webserver error: {:?}", e);
+            }
+        });
+    }
+
+    let app = webserver::create_app(status_ctx);
+    let addr = config.webserver.address();
     info!("Starting webserver on {}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))
+        .context(Exit::WebserverBind)?;
 
     tokio::select! {
         res = axum::serve(listener, app)
@@ -81,15 +285,110 @@ async fn main() -> anyhow::Result<()> {
         _ = shutdown_signal() => {
             info!("Shutdown signal received, stopping webserver");
         }
+        _ = crash.cancelled() => {
+            info!("Stopping webserver after repeated failed runs");
+        }
     }
 
-    // Cancel the cron scheduler jobs
+    // Cancel the cron scheduler jobs and the workload watcher
     main_cancellation_token.cancel();
     scheduler.shutdown().await?;
+    if let Some(leader_election) = leader_election {
+        // Wait for the lease to be released, so that another replica takes over right away
+        leader_election.await?;
+    }
+
+    if crash.is_cancelled() {
+        return Err(
+            anyhow::anyhow!("Scheduled runs failed repeatedly").context(Exit::RepeatedFailures)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the effective configuration with its secrets redacted
+fn print_config() -> anyhow::Result<()> {
+    let config_file = env::var("CONFIG_FILE")
+        .context("CONFIG_FILE is not set")
+        .context(Exit::Config)?;
+    let config = config::load_config(&config_file).context(Exit::Config)?;
+    print!("{}", serde_yaml_ng::to_string(&config.to_redacted_json()?)?);
+    Ok(())
+}
+
+/// Loads and validates the configuration, optionally verifying the registry credentials, without touching the cluster
+async fn validate_config(check_credentials: bool, test_images: &[String]) -> anyhow::Result<()> {
+    let config_file = env::var("CONFIG_FILE")
+        .context("CONFIG_FILE is not set")
+        .context(Exit::Config)?;
+    let config = config::load_config(&config_file).context(Exit::Config)?;
+    println!("Configuration file {} is valid", config_file);
+    for warning in config_lint::find_overlapping_patterns(&config) {
+        println!("WARN {}", warning);
+    }
+
+    if !check_credentials {
+        return Ok(());
+    }
+
+    let test_images = test_images
+        .iter()
+        .map(|image| {
+            ImageReference::parse(image)
+                .with_context(|| format!("Invalid test image reference {}", image))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if !config.tls.ca_certificate_config_maps.is_empty() {
+        println!("Note: CA bundles from ConfigMaps are not loaded for the credential check");
+    }
+    let client = oci_registry::create_client(&config, &[])?;
 
+    let checks = registry_probe::check_credentials(&client, &config, &test_images).await;
+    let mut failed = 0;
+    for check in &checks {
+        let verdict = if check.passed() { "PASS" } else { "FAIL" };
+        println!(
+            "{} {}: {:?}, {}",
+            verdict, check.probe.hostname_pattern, check.probe.status, check.probe.message
+        );
+        for (image, result) in &check.manifests {
+            match result {
+                Ok(()) => println!("    manifest {}: ok", image),
+                Err(e) => println!("    manifest {}: {}", image, e),
+            }
+        }
+        if !check.passed() {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!(
+            "Credential check failed for {} of {} registries",
+            failed,
+            checks.len()
+        );
+    }
     Ok(())
 }
 
+/// Starts the embedded mock OCI registry for local development, listening on `MOCK_REGISTRY_ADDR` (default `127.0.0.1:5000`)
+#[cfg(feature = "mock-registry")]
+async fn start_mock_registry(config_path: &str) -> anyhow::Result<mock_registry::MockRegistry> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read mock registry config file {}", config_path))?;
+    let config: mock_registry::MockRegistryConfig =
+        serde_yaml_ng::from_str(&content).context("Failed to parse mock registry config")?;
+    let addr = env::var("MOCK_REGISTRY_ADDR")
+        .unwrap_or_else(|_| "127.0.0.1:5000".to_string())
+        .parse()
+        .context("MOCK_REGISTRY_ADDR is not a valid socket address")?;
+
+    let registry = mock_registry::spawn(config, addr).await?;
+    info!(host = %registry.host(), "Started mock OCI registry");
+    Ok(registry)
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
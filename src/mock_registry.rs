@@ -0,0 +1,283 @@
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Configuration of the in-process OCI registry used for integration tests and local development
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockRegistryConfig {
+    #[serde(default)]
+    pub manifests: Vec<MockManifest>,
+    #[serde(default)]
+    pub auth: MockAuth,
+    /// Mimic a JFrog Artifactory set up with the repository path method: the plain `/v2/` endpoint
+    /// answers 404 with Artifactory headers, manifests are only served below `/artifactory/api/docker/`
+    #[serde(default, rename = "artifactoryRepositoryPath")]
+    pub artifactory_repository_path: bool,
+    /// Omit the `Docker-Content-Digest` header from HEAD responses like some registries do
+    #[serde(default, rename = "headWithoutDigest")]
+    pub head_without_digest: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockManifest {
+    pub repository: String,
+    pub tag: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub digest: String,
+    /// Child manifest digests, only rendered for index media types
+    #[serde(default)]
+    pub manifests: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type")]
+pub enum MockAuth {
+    #[default]
+    None,
+    /// Requires `Authorization: Bearer <token>` and answers 401 without an authentication challenge otherwise
+    Bearer { token: String },
+    /// Answers 401 with a `WWW-Authenticate` challenge pointing to the registry's own `/token` endpoint
+    TokenChallenge { token: String },
+    /// Mimics GitHub Container Registry, whose token endpoint requires a scope and accepts the credentials only as
+    /// basic authentication. Anonymous tokens are granted for public images, wrong credentials are answered with 403.
+    Ghcr {
+        token: String,
+        username: String,
+        password: String,
+        #[serde(default)]
+        public: bool,
+    },
+}
+
+pub struct MockRegistry {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MockRegistry {
+    /// Registry host as it would appear in an image reference, e.g. `127.0.0.1:41234`
+    pub fn host(&self) -> String {
+        self.addr.to_string()
+    }
+}
+
+impl Drop for MockRegistry {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct MockRegistryState {
+    config: MockRegistryConfig,
+    addr: SocketAddr,
+}
+
+pub async fn spawn(config: MockRegistryConfig, addr: SocketAddr) -> Result<MockRegistry> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind mock registry to {}", addr))?;
+    let addr = listener.local_addr()?;
+
+    let state = Arc::new(MockRegistryState { config, addr });
+    let app = Router::new()
+        .route("/token", get(token))
+        .fallback(manifest)
+        .with_state(state);
+
+    let handle = tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    Ok(MockRegistry { addr, handle })
+}
+
+async fn token(
+    State(state): State<Arc<MockRegistryState>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    match &state.config.auth {
+        MockAuth::TokenChallenge { token } => Json(json!({ "token": token })).into_response(),
+        MockAuth::Ghcr {
+            token,
+            username,
+            password,
+            public,
+        } => {
+            let has_scope = uri
+                .query()
+                .is_some_and(|query| query.split('&').any(|param| param.starts_with("scope=")));
+            if !has_scope {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            let basic = format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            );
+            match headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+            {
+                Some(authorization) if authorization == basic => {
+                    Json(json!({ "token": token })).into_response()
+                }
+                None if *public => Json(json!({ "token": token })).into_response(),
+                None => StatusCode::UNAUTHORIZED.into_response(),
+                Some(_) => StatusCode::FORBIDDEN.into_response(),
+            }
+        }
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn manifest(
+    State(state): State<Arc<MockRegistryState>>,
+    method: Method,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    debug!(method = %method, uri = %uri, "Mock registry request");
+    let path = uri.path();
+
+    if path == "/v2/" || path == "/v2" {
+        return match check_authorization(&state, &headers, None) {
+            Some(response) => response,
+            None => StatusCode::OK.into_response(),
+        };
+    }
+
+    let (repository, tag, via_artifactory) = match parse_manifest_path(path) {
+        Some(parsed) => parsed,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if let Some(response) = check_authorization(&state, &headers, Some(&repository)) {
+        return response;
+    }
+
+    if state.config.artifactory_repository_path && !via_artifactory {
+        return (
+            StatusCode::NOT_FOUND,
+            [("x-jfrog-version", "Artifactory/7.0.0")],
+        )
+            .into_response();
+    }
+
+    let Some(manifest) = state
+        .config
+        .manifests
+        .iter()
+        .find(|m| m.repository == repository && m.tag == tag)
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let body = json!({
+        "schemaVersion": 2,
+        "mediaType": manifest.media_type,
+        "manifests": manifest
+            .manifests
+            .iter()
+            .map(|digest| json!({ "digest": digest }))
+            .collect::<Vec<_>>(),
+    })
+    .to_string();
+
+    let mut response = (
+        StatusCode::OK,
+        [(CONTENT_TYPE.as_str(), manifest.media_type.as_str())],
+        body,
+    )
+        .into_response();
+    if method != Method::HEAD || !state.config.head_without_digest {
+        response.headers_mut().insert(
+            "Docker-Content-Digest",
+            manifest.digest.parse().expect("valid digest header"),
+        );
+    }
+    response
+}
+
+fn check_authorization(
+    state: &MockRegistryState,
+    headers: &HeaderMap,
+    repository: Option<&str>,
+) -> Option<Response> {
+    let expected_token = match &state.config.auth {
+        MockAuth::None => return None,
+        MockAuth::Bearer { token }
+        | MockAuth::TokenChallenge { token }
+        | MockAuth::Ghcr { token, .. } => token,
+    };
+
+    let authorized = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == format!("Bearer {}", expected_token));
+    if authorized {
+        return None;
+    }
+
+    match &state.config.auth {
+        MockAuth::TokenChallenge { .. } | MockAuth::Ghcr { .. } => {
+            let mut challenge = format!(
+                r#"Bearer realm="http://{}/token",service="mock-registry""#,
+                state.addr
+            );
+            if let Some(repository) = repository {
+                challenge.push_str(&format!(r#",scope="repository:{}:pull""#, repository));
+            }
+            Some((StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, challenge)]).into_response())
+        }
+        _ => Some(StatusCode::UNAUTHORIZED.into_response()),
+    }
+}
+
+/// Splits `/v2/<repository>/manifests/<tag>` and the Artifactory repository path variant
+/// `/artifactory/api/docker/<repo>/v2/<image>/manifests/<tag>` into repository and tag
+fn parse_manifest_path(path: &str) -> Option<(String, String, bool)> {
+    if let Some(rest) = path.strip_prefix("/artifactory/api/docker/") {
+        let (artifactory_repository, rest) = rest.split_once("/v2/")?;
+        let (image, tag) = rest.rsplit_once("/manifests/")?;
+        return Some((
+            format!("{}/{}", artifactory_repository, image),
+            tag.to_string(),
+            true,
+        ));
+    }
+
+    let rest = path.strip_prefix("/v2/")?;
+    let (repository, tag) = rest.rsplit_once("/manifests/")?;
+    Some((repository.to_string(), tag.to_string(), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest_path_handles_plain_and_artifactory_paths() {
+        assert_eq!(
+            parse_manifest_path("/v2/org/app/manifests/latest"),
+            Some(("org/app".to_string(), "latest".to_string(), false))
+        );
+        assert_eq!(
+            parse_manifest_path("/artifactory/api/docker/docker-local/v2/app/manifests/main"),
+            Some(("docker-local/app".to_string(), "main".to_string(), true))
+        );
+        assert_eq!(parse_manifest_path("/v2/"), None);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::config::NotificationTarget;
+use crate::state::ControllerContext;
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+
+/// Message about a rollout of a single workload, sent to every configured notification target
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub title: String,
+    /// One line per changed container
+    pub details: Vec<String>,
+    pub failed: bool,
+    /// Images rolled out to new digests, used to summarize aggregated notifications
+    pub images: Vec<String>,
+}
+
+impl Notification {
+    fn text(&self) -> String {
+        let mut lines = vec![self.title.clone()];
+        lines.extend(self.details.iter().map(|detail| format!("• {}", detail)));
+        lines.join("\n")
+    }
+
+    fn slack_payload(&self) -> Value {
+        let icon = if self.failed { ":x:" } else { ":rocket:" };
+        json!({ "text": format!("{} {}", icon, self.text()) })
+    }
+
+    /// Adaptive card as accepted by Teams workflow webhooks
+    fn teams_payload(&self) -> Value {
+        let mut body = vec![json!({
+            "type": "TextBlock",
+            "text": self.title,
+            "weight": "Bolder",
+            "color": if self.failed { "Attention" } else { "Good" },
+            "wrap": true,
+        })];
+        body.extend(self.details.iter().map(|detail| {
+            json!({
+                "type": "TextBlock",
+                "text": detail,
+                "wrap": true,
+            })
+        }));
+        json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "body": body,
+                },
+            }],
+        })
+    }
+}
+
+/// Sends the notification to all configured targets. Notifications are sent in the background and failures are only
+/// logged, so they never hold up or fail the reconciliation. With notification aggregation, the notifications of a
+/// window are collected and sent as a single summary.
+pub fn send(ctx: &ControllerContext, notification: Notification) {
+    if ctx.config.notifications.is_empty() {
+        return;
+    }
+    let Some(aggregation) = &ctx.config.notification_aggregation else {
+        send_to_targets(ctx, notification);
+        return;
+    };
+    let mut batch = ctx.notification_batch.lock().unwrap();
+    if let Some(batch) = batch.as_mut() {
+        batch.push(notification);
+        return;
+    }
+    *batch = Some(vec![notification]);
+    let ctx = ctx.clone();
+    let window = aggregation.window;
+    tokio::spawn(async move {
+        tokio::time::sleep(window).await;
+        flush(&ctx).await;
+    });
+}
+
+/// Sends the notifications collected in the current aggregation window right away, e.g. before a one-shot run exits
+pub async fn flush(ctx: &ControllerContext) {
+    let Some(notifications) = ctx.notification_batch.lock().unwrap().take() else {
+        return;
+    };
+    debug!(
+        notifications = %notifications.len(),
+        "Sending aggregated notifications"
+    );
+    deliver(ctx, summarize(notifications)).await;
+}
+
+fn send_to_targets(ctx: &ControllerContext, notification: Notification) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move { deliver(&ctx, notification).await });
+}
+
+async fn deliver(ctx: &ControllerContext, notification: Notification) {
+    let client = ctx.http_client();
+    for target in &ctx.config.notifications {
+        if let Err(err) = send_to_target(&client, target, &notification).await {
+            warn!(
+                error = ?err,
+                title = %notification.title,
+                "Failed to send notification"
+            );
+        }
+    }
+}
+
+/// Combines the notifications of an aggregation window into one, counting the workloads rolled out to new digests
+/// of each image. A single notification is sent as it is.
+fn summarize(mut notifications: Vec<Notification>) -> Notification {
+    if notifications.len() == 1 {
+        return notifications.remove(0);
+    }
+    let mut workloads_by_image: BTreeMap<&str, usize> = BTreeMap::new();
+    for notification in notifications
+        .iter()
+        .filter(|notification| !notification.failed)
+    {
+        for image in &notification.images {
+            *workloads_by_image.entry(image).or_default() += 1;
+        }
+    }
+    let describe_image = |(image, workloads): (&str, usize)| match workloads {
+        1 => format!("1 workload updated to a new digest of {}", image),
+        _ => format!(
+            "{} workloads updated to new digests of {}",
+            workloads, image
+        ),
+    };
+    let failed = notifications
+        .iter()
+        .filter(|notification| notification.failed)
+        .count();
+    let only_rollouts_of_one_image = failed == 0
+        && workloads_by_image.len() == 1
+        && notifications
+            .iter()
+            .all(|notification| !notification.images.is_empty());
+    let title = match (only_rollouts_of_one_image, failed) {
+        (true, _) => describe_image(workloads_by_image.pop_first().unwrap()),
+        (false, 0) => format!("{} notifications", notifications.len()),
+        (false, failed) => format!("{} notifications, {} failed", notifications.len(), failed),
+    };
+    let mut details: Vec<String> = workloads_by_image.into_iter().map(describe_image).collect();
+    details.extend(
+        notifications
+            .iter()
+            .map(|notification| notification.title.clone()),
+    );
+    Notification {
+        title,
+        details,
+        failed: failed > 0,
+        images: notifications
+            .into_iter()
+            .flat_map(|notification| notification.images)
+            .collect(),
+    }
+}
+
+async fn send_to_target(
+    client: &Client,
+    target: &NotificationTarget,
+    notification: &Notification,
+) -> Result<()> {
+    let (webhook_url, payload) = match target {
+        NotificationTarget::Slack { webhook_url } => (webhook_url, notification.slack_payload()),
+        NotificationTarget::Teams { webhook_url } => (webhook_url, notification.teams_payload()),
+    };
+    let response = client
+        .post(webhook_url.expose_secret())
+        .json(&payload)
+        .send()
+        .await
+        .context("Failed to send notification webhook")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "Notification webhook answered with status {}: {}",
+            status,
+            body
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_contain_title_and_details() {
+        let notification = Notification {
+            title: "Triggered rollout of Deployment default/app".to_string(),
+            details: vec![
+                "container app (ghcr.io/org/app:latest) sha256:a -> sha256:b".to_string(),
+            ],
+            failed: false,
+            images: vec!["ghcr.io/org/app:latest".to_string()],
+        };
+
+        assert_eq!(
+            notification.slack_payload()["text"],
+            ":rocket: Triggered rollout of Deployment default/app\n• container app (ghcr.io/org/app:latest) sha256:a -> sha256:b"
+        );
+        let card = &notification.teams_payload()["attachments"][0]["content"];
+        assert_eq!(card["body"][0]["text"], notification.title);
+        assert_eq!(card["body"][0]["color"], "Good");
+        assert_eq!(card["body"][1]["text"], notification.details[0]);
+    }
+
+    fn rollout(name: &str, images: &[&str], failed: bool) -> Notification {
+        Notification {
+            title: format!("Triggered rollout of Deployment default/{}", name),
+            details: Vec::new(),
+            failed,
+            images: images.iter().map(|image| image.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn summary_counts_workloads_per_image() {
+        let base = "ghcr.io/org/base:latest";
+        let single = summarize(vec![rollout("app", &[base], false)]);
+        assert_eq!(single.title, "Triggered rollout of Deployment default/app");
+
+        let summary = summarize(vec![
+            rollout("api", &[base], false),
+            rollout("worker", &[base], false),
+            rollout("web", &[base], false),
+        ]);
+        assert_eq!(
+            summary.title,
+            "3 workloads updated to new digests of ghcr.io/org/base:latest"
+        );
+        assert_eq!(summary.details.len(), 3);
+        assert!(!summary.failed);
+
+        let summary = summarize(vec![
+            rollout("api", &[base], false),
+            rollout("worker", &[base, "ghcr.io/org/sidecar:1"], false),
+            rollout("web", &[base], true),
+        ]);
+        assert_eq!(summary.title, "3 notifications, 1 failed");
+        assert_eq!(
+            summary.details[..2],
+            [
+                "2 workloads updated to new digests of ghcr.io/org/base:latest",
+                "1 workload updated to a new digest of ghcr.io/org/sidecar:1",
+            ]
+        );
+        assert!(summary.failed);
+    }
+}
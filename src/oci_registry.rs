@@ -1,18 +1,31 @@
 use crate::config::RegistrySecret::{ImagePullSecret, Opaque};
-use crate::config::{Config, RegistrySecret};
+use crate::config::{Config, Proxy, RegistryClient, RegistrySecret, RegistryTls, RetryPolicy};
+use crate::hostname;
 use crate::image_reference::ImageReference;
+use crate::platform::Platform;
+use crate::rate_limit::RateLimits;
+use crate::registry_requests::RegistryRequestLog;
+use crate::retry::send_with_retry;
 use crate::secret_string::SecretString;
+use crate::svid_files;
+use crate::tls;
+use crate::token_cache::TokenCache;
 use anyhow::{bail, Context, Result};
 use axum::http::{HeaderMap, StatusCode};
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
-use reqwest::{Certificate, Client, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, LINK, WWW_AUTHENTICATE};
+use reqwest::{Certificate, Client, ClientBuilder, Method, Response};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use tracing::{debug, info};
+use std::net::SocketAddr;
+use tracing::{debug, info, warn};
 
 const OCI_ACCEPT_HEADER: &str = "application/vnd.oci.image.index.v1+json, application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
-const OCI_IMAGE_MANIFEST_CONTENT_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+pub(crate) const OCI_IMAGE_MANIFEST_CONTENT_TYPE: &str =
+    "application/vnd.oci.image.manifest.v1+json";
 const OCI_IMAGE_INDEX_CONTENT_TYPE: &str = "application/vnd.oci.image.index.v1+json";
 const DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE: &str =
     "application/vnd.docker.distribution.manifest.v2+json";
@@ -22,23 +35,181 @@ const DOCKER_DISTRIBUTION_INDEX_CONTENT_TYPE: &str =
 #[derive(Deserialize)]
 struct OciIndexManifest {
     digest: String,
+    #[serde(default)]
+    platform: Option<Platform>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
 }
 
+/// BuildKit stores provenance and SBOM attestations as index entries of platform unknown/unknown
+const ATTESTATION_REFERENCE_TYPE_ANNOTATION: &str = "vnd.docker.reference.type";
+
+/// Username Quay expects in the token request for OAuth application tokens
+const OAUTH_TOKEN_USERNAME: &str = "$oauthtoken";
+
 /// OCI_IMAGE_INDEX_CONTENT_TYPE and DOCKER_DISTRIBUTION_INDEX_CONTENT_TYPE share the same content structure
 #[derive(Deserialize)]
 struct OciIndexResponse {
     manifests: Vec<OciIndexManifest>,
 }
 
+/// OCI_IMAGE_MANIFEST_CONTENT_TYPE and DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE share the same content structure
+#[derive(Deserialize)]
+struct OciImageManifest {
+    config: OciDescriptor,
+}
+
+#[derive(Deserialize)]
+struct OciDescriptor {
+    digest: String,
+}
+
+/// Upper bound of followed tag list pages, registries page by 100 tags or more
+const MAX_TAG_LIST_PAGES: usize = 100;
+
+#[derive(Deserialize)]
+struct TagListResponse {
+    /// null for repositories without tags on some registries
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+/// The registry answered that the tag does not exist (anymore), as opposed to failing to serve it
+#[derive(Debug)]
+pub struct TagNotFound {
+    pub url: String,
+}
+
+impl std::fmt::Display for TagNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tag not found at {}", self.url)
+    }
+}
+
+impl std::error::Error for TagNotFound {}
+
 #[derive(Deserialize)]
 struct RegistryTokenResponse {
     token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+    #[serde(default)]
+    issued_at: Option<DateTime<Utc>>,
+}
+
+/// Registry HTTP clients, with a dedicated client for every registry with its own TLS configuration
+#[derive(Clone)]
+pub struct HttpClients {
+    default: Client,
+    /// Clients by hostname pattern of the registry
+    registries: HashMap<String, Client>,
+}
+
+impl HttpClients {
+    pub fn default_client(&self) -> Client {
+        self.default.clone()
+    }
+
+    /// The client for the registry with the given hostname pattern
+    pub fn for_registry(&self, hostname_pattern: &str) -> Client {
+        self.registries
+            .get(hostname_pattern)
+            .unwrap_or(&self.default)
+            .clone()
+    }
 }
 
-pub fn create_client(config: &Config) -> Result<Client> {
+impl From<Client> for HttpClients {
+    fn from(default: Client) -> Self {
+        HttpClients {
+            default,
+            registries: HashMap::new(),
+        }
+    }
+}
+
+/// Builds the registry HTTP clients trusting the system roots, the configured CA files and the given PEM bundles
+pub fn create_client(config: &Config, additional_ca_bundles: &[String]) -> Result<HttpClients> {
     info!("Initializing OCI Registry HTTP client");
+    let default = client_builder(config, additional_ca_bundles, true, config.proxy.as_ref())?
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let mut registries = HashMap::new();
+    for registry in &config.registries {
+        if registry.tls.is_none() && registry.proxy.is_none() && registry.resolve_to.is_empty() {
+            continue;
+        }
+        let with_spiffe_identity = registry
+            .tls
+            .as_ref()
+            .is_none_or(|tls| tls.client_certificate_path.is_none());
+        let proxy = registry.proxy.as_ref().or(config.proxy.as_ref());
+        let mut builder =
+            client_builder(config, additional_ca_bundles, with_spiffe_identity, proxy)?;
+        if let Some(tls) = &registry.tls {
+            builder = configure_registry_tls(builder, tls, config).with_context(|| {
+                format!(
+                    "Failed to configure TLS for registry {}",
+                    registry.hostname_pattern
+                )
+            })?;
+        }
+        for (hostname, ip) in &registry.resolve_to {
+            // the port of the request URL is used, the port given here is ignored
+            builder = builder.resolve(hostname, SocketAddr::new(*ip, 0));
+        }
+        let client = builder.build().context("Failed to build HTTP client")?;
+        info!(
+            registry = %registry.hostname_pattern,
+            "Using dedicated HTTP client for registry"
+        );
+        registries.insert(registry.hostname_pattern.clone(), client);
+    }
+
+    Ok(HttpClients {
+        default,
+        registries,
+    })
+}
+
+fn configure_registry_tls(
+    mut client_builder: ClientBuilder,
+    tls: &RegistryTls,
+    config: &Config,
+) -> Result<ClientBuilder> {
+    if let Some(ca_path) = &tls.ca_certificate_path {
+        let bundle = fs::read(ca_path)
+            .with_context(|| format!("Failed to read file {}", ca_path.display()))?;
+        for cert in Certificate::from_pem_bundle(&bundle).context("Failed to parse CA bundle")? {
+            client_builder = client_builder.add_root_certificate(cert);
+        }
+    }
+    if let (Some(certificate_path), Some(key_path)) =
+        (&tls.client_certificate_path, &tls.client_key_path)
+    {
+        let certificate = fs::read(certificate_path)
+            .with_context(|| format!("Failed to read file {}", certificate_path.display()))?;
+        let key = fs::read(key_path)
+            .with_context(|| format!("Failed to read file {}", key_path.display()))?;
+        let identity = tls::identity_from_pem(&certificate, &key, config.tls.backend.as_ref())
+            .context("Failed to parse client certificate")?;
+        client_builder = client_builder.identity(identity);
+    }
+    Ok(client_builder)
+}
+
+/// Client builder with the global TLS configuration. The SPIFFE X.509-SVID is left out for registries bringing their
+/// own client certificate, as a client presents a single identity.
+fn client_builder(
+    config: &Config,
+    additional_ca_bundles: &[String],
+    with_spiffe_identity: bool,
+    proxy: Option<&Proxy>,
+) -> Result<ClientBuilder> {
     // System certificates are loaded automatically with rustls-tls-native-roots
-    let mut client_builder = Client::builder();
+    let mut client_builder =
+        tls::configure_backend(Client::builder(), config.tls.backend.as_ref())?;
 
     for file_path in &config.tls.ca_certificate_paths {
         let file_content = fs::read(file_path)
@@ -51,30 +222,162 @@ pub fn create_client(config: &Config) -> Result<Client> {
         );
     }
 
-    Ok(client_builder
-        .build()
-        .context("Failed to build HTTP client")?)
+    for ca_bundle in additional_ca_bundles {
+        let certs = Certificate::from_pem_bundle(ca_bundle.as_bytes())
+            .context("Failed to parse CA bundle")?;
+        info!(
+            certificate_count = %certs.len(),
+            "Adding ca certificate(s) given in CA bundle to truststore"
+        );
+        for cert in certs {
+            client_builder = client_builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(svid_files) = &config.tls.svid_files
+        && with_spiffe_identity
+    {
+        client_builder =
+            svid_files::configure_client(client_builder, svid_files, config.tls.backend.as_ref())?;
+    }
+
+    if let Some(proxy) = proxy {
+        client_builder = client_builder.proxy(create_proxy(proxy)?);
+    }
+
+    Ok(configure_connections(client_builder, &config.registry_client))
 }
 
+/// Applies the connection pool, HTTP/2 and TCP keepalive settings
+fn configure_connections(
+    mut client_builder: ClientBuilder,
+    registry_client: &RegistryClient,
+) -> ClientBuilder {
+    if let Some(max_idle) = registry_client.pool_max_idle_per_host {
+        client_builder = client_builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = registry_client.pool_idle_timeout {
+        client_builder = client_builder.pool_idle_timeout(idle_timeout);
+    }
+    if let Some(keepalive) = registry_client.tcp_keepalive {
+        client_builder = client_builder.tcp_keepalive(keepalive);
+    }
+    if !registry_client.http2 {
+        client_builder = client_builder.http1_only();
+    }
+    client_builder
+}
+
+/// Routes requests through the proxy unless their host matches a noProxy pattern. Configuring a proxy disables the
+/// proxy environment variables.
+fn create_proxy(proxy: &Proxy) -> Result<reqwest::Proxy> {
+    let url = reqwest::Url::parse(&proxy.url)
+        .with_context(|| format!("Failed to parse proxy URL {}", proxy.url))?;
+    let proxy = proxy.clone();
+    Ok(reqwest::Proxy::custom(move |target| {
+        let host = target.host_str()?;
+        (!proxy.is_bypassed(host)).then(|| url.clone())
+    }))
+}
+
+/// Fetches the digests from the image's registry, falling back to the given hosts in order while the previous
+/// ones can not be reached. Returns the digests along with the host that served them.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_digests_with_failover(
+    image_reference: &ImageReference,
+    fallback_hosts: &[String],
+    registry_secret: &RegistrySecret,
+    client: &Client,
+    tokens: &TokenCache,
+    rate_limits: &RateLimits,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    enable_jfrog_artifactory_fallback: bool,
+) -> Result<(Vec<String>, String)> {
+    let hosts = std::iter::once(&image_reference.registry).chain(fallback_hosts);
+    let mut last_error = None;
+
+    for host in hosts {
+        if let Some(err) = &last_error {
+            warn!(
+                error = %err,
+                registry = %image_reference.registry,
+                host = %host,
+                "Registry host is unreachable, failing over to next host"
+            );
+        }
+
+        let host_reference = ImageReference {
+            registry: host.clone(),
+            repository: image_reference.repository.clone(),
+            tag: image_reference.tag.clone(),
+        };
+        match fetch_digests_from_tag(
+            &host_reference,
+            registry_secret,
+            client,
+            tokens,
+            rate_limits,
+            retry,
+            requests,
+            enable_jfrog_artifactory_fallback,
+        )
+        .await
+        {
+            Ok(digests) => return Ok((digests, host.clone())),
+            Err(err) if is_registry_unreachable(&err) => last_error = Some(err),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(last_error.expect("at least the primary registry host has been tried"))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_digests_from_tag(
     image_reference: &ImageReference,
     registry_secret: &RegistrySecret,
     client: &Client,
+    tokens: &TokenCache,
+    rate_limits: &RateLimits,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
     enable_jfrog_artifactory_fallback: bool,
 ) -> Result<Vec<String>> {
-    let registry = rewrite_docker_io_registry_target(&image_reference.registry);
+    let registry = hostname::api_host(&image_reference.registry);
     let url = format!(
-        "https://{}/v2/{}/manifests/{}",
-        registry, image_reference.repository, image_reference.tag
+        "{}/v2/{}/manifests/{}",
+        registry_base_url(registry),
+        image_reference.repository,
+        image_reference.tag
     );
 
-    let response = fetch_docker_manifest(client, registry_secret, &url)
-        .await
-        .with_context(|| format!("Failed to fetch manifest from {}", url))?;
+    let response = fetch_docker_manifest(
+        client,
+        retry,
+        requests,
+        rate_limits,
+        Method::HEAD,
+        registry,
+        registry_secret,
+        &url,
+    )
+    .await
+    .with_context(|| format!("Failed to fetch manifest from {}", url))?;
 
     match response.status() {
-        StatusCode::OK => {
-            let digest = get_digests_from_response(response).await?;
+        StatusCode::OK | StatusCode::ACCEPTED => {
+            let digest = get_digests(
+                client,
+                retry,
+                requests,
+                rate_limits,
+                registry,
+                registry_secret,
+                &url,
+                response,
+            )
+            .await?;
             return Ok(digest);
         }
 
@@ -91,23 +394,49 @@ pub async fn fetch_digests_from_tag(
 
                 let registry_secret = handle_oauth_authentication_challenge(
                     client,
+                    tokens,
+                    retry,
+                    requests,
                     registry,
                     registry_secret,
                     www_authenticate_header,
+                    Some(&image_reference.repository),
                 )
                 .await
                 .context("Failed to fetch OAuth token from")?;
 
-                let response = fetch_docker_manifest(client, &registry_secret, &url)
-                    .await
-                    .with_context(|| format!("Failed to fetch manifest from {}", url))?;
+                let response = fetch_docker_manifest(
+                    client,
+                    retry,
+                    requests,
+                    rate_limits,
+                    Method::HEAD,
+                    registry,
+                    &registry_secret,
+                    &url,
+                )
+                .await
+                .with_context(|| format!("Failed to fetch manifest from {}", url))?;
 
                 debug!(
                     response = ?response,
                     "Authentication challenge response"
                 );
 
-                let digest = get_digests_from_response(response).await?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Err(TagNotFound { url }.into());
+                }
+                let digest = get_digests(
+                    client,
+                    retry,
+                    requests,
+                    rate_limits,
+                    registry,
+                    &registry_secret,
+                    &url,
+                    response,
+                )
+                .await?;
                 return Ok(digest);
             }
         }
@@ -121,18 +450,41 @@ pub async fn fetch_digests_from_tag(
                     "Received previous error status, fetching digest from Artifactory fallback url"
                 );
 
-                let response = fetch_docker_manifest(client, registry_secret, &fallback_url)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to fetch manifest from Artifactory fallback url {}",
-                            fallback_url
-                        )
-                    })?;
-
-                let digest = get_digests_from_response(response).await?;
+                let response = fetch_docker_manifest(
+                    client,
+                    retry,
+                    requests,
+                    rate_limits,
+                    Method::HEAD,
+                    registry,
+                    registry_secret,
+                    &fallback_url,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to fetch manifest from Artifactory fallback url {}",
+                        fallback_url
+                    )
+                })?;
+
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Err(TagNotFound { url: fallback_url }.into());
+                }
+                let digest = get_digests(
+                    client,
+                    retry,
+                    requests,
+                    rate_limits,
+                    registry,
+                    registry_secret,
+                    &fallback_url,
+                    response,
+                )
+                .await?;
                 return Ok(digest);
             }
+            return Err(TagNotFound { url }.into());
         }
 
         status => {
@@ -150,27 +502,234 @@ pub async fn fetch_digests_from_tag(
     );
 }
 
+/// Lists all tags of the image's repository, following the pagination links of the registry
+pub async fn list_tags(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    image_reference: &ImageReference,
+    registry_secret: &RegistrySecret,
+) -> Result<Vec<String>> {
+    let registry = hostname::api_host(&image_reference.registry);
+    let base_url = registry_base_url(registry);
+    let mut url = format!("{}/v2/{}/tags/list", base_url, image_reference.repository);
+    let mut tags = Vec::new();
+
+    for _ in 0..MAX_TAG_LIST_PAGES {
+        let response = get_with_authentication(
+            client,
+            tokens,
+            retry,
+            requests,
+            registry,
+            registry_secret,
+            &url,
+            "application/json",
+        )
+        .await?;
+        if !response.status().is_success() {
+            bail!(
+                "Registry {} returned error status {} while listing tags of {}",
+                image_reference.registry,
+                response.status(),
+                image_reference.repository
+            );
+        }
+        let next_url = response
+            .headers()
+            .get(LINK)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_next_page_link);
+        let page: TagListResponse = response
+            .json()
+            .await
+            .context("Failed to parse tag list response")?;
+        tags.extend(page.tags.unwrap_or_default());
+
+        match next_url {
+            Some(next_url) => url = resolve_next_page_url(&base_url, &next_url)?,
+            None => return Ok(tags),
+        }
+    }
+
+    bail!(
+        "Tag list of {} exceeds {} pages",
+        image_reference.repository,
+        MAX_TAG_LIST_PAGES
+    );
+}
+
+/// Extracts the URL of the next page from a Link header such as `</v2/org/app/tags/list?last=v1&n=100>; rel="next"`
+fn parse_next_page_link(header: &str) -> Option<String> {
+    header
+        .split(',')
+        .find(|link| link.contains("rel=\"next\""))
+        .and_then(|link| {
+            let start = link.find('<')? + 1;
+            let end = link.find('>')?;
+            Some(link.get(start..end)?.to_string())
+        })
+}
+
+/// Resolves the link to the next page against the registry. Links to other origins are rejected, as the next page is
+/// requested with the registry's credentials.
+fn resolve_next_page_url(base_url: &str, next_url: &str) -> Result<String> {
+    let base = reqwest::Url::parse(base_url)
+        .with_context(|| format!("Invalid registry URL {}", base_url))?;
+    let next = base
+        .join(next_url)
+        .with_context(|| format!("Invalid next page link {}", next_url))?;
+    if next.scheme() != base.scheme()
+        || next.host_str() != base.host_str()
+        || next.port_or_known_default() != base.port_or_known_default()
+    {
+        bail!(
+            "Refusing to follow the next page link {} to another host than {}",
+            next_url,
+            base_url
+        );
+    }
+    Ok(next.into())
+}
+
+/// Sends a GET request accepting the given media types, answering the registry's token challenge if there is one
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn get_with_authentication(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    registry: &str,
+    registry_secret: &RegistrySecret,
+    url: &str,
+    accept: &str,
+) -> Result<Response> {
+    let send = |registry_secret: &RegistrySecret| {
+        let authorization_header = get_authorization_header(registry_secret, registry);
+        send_with_retry(retry, requests, move || {
+            client
+                .get(url)
+                .header(ACCEPT, accept)
+                .header(AUTHORIZATION, &authorization_header)
+        })
+    };
+
+    let response = send(registry_secret)
+        .await
+        .with_context(|| format!("Failed to send GET request to {}", url))?;
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok());
+
+    match challenge {
+        Some(challenge) if response.status() == StatusCode::UNAUTHORIZED => {
+            let token_secret = handle_oauth_authentication_challenge(
+                client,
+                tokens,
+                retry,
+                requests,
+                registry,
+                registry_secret,
+                challenge,
+                repository_from_url(url),
+            )
+            .await?;
+            send(&token_secret)
+                .await
+                .with_context(|| format!("Failed to send GET request to {}", url))
+        }
+        _ => Ok(response),
+    }
+}
+
+/// Sends a HEAD request for the manifest, answering the registry's token challenge if there is one
+pub(crate) async fn head_manifest(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    image_reference: &ImageReference,
+    registry_secret: &RegistrySecret,
+) -> Result<StatusCode> {
+    let registry = hostname::api_host(&image_reference.registry);
+    let url = format!(
+        "{}/v2/{}/manifests/{}",
+        registry_base_url(registry),
+        image_reference.repository,
+        image_reference.tag
+    );
+    let url = url.as_str();
+    let send = |registry_secret: &RegistrySecret| {
+        let authorization_header = get_authorization_header(registry_secret, registry);
+        send_with_retry(retry, requests, move || {
+            client
+                .head(url)
+                .header(ACCEPT, OCI_ACCEPT_HEADER)
+                .header(AUTHORIZATION, &authorization_header)
+        })
+    };
+
+    let response = send(registry_secret)
+        .await
+        .with_context(|| format!("Failed to send HEAD request to {}", url))?;
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok());
+
+    match challenge {
+        Some(challenge) if response.status() == StatusCode::UNAUTHORIZED => {
+            let token_secret = handle_oauth_authentication_challenge(
+                client,
+                tokens,
+                retry,
+                requests,
+                registry,
+                registry_secret,
+                challenge,
+                Some(&image_reference.repository),
+            )
+            .await?;
+            let response = send(&token_secret)
+                .await
+                .with_context(|| format!("Failed to send HEAD request to {}", url))?;
+            Ok(response.status())
+        }
+        _ => Ok(response.status()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn fetch_docker_manifest(
     client: &Client,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    rate_limits: &RateLimits,
+    method: Method,
+    registry: &str,
     registry_secret: &RegistrySecret,
     url: &str,
 ) -> Result<Response> {
-    info!(url = %url, "Fetching docker manifest from URL");
+    info!(url = %url, method = %method, "Fetching docker manifest from URL");
 
-    let authorization_header = get_authorization_header(registry_secret);
+    let authorization_header = get_authorization_header(registry_secret, registry);
 
     debug!(
         authorization_header_length = %authorization_header.len(),
         "Acquired authorization header"
     );
 
-    let response = client
-        .get(url)
-        .header(ACCEPT, OCI_ACCEPT_HEADER)
-        .header(AUTHORIZATION, authorization_header)
-        .send()
-        .await
-        .context("Failed to send request to fetch manifest")?;
+    let response = send_with_retry(retry, requests, || {
+        client
+            .request(method.clone(), url)
+            .header(ACCEPT, OCI_ACCEPT_HEADER)
+            .header(AUTHORIZATION, &authorization_header)
+    })
+    .await
+    .context("Failed to send request to fetch manifest")?;
+    rate_limits.record(registry, response.headers());
 
     debug!(
         response = ?response,
@@ -191,13 +750,74 @@ fn get_artifactory_fallback_url(
     let image = repository_parts.next().context("Image name is missing")?;
     // Create URL according to JFrog Artifactory's Repository Path Method (https://jfrog.com/help/r/jfrog-artifactory-documentation/the-repository-path-method-for-docker)
     let fallback_url = format!(
-        "https://{}/artifactory/api/docker/{}/v2/{}/manifests/{}",
-        registry, repository, image, image_reference.tag
+        "{}/artifactory/api/docker/{}/v2/{}/manifests/{}",
+        registry_base_url(registry),
+        repository,
+        image,
+        image_reference.tag
     );
 
     Ok(fallback_url)
 }
 
+/// Resolves the digests from the successful HEAD response if its headers suffice, which is the case for single
+/// manifests. The children of an index are only listed in its body, so indexes and registries omitting the
+/// digest header on HEAD requests are fetched again with a GET request. Manifests Quay still answers with
+/// `202 Accepted` after the retries are reported as error and checked again with the next run.
+#[allow(clippy::too_many_arguments)]
+async fn get_digests(
+    client: &Client,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    rate_limits: &RateLimits,
+    registry: &str,
+    registry_secret: &RegistrySecret,
+    url: &str,
+    head_response: Response,
+) -> Result<Vec<String>> {
+    if head_response.status() == StatusCode::ACCEPTED {
+        bail!(
+            "Registry {} is still fetching manifest {}, e.g. an expired manifest of a Quay proxy cache",
+            registry,
+            url
+        );
+    }
+    if let Some(digest) = get_manifest_digest_from_head_response(&head_response) {
+        return Ok(vec![digest]);
+    }
+    debug!(url = %url, "HEAD response does not suffice to resolve the digests, fetching the manifest");
+    let response = fetch_docker_manifest(
+        client,
+        retry,
+        requests,
+        rate_limits,
+        Method::GET,
+        registry,
+        registry_secret,
+        url,
+    )
+    .await
+    .with_context(|| format!("Failed to fetch manifest from {}", url))?;
+    if !response.status().is_success() {
+        bail!(
+            "Registry returned error status {} while fetching manifest {}",
+            response.status(),
+            url
+        );
+    }
+    get_digests_from_response(response).await
+}
+
+fn get_manifest_digest_from_head_response(response: &Response) -> Option<String> {
+    let content_type = get_content_type_from_response(response).ok()?;
+    match content_type.as_str() {
+        OCI_IMAGE_MANIFEST_CONTENT_TYPE | DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE => {
+            parse_manifest_digest_from_response(response).ok()
+        }
+        _ => None,
+    }
+}
+
 async fn get_digests_from_response(response: Response) -> Result<Vec<String>> {
     let content_type = get_content_type_from_response(&response)?;
     let digests = match content_type.as_str() {
@@ -244,10 +864,25 @@ pub(crate) fn collect_index_response_digests(
     body: &str,
     top_level_digest: &str,
 ) -> Result<Vec<String>> {
-    let digests: OciIndexResponse =
+    let index: OciIndexResponse =
         serde_json::from_str(body).context("Failed to parse OCI index response")?;
 
-    let mut digests: Vec<String> = digests.manifests.iter().map(|m| m.digest.clone()).collect();
+    // A pod's imageID is either the index digest or the digest of the platform manifest the node pulled, so the
+    // pod's digest is compared against all runnable platform digests plus the index digest
+    let mut digests = Vec::new();
+    for manifest in platform_manifests(&index) {
+        debug!(
+            index_digest = %top_level_digest,
+            platform = %manifest
+                .platform
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "unknown".to_string()),
+            digest = %manifest.digest,
+            "Found platform manifest in index"
+        );
+        digests.push(manifest.digest.clone());
+    }
     digests.push(top_level_digest.to_owned());
     if digests.is_empty() {
         bail!("Parsed digests are empty");
@@ -256,6 +891,165 @@ pub(crate) fn collect_index_response_digests(
     Ok(digests)
 }
 
+/// Runnable platform manifests of the index, without the attestations stored alongside them
+fn platform_manifests(index: &OciIndexResponse) -> impl Iterator<Item = &OciIndexManifest> {
+    index.manifests.iter().filter(|manifest| {
+        !manifest
+            .annotations
+            .contains_key(ATTESTATION_REFERENCE_TYPE_ANNOTATION)
+    })
+}
+
+/// Fetches the manifest with the given digest, returning its content type and body
+async fn fetch_manifest_by_digest(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    image_reference: &ImageReference,
+    digest: &str,
+    registry_secret: &RegistrySecret,
+) -> Result<(String, String)> {
+    let registry = hostname::api_host(&image_reference.registry);
+    let url = format!(
+        "{}/v2/{}/manifests/{}",
+        registry_base_url(registry),
+        image_reference.repository,
+        digest
+    );
+    let response = get_with_authentication(
+        client,
+        tokens,
+        retry,
+        requests,
+        registry,
+        registry_secret,
+        &url,
+        OCI_ACCEPT_HEADER,
+    )
+    .await?;
+    if !response.status().is_success() {
+        bail!(
+            "Registry returned error status {} while fetching manifest {}",
+            response.status(),
+            url
+        );
+    }
+    let content_type = get_content_type_from_response(&response)?;
+    let body = response
+        .text()
+        .await
+        .with_context(|| format!("Failed to read manifest {}", url))?;
+    Ok((content_type, body))
+}
+
+/// Fetches the digests of the config blobs of the image with the given digest. The config of an image stays the
+/// same when only its index annotations or attached artifacts change. An index resolves to the configs of all its
+/// runnable platform manifests.
+pub async fn fetch_config_digests(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    image_reference: &ImageReference,
+    digest: &str,
+    registry_secret: &RegistrySecret,
+) -> Result<BTreeSet<String>> {
+    let (content_type, body) = fetch_manifest_by_digest(
+        client,
+        tokens,
+        retry,
+        requests,
+        image_reference,
+        digest,
+        registry_secret,
+    )
+    .await?;
+    match content_type.as_str() {
+        OCI_IMAGE_MANIFEST_CONTENT_TYPE | DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE => {
+            Ok(BTreeSet::from([parse_config_digest(&body)?]))
+        }
+        OCI_IMAGE_INDEX_CONTENT_TYPE | DOCKER_DISTRIBUTION_INDEX_CONTENT_TYPE => {
+            let index: OciIndexResponse =
+                serde_json::from_str(&body).context("Failed to parse OCI index response")?;
+            let mut config_digests = BTreeSet::new();
+            for manifest in platform_manifests(&index) {
+                let (_, body) = fetch_manifest_by_digest(
+                    client,
+                    tokens,
+                    retry,
+                    requests,
+                    image_reference,
+                    &manifest.digest,
+                    registry_secret,
+                )
+                .await?;
+                config_digests.insert(parse_config_digest(&body)?);
+            }
+            if config_digests.is_empty() {
+                bail!("Index {} contains no platform manifests", digest);
+            }
+            Ok(config_digests)
+        }
+        _ => bail!("Unknown content type '{}'", content_type),
+    }
+}
+
+/// Resolves the digest of the manifest a node of the given platform pulls for the image with the given digest. An
+/// index resolves to its manifest of that platform, a single manifest to its own digest.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_platform_digest(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    image_reference: &ImageReference,
+    digest: &str,
+    registry_secret: &RegistrySecret,
+    platform: &Platform,
+) -> Result<String> {
+    let (content_type, body) = fetch_manifest_by_digest(
+        client,
+        tokens,
+        retry,
+        requests,
+        image_reference,
+        digest,
+        registry_secret,
+    )
+    .await?;
+    match content_type.as_str() {
+        OCI_IMAGE_MANIFEST_CONTENT_TYPE | DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE => {
+            Ok(digest.to_string())
+        }
+        OCI_IMAGE_INDEX_CONTENT_TYPE | DOCKER_DISTRIBUTION_INDEX_CONTENT_TYPE => {
+            find_platform_digest(&body, platform)
+                .with_context(|| format!("Index {} contains no manifest for {}", digest, platform))
+        }
+        _ => bail!("Unknown content type '{}'", content_type),
+    }
+}
+
+fn find_platform_digest(index_body: &str, platform: &Platform) -> Result<String> {
+    let index: OciIndexResponse =
+        serde_json::from_str(index_body).context("Failed to parse OCI index response")?;
+    platform_manifests(&index)
+        .find(|manifest| {
+            manifest
+                .platform
+                .as_ref()
+                .is_some_and(|manifest_platform| platform.runs(manifest_platform))
+        })
+        .map(|manifest| manifest.digest.clone())
+        .context("No matching platform manifest")
+}
+
+fn parse_config_digest(body: &str) -> Result<String> {
+    let manifest: OciImageManifest =
+        serde_json::from_str(body).context("Failed to parse OCI image manifest")?;
+    Ok(manifest.config.digest)
+}
+
 fn get_content_type_from_response(response: &Response) -> Result<String> {
     let raw_content_type = response
         .headers()
@@ -278,17 +1072,53 @@ pub(crate) fn parse_content_type(raw_content_type: &str) -> Result<String> {
     Ok(media_type.to_owned())
 }
 
-fn rewrite_docker_io_registry_target(registry: &str) -> &str {
-    if registry.eq("docker.io") {
-        //rewrite "docker.io" to "registry-1.docker.io", to mimic containerd
-        debug!(
-            registry = %registry,
-            rewrite_to = "registry-1.docker.io",
-            "Rewriting docker.io to registry-1.docker.io"
-        );
-        return "registry-1.docker.io";
+/// Whether the registry lookup failed because the registry could not be reached at all,
+/// as opposed to the registry answering with an error
+pub fn is_registry_unreachable(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout())
+}
+
+pub fn is_tag_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<TagNotFound>())
+}
+
+/// Base URL of a registry host. Hosts that include a scheme, as configured per registry, are used as they are.
+pub(crate) fn registry_base_url(registry: &str) -> String {
+    if registry.contains("://") {
+        registry.trim_end_matches('/').to_string()
+    } else {
+        format!("{}://{}", registry_scheme(registry), registry)
+    }
+}
+
+/// Registries are talked to via https unless their scheme is configured
+#[cfg(not(any(test, feature = "mock-registry")))]
+fn registry_scheme(_registry: &str) -> &'static str {
+    "https"
+}
+
+/// Builds with the embedded mock registry mimic Docker and containerd, which treat registries on loopback addresses as
+/// insecure and talk plain HTTP to them
+#[cfg(any(test, feature = "mock-registry"))]
+fn registry_scheme(registry: &str) -> &'static str {
+    let host = match registry.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => registry.split(':').next().unwrap_or_default(),
+    };
+
+    let is_loopback = host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false);
+
+    if is_loopback {
+        "http"
+    } else {
+        "https"
     }
-    registry
 }
 
 fn is_artifactory_response(response_headers: &HeaderMap) -> bool {
@@ -297,23 +1127,40 @@ fn is_artifactory_response(response_headers: &HeaderMap) -> bool {
         || response_headers.contains_key("x-artifactory-node-id")
 }
 
-fn get_authorization_header(registry_secret: &RegistrySecret) -> String {
+/// Authorization header for requests to the registry. Image pull secrets covering several registries contribute the
+/// credentials of the entry matching the registry.
+pub(crate) fn get_authorization_header(registry_secret: &RegistrySecret, registry: &str) -> String {
     match registry_secret {
-        Opaque { token, .. } => format!("Bearer {}", token.expose_secret()),
-        ImagePullSecret { docker_config, .. } => {
-            let first_docker_config = docker_config.auths.iter().next().unwrap();
-            let docker_secret = &first_docker_config.1.auth;
-            format!("Basic {}", docker_secret.expose_secret())
+        Opaque { token, .. } | RegistrySecret::Bearer { token } => {
+            format!("Bearer {}", token.expose_secret())
+        }
+        ImagePullSecret { docker_config, .. } => match docker_config.auth_for_registry(registry) {
+            Some(docker_auth) => format!("Basic {}", docker_auth.auth.expose_secret()),
+            None => String::new(),
+        },
+        RegistrySecret::Basic { authorization } => {
+            format!("Basic {}", authorization.expose_secret())
         }
-        RegistrySecret::None => String::new(),
+        // cloud provider secrets are exchanged for basic authentication before any registry request
+        RegistrySecret::None
+        | RegistrySecret::EcrIam { .. }
+        | RegistrySecret::AzureManagedIdentity { .. } => String::new(),
     }
 }
 
-async fn handle_oauth_authentication_challenge(
+/// Exchanges the credentials for a bearer token, reusing cached tokens of the same realm, service, scope and
+/// credentials until shortly before they expire. Challenges without a scope, which GitHub Container Registry requires,
+/// are answered with a pull scope of the repository if it is known.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle_oauth_authentication_challenge(
     client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
     registry: &str,
     registry_secret: &RegistrySecret,
     www_authenticate_header: &str,
+    repository: Option<&str>,
 ) -> Result<RegistrySecret> {
     debug!(
         registry = %registry,
@@ -346,55 +1193,150 @@ async fn handle_oauth_authentication_challenge(
             registry
         )
     })?;
-    let scope = auth_challenge_map.get("scope").with_context(|| {
-        format!(
-            "Expected missing field scope in WWW-Authenticate challenge from {}",
-            registry
-        )
-    })?;
+    // the challenge of the /v2/ ping endpoint does not contain a scope
+    let scope = auth_challenge_map
+        .get("scope")
+        .map(|scope| scope.to_string())
+        .or_else(|| repository.map(|repository| format!("repository:{}:pull", repository)));
 
     info!(
         realm = %realm,
         service = %service,
-        scope = %scope,
+        scope = ?scope,
         "Requesting authentication token for service and scope"
     );
 
-    let token_url = format!("{}?service={}&scope={}", realm, service, scope);
-    let token_response = client
-        .get(&token_url)
-        .header(AUTHORIZATION, get_authorization_header(registry_secret))
-        .send()
+    if let RegistrySecret::Bearer { .. } = registry_secret {
+        bail!(
+            "Registry {} rejected the configured bearer token, it is not exchanged with auth mode bearer",
+            registry
+        );
+    }
+
+    let authorization_header = get_authorization_header(registry_secret, registry);
+    let cache_key = TokenCache::key(realm, service, scope.as_deref(), &authorization_header);
+    if let Some(token) = tokens.get(&cache_key) {
+        debug!(
+            realm = %realm,
+            scope = ?scope,
+            "Using cached authentication token"
+        );
+        return Ok(RegistrySecret::Opaque {
+            username: None,
+            token,
+        });
+    }
+
+    let token_url = match &scope {
+        Some(scope) => format!("{}?service={}&scope={}", realm, service, scope),
+        None => format!("{}?service={}", realm, service),
+    };
+    let authorizations =
+        token_request_authorizations(registry_secret, authorization_header, scope.is_some());
+    let mut attempts = authorizations.iter().peekable();
+    while let Some(authorization) = attempts.next() {
+        let token_response = send_with_retry(retry, requests, || {
+            let request = client.get(&token_url);
+            match authorization.is_empty() {
+                true => request,
+                false => request.header(AUTHORIZATION, authorization),
+            }
+        })
         .await
         .context("Failed to get token from registry")?;
 
-    match token_response.status() {
-        StatusCode::OK => {
-            let token_content = token_response
-                .json::<RegistryTokenResponse>()
-                .await
-                .context("Failed to parse JSON response from registry")?;
+        match token_response.status() {
+            StatusCode::OK => {
+                let token_content = token_response
+                    .json::<RegistryTokenResponse>()
+                    .await
+                    .context("Failed to parse JSON response from registry")?;
+
+                let token = SecretString::new(token_content.token);
+                tokens.insert(
+                    cache_key,
+                    token.clone(),
+                    token_content.expires_in,
+                    token_content.issued_at,
+                );
+                let registry_secret = RegistrySecret::Opaque {
+                    username: None,
+                    token,
+                };
+                return Ok(registry_secret);
+            }
 
-            let registry_secret = RegistrySecret::Opaque {
-                username: None,
-                token: SecretString::new(token_content.token),
-            };
-            Ok(registry_secret)
-        }
+            status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+                if attempts.peek().is_some() =>
+            {
+                warn!(
+                    realm = %realm,
+                    scope = ?scope,
+                    status = %status,
+                    retry_with = %describe_authorization(attempts.peek().unwrap()),
+                    "Token request was rejected, retrying with other credentials"
+                );
+            }
 
-        status => {
-            bail!(
-                "Failed to retrieve OAuth authentication token from {}, error code {}",
-                realm,
-                status
-            );
+            status => {
+                bail!(
+                    "Failed to retrieve OAuth authentication token from {}, error code {}",
+                    realm,
+                    status
+                );
+            }
         }
     }
+    unreachable!("at least one token request is sent")
+}
+
+/// Authorization headers the token is requested with, in order. Tokens such as GitHub personal access tokens are
+/// configured as opaque secrets with a username but only accepted as basic authentication by the token endpoint.
+/// Opaque secrets without a username are sent with the `$oauthtoken` username, as Quay expects for OAuth tokens.
+/// Requests for a repository scope finally fall back to an anonymous token, which registries such as GitHub
+/// Container Registry grant for public images even if the configured credentials are rejected.
+fn token_request_authorizations(
+    registry_secret: &RegistrySecret,
+    authorization_header: String,
+    anonymous_fallback: bool,
+) -> Vec<String> {
+    let mut authorizations = vec![authorization_header];
+    if let Opaque { username, token } = registry_secret {
+        authorizations.push(format!(
+            "Basic {}",
+            STANDARD.encode(format!(
+                "{}:{}",
+                username.as_deref().unwrap_or(OAUTH_TOKEN_USERNAME),
+                token.expose_secret()
+            ))
+        ));
+    }
+    if anonymous_fallback && !authorizations[0].is_empty() {
+        authorizations.push(String::new());
+    }
+    authorizations
+}
+
+/// Repository of a registry API URL such as `https://ghcr.io/v2/<owner>/<repo>/manifests/latest`
+fn repository_from_url(url: &str) -> Option<&str> {
+    let (_, path) = url.split_once("/v2/")?;
+    ["/manifests/", "/blobs/", "/tags/"]
+        .iter()
+        .find_map(|endpoint| path.split_once(endpoint))
+        .map(|(repository, _)| repository)
+}
+
+fn describe_authorization(authorization: &str) -> &str {
+    match authorization.split_once(' ') {
+        Some((scheme, _)) => scheme,
+        None => "anonymous",
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock_registry::{self, MockAuth, MockManifest, MockRegistry, MockRegistryConfig};
 
     fn contains_all(actual: &[String], expected: &[&str]) {
         for expected_digest in expected {
@@ -522,6 +1464,136 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_next_page_link_of_tag_list() {
+        assert_eq!(
+            parse_next_page_link(r#"</v2/org/app/tags/list?last=v1&n=100>; rel="next""#),
+            Some("/v2/org/app/tags/list?last=v1&n=100".to_string())
+        );
+        assert_eq!(
+            parse_next_page_link(
+                r#"<https://registry.example.com/v2/app/tags/list?last=b>; rel="prev""#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn next_page_links_stay_on_the_registry() {
+        let base_url = "https://registry.example.com";
+        assert_eq!(
+            resolve_next_page_url(base_url, "/v2/app/tags/list?last=b&n=100").unwrap(),
+            "https://registry.example.com/v2/app/tags/list?last=b&n=100"
+        );
+        assert_eq!(
+            resolve_next_page_url(
+                base_url,
+                "https://registry.example.com:443/v2/app/tags/list"
+            )
+            .unwrap(),
+            "https://registry.example.com/v2/app/tags/list"
+        );
+        for next_url in [
+            "https://attacker.example.com/v2/app/tags/list",
+            "http://registry.example.com/v2/app/tags/list",
+            "https://registry.example.com:8443/v2/app/tags/list",
+            "//attacker.example.com/v2/app/tags/list",
+        ] {
+            assert!(resolve_next_page_url(base_url, next_url).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_manifest_index_body_skips_attestation_manifests() {
+        let body = r#"
+        {
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "manifests": [
+            {
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "digest": "sha256:armv7digest",
+              "platform": { "architecture": "arm", "os": "linux", "variant": "v7" }
+            },
+            {
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "digest": "sha256:attestationdigest",
+              "annotations": {
+                "vnd.docker.reference.digest": "sha256:armv7digest",
+                "vnd.docker.reference.type": "attestation-manifest"
+              },
+              "platform": { "architecture": "unknown", "os": "unknown" }
+            }
+          ]
+        }
+        "#;
+
+        let result = collect_index_response_digests(body, "sha256:indexdigest")
+            .expect("OCI index body with attestations should parse");
+
+        assert_eq!(
+            result,
+            vec![
+                "sha256:armv7digest".to_string(),
+                "sha256:indexdigest".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn find_platform_digest_matches_the_node_platform() {
+        let body = r#"
+        {
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "manifests": [
+            {
+              "digest": "sha256:amd64digest",
+              "platform": { "architecture": "amd64", "os": "linux" }
+            },
+            {
+              "digest": "sha256:arm64digest",
+              "platform": { "architecture": "arm64", "os": "linux", "variant": "v8" }
+            }
+          ]
+        }
+        "#;
+        let node = |architecture: &str| Platform {
+            architecture: architecture.to_string(),
+            os: "linux".to_string(),
+            variant: None,
+        };
+
+        assert_eq!(
+            find_platform_digest(body, &node("arm64")).unwrap(),
+            "sha256:arm64digest"
+        );
+        assert_eq!(
+            find_platform_digest(body, &node("amd64")).unwrap(),
+            "sha256:amd64digest"
+        );
+        assert!(find_platform_digest(body, &node("s390x")).is_err());
+    }
+
+    #[test]
+    fn parse_config_digest_of_image_manifest() {
+        let body = r#"
+        {
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "config": {
+            "mediaType": "application/vnd.oci.image.config.v1+json",
+            "digest": "sha256:configdigest",
+            "size": 1469
+          },
+          "layers": []
+        }
+        "#;
+
+        assert_eq!(parse_config_digest(body).unwrap(), "sha256:configdigest");
+        assert!(parse_config_digest(r#"{ "manifests": [] }"#).is_err());
+    }
+
     #[test]
     fn parse_manifest_index_body_rejects_invalid_json() {
         let body = r#"{ "manifests": [ { "digest": 123 } ] }"#;
@@ -550,4 +1622,420 @@ mod tests {
 
         assert_eq!(result, vec!["sha256:indexdigest".to_string()]);
     }
+
+    fn closed_port_addr() -> std::net::SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_fails_over_to_next_reachable_host() {
+        let fallback = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "org/app",
+                OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+                "sha256:fallback",
+            )],
+            ..Default::default()
+        })
+        .await;
+        let image_reference =
+            ImageReference::parse(&format!("{}/org/app:latest", closed_port_addr())).unwrap();
+
+        let (digests, host) = fetch_digests_with_failover(
+            &image_reference,
+            &[closed_port_addr().to_string(), fallback.host()],
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(digests, vec!["sha256:fallback".to_string()]);
+        assert_eq!(host, fallback.host());
+    }
+
+    #[tokio::test]
+    async fn unreachable_registry_is_detected() {
+        let image_reference =
+            ImageReference::parse(&format!("{}/org/app:latest", closed_port_addr())).unwrap();
+
+        let err = fetch_digests_from_tag(
+            &image_reference,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(is_registry_unreachable(&err));
+        assert!(!is_registry_unreachable(&anyhow::anyhow!(
+            "Unexpected status code"
+        )));
+    }
+
+    #[test]
+    fn registry_scheme_uses_http_for_loopback_registries() {
+        assert_eq!(registry_scheme("localhost:5000"), "http");
+        assert_eq!(registry_scheme("127.0.0.1:5000"), "http");
+        assert_eq!(registry_scheme("[::1]:5000"), "http");
+        assert_eq!(registry_scheme("ghcr.io"), "https");
+        assert_eq!(registry_scheme("registry.internal:8443"), "https");
+    }
+
+    #[test]
+    fn registry_base_url_keeps_configured_schemes() {
+        assert_eq!(registry_base_url("ghcr.io"), "https://ghcr.io");
+        assert_eq!(registry_base_url("localhost:5000"), "http://localhost:5000");
+        assert_eq!(
+            registry_base_url("http://registry.internal:8080"),
+            "http://registry.internal:8080"
+        );
+    }
+
+    async fn spawn_mock_registry(config: MockRegistryConfig) -> MockRegistry {
+        mock_registry::spawn(config, "127.0.0.1:0".parse().unwrap())
+            .await
+            .expect("mock registry should start")
+    }
+
+    fn test_client() -> Client {
+        Client::builder().no_proxy().build().unwrap()
+    }
+
+    fn manifest(repository: &str, media_type: &str, digest: &str) -> MockManifest {
+        MockManifest {
+            repository: repository.to_string(),
+            tag: "latest".to_string(),
+            media_type: media_type.to_string(),
+            digest: digest.to_string(),
+            manifests: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_registry_manifest() {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "org/app",
+                OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+                "sha256:manifest",
+            )],
+            ..Default::default()
+        })
+        .await;
+        let image = ImageReference::parse(&format!("{}/org/app:latest", registry.host())).unwrap();
+
+        let digests = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .expect("digest should be fetched");
+
+        assert_eq!(digests, vec!["sha256:manifest".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_falls_back_to_get_without_digest_on_head() {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "org/app",
+                DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE,
+                "sha256:manifest",
+            )],
+            head_without_digest: true,
+            ..Default::default()
+        })
+        .await;
+        let image = ImageReference::parse(&format!("{}/org/app:latest", registry.host())).unwrap();
+
+        let digests = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .expect("digest should be fetched with a GET request");
+
+        assert_eq!(digests, vec!["sha256:manifest".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn missing_tag_is_classified_as_not_found() {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "org/app",
+                OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+                "sha256:manifest",
+            )],
+            ..Default::default()
+        })
+        .await;
+        let image =
+            ImageReference::parse(&format!("{}/org/removed:latest", registry.host())).unwrap();
+
+        let err = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .expect_err("removed tag should not be found");
+
+        assert!(is_tag_not_found(&err));
+        assert!(!is_registry_unreachable(&err));
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_registry_index() {
+        let mut index = manifest("org/app", OCI_IMAGE_INDEX_CONTENT_TYPE, "sha256:index");
+        index.manifests = vec!["sha256:amd64".to_string(), "sha256:arm64".to_string()];
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![index],
+            ..Default::default()
+        })
+        .await;
+        let image = ImageReference::parse(&format!("{}/org/app:latest", registry.host())).unwrap();
+
+        let digests = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .expect("digests should be fetched");
+
+        assert_eq!(digests.len(), 3);
+        contains_all(&digests, &["sha256:amd64", "sha256:arm64", "sha256:index"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_registry_with_token_challenge() {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "org/app",
+                DOCKER_DISTRIBUTION_MANIFEST_CONTENT_TYPE,
+                "sha256:private",
+            )],
+            auth: MockAuth::TokenChallenge {
+                token: "exchanged-token".to_string(),
+            },
+            ..Default::default()
+        })
+        .await;
+        let image = ImageReference::parse(&format!("{}/org/app:latest", registry.host())).unwrap();
+        let secret = RegistrySecret::Opaque {
+            username: Some("user".to_string()),
+            token: SecretString::new("personal-access-token".to_string()),
+        };
+
+        let digests = fetch_digests_from_tag(
+            &image,
+            &secret,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+        .expect("digest should be fetched after the token exchange");
+
+        assert_eq!(digests, vec!["sha256:private".to_string()]);
+    }
+
+    async fn fetch_from_mock_ghcr(public: bool, secret: RegistrySecret) -> Result<Vec<String>> {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "owner/app",
+                OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+                "sha256:ghcr",
+            )],
+            auth: MockAuth::Ghcr {
+                token: "ghcr-token".to_string(),
+                username: "octocat".to_string(),
+                password: "ghp_personal-access-token".to_string(),
+                public,
+            },
+            ..Default::default()
+        })
+        .await;
+        let image =
+            ImageReference::parse(&format!("{}/owner/app:latest", registry.host())).unwrap();
+        fetch_digests_from_tag(
+            &image,
+            &secret,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await
+    }
+
+    fn personal_access_token(token: &str) -> RegistrySecret {
+        RegistrySecret::Opaque {
+            username: Some("octocat".to_string()),
+            token: SecretString::new(token.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_ghcr_with_personal_access_token() {
+        let digests =
+            fetch_from_mock_ghcr(false, personal_access_token("ghp_personal-access-token"))
+                .await
+                .expect("the token should be requested with basic authentication");
+        assert_eq!(digests, vec!["sha256:ghcr".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_ghcr_public_image_anonymously() {
+        for secret in [
+            RegistrySecret::None,
+            personal_access_token("ghp_revoked-token"),
+        ] {
+            let digests = fetch_from_mock_ghcr(true, secret)
+                .await
+                .expect("public images should fall back to an anonymous token");
+            assert_eq!(digests, vec!["sha256:ghcr".to_string()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_ghcr_private_image_with_wrong_token_fails() {
+        let err = fetch_from_mock_ghcr(false, personal_access_token("ghp_revoked-token"))
+            .await
+            .expect_err("wrong credentials should be rejected");
+        assert!(
+            format!("{:#}", err).contains("error code 401"),
+            "unexpected error: {:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn repository_and_token_request_authorizations() {
+        assert_eq!(
+            repository_from_url("https://ghcr.io/v2/owner/app/manifests/latest"),
+            Some("owner/app")
+        );
+        assert_eq!(
+            repository_from_url("https://ghcr.io/v2/owner/app/tags/list?n=100"),
+            Some("owner/app")
+        );
+        assert_eq!(repository_from_url("https://ghcr.io/v2/"), None);
+
+        let secret = personal_access_token("ghp_token");
+        let authorizations = token_request_authorizations(
+            &secret,
+            get_authorization_header(&secret, "ghcr.io"),
+            true,
+        );
+        assert_eq!(
+            authorizations,
+            [
+                "Bearer ghp_token".to_string(),
+                format!("Basic {}", STANDARD.encode("octocat:ghp_token")),
+                String::new(),
+            ]
+        );
+        assert_eq!(
+            token_request_authorizations(&RegistrySecret::None, String::new(), true),
+            [String::new()]
+        );
+        assert_eq!(
+            token_request_authorizations(&secret, "Bearer ghp_token".to_string(), false).len(),
+            2
+        );
+
+        let quay_oauth_token = RegistrySecret::Opaque {
+            username: None,
+            token: SecretString::new("quay_token".to_string()),
+        };
+        assert_eq!(
+            token_request_authorizations(
+                &quay_oauth_token,
+                get_authorization_header(&quay_oauth_token, "quay.io"),
+                false,
+            )[1],
+            format!("Basic {}", STANDARD.encode("$oauthtoken:quay_token"))
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_digests_from_mock_registry_artifactory_fallback() {
+        let registry = spawn_mock_registry(MockRegistryConfig {
+            manifests: vec![manifest(
+                "docker-local/app",
+                OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+                "sha256:artifactory",
+            )],
+            artifactory_repository_path: true,
+            ..Default::default()
+        })
+        .await;
+        let image =
+            ImageReference::parse(&format!("{}/docker-local/app:latest", registry.host())).unwrap();
+
+        let without_fallback = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            false,
+        )
+        .await;
+        assert!(without_fallback.is_err());
+
+        let digests = fetch_digests_from_tag(
+            &image,
+            &RegistrySecret::None,
+            &test_client(),
+            &TokenCache::new(0),
+            &RateLimits::default(),
+            &RetryPolicy::default(),
+            &RegistryRequestLog::default(),
+            true,
+        )
+        .await
+        .expect("digest should be fetched from the fallback url");
+        assert_eq!(digests, vec!["sha256:artifactory".to_string()]);
+    }
 }
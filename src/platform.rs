@@ -0,0 +1,133 @@
+use k8s_openapi::api::core::v1::PodSpec;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Well-known node labels set by the kubelet
+const ARCH_LABEL: &str = "kubernetes.io/arch";
+const OS_LABEL: &str = "kubernetes.io/os";
+/// Operating system of nodes that do not announce one
+const DEFAULT_OS: &str = "linux";
+
+/// Platform of a manifest in an image index, or of the node a pod runs on
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Platform {
+    pub(crate) architecture: String,
+    pub(crate) os: String,
+    #[serde(default)]
+    pub(crate) variant: Option<String>,
+}
+
+impl Platform {
+    /// Platform announced by the well-known labels of a node
+    pub fn from_node_labels(labels: &BTreeMap<String, String>) -> Option<Self> {
+        Some(Platform {
+            architecture: labels.get(ARCH_LABEL)?.clone(),
+            os: labels
+                .get(OS_LABEL)
+                .map_or(DEFAULT_OS, String::as_str)
+                .to_string(),
+            variant: None,
+        })
+    }
+
+    /// Platform a pod is restricted to by its node selector, or by a required node affinity selecting a single
+    /// architecture
+    pub fn from_pod_spec(spec: &PodSpec) -> Option<Self> {
+        let selector = spec.node_selector.as_ref();
+        let architecture = selector
+            .and_then(|selector| selector.get(ARCH_LABEL))
+            .cloned()
+            .or_else(|| {
+                spec.affinity
+                    .as_ref()?
+                    .node_affinity
+                    .as_ref()?
+                    .required_during_scheduling_ignored_during_execution
+                    .as_ref()?
+                    .node_selector_terms
+                    .iter()
+                    .flat_map(|term| term.match_expressions.iter().flatten())
+                    .find_map(|requirement| match requirement.values.as_deref() {
+                        Some([architecture])
+                            if requirement.key == ARCH_LABEL && requirement.operator == "In" =>
+                        {
+                            Some(architecture.clone())
+                        }
+                        _ => None,
+                    })
+            })?;
+        Some(Platform {
+            architecture,
+            os: selector
+                .and_then(|selector| selector.get(OS_LABEL))
+                .map_or(DEFAULT_OS, String::as_str)
+                .to_string(),
+            variant: None,
+        })
+    }
+
+    /// Whether a manifest of the given platform runs on this platform. Nodes do not announce a variant, so a
+    /// missing variant matches any.
+    pub fn runs(&self, manifest: &Platform) -> bool {
+        self.os == manifest.os
+            && self.architecture == manifest.architecture
+            && (self.variant.is_none() || self.variant == manifest.variant)
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.variant {
+            Some(variant) => write!(f, "{}/{}/{}", self.os, self.architecture, variant),
+            None => write!(f, "{}/{}", self.os, self.architecture),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn platform_from_node_selector_or_required_affinity() {
+        let by_selector: PodSpec = serde_json::from_value(json!({
+            "containers": [],
+            "nodeSelector": { "kubernetes.io/arch": "arm64" },
+        }))
+        .unwrap();
+        let platform = Platform::from_pod_spec(&by_selector).unwrap();
+        assert_eq!(platform.to_string(), "linux/arm64");
+        assert!(platform.runs(&Platform {
+            architecture: "arm64".to_string(),
+            os: "linux".to_string(),
+            variant: Some("v8".to_string()),
+        }));
+
+        let by_affinity: PodSpec = serde_json::from_value(json!({
+            "containers": [],
+            "affinity": { "nodeAffinity": { "requiredDuringSchedulingIgnoredDuringExecution": {
+                "nodeSelectorTerms": [{ "matchExpressions": [
+                    { "key": "kubernetes.io/arch", "operator": "In", "values": ["amd64"] },
+                ] }],
+            } } },
+        }))
+        .unwrap();
+        assert_eq!(
+            Platform::from_pod_spec(&by_affinity).unwrap().architecture,
+            "amd64"
+        );
+
+        let any_architecture: PodSpec = serde_json::from_value(json!({
+            "containers": [],
+            "affinity": { "nodeAffinity": { "requiredDuringSchedulingIgnoredDuringExecution": {
+                "nodeSelectorTerms": [{ "matchExpressions": [
+                    { "key": "kubernetes.io/arch", "operator": "In", "values": ["amd64", "arm64"] },
+                ] }],
+            } } },
+        }))
+        .unwrap();
+        assert_eq!(Platform::from_pod_spec(&any_architecture), None);
+    }
+}
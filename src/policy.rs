@@ -0,0 +1,174 @@
+use crate::config::Policy;
+use crate::rollout::ChangedContainer;
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+/// Context of a rollout decision, sent to OPA as `input`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RolloutDecisionInput<'a> {
+    pub kind: &'a str,
+    pub namespace: &'a str,
+    pub name: &'a str,
+    pub changed_containers: &'a [ChangedContainer],
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PolicyDecision {
+    pub allowed: bool,
+    pub reason: Option<String>,
+}
+
+/// Accepts both a plain boolean rule result and an object with `allow` and an optional `reason`
+fn parse_decision(response: &Value) -> Result<PolicyDecision> {
+    match response.get("result") {
+        Some(Value::Bool(allowed)) => Ok(PolicyDecision {
+            allowed: *allowed,
+            reason: None,
+        }),
+        Some(Value::Object(result)) => {
+            let allowed = result
+                .get("allow")
+                .and_then(Value::as_bool)
+                .context("OPA decision is missing the boolean field allow")?;
+            let reason = result
+                .get("reason")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            Ok(PolicyDecision { allowed, reason })
+        }
+        // OPA omits the result when the queried rule is undefined
+        None => Ok(PolicyDecision {
+            allowed: false,
+            reason: Some("Policy rule is undefined".to_string()),
+        }),
+        Some(other) => bail!("Unexpected OPA decision {}", other),
+    }
+}
+
+async fn query_opa(
+    client: &Client,
+    policy: &Policy,
+    input: &RolloutDecisionInput<'_>,
+) -> Result<PolicyDecision> {
+    let response = client
+        .post(&policy.opa_url)
+        .timeout(policy.timeout)
+        .json(&json!({ "input": input }))
+        .send()
+        .await
+        .with_context(|| format!("Failed to query OPA at {}", policy.opa_url))?
+        .error_for_status()
+        .context("OPA returned an error status")?;
+
+    let body: Value = response
+        .json()
+        .await
+        .context("Failed to parse OPA response")?;
+    debug!(response = %body, "Received OPA decision");
+    parse_decision(&body)
+}
+
+/// Asks OPA whether the rollout may proceed. Errors deny the rollout unless the policy is configured to fail open.
+pub async fn authorize_rollout(
+    client: &Client,
+    policy: &Policy,
+    input: &RolloutDecisionInput<'_>,
+) -> PolicyDecision {
+    match query_opa(client, policy, input).await {
+        Ok(decision) => decision,
+        Err(err) => {
+            warn!(
+                error = ?err,
+                fail_open = %policy.fail_open,
+                "Failed to evaluate rollout policy"
+            );
+            PolicyDecision {
+                allowed: policy.fail_open,
+                reason: Some(format!("Policy evaluation failed: {:#}", err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_decision_handles_boolean_and_object_results() {
+        assert_eq!(
+            parse_decision(&json!({ "result": true })).unwrap(),
+            PolicyDecision {
+                allowed: true,
+                reason: None
+            }
+        );
+        assert_eq!(
+            parse_decision(&json!({ "result": { "allow": false, "reason": "change freeze" } }))
+                .unwrap(),
+            PolicyDecision {
+                allowed: false,
+                reason: Some("change freeze".to_string())
+            }
+        );
+        assert!(!parse_decision(&json!({})).unwrap().allowed);
+        assert!(parse_decision(&json!({ "result": "yes" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn authorize_rollout_posts_input_and_fails_closed() {
+        let app = Router::new().route(
+            "/v1/data/autorollout/allow",
+            post(|Json(body): Json<Value>| async move {
+                Json(json!({ "result": body["input"]["namespace"] == "staging" }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = Client::builder().no_proxy().build().unwrap();
+        let mut policy = Policy {
+            opa_url: format!("http://{}/v1/data/autorollout/allow", addr),
+            timeout: Duration::from_secs(5),
+            fail_open: false,
+        };
+        let input = |namespace| RolloutDecisionInput {
+            kind: "Deployment",
+            namespace,
+            name: "my-app",
+            changed_containers: &[],
+        };
+
+        assert!(
+            authorize_rollout(&client, &policy, &input("staging"))
+                .await
+                .allowed
+        );
+        assert!(
+            !authorize_rollout(&client, &policy, &input("production"))
+                .await
+                .allowed
+        );
+
+        policy.opa_url = format!("http://{}/missing", addr);
+        assert!(
+            !authorize_rollout(&client, &policy, &input("staging"))
+                .await
+                .allowed
+        );
+        policy.fail_open = true;
+        assert!(
+            authorize_rollout(&client, &policy, &input("staging"))
+                .await
+                .allowed
+        );
+    }
+}
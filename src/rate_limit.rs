@@ -0,0 +1,199 @@
+use crate::hostname;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Delay between two requests to a throttled registry whose rate limit headers neither name a window nor a reset
+const DEFAULT_THROTTLE_DELAY_SECONDS: i64 = 60;
+
+/// Rate limit a registry announced in the headers of its last manifest response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryRateLimit {
+    pub(crate) limit: u64,
+    pub(crate) remaining: u64,
+    /// Window the limit applies to, e.g. 21600 seconds on Docker Hub
+    pub(crate) window_seconds: Option<u64>,
+    /// Seconds until the limit is reset, if the registry announces it
+    pub(crate) reset_seconds: Option<u64>,
+    pub(crate) observed_at: DateTime<Utc>,
+}
+
+impl RegistryRateLimit {
+    /// Parses the `ratelimit-*` headers of Docker Hub, such as `ratelimit-remaining: 76;w=21600`, and the
+    /// `x-ratelimit-*` headers of Harbor and Artifactory
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .or_else(|| headers.get(format!("x-{}", name)))
+                .and_then(|value| value.to_str().ok())
+                .map(parse_quota)
+        };
+        let (limit, limit_window) = header("ratelimit-limit")?;
+        let (remaining, remaining_window) = header("ratelimit-remaining")?;
+        Some(RegistryRateLimit {
+            limit: limit?,
+            remaining: remaining?,
+            window_seconds: limit_window.or(remaining_window),
+            reset_seconds: header("ratelimit-reset").and_then(|(reset, _)| reset),
+            observed_at: Utc::now(),
+        })
+    }
+
+    /// Returns until when requests are held back because at most `min_remaining` requests are left. Requests are
+    /// spread over the time the registry needs to grant another one, or paused until the announced reset.
+    pub fn throttled_until(&self, min_remaining: u64) -> Option<DateTime<Utc>> {
+        if self.remaining > min_remaining {
+            return None;
+        }
+        let delay = match (self.reset_seconds, self.window_seconds) {
+            (Some(reset), _) => Duration::seconds(reset as i64),
+            (None, Some(window)) if self.limit > 0 => {
+                Duration::seconds((window / self.limit).max(1) as i64)
+            }
+            _ => Duration::seconds(DEFAULT_THROTTLE_DELAY_SECONDS),
+        };
+        Some(self.observed_at + delay).filter(|until| *until > Utc::now())
+    }
+}
+
+/// `100;w=21600` becomes the quota and its window in seconds
+fn parse_quota(value: &str) -> (Option<u64>, Option<u64>) {
+    let mut parts = value.split(';').map(str::trim);
+    let quota = parts.next().and_then(|quota| quota.parse().ok());
+    let window = parts
+        .find_map(|part| part.strip_prefix("w="))
+        .and_then(|window| window.parse().ok());
+    (quota, window)
+}
+
+/// Latest rate limit per normalized registry hostname
+#[derive(Default)]
+pub struct RateLimits(Mutex<BTreeMap<String, RegistryRateLimit>>);
+
+impl RateLimits {
+    pub fn record(&self, registry: &str, headers: &HeaderMap) {
+        if let Some(rate_limit) = RegistryRateLimit::from_headers(headers) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(hostname::normalize(registry), rate_limit);
+        }
+    }
+
+    pub fn get(&self, registry: &str) -> Option<RegistryRateLimit> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&hostname::normalize(registry))
+            .cloned()
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, RegistryRateLimit> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Registry with the fewest remaining requests, reported with each reconciliation run
+    pub fn lowest_remaining(&self) -> Option<(String, RegistryRateLimit)> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .min_by_key(|(_, rate_limit)| rate_limit.remaining)
+            .map(|(registry, rate_limit)| (registry.clone(), rate_limit.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn parses_docker_hub_and_x_ratelimit_headers() {
+        let docker_hub = RegistryRateLimit::from_headers(&headers(&[
+            ("ratelimit-limit", "100;w=21600"),
+            ("ratelimit-remaining", "76;w=21600"),
+            ("docker-ratelimit-source", "203.0.113.7"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            (
+                docker_hub.limit,
+                docker_hub.remaining,
+                docker_hub.window_seconds
+            ),
+            (100, 76, Some(21600))
+        );
+        assert_eq!(docker_hub.reset_seconds, None);
+
+        let harbor = RegistryRateLimit::from_headers(&headers(&[
+            ("x-ratelimit-limit", "500"),
+            ("x-ratelimit-remaining", "3"),
+            ("x-ratelimit-reset", "42"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            (harbor.limit, harbor.remaining, harbor.reset_seconds),
+            (500, 3, Some(42))
+        );
+
+        assert_eq!(
+            RegistryRateLimit::from_headers(&headers(&[("ratelimit-limit", "100;w=21600")])),
+            None
+        );
+    }
+
+    #[test]
+    fn low_budgets_throttle_until_another_request_is_granted() {
+        let mut rate_limit = RegistryRateLimit {
+            limit: 100,
+            remaining: 50,
+            window_seconds: Some(21600),
+            reset_seconds: None,
+            observed_at: Utc::now(),
+        };
+        assert_eq!(rate_limit.throttled_until(10), None);
+
+        rate_limit.remaining = 10;
+        assert_eq!(
+            rate_limit.throttled_until(10),
+            Some(rate_limit.observed_at + Duration::seconds(216))
+        );
+
+        rate_limit.reset_seconds = Some(30);
+        assert_eq!(
+            rate_limit.throttled_until(10),
+            Some(rate_limit.observed_at + Duration::seconds(30))
+        );
+
+        rate_limit.observed_at = Utc::now() - Duration::seconds(31);
+        assert_eq!(rate_limit.throttled_until(10), None);
+    }
+
+    #[test]
+    fn rate_limits_are_keyed_by_normalized_registry() {
+        let rate_limits = RateLimits::default();
+        rate_limits.record(
+            "registry-1.docker.io",
+            &headers(&[
+                ("ratelimit-limit", "100;w=21600"),
+                ("ratelimit-remaining", "5;w=21600"),
+            ]),
+        );
+        rate_limits.record("ghcr.io", &headers(&[]));
+        assert_eq!(rate_limits.get("docker.io").unwrap().remaining, 5);
+        assert!(rate_limits.get("ghcr.io").is_none());
+        assert_eq!(rate_limits.lowest_remaining().unwrap().0, "docker.io");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::acr;
+use crate::config::RegistrySecret;
+use crate::ecr;
+use crate::secret_string::SecretString;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Short-lived credentials are refreshed this long before they expire
+const CREDENTIAL_REFRESH_MARGIN_MINUTES: i64 = 60;
+
+/// Registry credentials obtained from a cloud provider, kept until shortly before they expire
+#[derive(Default)]
+pub struct CredentialCache {
+    credentials: Mutex<HashMap<String, (SecretString, DateTime<Utc>)>>,
+}
+
+impl CredentialCache {
+    fn get(&self, key: &str, now: DateTime<Utc>) -> Option<SecretString> {
+        self.credentials
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|(_, refresh_at)| *refresh_at > now)
+            .map(|(credential, _)| credential.clone())
+    }
+
+    fn insert(&self, key: String, credential: SecretString, expires_at: DateTime<Utc>) {
+        let refresh_at = expires_at - Duration::minutes(CREDENTIAL_REFRESH_MARGIN_MINUTES);
+        self.credentials
+            .lock()
+            .unwrap()
+            .insert(key, (credential, refresh_at));
+    }
+}
+
+/// Exchanges the credentials of secret types backed by a cloud provider for registry credentials. All other
+/// secrets are returned as they are.
+pub async fn resolve_registry_secret(
+    client: &Client,
+    cache: &CredentialCache,
+    registry_host: &str,
+    registry_secret: RegistrySecret,
+) -> Result<RegistrySecret> {
+    match &registry_secret {
+        RegistrySecret::EcrIam { region } => {
+            let region = match region {
+                Some(region) => region.as_str(),
+                None => ecr::region_from_hostname(registry_host).with_context(|| {
+                    format!(
+                        "Could not derive the ECR region from {}, set the region of the EcrIam secret",
+                        registry_host
+                    )
+                })?,
+            };
+            let key = format!("ecr {}", region);
+            if let Some(authorization) = cache.get(&key, Utc::now()) {
+                return Ok(RegistrySecret::Basic { authorization });
+            }
+            let token = ecr::get_authorization_token(client, region)
+                .await
+                .with_context(|| format!("Failed to authenticate to ECR in {}", region))?;
+            cache.insert(key, token.authorization.clone(), token.expires_at);
+            Ok(RegistrySecret::Basic {
+                authorization: token.authorization,
+            })
+        }
+        RegistrySecret::AzureManagedIdentity {
+            client_id,
+            tenant_id,
+        } => {
+            // refresh tokens are issued per registry
+            let key = format!("acr {}", registry_host);
+            if let Some(authorization) = cache.get(&key, Utc::now()) {
+                return Ok(RegistrySecret::Basic { authorization });
+            }
+            let credential = acr::get_refresh_token(
+                client,
+                registry_host,
+                client_id.as_deref(),
+                tenant_id.as_deref(),
+            )
+            .await
+            .with_context(|| format!("Failed to authenticate to ACR {}", registry_host))?;
+            cache.insert(key, credential.authorization.clone(), credential.expires_at);
+            Ok(RegistrySecret::Basic {
+                authorization: credential.authorization,
+            })
+        }
+        _ => Ok(registry_secret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_refreshed_before_they_expire() {
+        let cache = CredentialCache::default();
+        let now = Utc::now();
+        cache.insert(
+            "ecr eu-central-1".to_string(),
+            SecretString::new("token".to_string()),
+            now + Duration::hours(12),
+        );
+
+        assert!(cache.get("ecr eu-central-1", now).is_some());
+        assert!(cache
+            .get(
+                "ecr eu-central-1",
+                now + Duration::hours(11) + Duration::minutes(1)
+            )
+            .is_none());
+        assert!(cache.get("ecr us-east-1", now).is_none());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::config::{Config, Registry, RetryPolicy};
+use crate::hostname;
+use crate::image_reference::ImageReference;
+use crate::oci_registry::{
+    get_authorization_header, handle_oauth_authentication_challenge, head_manifest,
+    registry_base_url, HttpClients,
+};
+use crate::registry_credentials::{resolve_registry_secret, CredentialCache};
+use crate::registry_requests::RegistryRequestLog;
+use crate::state::ControllerContext;
+use crate::token_cache::TokenCache;
+use chrono::{DateTime, Utc};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ProbeStatus {
+    /// The registry accepted the configured credentials (or anonymous access)
+    Ok,
+    /// The registry is reachable but rejected the configured credentials
+    Unauthorized,
+    /// The registry could not be reached or answered unexpectedly
+    Failed,
+    /// The hostname pattern is a glob, there is no concrete host to probe
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryProbeResult {
+    pub(crate) hostname_pattern: String,
+    pub(crate) status: ProbeStatus,
+    pub(crate) message: String,
+    pub(crate) checked_at: DateTime<Utc>,
+}
+
+/// Probes every configured registry and records the results in the controller state
+pub async fn probe_registries(ctx: ControllerContext) {
+    for registry in &ctx.config.registries {
+        let client = ctx.registry_http_client(&registry.hostname_pattern);
+        let result = probe_registry(&client, registry).await;
+        match result.status {
+            ProbeStatus::Ok => info!(
+                registry = %result.hostname_pattern,
+                message = %result.message,
+                "Registry connectivity probe succeeded"
+            ),
+            ProbeStatus::Skipped => info!(
+                registry = %result.hostname_pattern,
+                message = %result.message,
+                "Registry connectivity probe skipped"
+            ),
+            _ => warn!(
+                registry = %result.hostname_pattern,
+                status = ?result.status,
+                message = %result.message,
+                "Registry connectivity probe failed"
+            ),
+        }
+        ctx.state
+            .write()
+            .unwrap()
+            .registry_probes
+            .insert(result.hostname_pattern.clone(), result);
+    }
+}
+
+pub async fn probe_registry(client: &Client, registry: &Registry) -> RegistryProbeResult {
+    let (status, message) = match probe_host(client, registry).await {
+        Ok(outcome) => outcome,
+        Err(e) => (ProbeStatus::Failed, format!("{:#}", e)),
+    };
+
+    RegistryProbeResult {
+        hostname_pattern: registry.hostname_pattern.clone(),
+        status,
+        message,
+        checked_at: Utc::now(),
+    }
+}
+
+/// Outcome of the preflight credential check of a single registry
+#[derive(Debug)]
+pub struct CredentialCheck {
+    pub probe: RegistryProbeResult,
+    /// HEAD request results for the test images served by this registry
+    pub manifests: Vec<(String, Result<(), String>)>,
+}
+
+impl CredentialCheck {
+    pub fn passed(&self) -> bool {
+        !matches!(
+            self.probe.status,
+            ProbeStatus::Unauthorized | ProbeStatus::Failed
+        ) && self.manifests.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+/// Pings every configured registry with its credentials and requests the manifests of the test images it serves
+pub async fn check_credentials(
+    clients: &HttpClients,
+    config: &Config,
+    test_images: &[ImageReference],
+) -> Vec<CredentialCheck> {
+    // credentials are verified by exchanging them, so tokens are never taken from a cache
+    let tokens = TokenCache::new(0);
+    let credentials = CredentialCache::default();
+    let mut checks = Vec::new();
+    for registry in &config.registries {
+        let client = clients.for_registry(&registry.hostname_pattern);
+        let probe = probe_registry(&client, registry).await;
+
+        let mut manifests = Vec::new();
+        for image in test_images {
+            let serving_registry = config.find_registry_for_hostname(&image.registry);
+            if serving_registry.map(|r| &r.hostname_pattern) != Some(&registry.hostname_pattern) {
+                continue;
+            }
+            let secret = resolve_registry_secret(
+                &client,
+                &credentials,
+                &image.registry,
+                registry.auth_secret(),
+            );
+            let result = match secret.await {
+                Ok(secret) => {
+                    let endpoint = config.registry_endpoint(image);
+                    head_manifest(
+                        &client,
+                        &tokens,
+                        &config.registry_retry,
+                        &RegistryRequestLog::default(),
+                        &endpoint,
+                        &secret,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            let result = match result {
+                Ok(status) if status.is_success() => Ok(()),
+                Ok(status) => Err(format!("Registry answered {}", status)),
+                Err(e) => Err(format!("{:#}", e)),
+            };
+            manifests.push((image.to_string(), result));
+        }
+
+        checks.push(CredentialCheck { probe, manifests });
+    }
+    checks
+}
+
+fn is_glob_pattern(hostname_pattern: &str) -> bool {
+    hostname_pattern.contains(['*', '?', '[', '{'])
+}
+
+async fn probe_host(client: &Client, registry: &Registry) -> anyhow::Result<(ProbeStatus, String)> {
+    if is_glob_pattern(&registry.hostname_pattern) {
+        return Ok((
+            ProbeStatus::Skipped,
+            "Hostname pattern contains wildcards".to_string(),
+        ));
+    }
+
+    let host = hostname::api_host(&registry.hostname_pattern);
+    let secret = resolve_registry_secret(
+        client,
+        &CredentialCache::default(),
+        host,
+        registry.auth_secret(),
+    )
+    .await?;
+    let url = format!("{}/v2/", registry_base_url(&registry.endpoint(host)));
+    let response = client
+        .get(&url)
+        .header(AUTHORIZATION, get_authorization_header(&secret, host))
+        .send()
+        .await?;
+
+    match response.status() {
+        StatusCode::OK => Ok((ProbeStatus::Ok, format!("{} answered 200", url))),
+        StatusCode::UNAUTHORIZED => {
+            let Some(challenge) = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| value.starts_with("Bearer "))
+            else {
+                return Ok((
+                    ProbeStatus::Unauthorized,
+                    format!("{} rejected the configured credentials", url),
+                ));
+            };
+
+            // token based registries answer the ping with a challenge, exchanging the credentials verifies them
+            let tokens = TokenCache::new(0);
+            // probes report the registry's current state, transient errors are not retried
+            let retry = RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            };
+            match handle_oauth_authentication_challenge(
+                client,
+                &tokens,
+                &retry,
+                &RegistryRequestLog::default(),
+                host,
+                &secret,
+                challenge,
+                None,
+            )
+            .await
+            {
+                Ok(_) => Ok((
+                    ProbeStatus::Ok,
+                    format!("Token exchange with {} succeeded", host),
+                )),
+                Err(e) => Ok((
+                    ProbeStatus::Unauthorized,
+                    format!("Token exchange failed: {:#}", e),
+                )),
+            }
+        }
+        StatusCode::FORBIDDEN => Ok((ProbeStatus::Unauthorized, format!("{} answered 403", url))),
+        status => Ok((
+            ProbeStatus::Failed,
+            format!("{} answered unexpected status {}", url, status),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthMode, DigestComparison, RegistrySecret};
+    use crate::mock_registry::{self, MockAuth, MockRegistryConfig};
+    use crate::secret_string::SecretString;
+
+    async fn probe_mock(auth: MockAuth, secret: RegistrySecret) -> RegistryProbeResult {
+        let mock = mock_registry::spawn(
+            MockRegistryConfig {
+                auth,
+                ..Default::default()
+            },
+            "127.0.0.1:0".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+        let registry = Registry {
+            hostname_pattern: mock.host(),
+            secret,
+            fallback_hosts: Vec::new(),
+            scheme: None,
+            port: None,
+            insecure: false,
+            tls: None,
+            proxy: None,
+            digest_comparison: DigestComparison::Manifest,
+            priority: 0,
+            auth_mode: AuthMode::Auto,
+            resolve_to: Default::default(),
+        };
+        let client = Client::builder().no_proxy().build().unwrap();
+        probe_registry(&client, &registry).await
+    }
+
+    fn opaque(token: &str) -> RegistrySecret {
+        RegistrySecret::Opaque {
+            username: None,
+            token: SecretString::new(token.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_anonymous_registry() {
+        let result = probe_mock(MockAuth::None, RegistrySecret::None).await;
+        assert_eq!(result.status, ProbeStatus::Ok);
+    }
+
+    #[tokio::test]
+    async fn probe_registry_with_token_challenge() {
+        let result = probe_mock(
+            MockAuth::TokenChallenge {
+                token: "exchanged".to_string(),
+            },
+            opaque("credentials"),
+        )
+        .await;
+        assert_eq!(result.status, ProbeStatus::Ok, "{}", result.message);
+    }
+
+    #[tokio::test]
+    async fn probe_registry_with_rejected_credentials() {
+        let result = probe_mock(
+            MockAuth::Bearer {
+                token: "expected".to_string(),
+            },
+            opaque("wrong"),
+        )
+        .await;
+        assert_eq!(result.status, ProbeStatus::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn check_credentials_requests_test_image_manifests() {
+        let mock = mock_registry::spawn(
+            MockRegistryConfig {
+                manifests: vec![mock_registry::MockManifest {
+                    repository: "org/app".to_string(),
+                    tag: "latest".to_string(),
+                    media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+                    digest: "sha256:app".to_string(),
+                    manifests: Vec::new(),
+                }],
+                auth: MockAuth::TokenChallenge {
+                    token: "exchanged".to_string(),
+                },
+                ..Default::default()
+            },
+            "127.0.0.1:0".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+        let yaml = format!(
+            r#"
+        webserver:
+          port: 8080
+        registries:
+          - hostnamePattern: "{}"
+            secret:
+              type: Opaque
+              token: credentials
+        "#,
+            mock.host()
+        );
+        let mut config: Config = serde_yaml_ng::from_str(&yaml).unwrap();
+        config.setup_glob_set().unwrap();
+        let test_images = vec![
+            ImageReference::parse(&format!("{}/org/app:latest", mock.host())).unwrap(),
+            ImageReference::parse(&format!("{}/org/missing:latest", mock.host())).unwrap(),
+        ];
+
+        let client = Client::builder().no_proxy().build().unwrap();
+        let checks = check_credentials(&client.into(), &config, &test_images).await;
+
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].probe.status, ProbeStatus::Ok);
+        assert!(checks[0].manifests[0].1.is_ok());
+        assert!(checks[0].manifests[1].1.is_err());
+        assert!(!checks[0].passed());
+    }
+
+    #[tokio::test]
+    async fn probe_skips_glob_patterns() {
+        let registry = Registry {
+            hostname_pattern: "*.example.com".to_string(),
+            secret: RegistrySecret::None,
+            fallback_hosts: Vec::new(),
+            scheme: None,
+            port: None,
+            insecure: false,
+            tls: None,
+            proxy: None,
+            digest_comparison: DigestComparison::Manifest,
+            priority: 0,
+            auth_mode: AuthMode::Auto,
+            resolve_to: Default::default(),
+        };
+        let result = probe_registry(&Client::new(), &registry).await;
+        assert_eq!(result.status, ProbeStatus::Skipped);
+    }
+}
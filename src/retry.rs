@@ -0,0 +1,229 @@
+use crate::config::RetryPolicy;
+use crate::registry_requests::{redact_headers, redact_url, RegistryRequest, RegistryRequestLog};
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Sends the request built by `build` and retries it with exponential backoff while the registry answers with a
+/// transient error status or the request times out. The registry's `Retry-After` is honored over the backoff.
+/// Connection errors are not retried, unreachable registries are handled by the failover and cooldown instead.
+/// Every attempt is kept in the request log.
+pub async fn send_with_retry(
+    policy: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    build: impl Fn() -> RequestBuilder,
+) -> reqwest::Result<Response> {
+    let mut attempt = 1;
+    loop {
+        let result = send_logged(requests, attempt, build()).await;
+        let retry_after = match &result {
+            Ok(response) if is_transient(response.status()) => {
+                parse_retry_after(response, Utc::now())
+            }
+            Err(err) if err.is_timeout() && !err.is_connect() => None,
+            _ => return result,
+        };
+        if attempt >= policy.max_attempts {
+            return result;
+        }
+
+        let delay = retry_after
+            .unwrap_or_else(|| backoff(policy, attempt))
+            .min(policy.max_delay);
+        warn!(
+            url = ?result.as_ref().map(|response| response.url().to_string()).ok(),
+            status = ?result.as_ref().map(|response| response.status()).ok(),
+            error = ?result.as_ref().err(),
+            attempt = %attempt,
+            delay = ?delay,
+            "Registry request failed transiently, retrying"
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+async fn send_logged(
+    requests: &RegistryRequestLog,
+    attempt: u32,
+    builder: RequestBuilder,
+) -> reqwest::Result<Response> {
+    if !requests.is_enabled() {
+        return builder.send().await;
+    }
+    let (client, request) = builder.build_split();
+    let request = request?;
+    let method = request.method().to_string();
+    let url = redact_url(request.url());
+    let request_headers = redact_headers(request.headers());
+    let sent_at = Utc::now();
+    let started = Instant::now();
+    let result = client.execute(request).await;
+    requests.record(RegistryRequest {
+        method,
+        url,
+        sent_at,
+        latency_ms: started.elapsed().as_millis() as u64,
+        attempt,
+        status: result
+            .as_ref()
+            .ok()
+            .map(|response| response.status().as_u16()),
+        error: result.as_ref().err().map(|err| err.to_string()),
+        request_headers,
+        response_headers: result
+            .as_ref()
+            .map(|response| redact_headers(response.headers()))
+            .unwrap_or_default(),
+    });
+    result
+}
+
+/// Quay answers with `202 Accepted` while it re-fetches an expired manifest of a proxy cache
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::ACCEPTED
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let backoff = policy
+        .base_delay
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(policy.max_delay);
+    if !policy.jitter {
+        return backoff;
+    }
+    let mut random = [0u8; 4];
+    let fraction = match SystemRandom::new().fill(&mut random) {
+        Ok(()) => u32::from_le_bytes(random) as f64 / u32::MAX as f64,
+        Err(_) => 1.0,
+    };
+    backoff.mul_f64(0.5 + fraction / 2.0)
+}
+
+/// Parses `Retry-After` given either in seconds or as HTTP date
+fn parse_retry_after(response: &Response, now: DateTime<Utc>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (retry_at.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header::RETRY_AFTER;
+    use axum::response::IntoResponse;
+
+    fn response(retry_after: &str) -> Response {
+        axum::http::Response::builder()
+            .status(503)
+            .header(RETRY_AFTER, retry_after)
+            .body("")
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn retry_after_is_parsed_from_seconds_and_dates() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after(&response("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(&response("Wed, 21 Oct 2026 07:28:30 GMT"), now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after(&response("Wed, 21 Oct 2026 07:27:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(&response("soon"), now), None);
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let requests = Arc::new(AtomicU32::new(0));
+        let counter = requests.clone();
+        let app = axum::Router::new().fallback(move || {
+            let counter = counter.clone();
+            async move {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, "0")]).into_response(),
+                    _ => StatusCode::OK.into_response(),
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v2/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let policy = RetryPolicy::default();
+        let log = RegistryRequestLog::new(10);
+        let response = send_with_retry(&policy, &log, || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let logged: Vec<_> = log
+            .snapshot()
+            .into_iter()
+            .map(|request| (request.attempt, request.status))
+            .collect();
+        assert_eq!(logged, [(2, Some(200)), (1, Some(503))]);
+
+        let single_attempt = RetryPolicy {
+            max_attempts: 1,
+            ..policy
+        };
+        requests.store(0, Ordering::SeqCst);
+        let response = send_with_retry(&single_attempt, &log, || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+            jitter: false,
+        };
+        assert_eq!(backoff(&policy, 1), Duration::from_millis(500));
+        assert_eq!(backoff(&policy, 3), Duration::from_secs(2));
+        assert_eq!(backoff(&policy, 4), Duration::from_secs(3));
+
+        let jittered = backoff(
+            &RetryPolicy {
+                jitter: true,
+                ..policy
+            },
+            3,
+        );
+        assert!(jittered >= Duration::from_secs(1) && jittered <= Duration::from_secs(2));
+    }
+}
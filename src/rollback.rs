@@ -0,0 +1,265 @@
+use crate::controller::describe_changed_container;
+use crate::events::{publish, workload_reference};
+use crate::history::{self, HistoryChange, HistoryEntry, RolloutResult};
+use crate::notify::{self, Notification};
+use crate::rollout::{ChangedContainer, Rollout};
+use crate::state::ControllerContext;
+use chrono::{DateTime, SubsecRound, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::ListParams;
+use kube::runtime::events::EventType;
+use kube::{Api, ResourceExt};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Waiting reasons of containers that will not start without another change to the workload
+static FAILED_CONTAINER_REASONS: &[&str] =
+    &["CrashLoopBackOff", "ImagePullBackOff", "ErrImagePull"];
+
+/// Watches the pods created by a rollout during the bake period in the background and rolls the workload back to the
+/// previous digests as soon as one of them fails to start
+pub fn spawn<T: Rollout>(
+    ctx: &ControllerContext,
+    api: Api<T>,
+    resource: &T,
+    kind: &str,
+    namespace: &str,
+    changed_containers: Vec<ChangedContainer>,
+) {
+    let Some(rollback) = ctx.config.auto_rollback.clone() else {
+        return;
+    };
+    let ctx = ctx.clone();
+    let resource = resource.clone();
+    let (kind, namespace) = (kind.to_string(), namespace.to_string());
+    // creation timestamps of pods only have a precision of seconds
+    let triggered_at = Utc::now().trunc_subsecs(0);
+    tokio::spawn(async move {
+        let pods: Api<Pod> = Api::namespaced(ctx.kube_client.clone(), &namespace);
+        let Some(failure) = find_failed_pod(
+            &pods,
+            rollback.bake_period,
+            rollback.poll_interval,
+            &resource.selector(),
+            triggered_at,
+        )
+        .await
+        else {
+            return;
+        };
+        roll_back(
+            &ctx,
+            &api,
+            &resource,
+            &kind,
+            &namespace,
+            &changed_containers,
+            &failure,
+        )
+        .await;
+    });
+}
+
+/// Returns why a pod created after the rollout failed, None if all of them started during the given period
+pub(crate) async fn find_failed_pod(
+    pods: &Api<Pod>,
+    period: Duration,
+    poll_interval: Duration,
+    selector: &BTreeMap<String, String>,
+    triggered_at: DateTime<Utc>,
+) -> Option<String> {
+    let label_selector = selector
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(",");
+    let deadline = Instant::now() + period;
+    while Instant::now() < deadline {
+        tokio::time::sleep(poll_interval).await;
+        let pod_list = match pods
+            .list(&ListParams::default().labels(&label_selector))
+            .await
+        {
+            Ok(pod_list) => pod_list,
+            Err(err) => {
+                warn!(error = ?err, "Failed to list pods to check the rollout");
+                continue;
+            }
+        };
+        if let Some(failure) = pod_list
+            .items
+            .iter()
+            .filter(|pod| created_at(pod).is_some_and(|created_at| created_at >= triggered_at))
+            .find_map(pod_failure)
+        {
+            return Some(failure);
+        }
+    }
+    None
+}
+
+/// Creation time of the pod, read from its JSON representation as the time types differ between API versions
+fn created_at(pod: &Pod) -> Option<DateTime<Utc>> {
+    let created_at = serde_json::to_value(&pod.metadata.creation_timestamp).ok()?;
+    DateTime::parse_from_rfc3339(created_at.as_str()?)
+        .ok()
+        .map(|created_at| created_at.with_timezone(&Utc))
+}
+
+fn pod_failure(pod: &Pod) -> Option<String> {
+    pod.status
+        .as_ref()?
+        .container_statuses
+        .as_ref()?
+        .iter()
+        .find_map(|status| {
+            let waiting = status.state.as_ref()?.waiting.as_ref()?;
+            let reason = waiting.reason.as_deref()?;
+            FAILED_CONTAINER_REASONS.contains(&reason).then(|| {
+                format!(
+                    "container {} of pod {} is in {}",
+                    status.name,
+                    pod.metadata.name.as_deref().unwrap_or_default(),
+                    reason
+                )
+            })
+        })
+}
+
+async fn roll_back<T: Rollout>(
+    ctx: &ControllerContext,
+    api: &Api<T>,
+    resource: &T,
+    kind: &str,
+    namespace: &str,
+    changed_containers: &[ChangedContainer],
+    failure: &str,
+) {
+    let name = resource.name_any();
+    let result = T::rollback(api, resource, changed_containers).await;
+    let mut details: Vec<String> = changed_containers
+        .iter()
+        .map(describe_changed_container)
+        .collect();
+    details.push(format!("reason: {}", failure));
+    let title = match &result {
+        Ok(()) => {
+            warn!(
+                kind = %kind,
+                resource = %name,
+                namespace = %namespace,
+                reason = %failure,
+                "Rolled back rollout whose pods failed to start"
+            );
+            publish(
+                ctx,
+                workload_reference(kind, namespace, &name),
+                EventType::Warning,
+                "RolledBack",
+                "Rollback",
+                format!("Rolled back to the previous digests, {}", failure),
+            );
+            format!("Rolled back {} {}/{}", kind, namespace, name)
+        }
+        Err(err) => {
+            warn!(
+                error = ?err,
+                kind = %kind,
+                resource = %name,
+                namespace = %namespace,
+                "Failed to roll back rollout whose pods failed to start"
+            );
+            details.push(format!("error: {:#}", err));
+            format!("Rolling back {} {}/{} failed", kind, namespace, name)
+        }
+    };
+    notify::send(
+        ctx,
+        Notification {
+            title,
+            details,
+            failed: true,
+            images: Vec::new(),
+        },
+    );
+    history::record(
+        ctx,
+        kind,
+        namespace,
+        &name,
+        HistoryEntry {
+            timestamp: Utc::now(),
+            result: match result {
+                Ok(()) => RolloutResult::RolledBack,
+                Err(_) => RolloutResult::Failed,
+            },
+            error: Some(failure.to_string()),
+            containers: changed_containers
+                .iter()
+                .map(HistoryChange::from_changed_container)
+                .collect(),
+        },
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pods_waiting_in_back_off_have_failed() {
+        let pod: Pod = serde_json::from_value(json!({
+            "metadata": { "name": "app-7c9f", "creationTimestamp": "2026-10-16T10:00:00Z" },
+            "status": {
+                "containerStatuses": [
+                    {
+                        "name": "istio-proxy",
+                        "image": "istio/proxyv2",
+                        "imageID": "",
+                        "ready": true,
+                        "restartCount": 0,
+                        "state": { "running": {} },
+                    },
+                    {
+                        "name": "app",
+                        "image": "ghcr.io/org/app:latest",
+                        "imageID": "",
+                        "ready": false,
+                        "restartCount": 4,
+                        "state": { "waiting": { "reason": "CrashLoopBackOff" } },
+                    },
+                ],
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            pod_failure(&pod).as_deref(),
+            Some("container app of pod app-7c9f is in CrashLoopBackOff")
+        );
+        assert_eq!(
+            created_at(&pod),
+            Some(
+                DateTime::parse_from_rfc3339("2026-10-16T10:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+
+        let mut starting = pod.clone();
+        starting
+            .status
+            .as_mut()
+            .unwrap()
+            .container_statuses
+            .as_mut()
+            .unwrap()[1]
+            .state =
+            serde_json::from_value(json!({ "waiting": { "reason": "ContainerCreating" } }))
+                .unwrap();
+        assert_eq!(pod_failure(&starting), None);
+    }
+}
@@ -1,19 +1,312 @@
+use crate::config::{RestartTrigger, RollingUpdateOverrides};
+use crate::rollout_strategy::RolloutStrategy;
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
-use k8s_openapi::api::core::v1::PodSpec;
+use k8s_openapi::api::core::v1::{Pod, PodSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use k8s_openapi::NamespaceResourceScope;
 use kube::api::{Patch, PatchParams};
-use kube::{Api, Resource};
+use kube::{Api, Resource, ResourceExt};
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
-use tracing::debug;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, info, warn};
 
-static KUBE_AUTOROLLOUT_ANNOTATION: &str = "kube-autorollout/restartedAt";
+pub(crate) static KUBE_AUTOROLLOUT_ANNOTATION: &str = "kube-autorollout/restartedAt";
 static KUBE_AUTOROLLOUT_FIELD_MANAGER: &str = "kube-autorollout";
-static KUBECTL_ROLLOUT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+pub(crate) static KUBECTL_ROLLOUT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+static KUBE_AUTOROLLOUT_CHANGED_CONTAINERS_ANNOTATION: &str = "kube-autorollout/changedContainers";
+static KUBE_AUTOROLLOUT_RESTARTED_AT_ENV: &str = "KUBE_AUTOROLLOUT_RESTARTED_AT";
+static KUBE_AUTOROLLOUT_PAUSED_ANNOTATION: &str = "kube-autorollout/paused";
+static KUBE_AUTOROLLOUT_DRY_RUN_ANNOTATION: &str = "kube-autorollout/dry-run";
+static KUBE_AUTOROLLOUT_CONTAINERS_ANNOTATION: &str = "kube-autorollout/containers";
+static KUBE_AUTOROLLOUT_EXCLUDE_CONTAINERS_ANNOTATION: &str = "kube-autorollout/exclude-containers";
+static KUBE_AUTOROLLOUT_RESTART_TRIGGER_ANNOTATION: &str = "kube-autorollout/restart-trigger";
+static KUBE_AUTOROLLOUT_COOLDOWN_ANNOTATION: &str = "kube-autorollout/cooldown";
+/// Registry host queried for the images of the workload, e.g. a mirror of a cluster-local registry alias
+static KUBE_AUTOROLLOUT_REGISTRY_ANNOTATION: &str = "kube-autorollout/registry";
+static KUBE_AUTOROLLOUT_TRACK_TAG_ANNOTATION: &str = "kube-autorollout/track-tag";
+static KUBE_AUTOROLLOUT_LAST_ROLLOUT_ANNOTATION: &str = "kube-autorollout/lastRolloutAt";
+/// Digests rolled back per container, which are not rolled out again
+static KUBE_AUTOROLLOUT_ROLLED_BACK_DIGESTS_ANNOTATION: &str = "kube-autorollout/rolledBackDigests";
+static KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION: &str =
+    "kube-autorollout/originalRollingUpdate";
+/// Hash of the changes the pod template was last patched for, so a pending rollout is not triggered again
+static KUBE_AUTOROLLOUT_CHANGE_HASH_ANNOTATION: &str = "kube-autorollout/changeHash";
+
+/// A container whose running digest is no longer among the recent digests of its image tag
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedContainer {
+    #[serde(rename = "container")]
+    pub container_name: String,
+    pub image: String,
+    pub previous_digest: String,
+    /// Digest the image tag resolves to now
+    #[serde(default)]
+    pub new_digest: String,
+    /// Whether the container is an init or sidecar container of the pod template
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub init_container: bool,
+    /// Image the container ran before its tag policy selected the newer tag in `image`, only set for tag updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_image: Option<String>,
+}
+
+impl ChangedContainer {
+    /// Whether the tag policy of the workload moves the container to a newer tag, instead of its tag resolving to a
+    /// new digest
+    pub fn is_tag_update(&self) -> bool {
+        self.previous_image.is_some()
+    }
+
+    /// What the container is rolled out to: the newer image of a tag update, the new digest otherwise
+    pub fn target(&self) -> &str {
+        match self.previous_image {
+            Some(_) => &self.image,
+            None => &self.new_digest,
+        }
+    }
+}
+
+/// Image to set on a container of the pod template
+struct ContainerImage<'a> {
+    name: &'a str,
+    init_container: bool,
+    image: String,
+}
+
+/// Builds the pod template patch restarting the workload once for all changed containers by setting the annotation
+pub(crate) fn build_rollout_patch(
+    annotation: &str,
+    changed_containers: &[ChangedContainer],
+) -> anyhow::Result<serde_json::Value> {
+    let changed_containers = serde_json::to_string(changed_containers)
+        .context("Failed to serialize changed containers")?;
+
+    Ok(json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        annotation: Utc::now().to_rfc3339(),
+                        KUBE_AUTOROLLOUT_CHANGED_CONTAINERS_ANNOTATION: changed_containers,
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// Builds a strategic merge patch setting the restart environment variable of every changed container.
+/// Containers and their environment variables are merged by name, so other containers and variables are kept.
+pub(crate) fn build_env_rollout_patch(
+    changed_containers: &[ChangedContainer],
+) -> serde_json::Value {
+    let restarted_at = Utc::now().to_rfc3339();
+    build_pod_spec_patch(changed_containers.iter().map(|container| {
+        (
+            container.init_container,
+            json!({
+                "name": container.container_name,
+                "env": [{ "name": KUBE_AUTOROLLOUT_RESTARTED_AT_ENV, "value": restarted_at }],
+            }),
+        )
+    }))
+}
+
+/// Builds a strategic merge patch pinning the image of every changed container to its new digest. The tag is kept
+/// in front of the digest, the container runtime pulls by digest while the tag remains tracked.
+pub(crate) fn build_pin_digest_patch(changed_containers: &[ChangedContainer]) -> serde_json::Value {
+    let images: Vec<_> = changed_containers
+        .iter()
+        .map(|container| ContainerImage {
+            name: &container.container_name,
+            init_container: container.init_container,
+            image: format!("{}@{}", container.image, container.new_digest),
+        })
+        .collect();
+    build_container_image_patch(&images)
+}
+
+/// Builds a strategic merge patch pinning the image of every changed container back to its previous digest and
+/// recording the new digests as rolled back. As the tag may still resolve to the new digest, only pinning the
+/// previous digest actually reverts the pods.
+fn build_rollback_patch(
+    changed_containers: &[ChangedContainer],
+    mut rolled_back_digests: BTreeMap<String, String>,
+) -> anyhow::Result<serde_json::Value> {
+    let images: Vec<_> = changed_containers
+        .iter()
+        .map(|container| ContainerImage {
+            name: &container.container_name,
+            init_container: container.init_container,
+            image: format!("{}@{}", container.image, container.previous_digest),
+        })
+        .collect();
+    rolled_back_digests.extend(changed_containers.iter().map(|container| {
+        (
+            container.container_name.clone(),
+            container.new_digest.clone(),
+        )
+    }));
+    let mut patch = build_container_image_patch(&images);
+    patch["metadata"]["annotations"][KUBE_AUTOROLLOUT_ROLLED_BACK_DIGESTS_ANNOTATION] =
+        json!(serde_json::to_string(&rolled_back_digests)?);
+    Ok(patch)
+}
+
+/// Builds a strategic merge patch setting the image of the named containers, other containers are kept
+fn build_container_image_patch(images: &[ContainerImage]) -> serde_json::Value {
+    build_pod_spec_patch(images.iter().map(|image| {
+        (
+            image.init_container,
+            json!({ "name": image.name, "image": image.image }),
+        )
+    }))
+}
+
+/// Builds a strategic merge patch of the pod template spec from container patches, which are placed in
+/// `initContainers` or `containers` depending on their flag
+fn build_pod_spec_patch(
+    container_patches: impl Iterator<Item = (bool, serde_json::Value)>,
+) -> serde_json::Value {
+    let (init_containers, containers): (Vec<_>, Vec<_>) =
+        container_patches.partition(|(init_container, _)| *init_container);
+    let mut spec = json!({
+        "containers": containers.into_iter().map(|(_, patch)| patch).collect::<Vec<_>>(),
+    });
+    if !init_containers.is_empty() {
+        spec["initContainers"] = json!(init_containers
+            .into_iter()
+            .map(|(_, patch)| patch)
+            .collect::<Vec<_>>());
+    }
+
+    json!({
+        "spec": {
+            "template": {
+                "spec": spec,
+            }
+        }
+    })
+}
+
+/// Deterministic hash of the new digests, or newer images, of the changed containers, independent of their order and
+/// of the time of the rollout
+pub fn change_hash(changed_containers: &[ChangedContainer]) -> String {
+    let mut changes: Vec<_> = changed_containers
+        .iter()
+        .map(|changed| {
+            format!(
+                "{}:{}@{}",
+                if changed.init_container {
+                    "init"
+                } else {
+                    "app"
+                },
+                changed.container_name,
+                changed.target()
+            )
+        })
+        .collect();
+    changes.sort();
+    let digest = ring::digest::digest(&ring::digest::SHA256, changes.join(";").as_bytes());
+    digest.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Records the hash of the changes on the pod template along with the restart
+pub(crate) fn with_change_hash_annotation(
+    mut patch: serde_json::Value,
+    changed_containers: &[ChangedContainer],
+) -> serde_json::Value {
+    patch["spec"]["template"]["metadata"]["annotations"][KUBE_AUTOROLLOUT_CHANGE_HASH_ANNOTATION] =
+        json!(change_hash(changed_containers));
+    patch
+}
+
+/// Records the time of the rollout on the workload itself, so the rollout cooldown survives controller restarts.
+/// The annotation is set on the workload's metadata and not its pod template, so it never restarts pods itself.
+pub(crate) fn with_last_rollout_annotation(mut patch: serde_json::Value) -> serde_json::Value {
+    patch["metadata"]["annotations"][KUBE_AUTOROLLOUT_LAST_ROLLOUT_ANNOTATION] =
+        json!(Utc::now().to_rfc3339());
+    patch
+}
+
+/// Rolling update parameters of a workload, unset parameters are serialized as null so that restoring them
+/// through a merge patch removes the temporary override again
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollingUpdateParameters {
+    #[serde(rename = "maxSurge")]
+    pub max_surge: Option<IntOrString>,
+    #[serde(rename = "maxUnavailable")]
+    pub max_unavailable: Option<IntOrString>,
+}
+
+/// Builds the merge patch applying the overrides. The original parameters are only recorded if no override is
+/// active yet, so repeated rollouts never record the overrides as the original values.
+fn build_rolling_update_override_patch(
+    strategy_field: &str,
+    original: Option<&RollingUpdateParameters>,
+    overrides: &RollingUpdateOverrides,
+) -> anyhow::Result<serde_json::Value> {
+    let mut rolling_update = serde_json::Map::new();
+    if let Some(max_surge) = &overrides.max_surge {
+        rolling_update.insert("maxSurge".to_string(), json!(max_surge));
+    }
+    if let Some(max_unavailable) = &overrides.max_unavailable {
+        rolling_update.insert("maxUnavailable".to_string(), json!(max_unavailable));
+    }
+
+    let mut patch = json!({
+        "spec": {
+            strategy_field: {
+                "rollingUpdate": rolling_update,
+            }
+        }
+    });
+    if let Some(original) = original {
+        let original = serde_json::to_string(original)
+            .context("Failed to serialize original rolling update parameters")?;
+        patch["metadata"] = json!({
+            "annotations": {
+                KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION: original,
+            }
+        });
+    }
+    Ok(patch)
+}
+
+/// Builds the merge patch restoring the recorded original parameters and removing the record
+fn build_rolling_update_restore_patch(
+    strategy_field: &str,
+    original: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let original: RollingUpdateParameters = serde_json::from_str(original)
+        .context("Failed to parse original rolling update parameters")?;
+    Ok(json!({
+        "metadata": {
+            "annotations": {
+                KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION: null,
+            }
+        },
+        "spec": {
+            strategy_field: {
+                "rollingUpdate": original,
+            }
+        }
+    }))
+}
 
 pub trait Rollout
 where
@@ -32,6 +325,177 @@ where
     fn desired_replicas(&self) -> i32;
     fn actual_replicas(&self) -> i32;
     fn pod_spec(&self) -> Option<&PodSpec>;
+    fn pod_template_metadata(&self) -> Option<&ObjectMeta>;
+
+    /// Whether the pod template was already patched for exactly these changes by an earlier check whose pods are not
+    /// replaced yet, so patching it again would only restart the pending rollout
+    fn is_rollout_triggered(&self, changed_containers: &[ChangedContainer]) -> bool {
+        self.pod_template_metadata()
+            .and_then(|metadata| metadata.annotations.as_ref())
+            .and_then(|annotations| annotations.get(KUBE_AUTOROLLOUT_CHANGE_HASH_ANNOTATION))
+            .is_some_and(|hash| *hash == change_hash(changed_containers))
+    }
+
+    /// Paused workloads are not restarted, as the restart would be queued until they are resumed
+    fn is_paused(&self) -> bool {
+        self.annotations()
+            .get(KUBE_AUTOROLLOUT_PAUSED_ANNOTATION)
+            .is_some_and(|value| value == "true")
+    }
+
+    /// Whether the digest of the container is checked. `kube-autorollout/containers` selects the checked containers,
+    /// `kube-autorollout/exclude-containers` excludes containers such as injected sidecars, both as comma separated
+    /// container names.
+    fn is_container_checked(&self, container_name: &str) -> bool {
+        let annotations = self.annotations();
+        let listed = |annotation: &str| {
+            annotations
+                .get(annotation)
+                .map(|names| names.split(',').any(|name| name.trim() == container_name))
+        };
+        listed(KUBE_AUTOROLLOUT_CONTAINERS_ANNOTATION).unwrap_or(true)
+            && !listed(KUBE_AUTOROLLOUT_EXCLUDE_CONTAINERS_ANNOTATION).unwrap_or(false)
+    }
+
+    /// Workloads in dry-run mode are checked, but changes are only reported instead of rolled out. The annotation
+    /// overrides the configured default in both directions.
+    fn is_dry_run(&self, default: bool) -> bool {
+        match self
+            .annotations()
+            .get(KUBE_AUTOROLLOUT_DRY_RUN_ANNOTATION)
+            .map(String::as_str)
+        {
+            Some("true") => true,
+            Some("false") => false,
+            _ => default,
+        }
+    }
+
+    /// Restart trigger selected by the workload's annotation, falling back to the configured one
+    fn restart_trigger(&self, default: RestartTrigger) -> RestartTrigger {
+        let Some(value) = self
+            .annotations()
+            .get(KUBE_AUTOROLLOUT_RESTART_TRIGGER_ANNOTATION)
+        else {
+            return default;
+        };
+        match serde_json::from_value(json!(value)) {
+            Ok(restart_trigger) => restart_trigger,
+            Err(_) => {
+                warn!(
+                    kind = %Self::kind_name(),
+                    resource = %self.name_any(),
+                    restart_trigger = %value,
+                    "Ignoring invalid restart trigger annotation, using the configured restart trigger"
+                );
+                default
+            }
+        }
+    }
+
+    /// Registry host queried for the images of the workload instead of the host in their references, keeping their
+    /// repository and tag. Values with a scheme or path are ignored.
+    fn registry_override(&self) -> Option<&str> {
+        let value = self
+            .annotations()
+            .get(KUBE_AUTOROLLOUT_REGISTRY_ANNOTATION)?
+            .trim();
+        if value.is_empty() || value.contains(['/', ' ']) {
+            warn!(
+                kind = %Self::kind_name(),
+                resource = %self.name_any(),
+                registry = %value,
+                "Ignoring invalid registry annotation, expected a host such as mirror.example.com:5000"
+            );
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Tag whose digest is compared against images pinned to a digest, replacing the tag co-declared in the image
+    fn track_tag(&self) -> Option<&str> {
+        let value = self
+            .annotations()
+            .get(KUBE_AUTOROLLOUT_TRACK_TAG_ANNOTATION)?
+            .trim();
+        if value.is_empty() || value.contains(['/', ':', '@', ' ']) {
+            warn!(
+                kind = %Self::kind_name(),
+                resource = %self.name_any(),
+                tag = %value,
+                "Ignoring invalid track-tag annotation, expected a tag such as v1"
+            );
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Digests that were rolled back, by container name
+    fn rolled_back_digests(&self) -> BTreeMap<String, String> {
+        self.annotations()
+            .get(KUBE_AUTOROLLOUT_ROLLED_BACK_DIGESTS_ANNOTATION)
+            .and_then(|value| serde_json::from_str(value).ok())
+            .unwrap_or_default()
+    }
+
+    /// Minimum interval between two rollouts selected by the workload's annotation, falling back to the configured one
+    fn rollout_cooldown(&self, default: Option<Duration>) -> Option<Duration> {
+        let Some(value) = self.annotations().get(KUBE_AUTOROLLOUT_COOLDOWN_ANNOTATION) else {
+            return default;
+        };
+        match humantime::parse_duration(value) {
+            Ok(cooldown) => Some(cooldown),
+            Err(err) => {
+                warn!(
+                    kind = %Self::kind_name(),
+                    resource = %self.name_any(),
+                    cooldown = %value,
+                    error = %err,
+                    "Ignoring invalid cooldown annotation, using the configured rollout cooldown"
+                );
+                default
+            }
+        }
+    }
+
+    /// Time of the last rollout triggered by kube-autorollout, None if it never restarted the workload
+    fn last_rollout_at(&self) -> Option<DateTime<Utc>> {
+        self.annotations()
+            .get(KUBE_AUTOROLLOUT_LAST_ROLLOUT_ANNOTATION)
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|last_rollout_at| last_rollout_at.with_timezone(&Utc))
+    }
+
+    /// Returns until when the workload is in its rollout cooldown, None if it may be rolled out now
+    fn rollout_cooldown_until(
+        &self,
+        default: Option<Duration>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let cooldown = chrono::Duration::from_std(self.rollout_cooldown(default)?).ok()?;
+        let until = self.last_rollout_at()? + cooldown;
+        (until > now).then_some(until)
+    }
+
+    /// Name of the spec field holding the update strategy, None for kinds without rolling update overrides
+    fn strategy_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Current rolling update parameters, None if the workload does not use a rolling update strategy
+    fn rolling_update(&self) -> Option<RollingUpdateParameters> {
+        None
+    }
+
+    /// Whether the current pod template has been rolled out to all replicas
+    fn rollout_complete(&self) -> bool {
+        true
+    }
+
+    /// Why the rollout of the current pod template failed, None while it is progressing or once it completed
+    fn rollout_failure(&self) -> Option<String> {
+        None
+    }
 
     fn image_pull_secrets(&self) -> Vec<String> {
         self.pod_spec()
@@ -40,52 +504,260 @@ where
             .unwrap_or_default()
     }
 
-    async fn patch_rollout_annotation(
+    /// Triggers the rollout of the changed containers with the strategy, by patching the workload and restarting its
+    /// pods for strategies that leave the pod template unchanged
+    fn patch_rollout(
         api: &Api<Self>,
-        resource_name: &str,
-        enable_kubectl_annotation: bool,
-    ) -> anyhow::Result<()> {
-        let k8s_resource_kind = Self::kind_name();
+        resource: &Self,
+        changed_containers: &[ChangedContainer],
+        strategy: &dyn RolloutStrategy,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let k8s_resource_kind = Self::kind_name();
+            let resource_name = &resource.name_any();
+            let patch = strategy.patch(changed_containers)?;
 
-        let annotation = match enable_kubectl_annotation {
-            true => KUBECTL_ROLLOUT_ANNOTATION,
-            false => KUBE_AUTOROLLOUT_ANNOTATION,
-        };
-        let patch = json!({
-            "spec": {
-                "template": {
-                    "metadata": {
-                        "annotations": {
-                            annotation: Utc::now().to_rfc3339(),
-                        }
+            debug!(
+                kind = %k8s_resource_kind,
+                resource = %resource_name,
+                patch = ?patch,
+                "Patching resource",
+            );
+            api.patch(
+                resource_name,
+                &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+                &patch,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to patch {} {} to trigger rollout",
+                    k8s_resource_kind, resource_name
+                )
+            })?;
+            let pods: Api<Pod> = Api::namespaced(
+                api.clone().into_client(),
+                &resource.namespace().unwrap_or_default(),
+            );
+            strategy
+                .restart_pods(&pods, &resource.selector())
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to restart the pods of {} {}",
+                        k8s_resource_kind, resource_name
+                    )
+                })?;
+            Ok(())
+        }
+    }
+
+    /// Sets or removes the annotation pausing automatic rollouts of the workload
+    fn set_paused(
+        api: &Api<Self>,
+        resource_name: &str,
+        paused: bool,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let value = paused.then_some("true");
+            let patch = json!({
+                "metadata": {
+                    "annotations": {
+                        KUBE_AUTOROLLOUT_PAUSED_ANNOTATION: value,
                     }
                 }
+            });
+            api.patch(
+                resource_name,
+                &PatchParams::default(),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to set the paused annotation of {} {}",
+                    Self::kind_name(),
+                    resource_name
+                )
+            })?;
+            Ok(())
+        }
+    }
+
+    /// Reverts the changed containers to their previous digests
+    fn rollback(
+        api: &Api<Self>,
+        resource: &Self,
+        changed_containers: &[ChangedContainer],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let resource_name = resource.name_any();
+            let patch = Patch::Strategic(build_rollback_patch(
+                changed_containers,
+                resource.rolled_back_digests(),
+            )?);
+            debug!(
+                kind = %Self::kind_name(),
+                resource = %resource_name,
+                patch = ?patch,
+                "Rolling back resource",
+            );
+            api.patch(
+                &resource_name,
+                &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+                &patch,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to roll back {} {}",
+                    Self::kind_name(),
+                    resource_name
+                )
+            })?;
+            Ok(())
+        }
+    }
+
+    /// Updates the images of containers following a tag policy to their newer tags
+    fn patch_container_images(
+        api: &Api<Self>,
+        resource_name: &str,
+        tag_updates: &[ChangedContainer],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let k8s_resource_kind = Self::kind_name();
+            let images: Vec<_> = tag_updates
+                .iter()
+                .map(|update| ContainerImage {
+                    name: &update.container_name,
+                    init_container: update.init_container,
+                    image: update.image.clone(),
+                })
+                .collect();
+            let patch = Patch::Strategic(with_last_rollout_annotation(
+                with_change_hash_annotation(build_container_image_patch(&images), tag_updates),
+            ));
+
+            debug!(
+                kind = %k8s_resource_kind,
+                resource = %resource_name,
+                patch = ?patch,
+                "Patching container images",
+            );
+            api.patch(
+                resource_name,
+                &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+                &patch,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to patch container images of {} {}",
+                    k8s_resource_kind, resource_name
+                )
+            })?;
+            Ok(())
+        }
+    }
+
+    /// Applies the temporary rolling update overrides ahead of a triggered rollout
+    fn apply_rolling_update_overrides(
+        api: &Api<Self>,
+        resource: &Self,
+        overrides: &RollingUpdateOverrides,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let (Some(strategy_field), Some(current)) =
+                (Self::strategy_field(), resource.rolling_update())
+            else {
+                return Ok(());
+            };
+            let override_active = resource
+                .annotations()
+                .contains_key(KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION);
+            let original = (!override_active).then_some(&current);
+            let patch = build_rolling_update_override_patch(strategy_field, original, overrides)?;
+
+            debug!(
+                kind = %Self::kind_name(),
+                resource = %resource.name_any(),
+                patch = ?patch,
+                "Overriding rolling update parameters",
+            );
+            api.patch(
+                &resource.name_any(),
+                &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to override rolling update parameters of {} {}",
+                    Self::kind_name(),
+                    resource.name_any()
+                )
+            })?;
+            Ok(())
+        }
+    }
+
+    /// Restores the original rolling update parameters once a rollout with overrides has completed
+    fn restore_rolling_update(
+        api: &Api<Self>,
+        resource: &Self,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        async move {
+            let (Some(strategy_field), Some(original)) = (
+                Self::strategy_field(),
+                resource
+                    .annotations()
+                    .get(KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION),
+            ) else {
+                return Ok(());
+            };
+            if !resource.rollout_complete() {
+                return Ok(());
             }
-        });
+            let patch = build_rolling_update_restore_patch(strategy_field, original)?;
 
-        debug!(
-            kind = %k8s_resource_kind,
-            resource = %resource_name,
-            patch = ?patch,
-            "Patching resource",
-        );
-        api.patch(
-            resource_name,
-            &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
-            &Patch::Merge(&patch),
-        )
-        .await
-        .with_context(|| {
-            format!(
-                "Failed to patch {} {} to trigger rollout",
-                k8s_resource_kind, resource_name
+            api.patch(
+                &resource.name_any(),
+                &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+                &Patch::Merge(&patch),
             )
-        })?;
-        Ok(())
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to restore rolling update parameters of {} {}",
+                    Self::kind_name(),
+                    resource.name_any()
+                )
+            })?;
+            info!(
+                kind = %Self::kind_name(),
+                resource = %resource.name_any(),
+                "Restored original rolling update parameters after completed rollout"
+            );
+            Ok(())
+        }
     }
 }
 
 impl Rollout for Deployment {
+    fn is_paused(&self) -> bool {
+        let spec_paused = self
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.paused)
+            .unwrap_or(false);
+        spec_paused
+            || self
+                .annotations()
+                .get(KUBE_AUTOROLLOUT_PAUSED_ANNOTATION)
+                .is_some_and(|value| value == "true")
+    }
+
     fn selector(&self) -> BTreeMap<String, String> {
         self.spec
             .as_ref()
@@ -108,6 +780,60 @@ impl Rollout for Deployment {
     fn pod_spec(&self) -> Option<&PodSpec> {
         self.spec.as_ref().and_then(|s| s.template.spec.as_ref())
     }
+    fn pod_template_metadata(&self) -> Option<&ObjectMeta> {
+        self.spec
+            .as_ref()
+            .and_then(|s| s.template.metadata.as_ref())
+    }
+    fn strategy_field() -> Option<&'static str> {
+        Some("strategy")
+    }
+
+    fn rolling_update(&self) -> Option<RollingUpdateParameters> {
+        let strategy = self.spec.as_ref()?.strategy.clone().unwrap_or_default();
+        if strategy
+            .type_
+            .as_deref()
+            .is_some_and(|type_| type_ != "RollingUpdate")
+        {
+            return None;
+        }
+        let rolling_update = strategy.rolling_update.unwrap_or_default();
+        Some(RollingUpdateParameters {
+            max_surge: rolling_update.max_surge,
+            max_unavailable: rolling_update.max_unavailable,
+        })
+    }
+
+    fn rollout_complete(&self) -> bool {
+        let (Some(spec), Some(status)) = (&self.spec, &self.status) else {
+            return false;
+        };
+        let replicas = spec.replicas.unwrap_or(1);
+        let updated_replicas = status.updated_replicas.unwrap_or(0);
+        status.observed_generation >= self.metadata.generation
+            && updated_replicas >= replicas
+            && status.replicas.unwrap_or(0) == updated_replicas
+            && status.available_replicas.unwrap_or(0) >= replicas
+    }
+
+    fn rollout_failure(&self) -> Option<String> {
+        self.status
+            .as_ref()?
+            .conditions
+            .as_ref()?
+            .iter()
+            .find(|condition| {
+                condition.type_ == "Progressing"
+                    && condition.reason.as_deref() == Some("ProgressDeadlineExceeded")
+            })
+            .map(|condition| {
+                condition
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| "progress deadline exceeded".to_string())
+            })
+    }
 }
 
 impl Rollout for StatefulSet {
@@ -133,6 +859,23 @@ impl Rollout for StatefulSet {
     fn pod_spec(&self) -> Option<&PodSpec> {
         self.spec.as_ref().and_then(|s| s.template.spec.as_ref())
     }
+    fn pod_template_metadata(&self) -> Option<&ObjectMeta> {
+        self.spec
+            .as_ref()
+            .and_then(|s| s.template.metadata.as_ref())
+    }
+
+    fn rollout_complete(&self) -> bool {
+        let (Some(spec), Some(status)) = (&self.spec, &self.status) else {
+            return false;
+        };
+        let replicas = spec.replicas.unwrap_or(1);
+        status.observed_generation >= self.metadata.generation
+            && status.update_revision.is_some()
+            && status.current_revision == status.update_revision
+            && status.updated_replicas.unwrap_or(0) >= replicas
+            && status.available_replicas.unwrap_or(0) >= replicas
+    }
 }
 
 impl Rollout for DaemonSet {
@@ -158,4 +901,440 @@ impl Rollout for DaemonSet {
     fn pod_spec(&self) -> Option<&PodSpec> {
         self.spec.as_ref().and_then(|s| s.template.spec.as_ref())
     }
+    fn pod_template_metadata(&self) -> Option<&ObjectMeta> {
+        self.spec
+            .as_ref()
+            .and_then(|s| s.template.metadata.as_ref())
+    }
+    fn strategy_field() -> Option<&'static str> {
+        Some("updateStrategy")
+    }
+
+    fn rolling_update(&self) -> Option<RollingUpdateParameters> {
+        let strategy = self
+            .spec
+            .as_ref()?
+            .update_strategy
+            .clone()
+            .unwrap_or_default();
+        if strategy
+            .type_
+            .as_deref()
+            .is_some_and(|type_| type_ != "RollingUpdate")
+        {
+            return None;
+        }
+        let rolling_update = strategy.rolling_update.unwrap_or_default();
+        Some(RollingUpdateParameters {
+            max_surge: rolling_update.max_surge,
+            max_unavailable: rolling_update.max_unavailable,
+        })
+    }
+
+    fn rollout_complete(&self) -> bool {
+        let Some(status) = &self.status else {
+            return false;
+        };
+        status.observed_generation >= self.metadata.generation
+            && status.updated_number_scheduled.unwrap_or(0) >= status.desired_number_scheduled
+            && status.number_available.unwrap_or(0) >= status.desired_number_scheduled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollout_cooldown_starts_at_the_last_rollout() {
+        let now = Utc::now();
+        let mut deployment = Deployment::default();
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_LAST_ROLLOUT_ANNOTATION.to_string(),
+            (now - chrono::Duration::minutes(10)).to_rfc3339(),
+        );
+
+        let global = Some(Duration::from_secs(5 * 60));
+        assert_eq!(deployment.rollout_cooldown_until(global, now), None);
+        assert_eq!(deployment.rollout_cooldown_until(None, now), None);
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_COOLDOWN_ANNOTATION.to_string(),
+            "30m".to_string(),
+        );
+        assert_eq!(
+            deployment
+                .rollout_cooldown_until(global, now)
+                .map(|until| (until - now).num_minutes()),
+            Some(20)
+        );
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_COOLDOWN_ANNOTATION.to_string(),
+            "soon".to_string(),
+        );
+        assert_eq!(deployment.rollout_cooldown(global), global);
+        assert_eq!(
+            Deployment::default().rollout_cooldown_until(global, now),
+            None
+        );
+    }
+
+    #[test]
+    fn rollout_patches_record_the_last_rollout() {
+        let patch = with_last_rollout_annotation(build_container_image_patch(&[ContainerImage {
+            name: "app",
+            init_container: false,
+            image: "ghcr.io/org/app:1.2.4".to_string(),
+        }]));
+        assert!(
+            patch["metadata"]["annotations"][KUBE_AUTOROLLOUT_LAST_ROLLOUT_ANNOTATION].is_string()
+        );
+        assert_eq!(
+            patch["spec"]["template"]["spec"]["containers"][0]["name"],
+            "app"
+        );
+    }
+
+    #[test]
+    fn rollouts_already_patched_for_the_changes_are_detected() {
+        let changed = |container_name: &str, new_digest: &str| ChangedContainer {
+            container_name: container_name.to_string(),
+            image: format!("ghcr.io/org/{}:latest", container_name),
+            previous_digest: "sha256:old".to_string(),
+            new_digest: new_digest.to_string(),
+            init_container: false,
+            previous_image: None,
+        };
+        let changed_containers = vec![changed("app", "sha256:a"), changed("worker", "sha256:b")];
+        assert_eq!(
+            change_hash(&changed_containers),
+            change_hash(&[changed("worker", "sha256:b"), changed("app", "sha256:a")])
+        );
+        assert_ne!(
+            change_hash(&changed_containers),
+            change_hash(&[changed("app", "sha256:a"), changed("worker", "sha256:c")])
+        );
+
+        let patch = with_change_hash_annotation(
+            build_env_rollout_patch(&changed_containers),
+            &changed_containers,
+        );
+        let mut deployment = Deployment::default();
+        assert!(!deployment.is_rollout_triggered(&changed_containers));
+        deployment.spec = Some(
+            serde_json::from_value(json!({
+                "selector": {},
+                "template": patch["spec"]["template"],
+            }))
+            .unwrap(),
+        );
+        assert!(deployment.is_rollout_triggered(&changed_containers));
+        assert!(!deployment.is_rollout_triggered(&[changed("app", "sha256:c")]));
+    }
+
+    #[test]
+    fn build_rollout_patch_lists_all_changed_containers() {
+        let changed_containers = vec![
+            ChangedContainer {
+                container_name: "app".to_string(),
+                image: "ghcr.io/org/app:latest".to_string(),
+                previous_digest: "sha256:app".to_string(),
+                new_digest: "sha256:app-new".to_string(),
+                init_container: false,
+                previous_image: None,
+            },
+            ChangedContainer {
+                container_name: "sidecar".to_string(),
+                image: "ghcr.io/org/sidecar:latest".to_string(),
+                previous_digest: "sha256:sidecar".to_string(),
+                new_digest: "sha256:sidecar-new".to_string(),
+                init_container: false,
+                previous_image: None,
+            },
+        ];
+
+        let patch = build_rollout_patch(KUBE_AUTOROLLOUT_ANNOTATION, &changed_containers).unwrap();
+        let annotations = &patch["spec"]["template"]["metadata"]["annotations"];
+        assert!(annotations[KUBE_AUTOROLLOUT_ANNOTATION].is_string());
+
+        let recorded: serde_json::Value = serde_json::from_str(
+            annotations[KUBE_AUTOROLLOUT_CHANGED_CONTAINERS_ANNOTATION]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(recorded[0]["container"], "app");
+        assert_eq!(recorded[1]["previousDigest"], "sha256:sidecar");
+    }
+
+    #[test]
+    fn build_env_rollout_patch_sets_variable_on_changed_containers() {
+        let changed_containers = vec![ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:app".to_string(),
+            new_digest: "sha256:app-new".to_string(),
+            init_container: false,
+            previous_image: None,
+        }];
+
+        let patch = build_env_rollout_patch(&changed_containers);
+        let containers = patch["spec"]["template"]["spec"]["containers"]
+            .as_array()
+            .unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0]["name"], "app");
+        assert_eq!(
+            containers[0]["env"][0]["name"],
+            KUBE_AUTOROLLOUT_RESTARTED_AT_ENV
+        );
+        assert!(containers[0]["env"][0]["value"].is_string());
+    }
+
+    #[test]
+    fn build_pin_digest_patch_pins_changed_containers_keeping_the_tag() {
+        let changed_containers = vec![ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:app".to_string(),
+            new_digest: "sha256:app-new".to_string(),
+            init_container: false,
+            previous_image: None,
+        }];
+
+        let patch = build_pin_digest_patch(&changed_containers);
+        assert_eq!(
+            patch["spec"]["template"]["spec"]["containers"],
+            json!([{ "name": "app", "image": "ghcr.io/org/app:latest@sha256:app-new" }])
+        );
+        assert!(patch["spec"]["template"]["spec"]["initContainers"].is_null());
+    }
+
+    #[test]
+    fn init_containers_are_patched_separately() {
+        let changed_containers = vec![
+            ChangedContainer {
+                container_name: "app".to_string(),
+                image: "ghcr.io/org/app:latest".to_string(),
+                previous_digest: "sha256:app".to_string(),
+                new_digest: "sha256:app-new".to_string(),
+                init_container: false,
+                previous_image: None,
+            },
+            ChangedContainer {
+                container_name: "migrate".to_string(),
+                image: "ghcr.io/org/migrate:latest".to_string(),
+                previous_digest: "sha256:migrate".to_string(),
+                new_digest: "sha256:migrate-new".to_string(),
+                init_container: true,
+                previous_image: None,
+            },
+        ];
+
+        let patch = build_pin_digest_patch(&changed_containers);
+        let spec = &patch["spec"]["template"]["spec"];
+        assert_eq!(
+            spec["containers"],
+            json!([{ "name": "app", "image": "ghcr.io/org/app:latest@sha256:app-new" }])
+        );
+        assert_eq!(
+            spec["initContainers"],
+            json!([{ "name": "migrate", "image": "ghcr.io/org/migrate:latest@sha256:migrate-new" }])
+        );
+
+        let serialized = serde_json::to_value(&changed_containers).unwrap();
+        assert!(serialized[0].get("initContainer").is_none());
+        assert_eq!(serialized[1]["initContainer"], true);
+    }
+
+    #[test]
+    fn restart_trigger_annotation_overrides_configured_trigger() {
+        let mut deployment = Deployment::default();
+        assert_eq!(
+            deployment.restart_trigger(RestartTrigger::Env),
+            RestartTrigger::Env
+        );
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_RESTART_TRIGGER_ANNOTATION.to_string(),
+            "pinDigest".to_string(),
+        );
+        assert_eq!(
+            deployment.restart_trigger(RestartTrigger::Annotation),
+            RestartTrigger::PinDigest
+        );
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_RESTART_TRIGGER_ANNOTATION.to_string(),
+            "unknown".to_string(),
+        );
+        assert_eq!(
+            deployment.restart_trigger(RestartTrigger::Annotation),
+            RestartTrigger::Annotation
+        );
+    }
+
+    #[test]
+    fn rolling_update_overrides_record_and_restore_original_parameters() {
+        let overrides = RollingUpdateOverrides {
+            max_surge: Some(IntOrString::Int(1)),
+            max_unavailable: None,
+        };
+        let original = RollingUpdateParameters {
+            max_surge: Some(IntOrString::String("50%".to_string())),
+            max_unavailable: None,
+        };
+
+        let patch =
+            build_rolling_update_override_patch("strategy", Some(&original), &overrides).unwrap();
+        assert_eq!(
+            patch["spec"]["strategy"]["rollingUpdate"],
+            json!({ "maxSurge": 1 })
+        );
+        let recorded = patch["metadata"]["annotations"]
+            [KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION]
+            .as_str()
+            .unwrap();
+
+        let patch = build_rolling_update_override_patch("strategy", None, &overrides).unwrap();
+        assert!(patch.get("metadata").is_none());
+
+        let patch = build_rolling_update_restore_patch("updateStrategy", recorded).unwrap();
+        assert_eq!(
+            patch["spec"]["updateStrategy"]["rollingUpdate"],
+            json!({ "maxSurge": "50%", "maxUnavailable": null })
+        );
+        assert!(patch["metadata"]["annotations"]
+            [KUBE_AUTOROLLOUT_ORIGINAL_ROLLING_UPDATE_ANNOTATION]
+            .is_null());
+    }
+
+    #[test]
+    fn deployments_are_paused_by_spec_or_annotation() {
+        let mut deployment = Deployment::default();
+        assert!(!deployment.is_paused());
+
+        deployment.spec = Some(k8s_openapi::api::apps::v1::DeploymentSpec {
+            paused: Some(true),
+            ..Default::default()
+        });
+        assert!(deployment.is_paused());
+
+        let mut stateful_set = StatefulSet::default();
+        stateful_set.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_PAUSED_ANNOTATION.to_string(),
+            "true".to_string(),
+        );
+        assert!(stateful_set.is_paused());
+    }
+
+    #[test]
+    fn rollback_pins_previous_digests_and_records_rolled_back_ones() {
+        let changed_containers = vec![ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:good".to_string(),
+            new_digest: "sha256:bad".to_string(),
+            init_container: false,
+            previous_image: None,
+        }];
+        let previous = BTreeMap::from([("worker".to_string(), "sha256:older".to_string())]);
+        let patch = build_rollback_patch(&changed_containers, previous).unwrap();
+        assert_eq!(
+            patch["spec"]["template"]["spec"]["containers"],
+            json!([{ "name": "app", "image": "ghcr.io/org/app:latest@sha256:good" }])
+        );
+
+        let mut deployment = Deployment::default();
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_ROLLED_BACK_DIGESTS_ANNOTATION.to_string(),
+            patch["metadata"]["annotations"][KUBE_AUTOROLLOUT_ROLLED_BACK_DIGESTS_ANNOTATION]
+                .as_str()
+                .unwrap()
+                .to_string(),
+        );
+        assert_eq!(
+            deployment.rolled_back_digests(),
+            BTreeMap::from([
+                ("app".to_string(), "sha256:bad".to_string()),
+                ("worker".to_string(), "sha256:older".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn containers_are_selected_and_excluded_by_annotation() {
+        let mut deployment = Deployment::default();
+        assert!(deployment.is_container_checked("istio-proxy"));
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_EXCLUDE_CONTAINERS_ANNOTATION.to_string(),
+            "istio-proxy, vault-agent".to_string(),
+        );
+        assert!(deployment.is_container_checked("app"));
+        assert!(!deployment.is_container_checked("vault-agent"));
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_CONTAINERS_ANNOTATION.to_string(),
+            "app,worker".to_string(),
+        );
+        assert!(deployment.is_container_checked("worker"));
+        assert!(!deployment.is_container_checked("migrations"));
+        assert!(!deployment.is_container_checked("istio-proxy"));
+    }
+
+    #[test]
+    fn registry_annotation_overrides_the_queried_host() {
+        let mut deployment = Deployment::default();
+        assert_eq!(deployment.registry_override(), None);
+
+        for (value, expected) in [
+            (
+                " mirror.internal.example.com:5000 ",
+                Some("mirror.internal.example.com:5000"),
+            ),
+            ("https://mirror.internal.example.com", None),
+            ("mirror.internal.example.com/library", None),
+            ("", None),
+        ] {
+            deployment.annotations_mut().insert(
+                KUBE_AUTOROLLOUT_REGISTRY_ANNOTATION.to_string(),
+                value.to_string(),
+            );
+            assert_eq!(deployment.registry_override(), expected);
+        }
+    }
+
+    #[test]
+    fn track_tag_annotation_names_a_valid_tag() {
+        let mut deployment = Deployment::default();
+        assert_eq!(deployment.track_tag(), None);
+
+        for (value, expected) in [(" v1 ", Some("v1")), ("app:v1", None), ("", None)] {
+            deployment.annotations_mut().insert(
+                KUBE_AUTOROLLOUT_TRACK_TAG_ANNOTATION.to_string(),
+                value.to_string(),
+            );
+            assert_eq!(deployment.track_tag(), expected);
+        }
+    }
+
+    #[test]
+    fn dry_run_annotation_overrides_the_configured_default() {
+        let mut deployment = Deployment::default();
+        assert!(deployment.is_dry_run(true));
+        assert!(!deployment.is_dry_run(false));
+
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_DRY_RUN_ANNOTATION.to_string(),
+            "false".to_string(),
+        );
+        assert!(!deployment.is_dry_run(true));
+        deployment.annotations_mut().insert(
+            KUBE_AUTOROLLOUT_DRY_RUN_ANNOTATION.to_string(),
+            "true".to_string(),
+        );
+        assert!(deployment.is_dry_run(false));
+    }
 }
@@ -0,0 +1,106 @@
+use crate::rollout::ChangedContainer;
+use anyhow::Context;
+use chrono::Utc;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client, CustomResource};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+static KUBE_AUTOROLLOUT_FIELD_MANAGER: &str = "kube-autorollout";
+
+/// A restart of a workload requested by kube-autorollout running in intent mode.
+/// An external actuator (or GitOps process) carries out the restart and records the outcome in the status.
+#[derive(CustomResource, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "kube-autorollout.io",
+    version = "v1alpha1",
+    kind = "RolloutIntent",
+    namespaced,
+    status = "RolloutIntentStatus",
+    printcolumn = r#"{"name":"Kind","type":"string","jsonPath":".spec.targetKind"}"#,
+    printcolumn = r#"{"name":"Target","type":"string","jsonPath":".spec.targetName"}"#,
+    printcolumn = r#"{"name":"Requested","type":"string","jsonPath":".spec.requestedAt"}"#,
+    printcolumn = r#"{"name":"Phase","type":"string","jsonPath":".status.phase"}"#
+)]
+pub struct RolloutIntentSpec {
+    #[serde(rename = "targetKind")]
+    pub target_kind: String,
+    #[serde(rename = "targetName")]
+    pub target_name: String,
+    #[serde(rename = "changedContainers")]
+    pub changed_containers: Vec<ChangedContainer>,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: String,
+}
+
+/// Written by the actuator, kube-autorollout never modifies the status
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RolloutIntentStatus {
+    #[serde(default)]
+    pub phase: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// One intent per workload, so repeated detections update the pending intent instead of piling up new ones
+fn intent_name(kind: &str, resource_name: &str) -> String {
+    format!("{}-{}", kind.to_lowercase(), resource_name)
+}
+
+/// Creates or updates the RolloutIntent describing the desired restart of the workload, next to the workload
+pub async fn apply_rollout_intent(
+    client: &Client,
+    namespace: &str,
+    kind: &str,
+    resource_name: &str,
+    changed_containers: &[ChangedContainer],
+) -> anyhow::Result<()> {
+    let api: Api<RolloutIntent> = Api::namespaced(client.clone(), namespace);
+    let name = intent_name(kind, resource_name);
+    let intent = RolloutIntent::new(
+        &name,
+        RolloutIntentSpec {
+            target_kind: kind.to_string(),
+            target_name: resource_name.to_string(),
+            changed_containers: changed_containers.to_vec(),
+            requested_at: Utc::now().to_rfc3339(),
+        },
+    );
+
+    debug!(
+        intent = %name,
+        spec = ?intent.spec,
+        "Applying RolloutIntent"
+    );
+    api.patch(
+        &name,
+        &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER).force(),
+        &Patch::Apply(&intent),
+    )
+    .await
+    .with_context(|| format!("Failed to apply RolloutIntent {}", name))?;
+
+    info!(
+        intent = %name,
+        namespace = %namespace,
+        kind = %kind,
+        resource = %resource_name,
+        "Created RolloutIntent for resource"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intent_name_is_stable_per_workload() {
+        assert_eq!(intent_name("Deployment", "my-app"), "deployment-my-app");
+        assert_eq!(
+            intent_name("StatefulSet", "my-app"),
+            intent_name("StatefulSet", "my-app")
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::config::{Config, RestartTrigger, RolloutPatchTemplate, TemplatePatchType};
+use crate::rollout::{
+    build_env_rollout_patch, build_pin_digest_patch, build_rollout_patch, change_hash,
+    with_change_hash_annotation, with_last_rollout_annotation, ChangedContainer,
+    KUBECTL_ROLLOUT_ANNOTATION, KUBE_AUTOROLLOUT_ANNOTATION,
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{EvictParams, ListParams, Patch};
+use kube::{Api, ResourceExt};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+/// How a workload is restarted to roll out its changed containers. Strategies are selected through the configured
+/// restart trigger or the workload's `kube-autorollout/restart-trigger` annotation.
+pub trait RolloutStrategy: Send + Sync {
+    /// Patch of the workload triggering the rollout. Patches changing the pod template also record the hash of the
+    /// changes, all patches record the time of the rollout on the workload.
+    fn patch(&self, changed_containers: &[ChangedContainer]) -> Result<Patch<Value>>;
+
+    /// Restarts the pods selected by the workload once it was patched, for strategies leaving the pod template
+    /// unchanged
+    fn restart_pods<'a>(
+        &'a self,
+        _pods: &'a Api<Pod>,
+        _selector: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Records the change hash on the pod template and the time of the rollout on the workload
+fn annotate(patch: Value, changed_containers: &[ChangedContainer]) -> Value {
+    with_last_rollout_annotation(with_change_hash_annotation(patch, changed_containers))
+}
+
+/// Sets a restartedAt annotation of the pod template
+pub struct AnnotationBump {
+    annotation: &'static str,
+}
+
+impl AnnotationBump {
+    pub fn kube_autorollout() -> Self {
+        AnnotationBump {
+            annotation: KUBE_AUTOROLLOUT_ANNOTATION,
+        }
+    }
+
+    /// Sets the annotation of `kubectl rollout restart`, which GitOps tools ignore in their state drift detection
+    pub fn kubectl() -> Self {
+        AnnotationBump {
+            annotation: KUBECTL_ROLLOUT_ANNOTATION,
+        }
+    }
+}
+
+impl RolloutStrategy for AnnotationBump {
+    fn patch(&self, changed_containers: &[ChangedContainer]) -> Result<Patch<Value>> {
+        Ok(Patch::Merge(annotate(
+            build_rollout_patch(self.annotation, changed_containers)?,
+            changed_containers,
+        )))
+    }
+}
+
+/// Sets the restart environment variable of the changed containers
+pub struct EnvBump;
+
+impl RolloutStrategy for EnvBump {
+    fn patch(&self, changed_containers: &[ChangedContainer]) -> Result<Patch<Value>> {
+        Ok(Patch::Strategic(annotate(
+            build_env_rollout_patch(changed_containers),
+            changed_containers,
+        )))
+    }
+}
+
+/// Pins the image of the changed containers to their new digest
+pub struct PinDigest;
+
+impl RolloutStrategy for PinDigest {
+    fn patch(&self, changed_containers: &[ChangedContainer]) -> Result<Patch<Value>> {
+        Ok(Patch::Strategic(annotate(
+            build_pin_digest_patch(changed_containers),
+            changed_containers,
+        )))
+    }
+}
+
+/// Evicts the pods of the workload, which its controller replaces with the unchanged pod template. The eviction API
+/// honors PodDisruptionBudgets, pods whose eviction is refused are left running and reported as failure, so the next
+/// check evicts them.
+pub struct PodEviction;
+
+impl RolloutStrategy for PodEviction {
+    fn patch(&self, _changed_containers: &[ChangedContainer]) -> Result<Patch<Value>> {
+        Ok(Patch::Merge(with_last_rollout_annotation(json!({}))))
+    }
+
+    fn restart_pods<'a>(
+        &'a self,
+        pods: &'a Api<Pod>,
+        selector: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let label_selector = selector
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            let pod_list = pods
+                .list(&ListParams::default().labels(&label_selector))
+                .await
+                .context("Failed to list pods to evict")?;
+            let mut refused = Vec::new();
+            for pod in pod_list {
+                let pod_name = pod.name_any();
+                match pods.evict(&pod_name, &EvictParams::default()).await {
+                    Ok(_) => info!(pod = %pod_name, "Evicted pod"),
+                    Err(err) => {
+                        warn!(pod = %pod_name, error = %err, "Failed to evict pod");
+                        refused.push(pod_name);
+                    }
+                }
+            }
+            if !refused.is_empty() {
+                bail!("Eviction of pods {} was refused", refused.join(", "));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Applies the configured patch template, with the placeholders in its string values replaced
+pub struct PatchTemplate(RolloutPatchTemplate);
+
+impl RolloutStrategy for PatchTemplate {
+    fn patch(&self, changed_containers: &[ChangedContainer]) -> Result<Patch<Value>> {
+        let changed = serde_json::to_string(changed_containers)
+            .context("Failed to serialize changed containers")?;
+        let placeholders = [
+            ("{{restartedAt}}", Utc::now().to_rfc3339()),
+            ("{{changeHash}}", change_hash(changed_containers)),
+            ("{{changedContainers}}", changed),
+        ];
+        let patch = annotate(
+            render_template(&self.0.patch, &placeholders),
+            changed_containers,
+        );
+        Ok(match self.0.patch_type {
+            TemplatePatchType::Strategic => Patch::Strategic(patch),
+            TemplatePatchType::Merge => Patch::Merge(patch),
+        })
+    }
+}
+
+fn render_template(template: &Value, placeholders: &[(&str, String)]) -> Value {
+    match template {
+        Value::String(value) => Value::String(
+            placeholders
+                .iter()
+                .fold(value.clone(), |value, (placeholder, replacement)| {
+                    value.replace(placeholder, replacement)
+                }),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| render_template(value, placeholders))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), render_template(value, placeholders)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Strategy of the restart trigger. The plain annotation trigger sets the kubectl annotation while the
+/// `enableKubectlAnnotation` feature flag is set.
+pub fn rollout_strategy(
+    restart_trigger: RestartTrigger,
+    config: &Config,
+) -> Result<Box<dyn RolloutStrategy>> {
+    Ok(match restart_trigger {
+        RestartTrigger::Annotation if config.feature_flags.enable_kubectl_annotation => {
+            Box::new(AnnotationBump::kubectl())
+        }
+        RestartTrigger::Annotation => Box::new(AnnotationBump::kube_autorollout()),
+        RestartTrigger::KubectlAnnotation => Box::new(AnnotationBump::kubectl()),
+        RestartTrigger::Env => Box::new(EnvBump),
+        RestartTrigger::PinDigest => Box::new(PinDigest),
+        RestartTrigger::EvictPods => Box::new(PodEviction),
+        RestartTrigger::PatchTemplate => Box::new(PatchTemplate(
+            config
+                .rollout_patch_template
+                .clone()
+                .context("Restart trigger patchTemplate requires rolloutPatchTemplate")?,
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed_containers() -> Vec<ChangedContainer> {
+        vec![ChangedContainer {
+            container_name: "app".to_string(),
+            image: "ghcr.io/org/app:latest".to_string(),
+            previous_digest: "sha256:old".to_string(),
+            new_digest: "sha256:new".to_string(),
+            init_container: false,
+            previous_image: None,
+        }]
+    }
+
+    fn patch_value(patch: Patch<Value>) -> Value {
+        match patch {
+            Patch::Merge(value) | Patch::Strategic(value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn pod_eviction_leaves_the_pod_template_unchanged() {
+        let patch = patch_value(PodEviction.patch(&changed_containers()).unwrap());
+        assert!(patch["metadata"]["annotations"]["kube-autorollout/lastRolloutAt"].is_string());
+        assert!(patch.get("spec").is_none());
+
+        let patch = patch_value(
+            AnnotationBump::kubectl()
+                .patch(&changed_containers())
+                .unwrap(),
+        );
+        let annotations = &patch["spec"]["template"]["metadata"]["annotations"];
+        assert!(annotations[KUBECTL_ROLLOUT_ANNOTATION].is_string());
+        assert_eq!(
+            annotations["kube-autorollout/changeHash"],
+            change_hash(&changed_containers())
+        );
+    }
+
+    #[test]
+    fn patch_template_placeholders_are_replaced() {
+        let strategy = PatchTemplate(RolloutPatchTemplate {
+            patch_type: TemplatePatchType::Merge,
+            patch: json!({
+                "spec": {
+                    "template": {
+                        "metadata": {
+                            "labels": { "rollout": "hash-{{changeHash}}" },
+                        }
+                    }
+                }
+            }),
+        });
+        let patch = strategy.patch(&changed_containers()).unwrap();
+        assert!(matches!(patch, Patch::Merge(_)));
+        let patch = patch_value(patch);
+        assert_eq!(
+            patch["spec"]["template"]["metadata"]["labels"]["rollout"],
+            format!("hash-{}", change_hash(&changed_containers()))
+        );
+        assert!(
+            patch["spec"]["template"]["metadata"]["annotations"]["kube-autorollout/changeHash"]
+                .is_string()
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::WaitForRollout;
+use crate::events::{publish, workload_reference};
+use crate::history::{self, HistoryChange, HistoryEntry, RolloutResult};
+use crate::notify::{self, Notification};
+use crate::rollout::Rollout;
+use crate::state::ControllerContext;
+use chrono::Utc;
+use kube::runtime::events::EventType;
+use kube::Api;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum RolloutOutcome {
+    Completed,
+    Failed(String),
+}
+
+/// Follows the rollout of a patched workload in the background and reports whether it completed through events,
+/// notifications and the rollout history
+pub fn spawn<T: Rollout>(
+    ctx: &ControllerContext,
+    api: Api<T>,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+    changes: Vec<HistoryChange>,
+) {
+    let Some(wait) = ctx.config.wait_for_rollout.clone() else {
+        return;
+    };
+    let ctx = ctx.clone();
+    let (kind, namespace, name) = (kind.to_string(), namespace.to_string(), name.to_string());
+    tokio::spawn(async move {
+        let outcome = wait_for_rollout(&api, &wait, &name).await;
+        report(&ctx, &kind, &namespace, &name, changes, outcome).await;
+    });
+}
+
+pub(crate) async fn wait_for_rollout<T: Rollout>(
+    api: &Api<T>,
+    wait: &WaitForRollout,
+    name: &str,
+) -> RolloutOutcome {
+    let deadline = Instant::now() + wait.timeout;
+    loop {
+        // the first status is read after one interval, the controllers need time to observe the new generation
+        tokio::time::sleep(wait.poll_interval).await;
+        match api.get(name).await {
+            Ok(resource) => {
+                if let Some(outcome) = outcome(&resource) {
+                    return outcome;
+                }
+            }
+            Err(err) => {
+                warn!(
+                    error = ?err,
+                    kind = %T::kind_name(),
+                    resource = %name,
+                    "Failed to read the rollout status"
+                );
+            }
+        }
+        if Instant::now() >= deadline {
+            return RolloutOutcome::Failed(format!(
+                "rollout did not complete within {}",
+                humantime::format_duration(wait.timeout)
+            ));
+        }
+    }
+}
+
+fn outcome<T: Rollout>(resource: &T) -> Option<RolloutOutcome> {
+    if let Some(failure) = resource.rollout_failure() {
+        return Some(RolloutOutcome::Failed(failure));
+    }
+    resource
+        .rollout_complete()
+        .then_some(RolloutOutcome::Completed)
+}
+
+async fn report(
+    ctx: &ControllerContext,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+    changes: Vec<HistoryChange>,
+    outcome: RolloutOutcome,
+) {
+    let mut details: Vec<String> = changes
+        .iter()
+        .map(|change| {
+            format!(
+                "container {} ({} {} -> {})",
+                change.container, change.image, change.previous, change.new
+            )
+        })
+        .collect();
+    let (entry, notification) = match &outcome {
+        RolloutOutcome::Completed => {
+            info!(kind = %kind, resource = %name, namespace = %namespace, "Rollout completed");
+            publish(
+                ctx,
+                workload_reference(kind, namespace, name),
+                EventType::Normal,
+                "RolloutCompleted",
+                "Rollout",
+                "All replicas run the new pod template".to_string(),
+            );
+            (
+                HistoryEntry {
+                    timestamp: Utc::now(),
+                    result: RolloutResult::Completed,
+                    error: None,
+                    containers: changes,
+                },
+                Notification {
+                    title: format!("Rollout of {} {}/{} completed", kind, namespace, name),
+                    details,
+                    failed: false,
+                    images: Vec::new(),
+                },
+            )
+        }
+        RolloutOutcome::Failed(reason) => {
+            warn!(
+                kind = %kind,
+                resource = %name,
+                namespace = %namespace,
+                reason = %reason,
+                "Rollout did not complete"
+            );
+            publish(
+                ctx,
+                workload_reference(kind, namespace, name),
+                EventType::Warning,
+                "RolloutNotCompleted",
+                "Rollout",
+                format!("Rollout did not complete: {}", reason),
+            );
+            details.push(format!("error: {}", reason));
+            (
+                HistoryEntry {
+                    timestamp: Utc::now(),
+                    result: RolloutResult::NotCompleted,
+                    error: Some(reason.clone()),
+                    containers: changes,
+                },
+                Notification {
+                    title: format!(
+                        "Rollout of {} {}/{} did not complete",
+                        kind, namespace, name
+                    ),
+                    details,
+                    failed: true,
+                    images: Vec::new(),
+                },
+            )
+        }
+    };
+    notify::send(ctx, notification);
+    history::record(ctx, kind, namespace, name, entry).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::apps::v1::{Deployment, DeploymentCondition, DeploymentStatus};
+    use serde_json::json;
+
+    #[test]
+    fn outcome_follows_the_deployment_status() {
+        let mut deployment: Deployment = serde_json::from_value(json!({
+            "metadata": { "name": "app", "generation": 2 },
+            "spec": {
+                "replicas": 2,
+                "selector": { "matchLabels": { "app": "app" } },
+                "template": {},
+            },
+            "status": {
+                "observedGeneration": 2,
+                "replicas": 3,
+                "updatedReplicas": 1,
+                "availableReplicas": 2,
+            },
+        }))
+        .unwrap();
+        assert_eq!(outcome(&deployment), None);
+
+        let status = deployment.status.as_mut().unwrap();
+        status.replicas = Some(2);
+        status.updated_replicas = Some(2);
+        assert_eq!(outcome(&deployment), Some(RolloutOutcome::Completed));
+
+        deployment.status = Some(DeploymentStatus {
+            observed_generation: Some(2),
+            conditions: Some(vec![DeploymentCondition {
+                type_: "Progressing".to_string(),
+                status: "False".to_string(),
+                reason: Some("ProgressDeadlineExceeded".to_string()),
+                message: Some("ReplicaSet \"app-5d4\" has timed out progressing.".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        assert_eq!(
+            outcome(&deployment),
+            Some(RolloutOutcome::Failed(
+                "ReplicaSet \"app-5d4\" has timed out progressing.".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use croner::parser::{CronParser, Seconds};
+use croner::Cron;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Upper bound of counted missed occurrences, so that a schedule of seconds does not iterate over a gap of days
+const MAX_COUNTED_MISSED_TICKS: u32 = 1000;
+
+/// Annotation overriding the global check schedule of a single workload, either a duration such as `10m` or a cron
+/// expression in the same format as `cronSchedule`
+pub static KUBE_AUTOROLLOUT_SCHEDULE_ANNOTATION: &str = "kube-autorollout/schedule";
+
+#[derive(Debug)]
+pub enum WorkloadSchedule {
+    Interval(Duration),
+    Cron(Box<Cron>),
+}
+
+impl WorkloadSchedule {
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Ok(interval) = humantime_serde::re::humantime::parse_duration(value) {
+            return Ok(WorkloadSchedule::Interval(interval));
+        }
+        let cron = CronParser::builder()
+            .seconds(Seconds::Optional)
+            .build()
+            .parse(value)
+            .with_context(|| {
+                format!(
+                    "Schedule {} is neither a duration nor a cron expression",
+                    value
+                )
+            })?;
+        Ok(WorkloadSchedule::Cron(Box::new(cron)))
+    }
+
+    /// Whether the workload is due for a check, given the time of its last scheduled check
+    pub fn is_due(&self, last_check: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<bool> {
+        let Some(last_check) = last_check else {
+            return Ok(true);
+        };
+        Ok(self.occurrence_after(last_check, 1)? <= now)
+    }
+
+    /// Time of the `count`th occurrence of the schedule after `from`
+    pub fn occurrence_after(&self, from: DateTime<Utc>, count: u32) -> Result<DateTime<Utc>> {
+        match self {
+            WorkloadSchedule::Interval(interval) => {
+                Ok(from + chrono::Duration::from_std(*interval)? * count as i32)
+            }
+            WorkloadSchedule::Cron(cron) => (0..count).try_fold(from, |occurrence, _| {
+                cron.find_next_occurrence(&occurrence, false)
+                    .context("Failed to compute the next occurrence of the schedule")
+            }),
+        }
+    }
+}
+
+impl WorkloadSchedule {
+    /// Schedule of the controller's runs: the `watch` resync interval, `checkInterval` or `cronSchedule`
+    pub fn of_controller(config: &Config) -> Result<Self> {
+        match (&config.watch, config.check_interval) {
+            (Some(watch), _) => Ok(WorkloadSchedule::Interval(watch.resync_interval)),
+            (None, Some(interval)) => Ok(WorkloadSchedule::Interval(interval)),
+            (None, None) => WorkloadSchedule::parse(&config.cron_schedule),
+        }
+    }
+}
+
+/// Start of a scheduled run compared to when the schedule expected it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledTick {
+    pub(crate) expected_at: DateTime<Utc>,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) delay_seconds: i64,
+    /// Occurrences of the schedule that passed without a run
+    pub(crate) missed_ticks: u32,
+    /// Whether the run started later than the tolerated delay
+    pub(crate) gap: bool,
+}
+
+/// Starts of the scheduled runs, to detect a scheduler that drifts or skips runs, e.g. when the runtime is starved
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleTicks {
+    pub(crate) last_tick: Option<ScheduledTick>,
+    pub(crate) last_gap: Option<ScheduledTick>,
+    pub(crate) gaps: u64,
+}
+
+impl ScheduleTicks {
+    /// Records a run started at `now`, expected at the first occurrence of the schedule after the previous run or,
+    /// for the first run, after `since`
+    pub fn record(
+        &mut self,
+        schedule: &WorkloadSchedule,
+        since: DateTime<Utc>,
+        tolerance: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<ScheduledTick> {
+        let previous = self
+            .last_tick
+            .as_ref()
+            .map_or(since, |tick| tick.started_at);
+        let expected_at = schedule.occurrence_after(previous, 1)?;
+        let tolerance = chrono::Duration::from_std(tolerance)?;
+        let mut missed_ticks = 0;
+        let mut occurrence = expected_at;
+        while missed_ticks < MAX_COUNTED_MISSED_TICKS {
+            occurrence = schedule.occurrence_after(occurrence, 1)?;
+            if occurrence + tolerance > now {
+                break;
+            }
+            missed_ticks += 1;
+        }
+        let tick = ScheduledTick {
+            expected_at,
+            started_at: now,
+            delay_seconds: (now - expected_at).num_seconds(),
+            missed_ticks,
+            gap: now - expected_at > tolerance,
+        };
+        if tick.gap {
+            self.gaps += 1;
+            self.last_gap = Some(tick.clone());
+        }
+        self.last_tick = Some(tick.clone());
+        Ok(tick)
+    }
+
+    /// Gaps are alerted with exponentially growing spacing, at the 1st, 2nd, 4th, 8th... gap, so that a scheduler that
+    /// keeps drifting does not flood the logs
+    pub fn alerts_gap(&self) -> bool {
+        self.gaps.is_power_of_two()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn interval_schedule_is_due_after_interval() {
+        let schedule = WorkloadSchedule::parse("10m").unwrap();
+        let last_check = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert!(schedule.is_due(None, last_check).unwrap());
+        assert!(!schedule
+            .is_due(Some(last_check), last_check + chrono::Duration::minutes(9))
+            .unwrap());
+        assert!(schedule
+            .is_due(Some(last_check), last_check + chrono::Duration::minutes(10))
+            .unwrap());
+    }
+
+    #[test]
+    fn cron_schedule_is_due_at_next_occurrence() {
+        let schedule = WorkloadSchedule::parse("0 0 * * * *").unwrap();
+        let last_check = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert!(!schedule
+            .is_due(Some(last_check), last_check + chrono::Duration::minutes(59))
+            .unwrap());
+        assert!(schedule
+            .is_due(Some(last_check), last_check + chrono::Duration::minutes(60))
+            .unwrap());
+        assert!(WorkloadSchedule::parse("every now and then").is_err());
+    }
+
+    #[test]
+    fn occurrence_after_skips_the_given_number_of_occurrences() {
+        let from = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            WorkloadSchedule::parse("*/45 * * * * *")
+                .unwrap()
+                .occurrence_after(from, 3)
+                .unwrap(),
+            Utc.with_ymd_and_hms(2025, 1, 1, 12, 1, 45).unwrap()
+        );
+        assert_eq!(
+            WorkloadSchedule::parse("30s")
+                .unwrap()
+                .occurrence_after(from, 3)
+                .unwrap(),
+            from + chrono::Duration::seconds(90)
+        );
+    }
+
+    #[test]
+    fn late_and_skipped_runs_are_recorded_as_gaps() {
+        let schedule = WorkloadSchedule::parse("1m").unwrap();
+        let tolerance = Duration::from_secs(10);
+        let since = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let at = |seconds| since + chrono::Duration::seconds(seconds);
+        let mut ticks = ScheduleTicks::default();
+
+        let on_time = ticks.record(&schedule, since, tolerance, at(65)).unwrap();
+        assert_eq!((on_time.delay_seconds, on_time.gap), (5, false));
+
+        // the run after 12:01:05 was expected at 12:02:05, the run at 12:03:05 was skipped
+        let late = ticks.record(&schedule, since, tolerance, at(250)).unwrap();
+        assert_eq!(late.expected_at, at(125));
+        assert_eq!(
+            (late.delay_seconds, late.missed_ticks, late.gap),
+            (125, 1, true)
+        );
+        assert_eq!(ticks.gaps, 1);
+        assert!(ticks.alerts_gap());
+
+        ticks.record(&schedule, since, tolerance, at(400)).unwrap();
+        assert_eq!(ticks.gaps, 2);
+        assert!(ticks.alerts_gap());
+        ticks.record(&schedule, since, tolerance, at(550)).unwrap();
+        assert!(!ticks.alerts_gap());
+
+        let recovered = ticks.record(&schedule, since, tolerance, at(610)).unwrap();
+        assert!(!recovered.gap);
+        assert_eq!(ticks.last_gap.as_ref().unwrap().started_at, at(550));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::config::{KeylessSignatures, RegistrySecret, RetryPolicy, SignaturePolicy};
+use crate::hostname;
+use crate::image_reference::ImageReference;
+use crate::oci_registry::{
+    get_with_authentication, registry_base_url, OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+};
+use crate::registry_requests::RegistryRequestLog;
+use crate::token_cache::TokenCache;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use globset::Glob;
+use reqwest::{Client, StatusCode};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, info};
+use x509_parser::der_parser::der::parse_der_utf8string;
+use x509_parser::oid_registry::OID_EC_P256;
+use x509_parser::pem::Pem;
+use x509_parser::prelude::*;
+use x509_parser::public_key::PublicKey;
+
+const COSIGN_SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const COSIGN_CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+const COSIGN_CHAIN_ANNOTATION: &str = "dev.sigstore.cosign/chain";
+/// Fulcio certificate extensions naming the OIDC issuer, the raw string of v1 was superseded by a DER string in v2
+const FULCIO_ISSUER_V1_OID: &str = "1.3.6.1.4.1.57264.1.1";
+const FULCIO_ISSUER_V2_OID: &str = "1.3.6.1.4.1.57264.1.8";
+const MAX_CERTIFICATE_CHAIN_LENGTH: usize = 4;
+
+/// The digest has no cosign signature at all, as opposed to carrying signatures that fail verification
+#[derive(Debug)]
+pub struct SignatureMissing {
+    pub image: String,
+}
+
+impl std::fmt::Display for SignatureMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No cosign signature found for {}", self.image)
+    }
+}
+
+impl std::error::Error for SignatureMissing {}
+
+pub fn is_signature_missing(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.downcast_ref::<SignatureMissing>().is_some())
+}
+
+#[derive(Deserialize)]
+struct SignatureManifest {
+    layers: Vec<SignatureLayer>,
+}
+
+#[derive(Deserialize)]
+struct SignatureLayer {
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+/// Red Hat simple signing payload signed by cosign
+#[derive(Deserialize)]
+struct SimpleSigningPayload {
+    critical: SimpleSigningCritical,
+}
+
+#[derive(Deserialize)]
+struct SimpleSigningCritical {
+    image: SimpleSigningImage,
+}
+
+#[derive(Deserialize)]
+struct SimpleSigningImage {
+    #[serde(rename = "docker-manifest-digest")]
+    docker_manifest_digest: String,
+}
+
+/// Rejects signature policies accepting no signature at all, or containing keys and certificates that can not be used
+pub fn validate_policy(policy: &SignaturePolicy) -> Result<()> {
+    if policy.public_keys.is_empty() && policy.keyless.is_none() {
+        bail!("signaturePolicy requires at least one public key or keyless identity");
+    }
+    for public_key in &policy.public_keys {
+        parse_public_key(public_key).context("Invalid public key in signaturePolicy")?;
+    }
+    if let Some(keyless) = &policy.keyless {
+        if keyless.identities.is_empty() {
+            bail!("signaturePolicy.keyless requires at least one identity");
+        }
+        let roots = parse_pem_bundle(&keyless.fulcio_roots)?;
+        if roots.is_empty() {
+            bail!("signaturePolicy.keyless.fulcioRoots contains no certificate");
+        }
+        for root in &roots {
+            root.parse_x509()
+                .context("Invalid certificate in signaturePolicy.keyless.fulcioRoots")?;
+        }
+        for identity in &keyless.identities {
+            Glob::new(&identity.subject)
+                .with_context(|| format!("invalid keyless subject pattern {}", identity.subject))?;
+        }
+    }
+    Ok(())
+}
+
+/// Verifies that the digest has a cosign signature accepted by the policy. Signatures are stored by cosign in the
+/// same repository as the image, under the tag `sha256-<hex>.sig`.
+#[allow(clippy::too_many_arguments)]
+pub async fn verify_image_signature(
+    client: &Client,
+    tokens: &TokenCache,
+    retry: &RetryPolicy,
+    requests: &RegistryRequestLog,
+    policy: &SignaturePolicy,
+    image_reference: &ImageReference,
+    digest: &str,
+    registry_secret: &RegistrySecret,
+) -> Result<()> {
+    let registry = hostname::api_host(&image_reference.registry);
+    let repository_url = format!(
+        "{}/v2/{}",
+        registry_base_url(registry),
+        image_reference.repository
+    );
+    let image = format!(
+        "{}/{}@{}",
+        image_reference.registry, image_reference.repository, digest
+    );
+    let url = format!(
+        "{}/manifests/{}.sig",
+        repository_url,
+        digest.replace(':', "-")
+    );
+    info!(url = %url, "Fetching cosign signature manifest");
+
+    let response = get_with_authentication(
+        client,
+        tokens,
+        retry,
+        requests,
+        registry,
+        registry_secret,
+        &url,
+        OCI_IMAGE_MANIFEST_CONTENT_TYPE,
+    )
+    .await?;
+    match response.status() {
+        StatusCode::OK => {}
+        StatusCode::NOT_FOUND => return Err(SignatureMissing { image }.into()),
+        status => bail!(
+            "Registry {} returned error status {} while fetching signature manifest",
+            image_reference.registry,
+            status
+        ),
+    }
+    let manifest: SignatureManifest = response
+        .json()
+        .await
+        .context("Failed to parse cosign signature manifest")?;
+
+    let mut failures = Vec::new();
+    for layer in &manifest.layers {
+        let Some(signature) = layer.annotations.get(COSIGN_SIGNATURE_ANNOTATION) else {
+            continue;
+        };
+        let blob_url = format!("{}/blobs/{}", repository_url, layer.digest);
+        let response = get_with_authentication(
+            client,
+            tokens,
+            retry,
+            requests,
+            registry,
+            registry_secret,
+            &blob_url,
+            "*/*",
+        )
+        .await?;
+        if !response.status().is_success() {
+            bail!(
+                "Registry {} returned error status {} while fetching signature payload {}",
+                image_reference.registry,
+                response.status(),
+                layer.digest
+            );
+        }
+        let payload = response
+            .bytes()
+            .await
+            .context("Failed to read signature payload")?;
+        if sha256_digest(&payload) != layer.digest {
+            failures.push(format!(
+                "payload does not match its digest {}",
+                layer.digest
+            ));
+            continue;
+        }
+
+        match verify_signature(policy, digest, &payload, signature, &layer.annotations) {
+            Ok(()) => {
+                debug!(image = %image, layer = %layer.digest, "Verified cosign signature");
+                return Ok(());
+            }
+            Err(err) => failures.push(format!("{:#}", err)),
+        }
+    }
+
+    if failures.is_empty() {
+        return Err(SignatureMissing { image }.into());
+    }
+    bail!(
+        "No signature of {} is accepted by the signature policy: {}",
+        image,
+        failures.join("; ")
+    );
+}
+
+fn sha256_digest(content: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, content);
+    let hex: String = digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Verifies a single signature layer: the payload must name the digest and be signed by a configured public key or,
+/// for keyless signatures, by a certificate issued to a configured identity
+fn verify_signature(
+    policy: &SignaturePolicy,
+    digest: &str,
+    payload: &[u8],
+    signature: &str,
+    annotations: &HashMap<String, String>,
+) -> Result<()> {
+    let signature = STANDARD
+        .decode(signature)
+        .context("Signature annotation is not valid base64")?;
+    let simple_signing: SimpleSigningPayload =
+        serde_json::from_slice(payload).context("Failed to parse signature payload")?;
+    if simple_signing.critical.image.docker_manifest_digest != digest {
+        bail!(
+            "signature payload is for digest {}",
+            simple_signing.critical.image.docker_manifest_digest
+        );
+    }
+
+    if let Some(certificate) = annotations.get(COSIGN_CERTIFICATE_ANNOTATION) {
+        let keyless = policy
+            .keyless
+            .as_ref()
+            .context("keyless signature, but no keyless identities are configured")?;
+        let chain = annotations
+            .get(COSIGN_CHAIN_ANNOTATION)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let public_key = verify_certificate(keyless, certificate, chain)?;
+        return UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+            .verify(payload, &signature)
+            .map_err(|_| anyhow::anyhow!("signature does not match the signing certificate"));
+    }
+
+    for public_key in &policy.public_keys {
+        let public_key = parse_public_key(public_key)?;
+        if UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+            .verify(payload, &signature)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    bail!("signature does not match any configured public key");
+}
+
+fn parse_pem_bundle(bundle: &str) -> Result<Vec<Pem>> {
+    Pem::iter_from_buffer(bundle.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to decode PEM bundle")
+}
+
+/// Returns the uncompressed EC point of a PEM encoded P-256 public key
+fn parse_public_key(public_key: &str) -> Result<Vec<u8>> {
+    let pem = parse_pem_bundle(public_key)?
+        .into_iter()
+        .next()
+        .context("Public key contains no PEM block")?;
+    let (_, spki) = SubjectPublicKeyInfo::from_der(&pem.contents)
+        .map_err(|err| anyhow::anyhow!("Failed to parse public key: {}", err))?;
+    ec_point(&spki)
+}
+
+fn ec_point(spki: &SubjectPublicKeyInfo) -> Result<Vec<u8>> {
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|parameters| parameters.as_oid().ok());
+    match spki.parsed() {
+        Ok(PublicKey::EC(point)) if curve == Some(OID_EC_P256) => Ok(point.data().to_vec()),
+        _ => bail!("only ECDSA P-256 public keys are supported"),
+    }
+}
+
+/// Verifies that the Fulcio certificate chains up to a configured root and was issued to a configured identity,
+/// returning its public key. Signing time is not checked against the Rekor transparency log, so the short validity
+/// of Fulcio certificates is not enforced either.
+fn verify_certificate(
+    keyless: &KeylessSignatures,
+    certificate: &str,
+    chain: &str,
+) -> Result<Vec<u8>> {
+    let leaf_pem = parse_pem_bundle(certificate)?
+        .into_iter()
+        .next()
+        .context("signing certificate contains no PEM block")?;
+    let leaf = leaf_pem
+        .parse_x509()
+        .context("Failed to parse signing certificate")?;
+    let root_pems = parse_pem_bundle(&keyless.fulcio_roots)?;
+    let roots = root_pems
+        .iter()
+        .map(Pem::parse_x509)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to parse Fulcio root certificates")?;
+    let chain_pems = parse_pem_bundle(chain)?;
+    let intermediates = chain_pems
+        .iter()
+        .map(Pem::parse_x509)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to parse signing certificate chain")?;
+
+    verify_certificate_chain(&leaf, &roots, &intermediates)?;
+
+    let issuer = certificate_oidc_issuer(&leaf).context("signing certificate names no issuer")?;
+    let subjects = certificate_subjects(&leaf);
+    let accepted = keyless.identities.iter().any(|identity| {
+        identity.issuer == issuer
+            && Glob::new(&identity.subject).is_ok_and(|glob| {
+                let matcher = glob.compile_matcher();
+                subjects.iter().any(|subject| matcher.is_match(subject))
+            })
+    });
+    if !accepted {
+        bail!(
+            "signing identity {} of issuer {} is not configured",
+            subjects.join(", "),
+            issuer
+        );
+    }
+
+    ec_point(leaf.public_key())
+}
+
+fn is_issued_by(certificate: &X509Certificate, issuer: &X509Certificate) -> bool {
+    certificate.issuer().as_raw() == issuer.subject().as_raw()
+        && certificate
+            .verify_signature(Some(issuer.public_key()))
+            .is_ok()
+}
+
+fn verify_certificate_chain(
+    leaf: &X509Certificate,
+    roots: &[X509Certificate],
+    intermediates: &[X509Certificate],
+) -> Result<()> {
+    let mut certificate = leaf;
+    for _ in 0..MAX_CERTIFICATE_CHAIN_LENGTH {
+        if roots.iter().any(|root| is_issued_by(certificate, root)) {
+            return Ok(());
+        }
+        certificate = intermediates
+            .iter()
+            .find(|intermediate| is_issued_by(certificate, intermediate))
+            .context("signing certificate is not issued by a configured Fulcio root")?;
+    }
+    bail!("signing certificate chain is too long");
+}
+
+fn certificate_oidc_issuer(certificate: &X509Certificate) -> Option<String> {
+    let extension = |oid: &str| {
+        certificate
+            .extensions()
+            .iter()
+            .find(|extension| extension.oid.to_id_string() == oid)
+    };
+    if let Some(extension) = extension(FULCIO_ISSUER_V2_OID) {
+        let (_, value) = parse_der_utf8string(extension.value).ok()?;
+        return value.as_str().ok().map(str::to_string);
+    }
+    extension(FULCIO_ISSUER_V1_OID)
+        .and_then(|extension| std::str::from_utf8(extension.value).ok())
+        .map(str::to_string)
+}
+
+fn certificate_subjects(certificate: &X509Certificate) -> Vec<String> {
+    let Ok(Some(names)) = certificate.subject_alternative_name() else {
+        return Vec::new();
+    };
+    names
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::RFC822Name(email) => Some(email.to_string()),
+            GeneralName::URI(uri) => Some(uri.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    /// DER prefix of a P-256 SubjectPublicKeyInfo, followed by the uncompressed point
+    const P256_SPKI_PREFIX: [u8; 26] = [
+        0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08,
+        0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+    ];
+    const DIGEST: &str = "sha256:4f1c1b5f1a4a6e2d1d9e3d0e1f9c8b7a6d5c4b3a29180706f5e4d3c2b1a09f8e";
+
+    fn generate_key() -> (EcdsaKeyPair, String) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let mut spki = P256_SPKI_PREFIX.to_vec();
+        spki.extend_from_slice(key_pair.public_key().as_ref());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            STANDARD.encode(spki)
+        );
+        (key_pair, pem)
+    }
+
+    fn sign(key_pair: &EcdsaKeyPair, digest: &str) -> (Vec<u8>, String) {
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "critical": {
+                "identity": { "docker-reference": "registry.example.com/app" },
+                "image": { "docker-manifest-digest": digest },
+                "type": "cosign container image signature"
+            },
+            "optional": null
+        }))
+        .unwrap();
+        let signature = key_pair.sign(&SystemRandom::new(), &payload).unwrap();
+        (payload, STANDARD.encode(signature.as_ref()))
+    }
+
+    #[test]
+    fn keyed_signature_of_the_digest_is_accepted() {
+        let (key_pair, public_key) = generate_key();
+        let policy = SignaturePolicy {
+            public_keys: vec![public_key],
+            keyless: None,
+        };
+        validate_policy(&policy).unwrap();
+
+        let (payload, signature) = sign(&key_pair, DIGEST);
+        assert!(verify_signature(&policy, DIGEST, &payload, &signature, &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn signatures_by_other_keys_or_of_other_digests_are_rejected() {
+        let (key_pair, _) = generate_key();
+        let (_, other_public_key) = generate_key();
+        let (_, public_key) = generate_key();
+        let policy = SignaturePolicy {
+            public_keys: vec![public_key, other_public_key],
+            keyless: None,
+        };
+        let (payload, signature) = sign(&key_pair, DIGEST);
+        assert!(verify_signature(&policy, DIGEST, &payload, &signature, &HashMap::new()).is_err());
+
+        let (signing_key, signing_public_key) = generate_key();
+        let policy = SignaturePolicy {
+            public_keys: vec![signing_public_key],
+            keyless: None,
+        };
+        let (payload, signature) = sign(&signing_key, "sha256:0000");
+        let err =
+            verify_signature(&policy, DIGEST, &payload, &signature, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("sha256:0000"));
+
+        let mut keyless_annotations = HashMap::new();
+        keyless_annotations.insert(
+            COSIGN_CERTIFICATE_ANNOTATION.to_string(),
+            "-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----\n".to_string(),
+        );
+        let (payload, signature) = sign(&signing_key, DIGEST);
+        assert!(
+            verify_signature(&policy, DIGEST, &payload, &signature, &keyless_annotations).is_err()
+        );
+    }
+}
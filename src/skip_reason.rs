@@ -0,0 +1,204 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+
+/// Why the last check of a workload did not roll it out, cleared by the next check that finds its digests
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SkipReason {
+    /// The namespace is not selected by the namespace patterns of the cluster-wide mode
+    NamespaceNotSelected,
+    /// The workload's own schedule annotation is not due yet
+    ScheduleNotDue {
+        schedule: String,
+    },
+    Paused,
+    Cooldown {
+        until: DateTime<Utc>,
+    },
+    /// The workload is scaled to zero, the containers whose changed digests are rolled out once it is scaled up are
+    /// listed
+    ZeroReplicas {
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pending_containers: Vec<String>,
+    },
+    /// No pod of the workload is scheduled and ready, so its running digests are unknown
+    PodsNotReady {
+        message: String,
+    },
+    NoMatchingRegistry {
+        image: String,
+    },
+    CredentialsUnavailable {
+        image: String,
+    },
+    RegistryUnavailable,
+    RegistryLookupFailed {
+        image: String,
+    },
+    RateLimited,
+    TagRemoved,
+    SignatureRejected {
+        message: String,
+    },
+    TrackedImagePaused,
+    /// The workload was already patched for the new digests and its pods are being replaced
+    RolloutInProgress,
+    DryRun,
+    PolicyDenied {
+        message: String,
+    },
+    WaitingForCanary {
+        message: String,
+    },
+    AwaitingApproval {
+        id: String,
+    },
+    UpdateAvailable {
+        id: String,
+    },
+    GroupBlocked {
+        group: String,
+        blocker: String,
+    },
+    /// The check failed, e.g. because of an unparsable image reference
+    CheckFailed {
+        error: String,
+    },
+    BackingOff {
+        retry_at: DateTime<Utc>,
+        attempt: u32,
+    },
+    DeadLettered {
+        attempts: u32,
+        last_error: String,
+    },
+    /// The reconcile pass hit its deadline before the workload was checked
+    Deferred,
+}
+
+impl SkipReason {
+    /// Reasons that are already published with an event of their own when they are recorded
+    pub fn has_own_event(&self) -> bool {
+        matches!(
+            self,
+            SkipReason::SignatureRejected { .. }
+                | SkipReason::DryRun
+                | SkipReason::PolicyDenied { .. }
+                | SkipReason::AwaitingApproval { .. }
+                | SkipReason::UpdateAvailable { .. }
+                | SkipReason::GroupBlocked { .. }
+                | SkipReason::DeadLettered { .. }
+        )
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NamespaceNotSelected => write!(f, "namespace not selected"),
+            SkipReason::ScheduleNotDue { schedule } => write!(f, "schedule {} not due", schedule),
+            SkipReason::Paused => write!(f, "workload paused"),
+            SkipReason::Cooldown { until } => write!(f, "rollout cooldown until {}", until),
+            SkipReason::ZeroReplicas { pending_containers } if pending_containers.is_empty() => {
+                write!(f, "zero replicas")
+            }
+            SkipReason::ZeroReplicas { pending_containers } => write!(
+                f,
+                "zero replicas, rolling out {} once scaled up",
+                pending_containers.join(", ")
+            ),
+            SkipReason::PodsNotReady { message } => write!(f, "pods not ready: {}", message),
+            SkipReason::NoMatchingRegistry { image } => {
+                write!(f, "no registry configuration matches {}", image)
+            }
+            SkipReason::CredentialsUnavailable { image } => {
+                write!(f, "registry credentials unavailable for {}", image)
+            }
+            SkipReason::RegistryUnavailable => write!(f, "registry unavailable"),
+            SkipReason::RegistryLookupFailed { image } => {
+                write!(f, "registry lookup failed for {}", image)
+            }
+            SkipReason::RateLimited => write!(f, "registry rate limit low"),
+            SkipReason::TagRemoved => write!(f, "tag removed upstream"),
+            SkipReason::SignatureRejected { message } => write!(f, "{}", message),
+            SkipReason::TrackedImagePaused => write!(f, "tracked image paused"),
+            SkipReason::RolloutInProgress => write!(f, "rollout in progress"),
+            SkipReason::DryRun => write!(f, "dry run"),
+            SkipReason::PolicyDenied { message } => write!(f, "policy denied: {}", message),
+            SkipReason::WaitingForCanary { message } => write!(f, "{}", message),
+            SkipReason::AwaitingApproval { id } => write!(f, "awaiting approval {}", id),
+            SkipReason::UpdateAvailable { id } => write!(f, "update available {}", id),
+            SkipReason::GroupBlocked { group, blocker } => {
+                write!(f, "group {} blocked: {}", group, blocker)
+            }
+            SkipReason::CheckFailed { error } => write!(f, "check failed: {}", error),
+            SkipReason::BackingOff { retry_at, attempt } => write!(
+                f,
+                "retrying failed check at {} (attempt {})",
+                retry_at, attempt
+            ),
+            SkipReason::DeadLettered {
+                attempts,
+                last_error,
+            } => write!(
+                f,
+                "dead-lettered after {} failed attempts: {}",
+                attempts, last_error
+            ),
+            SkipReason::Deferred => write!(f, "deferred to the next run by the pass deadline"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn skip_reasons_serialize_with_type_and_display_as_deferred_reason() {
+        let reason = SkipReason::GroupBlocked {
+            group: "payments".to_string(),
+            blocker: "Deployment/default/api paused".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&reason).unwrap(),
+            json!({
+                "type": "groupBlocked",
+                "group": "payments",
+                "blocker": "Deployment/default/api paused",
+            })
+        );
+        assert_eq!(
+            reason.to_string(),
+            "group payments blocked: Deployment/default/api paused"
+        );
+        assert_eq!(
+            serde_json::to_value(SkipReason::DeadLettered {
+                attempts: 10,
+                last_error: "invalid image".to_string(),
+            })
+            .unwrap(),
+            json!({ "type": "deadLettered", "attempts": 10, "lastError": "invalid image" })
+        );
+        assert_eq!(
+            serde_json::to_value(SkipReason::ZeroReplicas {
+                pending_containers: Vec::new(),
+            })
+            .unwrap(),
+            json!({ "type": "zeroReplicas" })
+        );
+        assert_eq!(
+            SkipReason::ZeroReplicas {
+                pending_containers: vec!["app".to_string()],
+            }
+            .to_string(),
+            "zero replicas, rolling out app once scaled up"
+        );
+    }
+}
@@ -1,15 +1,453 @@
-use crate::config::Config;
+use crate::approval::PendingApproval;
+use crate::cache::{CacheStats, LruCache, Weight};
+use crate::canary::CanaryRollout;
+use crate::config::{CacheConfig, Config, DockerConfig};
+use crate::error_report::ReportedErrors;
 use crate::image_reference::ImageReference;
+use crate::in_flight::InFlightOperations;
+use crate::notify::Notification;
+use crate::oci_registry::HttpClients;
+use crate::rate_limit::RateLimits;
+use crate::registry_credentials::CredentialCache;
+use crate::registry_probe::RegistryProbeResult;
+use crate::registry_requests::RegistryRequestLog;
+use crate::schedule::ScheduleTicks;
+use crate::skip_reason::SkipReason;
+use crate::token_cache::TokenCache;
+use crate::workqueue::WorkQueue;
+use chrono::{DateTime, Duration, Utc};
+use kube::runtime::events::Recorder;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone)]
 pub struct ControllerContext {
     pub(crate) kube_client: kube::Client,
-    pub(crate) config: Config,
-    pub(crate) http_client: reqwest::Client,
+    /// Configuration of the current run, taken from `latest_config` when the run starts
+    pub(crate) config: Arc<Config>,
+    /// Configuration as last loaded from the configuration file, replaced whenever the file is reloaded
+    pub(crate) latest_config: Arc<RwLock<Arc<Config>>>,
+    pub(crate) http_client: Arc<RwLock<HttpClients>>,
+    /// CA bundles read from ConfigMaps, trusted in addition to the configured CA files
+    pub(crate) ca_bundles: Arc<RwLock<Vec<String>>>,
+    pub(crate) state: Arc<RwLock<ControllerState>>,
+    pub(crate) caches: Arc<ControllerCaches>,
+    /// Publishes Kubernetes events on workloads, None unless enabled by the feature flag
+    pub(crate) events: Option<Recorder>,
+    /// Held exclusively while a reconciliation run is in progress, so that two runs never patch the same workloads at
+    /// once. Reconciles of the watch mode share it, they only exclude runs.
+    pub(crate) run_lock: Arc<tokio::sync::RwLock<()>>,
+    /// Whether this replica won the leader election, always set without leader election
+    pub(crate) leader: Arc<AtomicBool>,
+    /// Rate limits announced by the registries in their manifest responses
+    pub(crate) rate_limits: Arc<RateLimits>,
+    /// Notifications of the current aggregation window, None while no window is open
+    pub(crate) notification_batch: Arc<Mutex<Option<Vec<Notification>>>>,
+    /// Reconcile passes and API requests in flight
+    pub(crate) in_flight: Arc<InFlightOperations>,
+    /// Errors reported to the error-reporting service within its deduplication window
+    pub(crate) reported_errors: Arc<ReportedErrors>,
+    /// The last registry requests, served by `/debug/registry-requests`
+    pub(crate) registry_requests: Arc<RegistryRequestLog>,
+    /// Cancelled once scheduled runs failed in a row as often as `crashOnRepeatedFailure` allows, exiting the process
+    pub(crate) crash: CancellationToken,
+}
+
+impl ControllerContext {
+    /// Only the leader reconciles workloads, followers merely serve the webserver
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    /// Returns a context with the configuration as last loaded, to be taken at the start of a run or request so that
+    /// it uses a single configuration throughout
+    pub fn with_latest_config(&self) -> ControllerContext {
+        ControllerContext {
+            config: self.latest_config.read().unwrap().clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the current registry HTTP client, which is swapped out whenever its TLS configuration changes
+    pub fn http_client(&self) -> reqwest::Client {
+        self.http_client.read().unwrap().default_client()
+    }
+
+    /// Returns the HTTP client for requests to the given registry host, which differs from the default client for
+    /// registries with their own TLS configuration
+    pub fn registry_http_client(&self, host: &str) -> reqwest::Client {
+        match self.config.find_registry_for_hostname(host) {
+            Some(registry) => self
+                .http_client
+                .read()
+                .unwrap()
+                .for_registry(&registry.hostname_pattern),
+            None => self.http_client(),
+        }
+    }
+
+    /// Rebuilds the registry HTTP client from the current TLS material, keeping the previous client on failure
+    pub fn rebuild_http_client(&self) -> anyhow::Result<()> {
+        let client = {
+            let ca_bundles = self.ca_bundles.read().unwrap();
+            let config = self.latest_config.read().unwrap().clone();
+            crate::oci_registry::create_client(&config, &ca_bundles)?
+        };
+        *self.http_client.write().unwrap() = client;
+        Ok(())
+    }
+}
+
+/// Size-bounded caches shared by all reconcile passes
+pub struct ControllerCaches {
+    /// Recent registry digests keyed by full image reference
+    pub(crate) digests: Mutex<LruCache<String, CachedDigests>>,
+    /// Registry bearer tokens keyed by realm, service, scope and credentials
+    pub(crate) tokens: TokenCache,
+    /// Decoded image pull secrets keyed by namespace and name
+    pub(crate) secrets: Mutex<LruCache<String, CachedSecret>>,
+    /// Registry credentials obtained from cloud providers
+    pub(crate) credentials: CredentialCache,
+}
+
+pub struct CachedDigests {
+    pub(crate) digests: Vec<String>,
+    /// Host the digests were fetched from, differs from the image's registry after a failover
+    pub(crate) registry_host: String,
+    pub(crate) fetched_at: Instant,
+    /// Reconcile pass the digests were fetched in
+    pub(crate) pass: u64,
+}
+
+impl CachedDigests {
+    /// Digests are reused within the pass they were fetched in and across passes until the TTL expires
+    pub fn is_fresh(&self, pass: u64, ttl: std::time::Duration) -> bool {
+        self.pass == pass || self.fetched_at.elapsed() < ttl
+    }
+}
+
+impl Weight for CachedDigests {
+    fn weight(&self) -> usize {
+        self.digests.weight() + self.registry_host.len()
+    }
+}
+
+pub struct CachedSecret {
+    pub(crate) docker_config: DockerConfig,
+    /// resourceVersion of the Secret the config was decoded from
+    pub(crate) resource_version: Option<String>,
+    /// When the resourceVersion was last confirmed
+    pub(crate) validated_at: Instant,
+}
+
+impl CachedSecret {
+    /// Secrets are reused until the TTL expires, then reused further only if their resourceVersion is unchanged
+    pub fn is_fresh(&self, ttl: std::time::Duration) -> bool {
+        self.validated_at.elapsed() < ttl
+    }
+}
+
+impl Weight for CachedSecret {
+    fn weight(&self) -> usize {
+        self.docker_config.weight() + self.resource_version.as_ref().map_or(0, String::len)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ControllerCachesStats {
+    pub(crate) digests: CacheStats,
+    pub(crate) tokens: CacheStats,
+    pub(crate) secrets: CacheStats,
+}
+
+impl ControllerCaches {
+    pub fn new(config: &CacheConfig) -> Self {
+        ControllerCaches {
+            digests: Mutex::new(LruCache::new(config.digests.max_entries, None)),
+            tokens: TokenCache::new(config.tokens.max_entries),
+            secrets: Mutex::new(LruCache::new(config.secrets.max_entries, None)),
+            credentials: CredentialCache::default(),
+        }
+    }
+
+    pub fn stats(&self) -> ControllerCachesStats {
+        ControllerCachesStats {
+            digests: self.digests.lock().unwrap().stats(),
+            tokens: self.tokens.stats(),
+            secrets: self.secrets.lock().unwrap().stats(),
+        }
+    }
 }
 
 pub struct ContainerImageReference {
     pub(crate) container_name: String,
     pub(crate) image_reference: ImageReference,
     pub(crate) digest: String,
+    /// Init or sidecar container of the pod
+    pub(crate) init_container: bool,
+}
+
+impl ContainerImageReference {
+    /// The same container with its image looked up at another registry host
+    pub fn with_registry(&self, registry: &str) -> Self {
+        ContainerImageReference {
+            image_reference: ImageReference {
+                registry: registry.to_string(),
+                ..self.image_reference.clone()
+            },
+            container_name: self.container_name.clone(),
+            digest: self.digest.clone(),
+            init_container: self.init_container,
+        }
+    }
+}
+
+/// In-memory view of everything the controller has observed, used for debugging and introspection
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerState {
+    pub(crate) workloads: BTreeMap<String, TrackedWorkload>,
+    /// Latest connectivity probe result per configured registry hostname pattern
+    pub(crate) registry_probes: BTreeMap<String, RegistryProbeResult>,
+    /// Registries that could not be reached, mapped to the end of their cooldown
+    pub(crate) unavailable_registries: BTreeMap<String, DateTime<Utc>>,
+    /// Images of labeled workloads that match neither a configured registry nor an image pull secret, by workload key
+    pub(crate) unmatched_workloads: BTreeMap<String, BTreeSet<String>>,
+    /// Images whose tag was removed upstream, mapped to the end of their backoff
+    pub(crate) removed_tags: BTreeMap<String, DateTime<Utc>>,
+    /// Images whose latest digest failed the signature policy, mapped to the reason
+    pub(crate) signature_failures: BTreeMap<String, String>,
+    /// How often each configured registry pattern matched a workload image
+    pub(crate) registry_pattern_matches: BTreeMap<String, u64>,
+    pub(crate) completed_cycles: u64,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+    pub(crate) last_completed_run_at: Option<DateTime<Utc>>,
+    /// Scheduled runs skipped because the previous run was still in progress
+    pub(crate) skipped_runs: u64,
+    /// Kubernetes API requests that failed in a row, reset by the next successful request
+    pub(crate) kube_api_errors: u32,
+    /// Scheduled runs that failed in a row, reset by the next successful run
+    pub(crate) failed_runs: u32,
+    /// Why the configuration file could not be reloaded, the previous configuration is still in use
+    pub(crate) config_error: Option<String>,
+    /// Rollouts waiting for approval, by approval ID
+    pub(crate) pending_approvals: BTreeMap<String, PendingApproval>,
+    /// Latest canary rollout per image reference
+    pub(crate) canaries: BTreeMap<String, CanaryRollout>,
+    /// Workloads whose checks failed in a row, retried with backoff or dead-lettered
+    pub(crate) work_queue: WorkQueue,
+    /// Expected and actual starts of the scheduled runs
+    pub(crate) schedule_ticks: ScheduleTicks,
+    /// Workloads not checked before the deadline of the last pass, checked first in the next pass
+    pub(crate) deferred_workloads: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedWorkload {
+    pub(crate) kind: String,
+    pub(crate) namespace: String,
+    pub(crate) name: String,
+    pub(crate) containers: BTreeMap<String, TrackedContainer>,
+    pub(crate) last_checked_at: Option<DateTime<Utc>>,
+    pub(crate) last_rollout_at: Option<DateTime<Utc>>,
+    /// Start of the last check of a workload with its own schedule annotation
+    pub(crate) last_scheduled_check_at: Option<DateTime<Utc>>,
+    /// Why the last check of this workload did not roll it out, cleared by the next successful check
+    pub(crate) skipped: Option<SkipReason>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackedContainer {
+    pub(crate) image: String,
+    pub(crate) running_digest: String,
+    /// Host the digests were fetched from, differs from the image's registry after a failover
+    pub(crate) registry_host: String,
+    pub(crate) registry_digests: Vec<String>,
+}
+
+impl ControllerState {
+    pub fn workload_key(kind: &str, namespace: &str, name: &str) -> String {
+        format!("{}/{}/{}", kind, namespace, name)
+    }
+
+    pub fn workload_mut(
+        &mut self,
+        kind: &str,
+        namespace: &str,
+        name: &str,
+    ) -> &mut TrackedWorkload {
+        self.workloads
+            .entry(Self::workload_key(kind, namespace, name))
+            .or_insert_with(|| TrackedWorkload {
+                kind: kind.to_string(),
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+                containers: BTreeMap::new(),
+                last_checked_at: None,
+                last_rollout_at: None,
+                last_scheduled_check_at: None,
+                skipped: None,
+            })
+    }
+
+    pub fn record_container_check(
+        &mut self,
+        kind: &str,
+        namespace: &str,
+        name: &str,
+        reference: &ContainerImageReference,
+        registry_host: &str,
+        registry_digests: &[String],
+    ) {
+        let workload = self.workload_mut(kind, namespace, name);
+        workload.last_checked_at = Some(Utc::now());
+        workload.skipped = None;
+        workload.containers.insert(
+            reference.container_name.clone(),
+            TrackedContainer {
+                image: reference.image_reference.to_string(),
+                running_digest: reference.digest.clone(),
+                registry_host: registry_host.to_string(),
+                registry_digests: registry_digests.to_vec(),
+            },
+        );
+    }
+
+    pub fn record_kube_api_request(&mut self, succeeded: bool) {
+        self.kube_api_errors = match succeeded {
+            true => 0,
+            false => self.kube_api_errors.saturating_add(1),
+        };
+    }
+
+    /// Records the outcome of a scheduled run, returning how many runs failed in a row
+    pub fn record_run(&mut self, succeeded: bool) -> u32 {
+        self.failed_runs = match succeeded {
+            true => 0,
+            false => self.failed_runs.saturating_add(1),
+        };
+        self.failed_runs
+    }
+
+    pub fn record_rollout(&mut self, kind: &str, namespace: &str, name: &str) {
+        self.workload_mut(kind, namespace, name).last_rollout_at = Some(Utc::now());
+    }
+
+    pub fn remove_pending_approval(&mut self, kind: &str, namespace: &str, name: &str) {
+        self.pending_approvals.retain(|_, approval| {
+            approval.kind != kind || approval.namespace != namespace || approval.name != name
+        });
+    }
+
+    pub fn record_skip(&mut self, kind: &str, namespace: &str, name: &str, reason: SkipReason) {
+        self.workload_mut(kind, namespace, name).skipped = Some(reason);
+    }
+
+    pub fn record_unmatched_image(&mut self, kind: &str, namespace: &str, name: &str, image: &str) {
+        self.unmatched_workloads
+            .entry(Self::workload_key(kind, namespace, name))
+            .or_default()
+            .insert(image.to_string());
+    }
+
+    /// Forgets previously unmatched images of the workload before it is checked again
+    pub fn clear_unmatched_images(&mut self, kind: &str, namespace: &str, name: &str) {
+        self.unmatched_workloads
+            .remove(&Self::workload_key(kind, namespace, name));
+    }
+
+    pub fn record_registry_pattern_match(&mut self, hostname_pattern: &str) {
+        *self
+            .registry_pattern_matches
+            .entry(hostname_pattern.to_string())
+            .or_default() += 1;
+    }
+
+    /// Returns the end of the cooldown if the registry has recently been found unreachable
+    pub fn registry_unavailable_until(&self, registry: &str) -> Option<DateTime<Utc>> {
+        self.unavailable_registries
+            .get(registry)
+            .copied()
+            .filter(|until| *until > Utc::now())
+    }
+
+    pub fn mark_registry_unavailable(&mut self, registry: &str, cooldown: Duration) {
+        self.unavailable_registries
+            .insert(registry.to_string(), Utc::now() + cooldown);
+    }
+
+    pub fn mark_registry_available(&mut self, registry: &str) {
+        self.unavailable_registries.remove(registry);
+    }
+
+    /// Returns the end of the backoff if the tag of the image has recently been found removed upstream
+    pub fn tag_removed_until(&self, image: &str) -> Option<DateTime<Utc>> {
+        self.removed_tags
+            .get(image)
+            .copied()
+            .filter(|until| *until > Utc::now())
+    }
+
+    pub fn mark_tag_removed(&mut self, image: &str, backoff: Duration) {
+        self.removed_tags
+            .insert(image.to_string(), Utc::now() + backoff);
+    }
+
+    pub fn mark_tag_present(&mut self, image: &str) {
+        self.removed_tags.remove(image);
+    }
+
+    pub fn record_signature_failure(&mut self, image: &str, reason: &str) {
+        self.signature_failures
+            .insert(image.to_string(), reason.to_string());
+    }
+
+    pub fn clear_signature_failure(&mut self, image: &str) {
+        self.signature_failures.remove(image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_unavailability_expires_after_cooldown() {
+        let mut state = ControllerState::default();
+        state.mark_registry_unavailable("registry.example.com", Duration::seconds(60));
+        assert!(state
+            .registry_unavailable_until("registry.example.com")
+            .is_some());
+        assert!(state.registry_unavailable_until("ghcr.io").is_none());
+
+        state.mark_registry_unavailable("registry.example.com", Duration::seconds(-1));
+        assert!(state
+            .registry_unavailable_until("registry.example.com")
+            .is_none());
+
+        state.mark_registry_unavailable("registry.example.com", Duration::seconds(60));
+        state.mark_registry_available("registry.example.com");
+        assert!(state
+            .registry_unavailable_until("registry.example.com")
+            .is_none());
+    }
+
+    #[test]
+    fn unmatched_images_are_cleared_per_workload() {
+        let mut state = ControllerState::default();
+        state.record_unmatched_image("Deployment", "default", "a", "unknown.io/org/a:latest");
+        state.record_unmatched_image("Deployment", "default", "b", "unknown.io/org/b:latest");
+
+        state.clear_unmatched_images("Deployment", "default", "a");
+        assert_eq!(
+            state.unmatched_workloads.keys().collect::<Vec<_>>(),
+            vec!["Deployment/default/b"]
+        );
+    }
 }
@@ -0,0 +1,111 @@
+use crate::in_flight::InFlightOperation;
+use crate::state::{ControllerCachesStats, ControllerContext, ControllerState};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde_json::json;
+use std::fs;
+use tracing::{error, info};
+
+/// Dumps the controller state as JSON whenever the process receives SIGUSR1
+#[cfg(unix)]
+pub async fn listen_for_dump_signal(ctx: ControllerContext) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigusr1 = match signal(SignalKind::user_defined1()) {
+        Ok(sigusr1) => sigusr1,
+        Err(e) => {
+            error!(error = %e, "Failed to install SIGUSR1 handler, state dumps are disabled");
+            return;
+        }
+    };
+
+    while sigusr1.recv().await.is_some() {
+        info!("SIGUSR1 received, dumping controller state");
+        if let Err(e) = dump_state(&ctx) {
+            error!("Failed to dump controller state: {:?}", e);
+        }
+    }
+}
+
+fn dump_state(ctx: &ControllerContext) -> Result<()> {
+    let dump = {
+        let state = ctx.state.read().unwrap();
+        render_state_dump(&state, &ctx.caches.stats(), &ctx.in_flight.snapshot())?
+    };
+
+    match &ctx.config.diagnostics.state_dump_path {
+        Some(path) => {
+            fs::write(path, &dump)
+                .with_context(|| format!("Failed to write state dump to {}", path.display()))?;
+            info!(path = %path.display(), "Wrote controller state dump to file");
+        }
+        None => info!(state = %dump, "Controller state dump"),
+    }
+    Ok(())
+}
+
+pub(crate) fn render_state_dump(
+    state: &ControllerState,
+    caches: &ControllerCachesStats,
+    in_flight: &[InFlightOperation],
+) -> Result<String> {
+    let dump = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "dumpedAt": Utc::now().to_rfc3339(),
+        "state": state,
+        "caches": caches,
+        "inFlight": in_flight,
+    });
+    serde_json::to_string_pretty(&dump).context("Failed to serialize controller state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CacheConfig;
+    use crate::image_reference::ImageReference;
+    use crate::state::{ContainerImageReference, ControllerCaches};
+
+    #[test]
+    fn render_state_dump_contains_tracked_workloads() {
+        let mut state = ControllerState::default();
+        let reference = ContainerImageReference {
+            container_name: "app".to_string(),
+            image_reference: ImageReference::parse("ghcr.io/org/app:latest").unwrap(),
+            digest: "sha256:running".to_string(),
+            init_container: false,
+        };
+        state.record_container_check(
+            "Deployment",
+            "default",
+            "my-app",
+            &reference,
+            "ghcr.io",
+            &["sha256:recent".to_string()],
+        );
+        state.record_rollout("Deployment", "default", "my-app");
+
+        let caches = ControllerCaches::new(&CacheConfig::default());
+
+        let dump =
+            render_state_dump(&state, &caches.stats(), &[]).expect("state dump should render");
+        let parsed: serde_json::Value = serde_json::from_str(&dump).unwrap();
+        let workload = &parsed["state"]["workloads"]["Deployment/default/my-app"];
+
+        assert_eq!(workload["name"], "my-app");
+        assert_eq!(
+            workload["containers"]["app"]["image"],
+            "ghcr.io/org/app:latest"
+        );
+        assert_eq!(
+            workload["containers"]["app"]["runningDigest"],
+            "sha256:running"
+        );
+        assert_eq!(
+            workload["containers"]["app"]["registryDigests"][0],
+            "sha256:recent"
+        );
+        assert!(workload["lastRolloutAt"].is_string());
+        assert_eq!(parsed["caches"]["digests"]["maxEntries"], 10000);
+    }
+}
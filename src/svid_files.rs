@@ -0,0 +1,140 @@
+use crate::config::{SvidFiles, TlsBackend};
+use crate::state::ControllerContext;
+use crate::tls;
+use anyhow::{Context, Result};
+use reqwest::{Certificate, ClientBuilder};
+use std::fs;
+use tracing::{error, info};
+
+/// Attaches the X.509-SVID as client certificate and trusts the SPIFFE trust bundle.
+///
+/// This is a file-based SVID source, not a Workload API client: the files are expected to be kept up to date by a SPIFFE
+/// Workload API client running alongside kube-autorollout, e.g. spiffe-helper or the SPIFFE CSI driver.
+pub fn configure_client(
+    mut client_builder: ClientBuilder,
+    svid_files: &SvidFiles,
+    backend: Option<&TlsBackend>,
+) -> Result<ClientBuilder> {
+    let svid_pem = fs::read(&svid_files.svid_path).with_context(|| {
+        format!(
+            "Failed to read X.509-SVID from file {}",
+            svid_files.svid_path.display()
+        )
+    })?;
+    let key_pem = fs::read(&svid_files.svid_key_path).with_context(|| {
+        format!(
+            "Failed to read X.509-SVID private key from file {}",
+            svid_files.svid_key_path.display()
+        )
+    })?;
+
+    let identity = tls::identity_from_pem(&svid_pem, &key_pem, backend)
+        .context("Failed to parse X.509-SVID")?;
+    client_builder = client_builder.identity(identity);
+    info!(
+        svid = %svid_files.svid_path.display(),
+        "Using X.509-SVID as registry client certificate"
+    );
+
+    if let Some(bundle_path) = &svid_files.bundle_path {
+        let bundle = fs::read(bundle_path).with_context(|| {
+            format!(
+                "Failed to read SPIFFE trust bundle from file {}",
+                bundle_path.display()
+            )
+        })?;
+        for cert in
+            Certificate::from_pem_bundle(&bundle).context("Failed to parse SPIFFE trust bundle")?
+        {
+            client_builder = client_builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(client_builder)
+}
+
+/// Content of all SVID files, compared between polls to detect rotations
+fn read_svid_files(svid_files: &SvidFiles) -> Result<Vec<Vec<u8>>> {
+    let mut paths = vec![&svid_files.svid_path, &svid_files.svid_key_path];
+    paths.extend(&svid_files.bundle_path);
+
+    paths
+        .into_iter()
+        .map(|path| {
+            fs::read(path).with_context(|| format!("Failed to read file {}", path.display()))
+        })
+        .collect()
+}
+
+/// Polls the SVID files and rebuilds the registry HTTP client whenever the SVID has been rotated
+pub async fn reload_rotated_svids(ctx: ControllerContext) {
+    let Some(svid_files) = ctx.config.tls.svid_files.clone() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(svid_files.refresh_interval);
+    let mut current = read_svid_files(&svid_files).ok();
+
+    loop {
+        interval.tick().await;
+
+        let files = match read_svid_files(&svid_files) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("Failed to read X.509-SVID files: {:?}", e);
+                continue;
+            }
+        };
+        if current.as_ref() == Some(&files) {
+            continue;
+        }
+
+        info!("X.509-SVID has been rotated, rebuilding registry HTTP client");
+        match ctx.rebuild_http_client() {
+            Ok(()) => current = Some(files),
+            Err(e) => error!(
+                "Failed to rebuild registry HTTP client with rotated X.509-SVID, keeping the previous one: {:?}",
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn read_svid_files_detects_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let svid_files = SvidFiles {
+            svid_path: dir.path().join("svid.pem"),
+            svid_key_path: dir.path().join("svid_key.pem"),
+            bundle_path: Some(dir.path().join("bundle.pem")),
+            refresh_interval: Duration::from_secs(30),
+        };
+        fs::write(&svid_files.svid_path, "svid-1").unwrap();
+        fs::write(&svid_files.svid_key_path, "key-1").unwrap();
+        fs::write(svid_files.bundle_path.as_ref().unwrap(), "bundle-1").unwrap();
+
+        let before = read_svid_files(&svid_files).unwrap();
+        assert_eq!(before, read_svid_files(&svid_files).unwrap());
+
+        fs::write(&svid_files.svid_path, "svid-2").unwrap();
+        assert_ne!(before, read_svid_files(&svid_files).unwrap());
+    }
+
+    #[test]
+    fn configure_client_fails_on_missing_svid() {
+        let dir = tempfile::tempdir().unwrap();
+        let svid_files = SvidFiles {
+            svid_path: dir.path().join("missing.pem"),
+            svid_key_path: dir.path().join("missing_key.pem"),
+            bundle_path: None,
+            refresh_interval: Duration::from_secs(30),
+        };
+
+        let err = configure_client(reqwest::Client::builder(), &svid_files, None).unwrap_err();
+        assert!(format!("{err:#}").contains("Failed to read X.509-SVID"));
+    }
+}
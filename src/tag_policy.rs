@@ -0,0 +1,213 @@
+use anyhow::{bail, Context};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use semver::Version;
+
+pub const KUBE_AUTOROLLOUT_TAG_POLICY_ANNOTATION: &str = "kube-autorollout/tag-policy";
+
+/// Newer tags a workload following versioned tags is updated to, selected by the workload's tag policy annotation
+#[derive(Debug)]
+pub enum TagPolicy {
+    /// Any newer version
+    Major,
+    /// Newer versions of the same major version
+    Minor,
+    /// Newer versions of the same major and minor version
+    Patch,
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl TagPolicy {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "major" => Ok(TagPolicy::Major),
+            "minor" => Ok(TagPolicy::Minor),
+            "patch" => Ok(TagPolicy::Patch),
+            _ => {
+                if let Some(pattern) = value.strip_prefix("glob:") {
+                    let glob = Glob::new(pattern)
+                        .with_context(|| format!("invalid tag glob pattern {}", pattern))?;
+                    Ok(TagPolicy::Glob(glob.compile_matcher()))
+                } else if let Some(pattern) = value.strip_prefix("regex:") {
+                    let regex = Regex::new(pattern)
+                        .with_context(|| format!("invalid tag regex {}", pattern))?;
+                    Ok(TagPolicy::Regex(regex))
+                } else {
+                    bail!(
+                        "unknown tag policy {}, expected major, minor, patch, glob:<pattern> or regex:<pattern>",
+                        value
+                    )
+                }
+            }
+        }
+    }
+
+    /// Returns the newest of the tags the current tag may be updated to, None if the current tag is the newest one
+    pub fn select_newest_tag(&self, current: &str, tags: &[String]) -> Option<String> {
+        match self {
+            TagPolicy::Major | TagPolicy::Minor | TagPolicy::Patch => {
+                self.select_newest_version(current, tags)
+            }
+            TagPolicy::Glob(matcher) => {
+                select_newest_match(current, tags, |tag| matcher.is_match(tag))
+            }
+            TagPolicy::Regex(regex) => {
+                select_newest_match(current, tags, |tag| regex.is_match(tag))
+            }
+        }
+    }
+
+    fn select_newest_version(&self, current: &str, tags: &[String]) -> Option<String> {
+        let current_version = parse_version(current)?;
+        tags.iter()
+            .filter(|tag| has_version_prefix(tag) == has_version_prefix(current))
+            .filter_map(|tag| Some((parse_version(tag)?, tag)))
+            .filter(|(version, _)| {
+                // pre-releases are only followed by workloads already running a pre-release
+                (version.pre.is_empty() || !current_version.pre.is_empty())
+                    && *version > current_version
+                    && match self {
+                        TagPolicy::Patch => {
+                            version.major == current_version.major
+                                && version.minor == current_version.minor
+                        }
+                        TagPolicy::Minor => version.major == current_version.major,
+                        _ => true,
+                    }
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, tag)| tag.clone())
+    }
+}
+
+/// Versions are newer than any tag that is not a version, which are ordered by name with numbers compared by value
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum TagOrder<'a> {
+    Name(Vec<NameChunk<'a>>),
+    Version(Version),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum NameChunk<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+fn tag_order(tag: &str) -> TagOrder<'_> {
+    if let Some(version) = parse_version(tag) {
+        return TagOrder::Version(version);
+    }
+    let mut chunks = Vec::new();
+    let mut rest = tag;
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, remainder) = rest.split_at(end);
+        chunks.push(match chunk.parse() {
+            Ok(number) if is_digit => NameChunk::Number(number),
+            _ => NameChunk::Text(chunk),
+        });
+        rest = remainder;
+    }
+    TagOrder::Name(chunks)
+}
+
+fn select_newest_match(
+    current: &str,
+    tags: &[String],
+    matches: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let newest = tags
+        .iter()
+        .filter(|tag| matches(tag))
+        .max_by(|a, b| tag_order(a).cmp(&tag_order(b)))?;
+    (tag_order(newest) > tag_order(current)).then(|| newest.clone())
+}
+
+fn has_version_prefix(tag: &str) -> bool {
+    tag.starts_with('v')
+}
+
+/// Parses tags such as `1.2.3`, `v1.2.3-rc.1` or the shortened `v1.2` and `1` as semantic versions
+fn parse_version(tag: &str) -> Option<Version> {
+    let version = tag.strip_prefix('v').unwrap_or(tag);
+    if let Ok(version) = Version::parse(version) {
+        return Some(version);
+    }
+    let core_end = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(core_end);
+    let padding = match core.matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => return None,
+    };
+    Version::parse(&format!("{}{}{}", core, padding, suffix)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn semver_policies_select_the_newest_allowed_version() {
+        let available = tags(&[
+            "1.2.3",
+            "1.2.4",
+            "1.3.0",
+            "2.0.0",
+            "2.1.0-rc.1",
+            "v9.0.0",
+            "latest",
+        ]);
+
+        let select = |policy: &str, current: &str| {
+            TagPolicy::parse(policy)
+                .unwrap()
+                .select_newest_tag(current, &available)
+        };
+        assert_eq!(select("patch", "1.2.3"), Some("1.2.4".to_string()));
+        assert_eq!(select("minor", "1.2.3"), Some("1.3.0".to_string()));
+        assert_eq!(select("major", "1.2.3"), Some("2.0.0".to_string()));
+        assert_eq!(select("major", "2.0.0"), None);
+        assert_eq!(
+            select("major", "2.1.0-rc.0"),
+            Some("2.1.0-rc.1".to_string())
+        );
+        assert_eq!(select("minor", "v1.0"), None);
+        assert_eq!(select("major", "v1.0"), Some("v9.0.0".to_string()));
+        assert_eq!(select("major", "latest"), None);
+    }
+
+    #[test]
+    fn pattern_policies_select_the_newest_matching_tag() {
+        let available = tags(&[
+            "build-100",
+            "build-99",
+            "1.4.0-alpine",
+            "1.5.0-alpine",
+            "1.6.0",
+        ]);
+
+        let glob = TagPolicy::parse("glob:build-*").unwrap();
+        assert_eq!(
+            glob.select_newest_tag("build-10", &available),
+            Some("build-100".to_string())
+        );
+
+        let regex = TagPolicy::parse(r"regex:^\d+\.\d+\.\d+-alpine$").unwrap();
+        assert_eq!(
+            regex.select_newest_tag("1.4.0-alpine", &available),
+            Some("1.5.0-alpine".to_string())
+        );
+        assert_eq!(regex.select_newest_tag("1.5.0-alpine", &available), None);
+
+        assert!(TagPolicy::parse("newest").is_err());
+    }
+}
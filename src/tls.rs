@@ -0,0 +1,142 @@
+use crate::config::TlsBackend;
+use anyhow::{bail, Result};
+use reqwest::{ClientBuilder, Identity};
+use tracing::info;
+
+#[cfg(not(any(
+    feature = "rustls-ring",
+    feature = "rustls-aws-lc",
+    feature = "native-tls"
+)))]
+compile_error!(
+    "No TLS backend enabled, enable one of the features rustls-ring, rustls-aws-lc or native-tls"
+);
+
+#[cfg(all(feature = "rustls-ring", feature = "rustls-aws-lc"))]
+compile_error!(
+    "The features rustls-ring and rustls-aws-lc are mutually exclusive, build with --no-default-features to use \
+    rustls-aws-lc or fips"
+);
+
+pub const RUSTLS_AVAILABLE: bool = cfg!(any(feature = "rustls-ring", feature = "rustls-aws-lc"));
+pub const NATIVE_TLS_AVAILABLE: bool = cfg!(feature = "native-tls");
+
+/// Name of the crypto provider compiled into this build, for logs and diagnostics
+pub fn crypto_provider_name() -> &'static str {
+    if cfg!(feature = "fips") {
+        "aws-lc-rs (FIPS)"
+    } else if cfg!(feature = "rustls-aws-lc") {
+        "aws-lc-rs"
+    } else if cfg!(feature = "rustls-ring") {
+        "ring"
+    } else {
+        "native-tls"
+    }
+}
+
+/// Installs the rustls crypto provider selected at build time as process-wide default,
+/// so every rustls client (registry and Kubernetes API) uses it
+pub fn install_crypto_provider() -> Result<()> {
+    #[cfg(feature = "fips")]
+    let provider = rustls::crypto::default_fips_provider();
+    #[cfg(all(feature = "rustls-aws-lc", not(feature = "fips")))]
+    let provider = rustls::crypto::aws_lc_rs::default_provider();
+
+    #[cfg(feature = "rustls-aws-lc")]
+    if provider.install_default().is_err() {
+        bail!("A rustls crypto provider has already been installed");
+    }
+
+    info!(
+        provider = %crypto_provider_name(),
+        "Using TLS crypto provider"
+    );
+    Ok(())
+}
+
+/// Resolves the configured backend, defaulting to rustls whenever it has been compiled in
+pub fn effective_backend(configured: Option<&TlsBackend>) -> TlsBackend {
+    match configured {
+        Some(backend) => backend.clone(),
+        None if RUSTLS_AVAILABLE => TlsBackend::Rustls,
+        None => TlsBackend::NativeTls,
+    }
+}
+
+pub fn validate_backend(configured: Option<&TlsBackend>) -> Result<()> {
+    match effective_backend(configured) {
+        TlsBackend::Rustls if !RUSTLS_AVAILABLE => {
+            bail!("TLS backend rustls is not available in this build")
+        }
+        TlsBackend::NativeTls if !NATIVE_TLS_AVAILABLE => {
+            bail!("TLS backend native-tls is not available in this build")
+        }
+        TlsBackend::NativeTls if cfg!(feature = "fips") => {
+            bail!("FIPS builds only support the rustls TLS backend")
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn configure_backend(
+    client_builder: ClientBuilder,
+    configured: Option<&TlsBackend>,
+) -> Result<ClientBuilder> {
+    match effective_backend(configured) {
+        #[cfg(any(feature = "rustls-ring", feature = "rustls-aws-lc"))]
+        TlsBackend::Rustls => Ok(client_builder.use_rustls_tls()),
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => Ok(client_builder.use_native_tls()),
+        #[allow(unreachable_patterns)]
+        backend => bail!("TLS backend {:?} is not available in this build", backend),
+    }
+}
+
+/// Builds a client certificate identity from a PEM encoded certificate chain and private key
+pub fn identity_from_pem(
+    certificate_chain: &[u8],
+    private_key: &[u8],
+    configured: Option<&TlsBackend>,
+) -> Result<Identity> {
+    match effective_backend(configured) {
+        #[cfg(any(feature = "rustls-ring", feature = "rustls-aws-lc"))]
+        TlsBackend::Rustls => {
+            let mut pem = certificate_chain.to_vec();
+            pem.push(b'\n');
+            pem.extend_from_slice(private_key);
+            Ok(Identity::from_pem(&pem)?)
+        }
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => Ok(Identity::from_pkcs8_pem(certificate_chain, private_key)?),
+        #[allow(unreachable_patterns)]
+        backend => bail!("TLS backend {:?} is not available in this build", backend),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_backend_prefers_configured_backend() {
+        assert_eq!(
+            effective_backend(Some(&TlsBackend::NativeTls)),
+            TlsBackend::NativeTls
+        );
+        if RUSTLS_AVAILABLE {
+            assert_eq!(effective_backend(None), TlsBackend::Rustls);
+        }
+    }
+
+    #[test]
+    fn validate_backend_rejects_backends_missing_from_build() {
+        assert_eq!(
+            validate_backend(Some(&TlsBackend::NativeTls)).is_ok(),
+            NATIVE_TLS_AVAILABLE && !cfg!(feature = "fips")
+        );
+        assert_eq!(
+            validate_backend(Some(&TlsBackend::Rustls)).is_ok(),
+            RUSTLS_AVAILABLE
+        );
+    }
+}
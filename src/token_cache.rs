@@ -0,0 +1,140 @@
+use crate::cache::{CacheStats, LruCache};
+use crate::secret_string::SecretString;
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Lifetime of tokens whose response omits `expires_in`, as defined by the Docker token authentication spec
+const DEFAULT_TOKEN_LIFETIME_SECONDS: i64 = 60;
+/// Tokens are dropped from the cache before they expire, so that requests using them do not race the expiry
+const TOKEN_EXPIRY_MARGIN_SECONDS: i64 = 10;
+
+/// Registry bearer tokens keyed by realm, service, scope and the credentials they were requested with
+pub struct TokenCache {
+    tokens: Mutex<LruCache<String, SecretString>>,
+}
+
+impl TokenCache {
+    pub fn new(max_entries: usize) -> Self {
+        TokenCache {
+            tokens: Mutex::new(LruCache::new(max_entries, None)),
+        }
+    }
+
+    /// The credentials are part of the key, so workloads with different pull secrets never share a token
+    pub fn key(realm: &str, service: &str, scope: Option<&str>, authorization: &str) -> String {
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, authorization.as_bytes());
+        let fingerprint: String = fingerprint
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!(
+            "{} {} {} {}",
+            realm,
+            service,
+            scope.unwrap_or_default(),
+            fingerprint
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<SecretString> {
+        self.tokens.lock().unwrap().get(&key.to_string()).cloned()
+    }
+
+    /// Caches the token until shortly before it expires, tokens about to expire are not cached at all
+    pub fn insert(
+        &self,
+        key: String,
+        token: SecretString,
+        expires_in: Option<u64>,
+        issued_at: Option<DateTime<Utc>>,
+    ) {
+        if let Some(lifetime) = cache_lifetime(expires_in, issued_at, Utc::now()) {
+            self.tokens
+                .lock()
+                .unwrap()
+                .insert_with_ttl(key, token, Some(lifetime));
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.tokens.lock().unwrap().stats()
+    }
+}
+
+fn cache_lifetime(
+    expires_in: Option<u64>,
+    issued_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<Duration> {
+    let expires_in = expires_in
+        .and_then(|expires_in| i64::try_from(expires_in).ok())
+        .unwrap_or(DEFAULT_TOKEN_LIFETIME_SECONDS);
+    // registries and the controller disagree on the time now and then, a token is never older than zero seconds
+    let age = issued_at
+        .map(|issued_at| (now - issued_at).num_seconds().max(0))
+        .unwrap_or(0);
+    let remaining = expires_in - age - TOKEN_EXPIRY_MARGIN_SECONDS;
+    (remaining > 0).then(|| Duration::from_secs(remaining as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifetime_honors_expires_in_and_issued_at() {
+        let now = Utc::now();
+        assert_eq!(
+            cache_lifetime(Some(300), None, now),
+            Some(Duration::from_secs(290))
+        );
+        assert_eq!(
+            cache_lifetime(None, None, now),
+            Some(Duration::from_secs(50))
+        );
+        assert_eq!(
+            cache_lifetime(Some(300), Some(now - chrono::Duration::seconds(100)), now),
+            Some(Duration::from_secs(190))
+        );
+        assert_eq!(
+            cache_lifetime(Some(300), Some(now + chrono::Duration::seconds(30)), now),
+            Some(Duration::from_secs(290))
+        );
+        assert_eq!(
+            cache_lifetime(Some(60), Some(now - chrono::Duration::seconds(55)), now),
+            None
+        );
+    }
+
+    #[test]
+    fn tokens_are_cached_per_credentials() {
+        let cache = TokenCache::new(10);
+        let scope = Some("repository:org/app:pull");
+        let key = TokenCache::key(
+            "https://auth.example.com/token",
+            "registry",
+            scope,
+            "Basic a",
+        );
+        let other_key = TokenCache::key(
+            "https://auth.example.com/token",
+            "registry",
+            scope,
+            "Basic b",
+        );
+        cache.insert(
+            key.clone(),
+            SecretString::new("token".to_string()),
+            Some(300),
+            None,
+        );
+
+        assert_eq!(
+            cache.get(&key),
+            Some(SecretString::new("token".to_string()))
+        );
+        assert_eq!(cache.get(&other_key), None);
+    }
+}
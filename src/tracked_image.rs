@@ -0,0 +1,202 @@
+use crate::config::{Registry, RegistrySecret};
+use crate::image_reference::ImageReference;
+use crate::oci_registry::fetch_digests_with_failover;
+use crate::registry_credentials::resolve_registry_secret;
+use crate::state::ControllerContext;
+use anyhow::Context;
+use chrono::Utc;
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::{Api, CustomResource, ResourceExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+/// Annotation on workloads naming the TrackedImage their containers follow
+pub static TRACKED_IMAGE_ANNOTATION: &str = "kube-autorollout/tracked-image";
+static KUBE_AUTOROLLOUT_FIELD_MANAGER: &str = "kube-autorollout";
+
+/// An image reference resolved once per cycle and fanned out to every workload referencing it
+#[derive(CustomResource, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "kube-autorollout.io",
+    version = "v1alpha1",
+    kind = "TrackedImage",
+    namespaced,
+    status = "TrackedImageStatus",
+    shortname = "ti",
+    printcolumn = r#"{"name":"Image","type":"string","jsonPath":".spec.image"}"#,
+    printcolumn = r#"{"name":"Paused","type":"boolean","jsonPath":".spec.paused"}"#,
+    printcolumn = r#"{"name":"Resolved","type":"string","jsonPath":".status.resolvedAt"}"#
+)]
+pub struct TrackedImageSpec {
+    /// Image reference including the tag to follow, e.g. ghcr.io/org/app:latest
+    pub image: String,
+    /// Gate holding back rollouts of all referencing workloads while set
+    #[serde(default)]
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TrackedImageStatus {
+    #[serde(default)]
+    pub digests: Vec<String>,
+    #[serde(default, rename = "registryHost")]
+    pub registry_host: Option<String>,
+    #[serde(default, rename = "resolvedAt")]
+    pub resolved_at: Option<String>,
+}
+
+/// Outcome of resolving a TrackedImage, looked up by name while reconciling workloads
+#[derive(Debug, Clone)]
+pub struct ResolvedTrackedImage {
+    pub image_reference: ImageReference,
+    pub paused: bool,
+    pub digests: Vec<String>,
+    pub registry_host: String,
+}
+
+impl ResolvedTrackedImage {
+    pub fn tracks(&self, image_reference: &ImageReference) -> bool {
+        self.image_reference == *image_reference
+    }
+}
+
+/// Resolves every TrackedImage in the controller's namespace, keyed by name.
+/// TrackedImages that fail to resolve are left out, so their workloads fall back to per-container lookups.
+pub async fn resolve_tracked_images(
+    ctx: &ControllerContext,
+) -> anyhow::Result<BTreeMap<String, ResolvedTrackedImage>> {
+    let api: Api<TrackedImage> = Api::default_namespaced(ctx.kube_client.clone());
+    let tracked_images = api
+        .list(&ListParams::default())
+        .await
+        .context("Failed to list TrackedImages")?;
+
+    info!(
+        count = %tracked_images.items.len(),
+        "Resolving TrackedImages"
+    );
+
+    let mut resolved = BTreeMap::new();
+    for tracked_image in tracked_images.items {
+        let name = tracked_image.name_any();
+        match resolve_tracked_image(ctx, &api, &tracked_image).await {
+            Ok(resolved_image) => {
+                resolved.insert(name, resolved_image);
+            }
+            Err(err) => warn!(
+                error = ?err,
+                tracked_image = %name,
+                "Failed to resolve TrackedImage"
+            ),
+        }
+    }
+    Ok(resolved)
+}
+
+async fn resolve_tracked_image(
+    ctx: &ControllerContext,
+    api: &Api<TrackedImage>,
+    tracked_image: &TrackedImage,
+) -> anyhow::Result<ResolvedTrackedImage> {
+    let name = tracked_image.name_any();
+    let image_reference = ImageReference::parse(&tracked_image.spec.image)
+        .with_context(|| format!("Invalid image reference {}", tracked_image.spec.image))?;
+
+    let registry = ctx
+        .config
+        .find_registry_for_hostname(&image_reference.registry);
+    let registry_secret = resolve_registry_secret(
+        &ctx.http_client(),
+        &ctx.caches.credentials,
+        &image_reference.registry,
+        registry
+            .map(Registry::auth_secret)
+            .unwrap_or(RegistrySecret::None),
+    )
+    .await?;
+    let fallback_hosts = registry
+        .map(Registry::fallback_endpoints)
+        .unwrap_or_default();
+
+    let (digests, registry_host) = fetch_digests_with_failover(
+        &ctx.config.registry_endpoint(&image_reference),
+        &fallback_hosts,
+        &registry_secret,
+        &ctx.registry_http_client(&image_reference.registry),
+        &ctx.caches.tokens,
+        &ctx.rate_limits,
+        &ctx.config.registry_retry,
+        &ctx.registry_requests,
+        ctx.config.feature_flags.enable_jfrog_artifactory_fallback,
+    )
+    .await
+    .context("Failed to retrieve recent digests from registry")?;
+
+    info!(
+        tracked_image = %name,
+        image = %image_reference,
+        recent_digests = %digests.join(","),
+        "Resolved TrackedImage"
+    );
+
+    let status = json!({
+        "status": TrackedImageStatus {
+            digests: digests.clone(),
+            registry_host: Some(registry_host.clone()),
+            resolved_at: Some(Utc::now().to_rfc3339()),
+        }
+    });
+    if let Err(err) = api
+        .patch_status(
+            &name,
+            &PatchParams::apply(KUBE_AUTOROLLOUT_FIELD_MANAGER),
+            &Patch::Merge(&status),
+        )
+        .await
+    {
+        warn!(
+            error = %err,
+            tracked_image = %name,
+            "Failed to update TrackedImage status"
+        );
+    }
+
+    Ok(ResolvedTrackedImage {
+        image_reference,
+        paused: tracked_image.spec.paused,
+        digests,
+        registry_host,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::CustomResourceExt;
+
+    #[test]
+    fn crd_is_namespaced_with_status_subresource() {
+        let crd = TrackedImage::crd();
+        assert_eq!(crd.spec.names.kind, "TrackedImage");
+        assert_eq!(crd.spec.scope, "Namespaced");
+        let version = &crd.spec.versions[0];
+        assert_eq!(version.name, "v1alpha1");
+        assert!(version.subresources.as_ref().unwrap().status.is_some());
+    }
+
+    #[test]
+    fn resolved_tracked_image_only_tracks_identical_references() {
+        let resolved = ResolvedTrackedImage {
+            image_reference: ImageReference::parse("ghcr.io/org/app:latest").unwrap(),
+            paused: false,
+            digests: vec!["sha256:recent".to_string()],
+            registry_host: "ghcr.io".to_string(),
+        };
+        assert!(resolved.tracks(&ImageReference::parse("ghcr.io/org/app:latest").unwrap()));
+        assert!(!resolved.tracks(&ImageReference::parse("ghcr.io/org/app:stable").unwrap()));
+        assert!(!resolved.tracks(&ImageReference::parse("ghcr.io/org/other:latest").unwrap()));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::Webhooks;
+use crate::controller;
+use crate::hostname;
+use crate::image_reference::split_registry;
+use crate::state::ControllerContext;
+use axum::extract::{Query, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn};
+
+/// Repository pushed to a registry, as announced by the registry's webhook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushEvent {
+    /// Registry host, unknown for registries whose events only name the repository
+    pub registry: Option<String>,
+    pub repository: String,
+    pub tag: Option<String>,
+}
+
+impl PushEvent {
+    /// Whether the image of a workload's pod template refers to the pushed repository
+    pub fn matches_image(&self, image: &str) -> bool {
+        let name = image.split('@').next().unwrap_or(image);
+        let name = match (name.rfind(':'), name.rfind('/')) {
+            (Some(colon), slash) if slash.is_none_or(|slash| colon > slash) => &name[..colon],
+            _ => name,
+        };
+        let (registry, repository) = split_registry(name);
+
+        let registry_matches = self
+            .registry
+            .as_deref()
+            .is_none_or(|pushed| hostname::normalize(pushed) == hostname::normalize(registry));
+        // Artifactory names repositories without the repository key that may prefix the image path
+        registry_matches
+            && (repository == self.repository
+                || repository.ends_with(&format!("/{}", self.repository)))
+    }
+}
+
+/// Splits `host/repository[:tag]` as used in Harbor, Quay and GitHub events
+fn split_image_url(url: &str) -> Option<PushEvent> {
+    let url = url.split('@').next()?;
+    let (registry, rest) = url.split_once('/')?;
+    let (repository, tag) = match rest.rsplit_once(':') {
+        Some((repository, tag)) => (repository, Some(tag.to_string())),
+        None => (rest, None),
+    };
+    Some(PushEvent {
+        registry: Some(registry.to_string()),
+        repository: repository.to_string(),
+        tag,
+    })
+}
+
+/// Recognizes push events of Harbor, Docker Hub, Quay, GitHub Packages (GHCR) and JFrog Artifactory
+pub fn parse_push_event(body: &Value) -> Option<PushEvent> {
+    // Harbor
+    if let Some(event_type) = body["type"].as_str() {
+        if event_type != "PUSH_ARTIFACT" && event_type != "pushImage" {
+            return None;
+        }
+        let resource_url = body["event_data"]["resources"][0]["resource_url"].as_str()?;
+        let mut event = split_image_url(resource_url)?;
+        if let Some(repository) = body["event_data"]["repository"]["repo_full_name"].as_str() {
+            event.repository = repository.to_string();
+        }
+        return Some(event);
+    }
+
+    // Docker Hub
+    if body.get("push_data").is_some() {
+        return Some(PushEvent {
+            registry: Some("docker.io".to_string()),
+            repository: body["repository"]["repo_name"].as_str()?.to_string(),
+            tag: body["push_data"]["tag"].as_str().map(str::to_string),
+        });
+    }
+
+    // Quay
+    if let Some(docker_url) = body["docker_url"].as_str() {
+        let mut event = split_image_url(docker_url)?;
+        event.tag = body["updated_tags"][0].as_str().map(str::to_string);
+        return Some(event);
+    }
+
+    // GitHub package and registry_package events
+    let package = body.get("registry_package").or_else(|| body.get("package"));
+    if let Some(package) = package {
+        if !matches!(body["action"].as_str(), Some("published" | "updated")) {
+            return None;
+        }
+        return split_image_url(package["package_version"]["package_url"].as_str()?);
+    }
+
+    // JFrog Artifactory
+    if body["domain"].as_str() == Some("docker") {
+        if body["event_type"].as_str() != Some("pushed") {
+            return None;
+        }
+        return Some(PushEvent {
+            registry: None,
+            repository: body["data"]["image_name"].as_str()?.to_string(),
+            tag: body["data"]["tag"].as_str().map(str::to_string),
+        });
+    }
+
+    None
+}
+
+#[derive(Deserialize)]
+pub struct WebhookQuery {
+    token: Option<String>,
+}
+
+/// Registries that can not send custom headers, such as Docker Hub, pass the token as query parameter instead
+fn is_authorized(webhooks: &Webhooks, headers: &HeaderMap, query: &WebhookQuery) -> bool {
+    let expected = webhooks.token.expose_secret();
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer == Some(expected) || query.token.as_deref() == Some(expected)
+}
+
+pub async fn receive_registry_webhook(
+    State(ctx): State<ControllerContext>,
+    Query(query): Query<WebhookQuery>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    // The route stays registered so that webhooks enabled by a config reload take effect without a restart
+    let Some(webhooks) = &ctx.config.webhooks else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !is_authorized(webhooks, &headers, &query) {
+        warn!("Rejecting registry webhook with missing or invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let Some(event) = parse_push_event(&body) else {
+        debug!(body = %body, "Ignoring registry webhook that is not a push event");
+        return StatusCode::NO_CONTENT.into_response();
+    };
+
+    info!(
+        registry = ?event.registry,
+        repository = %event.repository,
+        tag = ?event.tag,
+        "Received registry push event, reconciling workloads using the repository"
+    );
+    let response = json!({
+        "registry": event.registry,
+        "repository": event.repository,
+        "tag": event.tag,
+    });
+    tokio::spawn(async move {
+        if let Err(err) = controller::run_for_push(ctx, &event).await {
+            error!(
+                error = ?err,
+                repository = %event.repository,
+                "Error while reconciling workloads after push event"
+            );
+        }
+    });
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(registry: Option<&str>, repository: &str, tag: &str) -> PushEvent {
+        PushEvent {
+            registry: registry.map(str::to_string),
+            repository: repository.to_string(),
+            tag: Some(tag.to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_push_events_of_supported_registries() {
+        let harbor = json!({
+            "type": "PUSH_ARTIFACT",
+            "event_data": {
+                "resources": [{ "tag": "latest", "resource_url": "harbor.example.com/library/app:latest" }],
+                "repository": { "repo_full_name": "library/app" }
+            }
+        });
+        let docker_hub = json!({
+            "push_data": { "tag": "latest" },
+            "repository": { "repo_name": "org/app" }
+        });
+        let quay = json!({
+            "repository": "org/app",
+            "docker_url": "quay.io/org/app",
+            "updated_tags": ["latest"]
+        });
+        let github = json!({
+            "action": "published",
+            "registry_package": {
+                "package_version": { "package_url": "ghcr.io/org/app:latest" }
+            }
+        });
+        let artifactory = json!({
+            "domain": "docker",
+            "event_type": "pushed",
+            "data": { "repo_key": "docker-local", "image_name": "org/app", "tag": "latest" }
+        });
+
+        assert_eq!(
+            parse_push_event(&harbor),
+            Some(event(Some("harbor.example.com"), "library/app", "latest"))
+        );
+        assert_eq!(
+            parse_push_event(&docker_hub),
+            Some(event(Some("docker.io"), "org/app", "latest"))
+        );
+        assert_eq!(
+            parse_push_event(&quay),
+            Some(event(Some("quay.io"), "org/app", "latest"))
+        );
+        assert_eq!(
+            parse_push_event(&github),
+            Some(event(Some("ghcr.io"), "org/app", "latest"))
+        );
+        assert_eq!(
+            parse_push_event(&artifactory),
+            Some(event(None, "org/app", "latest"))
+        );
+        assert_eq!(
+            parse_push_event(&json!({ "type": "DELETE_ARTIFACT" })),
+            None
+        );
+    }
+
+    #[test]
+    fn push_event_matches_workload_images() {
+        let docker_hub = event(Some("docker.io"), "org/app", "latest");
+        assert!(docker_hub.matches_image("org/app:latest"));
+        assert!(docker_hub.matches_image("index.docker.io/org/app"));
+        assert!(!docker_hub.matches_image("ghcr.io/org/app:latest"));
+        assert!(!docker_hub.matches_image("org/app-worker:latest"));
+
+        let registry_with_port = event(Some("registry.internal:5000"), "team/app", "v1");
+        assert!(registry_with_port.matches_image("registry.internal:5000/team/app:v1"));
+
+        let artifactory = event(None, "org/app", "latest");
+        assert!(artifactory.matches_image("artifactory.example.com/docker-local/org/app:latest"));
+        assert!(!artifactory.matches_image("artifactory.example.com/docker-local/org/other:latest"));
+    }
+}
@@ -1,15 +1,207 @@
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+use crate::api::{
+    approve_rollout, effective_config, info, last_run, list_approvals, list_dead_letters,
+    list_history, list_registry_requests, list_workloads, pause_workload, resume_workload,
+    trigger_reconcile,
+};
+use crate::config::{Config, UnmatchedWorkloads};
+use crate::config_lint;
+use crate::schedule::WorkloadSchedule;
+use crate::state::{ControllerContext, ControllerState};
+use crate::webhook::receive_registry_webhook;
+use axum::extract::State;
+use axum::routing::post;
+use axum::{http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::{debug, warn};
 
-pub async fn readiness_probe() -> impl IntoResponse {
-    StatusCode::NO_CONTENT
+pub async fn readiness_probe(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    let state = ctx.state.read().unwrap();
+    match find_unreadiness(&ctx.config, &state) {
+        Some(reason) => {
+            debug!(reason = %reason, "Readiness probe failed");
+            (StatusCode::SERVICE_UNAVAILABLE, reason).into_response()
+        }
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
 }
 
-pub async fn liveness_probe() -> impl IntoResponse {
-    StatusCode::NO_CONTENT
+/// Returns why the controller is not ready, if it is not
+fn find_unreadiness(config: &Config, state: &ControllerState) -> Option<String> {
+    if let Some(config_error) = &state.config_error {
+        return Some(format!("Configuration file is invalid: {}", config_error));
+    }
+    if state.kube_api_errors >= config.health.max_kube_api_errors {
+        return Some(format!(
+            "The last {} Kubernetes API requests failed",
+            state.kube_api_errors
+        ));
+    }
+    if config.health.require_completed_run && state.completed_cycles == 0 {
+        return Some("No run has completed yet".to_string());
+    }
+    if let Some(tick) = state
+        .schedule_ticks
+        .last_tick
+        .as_ref()
+        .filter(|tick| tick.gap)
+    {
+        return Some(format!(
+            "The last scheduled run started {}s late at {}, {} scheduled runs were skipped",
+            tick.delay_seconds, tick.started_at, tick.missed_ticks
+        ));
+    }
+    if config.unmatched_workloads == UnmatchedWorkloads::Strict
+        && !state.unmatched_workloads.is_empty()
+    {
+        return Some("Workloads use images of unmatched registries".to_string());
+    }
+    None
 }
 
-pub fn create_app() -> Router {
+pub async fn liveness_probe(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    let state = ctx.state.read().unwrap();
+    match find_stalled_runs(&ctx.config, &state, Utc::now()) {
+        Some(reason) => {
+            warn!(reason = %reason, "Liveness probe failed");
+            (StatusCode::SERVICE_UNAVAILABLE, reason).into_response()
+        }
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Returns why the controller is considered stuck, which is when no run completed within `maxMissedRuns`
+/// occurrences of its schedule since the last completed run or the start
+fn find_stalled_runs(
+    config: &Config,
+    state: &ControllerState,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    let since = state.last_completed_run_at.or(state.started_at)?;
+    let schedule = WorkloadSchedule::of_controller(config).ok()?;
+    let deadline = schedule
+        .occurrence_after(since, config.health.max_missed_runs)
+        .ok()?;
+    (deadline < now).then(|| {
+        format!(
+            "No run has completed since {}, {} scheduled runs were missed",
+            since, config.health.max_missed_runs
+        )
+    })
+}
+
+pub async fn status(State(ctx): State<ControllerContext>) -> impl IntoResponse {
+    let ctx = ctx.with_latest_config();
+    let state = ctx.state.read().unwrap();
+    let mut config_warnings = config_lint::find_unused_patterns(&ctx.config, &state);
+    config_warnings.extend(config_lint::find_overlapping_patterns(&ctx.config));
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "leader": ctx.is_leader(),
+        "trackedWorkloads": state.workloads.len(),
+        "registries": state.registry_probes,
+        "unmatchedWorkloads": state.unmatched_workloads,
+        "removedTags": state.removed_tags,
+        "signatureFailures": state.signature_failures,
+        "retryingWorkloads": state.work_queue.retrying(),
+        "deadLetteredWorkloads": state.work_queue.dead_letters().len(),
+        "scheduleGaps": state.schedule_ticks.gaps,
+        "configWarnings": config_warnings,
+        "caches": ctx.caches.stats(),
+        "rateLimits": ctx.rate_limits.snapshot(),
+    }))
+}
+
+fn health_routes() -> Router<ControllerContext> {
     Router::new()
         .route("/health/live", get(liveness_probe))
         .route("/health/ready", get(readiness_probe))
 }
+
+/// Serves the health probes only, for the health listener configured apart from the API
+pub fn create_health_app(ctx: ControllerContext) -> Router {
+    health_routes().with_state(ctx)
+}
+
+/// Serves the status, API and webhook endpoints, and the health probes unless they have a listener of their own
+pub fn create_app(ctx: ControllerContext) -> Router {
+    let mut router = Router::new()
+        .route("/status", get(status))
+        .route("/api/v1/workloads", get(list_workloads))
+        .route("/api/v1/history", get(list_history))
+        .route("/api/v1/approvals", get(list_approvals))
+        .route("/api/v1/approvals/{id}/approve", post(approve_rollout))
+        .route("/api/v1/dead-letters", get(list_dead_letters))
+        .route("/api/v1/last-run", get(last_run))
+        .route("/api/v1/info", get(info))
+        .route("/api/v1/config", get(effective_config))
+        .route("/api/v1/reconcile", post(trigger_reconcile))
+        .route("/debug/registry-requests", get(list_registry_requests))
+        .route("/webhooks/registry", post(receive_registry_webhook))
+        .route(
+            "/api/v1/workloads/{namespace}/{kind}/{name}/pause",
+            post(pause_workload),
+        )
+        .route(
+            "/api/v1/workloads/{namespace}/{kind}/{name}/resume",
+            post(resume_workload),
+        );
+    if ctx.config.webserver.health.is_none() {
+        router = router.merge(health_routes());
+    }
+    #[cfg(feature = "runtime-diagnostics")]
+    {
+        router = router.route("/debug/tasks", get(crate::api::list_tasks));
+    }
+    router.with_state(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_ready_after_repeated_kube_api_errors_or_invalid_config() {
+        let mut config: Config =
+            serde_yaml_ng::from_str("webserver:\n  port: 8080\nregistries: []\n").unwrap();
+        let mut state = ControllerState::default();
+        assert_eq!(find_unreadiness(&config, &state), None);
+
+        state.kube_api_errors = 2;
+        assert_eq!(find_unreadiness(&config, &state), None);
+        state.record_kube_api_request(false);
+        assert!(find_unreadiness(&config, &state).is_some());
+        state.record_kube_api_request(true);
+        assert_eq!(find_unreadiness(&config, &state), None);
+
+        config.health.require_completed_run = true;
+        assert!(find_unreadiness(&config, &state).is_some());
+        state.completed_cycles = 1;
+        assert_eq!(find_unreadiness(&config, &state), None);
+
+        state.config_error = Some("invalid YAML".to_string());
+        assert!(find_unreadiness(&config, &state).is_some());
+    }
+
+    #[test]
+    fn not_live_after_missed_runs() {
+        let config: Config = serde_yaml_ng::from_str(
+            "webserver:\n  port: 8080\nregistries: []\ncheckInterval: 30s\n",
+        )
+        .unwrap();
+        let started_at = Utc::now();
+        let mut state = ControllerState {
+            started_at: Some(started_at),
+            ..Default::default()
+        };
+        let after = |seconds| started_at + chrono::Duration::seconds(seconds);
+
+        assert_eq!(find_stalled_runs(&config, &state, after(150)), None);
+        assert!(find_stalled_runs(&config, &state, after(151)).is_some());
+
+        state.last_completed_run_at = Some(after(120));
+        assert_eq!(find_stalled_runs(&config, &state, after(200)), None);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::config::{RestartTrigger, WorkloadKind};
+use crate::controller::{trigger_rollout, KUBE_AUTOROLLOUT_LABEL};
+use crate::events::{publish, workload_reference};
+use crate::rollout::{ChangedContainer, Rollout};
+use crate::skip_reason::SkipReason;
+use crate::state::ControllerContext;
+use anyhow::Context;
+use chrono::Utc;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use kube::api::ListParams;
+use kube::runtime::events::EventType;
+use kube::{Api, ResourceExt};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Workloads of a namespace sharing this annotation are rolled out together when any of them has a new digest
+pub static KUBE_AUTOROLLOUT_GROUP_ANNOTATION: &str = "kube-autorollout/group";
+
+/// Changes of a group member found during a check, rolled out once all members have been checked
+pub struct GroupChange {
+    pub kind: &'static str,
+    pub name: String,
+    pub changed_containers: Vec<ChangedContainer>,
+    pub restart_trigger: RestartTrigger,
+    pub requires_approval: bool,
+}
+
+/// Groups with changed members, collected over a run and keyed by namespace and group name
+#[derive(Default)]
+pub struct GroupTriggers(Mutex<BTreeMap<(String, String), Vec<GroupChange>>>);
+
+impl GroupTriggers {
+    pub fn add(&self, namespace: &str, group: &str, change: GroupChange) {
+        self.0
+            .lock()
+            .unwrap()
+            .entry((namespace.to_string(), group.to_string()))
+            .or_default()
+            .push(change);
+    }
+}
+
+pub fn group_name<T: Rollout>(resource: &T) -> Option<&str> {
+    resource
+        .annotations()
+        .get(KUBE_AUTOROLLOUT_GROUP_ANNOTATION)
+        .map(String::as_str)
+        .filter(|group| !group.is_empty())
+}
+
+/// Rolls out every group with a changed member, returns the failures to be reported with the run
+pub async fn trigger_all(ctx: &ControllerContext, triggers: GroupTriggers) -> Vec<String> {
+    let mut failures = Vec::new();
+    for ((namespace, group), changes) in triggers.0.into_inner().unwrap() {
+        if let Err(err) = trigger(ctx, &namespace, &group, &changes).await {
+            warn!(
+                error = ?err,
+                namespace = %namespace,
+                group = %group,
+                "Failed to roll out workload group"
+            );
+            failures.push(format!("group {}/{}: {:#}", namespace, group, err));
+        }
+    }
+    failures
+}
+
+/// Labeled workloads of all configured kinds belonging to a group
+struct GroupMembers {
+    deployments: Vec<Deployment>,
+    stateful_sets: Vec<StatefulSet>,
+    daemon_sets: Vec<DaemonSet>,
+}
+
+/// Rolls out all members of the group, or none of them if any member can not be rolled out right now
+async fn trigger(
+    ctx: &ControllerContext,
+    namespace: &str,
+    group: &str,
+    changes: &[GroupChange],
+) -> anyhow::Result<()> {
+    let mut members = GroupMembers {
+        deployments: Vec::new(),
+        stateful_sets: Vec::new(),
+        daemon_sets: Vec::new(),
+    };
+    for kind in &ctx.config.workload_kinds {
+        match kind {
+            WorkloadKind::Deployment => {
+                members.deployments = list_members(ctx, namespace, group).await?
+            }
+            WorkloadKind::StatefulSet => {
+                members.stateful_sets = list_members(ctx, namespace, group).await?
+            }
+            WorkloadKind::DaemonSet => {
+                members.daemon_sets = list_members(ctx, namespace, group).await?
+            }
+        }
+    }
+
+    let blocker = find_blocker(ctx, &members.deployments)
+        .or_else(|| find_blocker(ctx, &members.stateful_sets))
+        .or_else(|| find_blocker(ctx, &members.daemon_sets));
+    if let Some(blocker) = blocker {
+        info!(
+            namespace = %namespace,
+            group = %group,
+            blocker = %blocker,
+            "Deferring rollout of workload group, one of its members can not be rolled out"
+        );
+        for change in changes {
+            ctx.state.write().unwrap().record_skip(
+                change.kind,
+                namespace,
+                &change.name,
+                SkipReason::GroupBlocked {
+                    group: group.to_string(),
+                    blocker: blocker.to_string(),
+                },
+            );
+            publish(
+                ctx,
+                workload_reference(change.kind, namespace, &change.name),
+                EventType::Normal,
+                "RolloutSkipped",
+                "Rollout",
+                format!("Rollout of group {} deferred, {}", group, blocker),
+            );
+        }
+        return Ok(());
+    }
+
+    info!(
+        namespace = %namespace,
+        group = %group,
+        members = %(members.deployments.len() + members.stateful_sets.len() + members.daemon_sets.len()),
+        "Triggering rollout of workload group"
+    );
+    let mut failures = Vec::new();
+    failures.extend(roll_out_members(ctx, namespace, &members.deployments, changes).await);
+    failures.extend(roll_out_members(ctx, namespace, &members.stateful_sets, changes).await);
+    failures.extend(roll_out_members(ctx, namespace, &members.daemon_sets, changes).await);
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "Failed to roll out {} members: {}",
+            failures.len(),
+            failures.join("; ")
+        );
+    }
+    Ok(())
+}
+
+async fn list_members<T: Rollout>(
+    ctx: &ControllerContext,
+    namespace: &str,
+    group: &str,
+) -> anyhow::Result<Vec<T>> {
+    let api: Api<T> = Api::namespaced(ctx.kube_client.clone(), namespace);
+    let resource_list = api
+        .list(&ListParams::default().labels(KUBE_AUTOROLLOUT_LABEL))
+        .await;
+    ctx.state
+        .write()
+        .unwrap()
+        .record_kube_api_request(resource_list.is_ok());
+    Ok(resource_list
+        .with_context(|| format!("Failed to list {}s of group {}", T::kind_name(), group))?
+        .items
+        .into_iter()
+        .filter(|resource| group_name(resource) == Some(group))
+        .collect())
+}
+
+/// Why a member can not be rolled out right now, as the group is only rolled out as a whole
+fn find_blocker<T: Rollout>(ctx: &ControllerContext, members: &[T]) -> Option<String> {
+    members.iter().find_map(|member| {
+        let member_name = format!("{} {}", T::kind_name(), member.name_any());
+        if member.is_paused() {
+            return Some(format!("{} is paused", member_name));
+        }
+        if member.is_dry_run(ctx.config.dry_run) {
+            return Some(format!("{} is in dry-run mode", member_name));
+        }
+        member
+            .rollout_cooldown_until(ctx.config.rollout_cooldown, Utc::now())
+            .map(|until| format!("{} is in its rollout cooldown until {}", member_name, until))
+    })
+}
+
+/// Rolls out the members with their own changes, members without changes are restarted through the annotation
+async fn roll_out_members<T: Rollout>(
+    ctx: &ControllerContext,
+    namespace: &str,
+    members: &[T],
+    changes: &[GroupChange],
+) -> Vec<String> {
+    let api: Api<T> = Api::namespaced(ctx.kube_client.clone(), namespace);
+    let mut failures = Vec::new();
+    for member in members {
+        let name = member.name_any();
+        let change = changes
+            .iter()
+            .find(|change| change.kind == T::kind_name() && change.name == name);
+        let result = match change {
+            Some(change) => {
+                trigger_rollout(
+                    ctx,
+                    &api,
+                    member,
+                    namespace,
+                    &change.changed_containers,
+                    change.restart_trigger,
+                    change.requires_approval,
+                )
+                .await
+            }
+            None => {
+                trigger_rollout(
+                    ctx,
+                    &api,
+                    member,
+                    namespace,
+                    &[],
+                    RestartTrigger::Annotation,
+                    false,
+                )
+                .await
+            }
+        };
+        if let Err(err) = result {
+            failures.push(format!("{} {}: {:#}", T::kind_name(), name, err));
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn deployment(group: Option<&str>) -> Deployment {
+        Deployment {
+            metadata: ObjectMeta {
+                name: Some("payments-api".to_string()),
+                annotations: group.map(|group| {
+                    BTreeMap::from([(
+                        KUBE_AUTOROLLOUT_GROUP_ANNOTATION.to_string(),
+                        group.to_string(),
+                    )])
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn group_name_ignores_empty_annotations() {
+        assert_eq!(group_name(&deployment(Some("payments"))), Some("payments"));
+        assert_eq!(group_name(&deployment(Some(""))), None);
+        assert_eq!(group_name(&deployment(None)), None);
+    }
+
+    #[test]
+    fn group_triggers_are_keyed_by_namespace_and_group() {
+        let triggers = GroupTriggers::default();
+        for (namespace, name) in [("shop", "api"), ("shop", "worker"), ("staging", "api")] {
+            triggers.add(
+                namespace,
+                "payments",
+                GroupChange {
+                    kind: "Deployment",
+                    name: name.to_string(),
+                    changed_containers: Vec::new(),
+                    restart_trigger: RestartTrigger::Annotation,
+                    requires_approval: false,
+                },
+            );
+        }
+        let triggers = triggers.0.into_inner().unwrap();
+        assert_eq!(triggers.len(), 2);
+        assert_eq!(
+            triggers[&("shop".to_string(), "payments".to_string())]
+                .iter()
+                .map(|change| change.name.as_str())
+                .collect::<Vec<_>>(),
+            ["api", "worker"]
+        );
+    }
+}
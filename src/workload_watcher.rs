@@ -0,0 +1,205 @@
+use crate::config::{Watch, WorkloadKind};
+use crate::controller::{reconcile_resource, KUBE_AUTOROLLOUT_LABEL};
+use crate::rollout::Rollout;
+use crate::state::{ControllerContext, ControllerState};
+use crate::tracked_image::{resolve_tracked_images, ResolvedTrackedImage};
+use crate::workload_group::{self, GroupTriggers};
+use chrono::Utc;
+use futures::future::{join_all, BoxFuture};
+use futures::{FutureExt, StreamExt};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use kube::runtime::controller::Action;
+use kube::runtime::{watcher, Controller};
+use kube::{Api, ResourceExt};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+/// Events arriving sooner after the last check of a workload, e.g. status updates during its own rollout, are
+/// answered by the next resync instead
+const MIN_CHECK_INTERVAL: chrono::TimeDelta = chrono::TimeDelta::seconds(10);
+const ERROR_REQUEUE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct WatchContext {
+    ctx: ControllerContext,
+    resync_interval: Duration,
+    /// Resolved once per resync interval instead of on every event
+    tracked_images: RwLock<BTreeMap<String, ResolvedTrackedImage>>,
+    /// Limits the workloads checked at the same time across all kinds, like `reconcileConcurrency` in scheduled runs
+    limiter: Semaphore,
+}
+
+#[derive(Debug)]
+struct ReconcileError(anyhow::Error);
+
+impl std::fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for ReconcileError {}
+
+/// Watches the labeled workloads of all configured kinds and checks them whenever they are added or changed,
+/// and again after every resync interval
+pub async fn run(ctx: ControllerContext, watch: Watch) {
+    info!(
+        resync_interval = %humantime_serde::re::humantime::format_duration(watch.resync_interval),
+        "Watching labeled workloads"
+    );
+    let limiter = Semaphore::new(ctx.config.reconcile_concurrency);
+    let watch_ctx = Arc::new(WatchContext {
+        ctx,
+        resync_interval: watch.resync_interval,
+        tracked_images: RwLock::new(BTreeMap::new()),
+        limiter,
+    });
+
+    let mut tasks: Vec<BoxFuture<'static, ()>> = vec![resync(watch_ctx.clone()).boxed()];
+    for kind in &watch_ctx.ctx.config.workload_kinds {
+        let task = match kind {
+            WorkloadKind::Deployment => watch_kind::<Deployment>(watch_ctx.clone()).boxed(),
+            WorkloadKind::StatefulSet => watch_kind::<StatefulSet>(watch_ctx.clone()).boxed(),
+            WorkloadKind::DaemonSet => watch_kind::<DaemonSet>(watch_ctx.clone()).boxed(),
+        };
+        tasks.push(task);
+    }
+    join_all(tasks).await;
+}
+
+/// Refreshes the state shared by all watched workloads once per resync interval
+async fn resync(watch_ctx: Arc<WatchContext>) {
+    loop {
+        let ctx = watch_ctx.ctx.with_latest_config();
+        if ctx.config.feature_flags.enable_tracked_images {
+            match resolve_tracked_images(&ctx).await {
+                Ok(tracked_images) => *watch_ctx.tracked_images.write().unwrap() = tracked_images,
+                Err(err) => warn!(
+                    error = ?err,
+                    "Failed to resolve TrackedImages, keeping the previously resolved digests"
+                ),
+            }
+        }
+        tokio::time::sleep(watch_ctx.resync_interval).await;
+        // without any labeled workload nothing is reconciled, which does not make the watcher stuck
+        if ctx.state.read().unwrap().workloads.is_empty() {
+            record_completed_reconcile(&ctx);
+        }
+    }
+}
+
+/// Every finished reconcile completes a run in watch mode, so that the liveness probe detects a watcher that stopped
+/// reconciling
+fn record_completed_reconcile(ctx: &ControllerContext) {
+    let mut state = ctx.state.write().unwrap();
+    state.completed_cycles += 1;
+    state.last_completed_run_at = Some(Utc::now());
+}
+
+async fn watch_kind<T>(watch_ctx: Arc<WatchContext>)
+where
+    T: Rollout,
+{
+    let client = watch_ctx.ctx.kube_client.clone();
+    let api: Api<T> = match watch_ctx.ctx.config.namespaces.cluster_wide {
+        true => Api::all(client),
+        false => Api::default_namespaced(client),
+    };
+
+    Controller::new(
+        api,
+        watcher::Config::default().labels(KUBE_AUTOROLLOUT_LABEL),
+    )
+    .run(reconcile::<T>, error_policy::<T>, watch_ctx)
+    .for_each(|result| async move {
+        if let Err(err) = result {
+            debug!(error = %err, kind = %T::kind_name(), "Watch event was not reconciled");
+        }
+    })
+    .await;
+}
+
+async fn reconcile<T>(
+    resource: Arc<T>,
+    watch_ctx: Arc<WatchContext>,
+) -> Result<Action, ReconcileError>
+where
+    T: Rollout,
+{
+    if !watch_ctx.ctx.is_leader() {
+        debug!(kind = %T::kind_name(), "Skipping watch event, this replica is not the leader");
+        // a follower has nothing to do, but its watcher is alive
+        watch_ctx.ctx.state.write().unwrap().last_completed_run_at = Some(Utc::now());
+        return Ok(Action::requeue(watch_ctx.resync_interval));
+    }
+    let key = ControllerState::workload_key(
+        T::kind_name(),
+        &resource.namespace().unwrap_or_default(),
+        &resource.name_any(),
+    );
+    let last_checked_at = watch_ctx
+        .ctx
+        .state
+        .read()
+        .unwrap()
+        .workloads
+        .get(&key)
+        .and_then(|workload| workload.last_checked_at);
+    if last_checked_at.is_some_and(|checked_at| Utc::now() - checked_at < MIN_CHECK_INTERVAL) {
+        debug!(workload = %key, "Skipping watch event, workload has just been checked");
+        return Ok(Action::requeue(watch_ctx.resync_interval));
+    }
+
+    // runs triggered through the API or by push events do not overlap the reconciles of the watch mode
+    let _run_guard = watch_ctx.ctx.run_lock.read().await;
+    let _permit = watch_ctx
+        .limiter
+        .acquire()
+        .await
+        .map_err(|err| ReconcileError(err.into()))?;
+    let tracked_images = watch_ctx.tracked_images.read().unwrap().clone();
+    let ctx = watch_ctx.ctx.with_latest_config();
+    let groups = GroupTriggers::default();
+    let result = reconcile_resource(&ctx, resource.as_ref(), &tracked_images, &groups).await;
+    // a failed workload still completed its reconcile, like a run with failed workloads
+    record_completed_reconcile(&ctx);
+    result.map_err(ReconcileError)?;
+    let failures = workload_group::trigger_all(&ctx, groups).await;
+    if !failures.is_empty() {
+        return Err(ReconcileError(anyhow::anyhow!(failures.join("; "))));
+    }
+    Ok(Action::requeue(watch_ctx.resync_interval))
+}
+
+/// Failed workloads are requeued when the work queue retries them, dead-lettered ones with the next resync
+fn error_policy<T>(resource: Arc<T>, err: &ReconcileError, watch_ctx: Arc<WatchContext>) -> Action
+where
+    T: Rollout,
+{
+    warn!(
+        error = ?err.0,
+        kind = %T::kind_name(),
+        resource = %resource.name_any(),
+        "Failed to reconcile watched resource"
+    );
+    let key = ControllerState::workload_key(
+        T::kind_name(),
+        &resource.namespace().unwrap_or_default(),
+        &resource.name_any(),
+    );
+    let retry_at = match watch_ctx.ctx.state.read().unwrap().work_queue.get(&key) {
+        Some(failure) => failure.retry_at,
+        None => return Action::requeue(ERROR_REQUEUE_INTERVAL),
+    };
+    match retry_at {
+        Some(retry_at) => Action::requeue(
+            (retry_at - Utc::now())
+                .max(MIN_CHECK_INTERVAL)
+                .to_std()
+                .unwrap_or(ERROR_REQUEUE_INTERVAL),
+        ),
+        None => Action::requeue(watch_ctx.resync_interval),
+    }
+}
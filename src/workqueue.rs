@@ -0,0 +1,156 @@
+use crate::config::WorkloadRetry;
+use crate::skip_reason::SkipReason;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Latest failure of a workload whose checks failed in a row
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkloadFailure {
+    pub(crate) attempts: u32,
+    pub(crate) last_error: String,
+    pub(crate) last_failed_at: DateTime<Utc>,
+    /// When the workload is checked again, None once it is dead-lettered
+    pub(crate) retry_at: Option<DateTime<Utc>>,
+    /// Generation of the workload when it failed, a changed spec is checked right away
+    pub(crate) generation: Option<i64>,
+}
+
+/// Failing workloads by workload key. Every failure in a row doubles the delay until the workload is checked again,
+/// after the maximum attempts it is dead-lettered and only checked again once its spec changes or it is reconciled
+/// through the API.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct WorkQueue {
+    failures: BTreeMap<String, WorkloadFailure>,
+}
+
+impl WorkQueue {
+    /// Returns why the workload is held back, None if it is due. Failures of an older generation are forgotten.
+    pub fn hold(
+        &mut self,
+        key: &str,
+        generation: Option<i64>,
+        now: DateTime<Utc>,
+    ) -> Option<SkipReason> {
+        let failure = self.failures.get(key)?;
+        if failure.generation != generation {
+            self.failures.remove(key);
+            return None;
+        }
+        match failure.retry_at {
+            None => Some(SkipReason::DeadLettered {
+                attempts: failure.attempts,
+                last_error: failure.last_error.clone(),
+            }),
+            Some(retry_at) if retry_at > now => Some(SkipReason::BackingOff {
+                retry_at,
+                attempt: failure.attempts + 1,
+            }),
+            Some(_) => None,
+        }
+    }
+
+    /// Records a failed check and returns the failure, dead-lettered if it used up the attempts
+    pub fn record_failure(
+        &mut self,
+        key: &str,
+        generation: Option<i64>,
+        error: &str,
+        retry: &WorkloadRetry,
+        now: DateTime<Utc>,
+    ) -> WorkloadFailure {
+        let attempts = self
+            .failures
+            .get(key)
+            .filter(|failure| failure.generation == generation)
+            .map_or(0, |failure| failure.attempts)
+            + 1;
+        let failure = WorkloadFailure {
+            attempts,
+            last_error: error.to_string(),
+            last_failed_at: now,
+            retry_at: (attempts < retry.max_attempts).then(|| now + backoff(retry, attempts)),
+            generation,
+        };
+        self.failures.insert(key.to_string(), failure.clone());
+        failure
+    }
+
+    /// Forgets the failures of a workload after a successful check or before it is reconciled through the API
+    pub fn forget(&mut self, key: &str) {
+        self.failures.remove(key);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&WorkloadFailure> {
+        self.failures.get(key)
+    }
+
+    pub fn dead_letters(&self) -> BTreeMap<String, WorkloadFailure> {
+        self.failures
+            .iter()
+            .filter(|(_, failure)| failure.retry_at.is_none())
+            .map(|(key, failure)| (key.clone(), failure.clone()))
+            .collect()
+    }
+
+    /// Workloads waiting for their next attempt, not counting dead-lettered ones
+    pub fn retrying(&self) -> usize {
+        self.failures
+            .values()
+            .filter(|failure| failure.retry_at.is_some())
+            .count()
+    }
+}
+
+fn backoff(retry: &WorkloadRetry, attempts: u32) -> Duration {
+    let delay = retry
+        .base_delay
+        .saturating_mul(2u32.saturating_pow(attempts - 1))
+        .min(retry.max_delay);
+    Duration::from_std(delay).unwrap_or(Duration::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_workloads_back_off_until_dead_lettered() {
+        let retry = WorkloadRetry {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_secs(30),
+            max_delay: std::time::Duration::from_secs(45),
+        };
+        let mut queue = WorkQueue::default();
+        let key = "Deployment/default/app";
+        let now = Utc::now();
+        assert_eq!(queue.hold(key, Some(1), now), None);
+
+        let first = queue.record_failure(key, Some(1), "invalid image", &retry, now);
+        assert_eq!(first.retry_at, Some(now + Duration::seconds(30)));
+        assert!(queue.hold(key, Some(1), now).is_some());
+        assert_eq!(queue.hold(key, Some(1), now + Duration::seconds(30)), None);
+
+        let second = queue.record_failure(key, Some(1), "invalid image", &retry, now);
+        assert_eq!(second.retry_at, Some(now + Duration::seconds(45)));
+        assert_eq!(queue.retrying(), 1);
+
+        let third = queue.record_failure(key, Some(1), "invalid image", &retry, now);
+        assert_eq!((third.attempts, third.retry_at), (3, None));
+        assert_eq!(
+            queue.hold(key, Some(1), now + Duration::days(1)),
+            Some(SkipReason::DeadLettered {
+                attempts: 3,
+                last_error: "invalid image".to_string(),
+            })
+        );
+        assert_eq!(queue.dead_letters().len(), 1);
+        assert_eq!(queue.retrying(), 0);
+
+        // a changed spec is checked right away and starts over
+        assert_eq!(queue.hold(key, Some(2), now), None);
+        assert!(queue.dead_letters().is_empty());
+    }
+}